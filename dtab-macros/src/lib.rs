@@ -0,0 +1,169 @@
+//! Compile-time checked dtab literals for the [`dtab`] crate.
+//!
+//! These macros read their literals with `dtab`'s own parser while the
+//! calling crate is compiled, so a literal which the parser would reject is
+//! a compile error, and the value is built directly from the parsed dtab,
+//! without parsing anything at runtime. Since the expansion names `::dtab`,
+//! the calling crate must depend on `dtab` under that name.
+//!
+//! [`dtab`]: https://docs.rs/dtab
+extern crate dtab;
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use] extern crate quote;
+extern crate syn;
+
+use dtab::{Dentry, Dtab, NameTree};
+use dtab::prefix::Elem;
+use dtab::weight::Weight;
+use proc_macro2::{Literal, TokenStream};
+
+/// Macro for constructing a [`Dtab`] from a string literal in the dtab
+/// syntax.
+///
+/// The literal is read with [`Dtab::read`] at compile time, so a typo in the
+/// dtab is a compile error rather than an error to handle at runtime, and
+/// the macro expands to an expression which builds the parsed dtab's
+/// dentries directly.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use] extern crate dtab;
+/// extern crate dtab_macros;
+/// use dtab_macros::dtab_static;
+///
+/// # fn main() {
+/// let dtab = dtab_static!("
+///     /smitten       => /USA/CA/SF/Harrison/2790; # the Mission
+///     /iceCreamStore => /humphrys | 0.7 * /smitten & 0.3 * /birite;
+/// ");
+/// let expected = vec![
+///     dentry!("/smitten" => "/USA/CA/SF/Harrison/2790"),
+///     dentry!("/iceCreamStore" => "/humphrys" | 0.7 * "/smitten" & 0.3 * "/birite"),
+/// ];
+/// assert_eq!(dtab, dtab::Dtab::from(expected));
+/// # }
+/// ```
+///
+/// Invalid dtabs are compile errors, which point at the literal and say
+/// where in it the parser failed:
+///
+/// ```compile_fail
+/// # extern crate dtab;
+/// # extern crate dtab_macros;
+/// # use dtab_macros::dtab_static;
+/// # fn main() {
+/// let dtab = dtab_static!("/smitten => /USA/CA/SF/Harrison/2790; /iceCreamStore =>");
+/// # }
+/// ```
+///
+/// So are weights too large to read, and name trees deeper than the default
+/// limit:
+///
+/// ```compile_fail
+/// # extern crate dtab;
+/// # extern crate dtab_macros;
+/// # use dtab_macros::dtab_static;
+/// # fn main() {
+/// let dtab = dtab_static!("/iceCreamStore => 1e30 * /humphrys & 1 * /birite");
+/// # }
+/// ```
+///
+/// [`Dtab`]: https://docs.rs/dtab/*/dtab/struct.Dtab.html
+/// [`Dtab::read`]: https://docs.rs/dtab/*/dtab/struct.Dtab.html#method.read
+#[proc_macro]
+pub fn dtab_static(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let literal = syn::parse_macro_input!(input as syn::LitStr);
+    match expand_dtab(&literal.value()) {
+        Ok(tokens) => tokens.into()
+        // not `syn::Error::to_compile_error`, which names `::core`, since
+        // crates using the 2015 edition can't refer to it.
+      , Err(message) => quote_spanned!(literal.span()=> compile_error!(#message)).into()
+    }
+}
+
+/// Read `src` as [`Dtab::read`] does, returning an expression which builds
+/// the dtab, or a message describing why it isn't valid.
+fn expand_dtab(src: &str) -> Result<TokenStream, String> {
+    let dtab = Dtab::read(src)
+        .map_err(|error| format!("invalid dtab literal: {}", error.locate(src)))?;
+    let dentries = dtab.iter().map(dentry);
+    Ok(quote! { ::dtab::Dtab::from(::std::vec![ #(#dentries),* ]) })
+}
+
+/// An expression which builds `dentry`.
+fn dentry(dentry: &Dentry) -> TokenStream {
+    let elems = dentry.prefix().elems().iter().map(|elem| match *elem {
+        Elem::Label(ref label) => {
+            let label = Literal::byte_string(label);
+            quote! { ::dtab::prefix::Elem::Label(#label.to_vec()) }
+        }
+      , Elem::AnyElem => quote! { ::dtab::prefix::Elem::AnyElem }
+    });
+    let dst = tree(dentry.dst());
+    quote! {
+        ::dtab::Dentry::new( ::dtab::Prefix::new(::std::vec![ #(#elems),* ])
+                                 .expect("prefix was read at compile time")
+                           , #dst)
+    }
+}
+
+/// An expression which builds `tree`.
+///
+/// This recurses, but the parser has already limited the depth of `tree` to
+/// the default maximum.
+fn tree(tree: &NameTree<String>) -> TokenStream {
+    match *tree {
+        NameTree::Leaf(ref leaf) =>
+            quote! { ::dtab::NameTree::Leaf(::std::string::String::from(#leaf)) }
+      , NameTree::Union(ref left, ref right) => {
+            let (left_weight, left) = (weight(left.weight()), self::tree(left.tree()));
+            let (right_weight, right) = (weight(right.weight()), self::tree(right.tree()));
+            quote! {
+                ::dtab::NameTree::Union( #left.weighted(#left_weight)
+                                       , #right.weighted(#right_weight))
+            }
+        }
+      , NameTree::Alt(ref left, ref right) => {
+            let (left, right) = (self::tree(left), self::tree(right));
+            quote! {
+                ::dtab::NameTree::Alt( ::std::boxed::Box::new(#left)
+                                     , ::std::boxed::Box::new(#right))
+            }
+        }
+      , NameTree::Neg => quote! { ::dtab::NameTree::Neg }
+      , NameTree::Empty => quote! { ::dtab::NameTree::Empty }
+      , NameTree::Fail => quote! { ::dtab::NameTree::Fail }
+    }
+}
+
+/// An expression which builds `weight` exactly.
+fn weight(weight: Weight) -> TokenStream {
+    let (num, den) = (weight.numer(), weight.denom());
+    quote! { ::dtab::weight::Weight::ratio(#num, #den) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_literals() {
+        assert_eq!( expand_dtab("/a => /b; /c =>").unwrap_err()
+                  , "invalid dtab literal: 1:16: expected a name tree, found end of input");
+        assert!( expand_dtab("/a => 1e30 * /b & 1 * /c").unwrap_err()
+                     .starts_with("invalid dtab literal: 1:7: "));
+        assert!(expand_dtab("/a => /b; /c => /d").is_ok());
+    }
+
+    #[test]
+    fn expansion() {
+        let expanded = expand_dtab("/a/* => 0.25 * /b & 0.75 * (~ | !)").unwrap().to_string();
+        assert!(expanded.contains(r#"Elem :: Label (b"a" . to_vec ())"#), "{}", expanded);
+        assert!(expanded.contains("Elem :: AnyElem"), "{}", expanded);
+        assert!(expanded.contains("Weight :: ratio (1u64 , 4u64)"), "{}", expanded);
+        assert!(expanded.contains("Weight :: ratio (3u64 , 4u64)"), "{}", expanded);
+        assert!(expanded.contains("NameTree :: Alt"), "{}", expanded);
+    }
+}
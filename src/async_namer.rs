@@ -0,0 +1,221 @@
+//! Binding names with asynchronous service discovery backends.
+//!
+//! Real namers, which resolve names with DNS, the Kubernetes API or namerd,
+//! wait on the network, and proxies built on an async runtime such as tokio
+//! can't block a thread while they do. An [`AsyncNamer`] returns a future of
+//! the tree a path is bound to instead, and [`delegate`] returns a future of
+//! the delegation's result.
+//!
+//! Since the names a namer binds paths to are never delegated again, the
+//! paths delegation asks a namer to bind don't depend on what it binds them
+//! to. [`delegate`] therefore delegates the path once to find every path
+//! the namer must bind, binds them all at once, and then delegates the path
+//! again with their bindings. The futures are polled concurrently, and the
+//! delegation itself never waits.
+//!
+//! This module requires the `futures` feature. It doesn't depend on any
+//! particular runtime.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # extern crate futures;
+//! # fn main() {
+//! use futures::executor::block_on;
+//! use futures::future::{self, BoxFuture, FutureExt};
+//! use dtab::NameTree;
+//! use dtab::async_namer::{self, AsyncNamer};
+//! use dtab::delegate::DelegateOptions;
+//! use dtab::path::Path;
+//!
+//! /// Binds `/#/static/<service>` to a single replica of each service.
+//! struct Static;
+//!
+//! impl AsyncNamer for Static {
+//!     fn lookup(&self, path: Path) -> BoxFuture<'_, Option<NameTree<Path>>> {
+//!         let service = path.labels().nth(2).map(|l| String::from_utf8_lossy(l).into_owned());
+//!         let tree = service.map(|s| NameTree::Leaf(Path::read(&format!("/{}-0", s)).unwrap()));
+//!         future::ready(tree).boxed()
+//!     }
+//! }
+//!
+//! let dtab = dtab!["/svc" => "/#/static";];
+//! let path = Path::read("/svc/users").unwrap();
+//! let options = DelegateOptions::default();
+//! let tree = block_on(async_namer::delegate(&dtab, &path, &options, &Static, ())).unwrap();
+//! assert_eq!(&tree.to_string(), "/users-0");
+//! # }
+//! ```
+//!
+//! [`AsyncNamer`]: trait.AsyncNamer.html
+//! [`delegate`]: fn.delegate.html
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+
+use futures::future::{self, BoxFuture, Either, Future, FutureExt};
+
+use super::{Dtab, NameTree};
+use super::delegate::{self, DelegateError, DelegateOptions};
+use super::name::Name;
+use super::namer::Namer;
+use super::observe::DelegationObserver;
+use super::path::Path;
+
+/// A service discovery backend which binds paths asynchronously.
+///
+/// This is the asynchronous counterpart of [`Namer`], and is asked to bind
+/// the same paths.
+///
+/// [`Namer`]: ../namer/trait.Namer.html
+pub trait AsyncNamer {
+    /// Bind `path`, returning a future of the tree of names it is bound to,
+    /// or of `None` if this namer doesn't know it.
+    ///
+    /// The path is passed by value, so that the future need not borrow it.
+    fn lookup(&self, path: Path) -> BoxFuture<'_, Option<NameTree<Path>>>;
+}
+
+/// Knows no paths.
+impl AsyncNamer for () {
+    #[inline] fn lookup(&self, _: Path) -> BoxFuture<'_, Option<NameTree<Path>>> {
+        future::ready(None).boxed()
+    }
+}
+
+impl<N> AsyncNamer for &N
+where N: AsyncNamer + ?Sized {
+    #[inline] fn lookup(&self, path: Path) -> BoxFuture<'_, Option<NameTree<Path>>> {
+        (**self).lookup(path)
+    }
+}
+
+/// An [`AsyncNamer`] which binds paths with a [`Namer`], whose lookups are
+/// already ready.
+///
+/// This lets namers which never wait, such as a [`MockNamer`], be used
+/// with [`delegate`].
+///
+/// [`AsyncNamer`]: trait.AsyncNamer.html
+/// [`Namer`]: ../namer/trait.Namer.html
+/// [`MockNamer`]: ../namer/struct.MockNamer.html
+/// [`delegate`]: fn.delegate.html
+#[derive(Clone, Debug, Default)]
+pub struct Immediate<N>(pub N);
+
+impl<N> AsyncNamer for Immediate<N>
+where N: Namer + Sync {
+    fn lookup(&self, path: Path) -> BoxFuture<'_, Option<NameTree<Path>>> {
+        future::ready(self.0.lookup(&path)).boxed()
+    }
+}
+
+/// Delegate `path` through `dtab`, as [`delegate::delegate`] does, binding
+/// paths which begin with `/$` or `/#` with `namer`.
+///
+/// Every path the namer is asked to bind is looked up concurrently. The
+/// observer is only notified of the lookups made once they are bound, so it
+/// sees each event once. If delegation fails, the returned future is ready
+/// immediately, without asking the namer to bind anything.
+///
+/// [`delegate::delegate`]: ../delegate/fn.delegate.html
+pub fn delegate<'a, N, O>( dtab: &'a Dtab, path: &'a Path, options: &'a DelegateOptions
+                         , namer: &'a N, observer: O)
+                         -> impl Future<Output=Result<NameTree<Name>, DelegateError>> + 'a
+where N: AsyncNamer + ?Sized
+    , O: DelegationObserver + 'a {
+    let unbound = Unbound::default();
+    if let Err(error) = delegate::delegate(dtab, path, options, &unbound, ()) {
+        return Either::Left(future::ready(Err(error)))
+    }
+    let lookups = unbound.0.into_inner().into_iter().map(move |unbound| {
+        namer.lookup(unbound.clone()).map(|tree| (unbound, tree))
+    });
+    Either::Right(future::join_all(lookups).map(move |bindings| {
+        let bound = Bound(bindings.into_iter()
+                                  .filter_map(|(path, tree)| tree.map(|tree| (path, tree)))
+                                  .collect());
+        delegate::delegate(dtab, path, options, &bound, observer)
+    }))
+}
+
+/// Records every path it is asked to bind, and binds none of them.
+#[derive(Default)]
+struct Unbound(RefCell<BTreeSet<Path>>);
+
+impl Namer for Unbound {
+    fn lookup(&self, path: &Path) -> Option<NameTree<Path>> {
+        self.0.borrow_mut().insert(path.clone());
+        None
+    }
+}
+
+/// Binds exactly the paths an `AsyncNamer` bound.
+struct Bound(HashMap<Path, NameTree<Path>>);
+
+impl Namer for Bound {
+    #[inline] fn lookup(&self, path: &Path) -> Option<NameTree<Path>> {
+        self.0.get(path).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::task::Poll;
+    use futures::executor::block_on;
+    use futures::channel::oneshot;
+    use namer::MockNamer;
+
+    fn path(s: &str) -> Path { Path::read(s).unwrap() }
+
+    #[test]
+    fn matches_sync_delegation() {
+        let namer: MockNamer = vec![ (path("/#/k8s/users"), NameTree::Leaf(path("/10.0.0.1")))
+                                   , (path("/#/k8s/orders"), NameTree::Neg) ]
+            .into_iter().collect();
+        let dtab = dtab![ "/svc" => NameTree::from("/#/k8s") | "/$/inet/fallback";
+                          "/svc/cart" => "/$/nil";
+                          "/a" => "/b"; "/b" => "/a"; ];
+        let options = DelegateOptions::default();
+        for p in &["/svc/users", "/svc/orders", "/svc/cart", "/other", "/a"] {
+            let p = path(p);
+            let expected = delegate::delegate(&dtab, &p, &options, &namer, ());
+            let actual = block_on(delegate(&dtab, &p, &options, &Immediate(&namer), ()));
+            assert_eq!(actual, expected, "delegating {}", p);
+        }
+    }
+
+    /// Binds each path once the test sends it a tree.
+    #[derive(Default)]
+    struct Pending(Mutex<Vec<(Path, oneshot::Sender<NameTree<Path>>)>>);
+
+    impl AsyncNamer for Pending {
+        fn lookup(&self, path: Path) -> BoxFuture<'_, Option<NameTree<Path>>> {
+            let (tx, rx) = oneshot::channel();
+            self.0.lock().unwrap().push((path, tx));
+            rx.map(Result::ok).boxed()
+        }
+    }
+
+    #[test]
+    fn concurrent_lookups() {
+        let dtab = dtab!["/svc" => NameTree::from("/#/a") & "/#/b";];
+        let (p, options, namer) = (path("/svc/x"), DelegateOptions::default(), Pending::default());
+        let mut delegation = delegate(&dtab, &p, &options, &namer, ()).boxed();
+        let result = block_on(future::poll_fn(|cx| {
+            if let Poll::Ready(result) = delegation.poll_unpin(cx) {
+                return Poll::Ready(result)
+            }
+            // both paths are being bound at once.
+            let mut pending = namer.0.lock().unwrap();
+            assert_eq!(pending.len(), 2);
+            for (unbound, tx) in pending.drain(..) {
+                tx.send(NameTree::Leaf(unbound.concat(&path("/bound")))).unwrap();
+            }
+            Poll::Pending
+        }));
+        assert_eq!(&result.unwrap().to_string(), "0.5 * /#/a/x/bound & 0.5 * /#/b/x/bound");
+    }
+}
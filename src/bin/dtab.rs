@@ -0,0 +1,71 @@
+//! Command-line tools for working with dtabs.
+//!
+//! ```text
+//! dtab fmt FILE     print FILE, formatted
+//! dtab repl FILE    explore delegation through the dtab in FILE
+//! dtab lsp          run a language server over stdin and stdout
+//! ```
+//!
+//! Files are parsed leniently, so stray `;`s, as in `/a => /b;;`, are
+//! accepted, and `dtab fmt` removes them.
+//!
+//! `dtab lsp` requires the `lsp` feature.
+extern crate dtab;
+
+use std::{env, fs, io, process};
+
+use dtab::format::{self, FormatOptions};
+use dtab::parse::{self, ParseOptions};
+use dtab::repl::Repl;
+
+const USAGE: &str = "usage: dtab fmt FILE\n       dtab repl FILE\n       dtab lsp";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let result = match args[..] {
+        ["fmt", file] => fmt(file)
+      , ["repl", file] => repl(file)
+      , ["lsp"] => lsp()
+      , _ => Err(USAGE.to_string())
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn read(file: &str) -> Result<String, String> {
+    fs::read_to_string(file).map_err(|e| format!("could not read {}: {}", file, e))
+}
+
+fn fmt(file: &str) -> Result<(), String> {
+    let source = read(file)?;
+    let options = FormatOptions { parse: ParseOptions::lenient(), ..FormatOptions::default() };
+    let formatted = format::format(&source, &options)
+        .map_err(|e| format!("could not parse {}:\n{}", file, e.render(&source)))?;
+    print!("{}", formatted);
+    Ok(())
+}
+
+fn repl(file: &str) -> Result<(), String> {
+    let source = read(file)?;
+    let dtab = parse::dtab(&source, &ParseOptions::lenient())
+        .map_err(|e| format!("could not parse {}:\n{}", file, e.render(&source)))?;
+    println!("loaded {} dentries from {}; type :help for help", dtab.len(), file);
+    let stdin = io::stdin();
+    Repl::new(dtab).run(stdin.lock(), io::stdout())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "lsp")]
+fn lsp() -> Result<(), String> {
+    let stdin = io::stdin();
+    dtab::lsp::Server::new().run(stdin.lock(), io::stdout())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "lsp"))]
+fn lsp() -> Result<(), String> {
+    Err("dtab was built without the `lsp` feature".to_string())
+}
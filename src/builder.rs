@@ -0,0 +1,408 @@
+//! Fluent builders for dentries and dtabs.
+//!
+//! The `NameTree` operator DSL is convenient for dentries written out in
+//! source code, but awkward for code which assembles rules from runtime
+//! data. A [`DentryBuilder`] constructs the same dentries one method call
+//! at a time, and validates the result when it is built. A [`DtabBuilder`]
+//! does the same for a whole table.
+//!
+//! # Examples
+//!
+//! ```
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use dtab::Dentry;
+//!
+//! let dentry = Dentry::builder()
+//!     .prefix("/svc/foo")?
+//!     .alt("/a")
+//!     .alt("/b")
+//!     .weighted(0.9, "/c")
+//!     .weighted(0.1, "/d")
+//!     .build()?;
+//! assert_eq!( &dentry.to_string()
+//!           , "/svc/foo => /a | /b | 0.9 * /c & 0.1 * /d;");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use dtab::builder::DtabBuilder;
+//!
+//! let dtab = DtabBuilder::new()
+//!     .rule("/svc", "/srv")?
+//!     .rule("/svc/x", "/srv/x-v1")?
+//!     .canary("/svc/x", "/srv/x-v2", 0.05)?
+//!     .build()?;
+//! assert_eq!( &dtab.to_string()
+//!           , "/svc => /srv;\n\
+//!              /svc/x => 0.95 * /srv/x-v1 & 0.05 * /srv/x-v2;\n");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`DentryBuilder`]: struct.DentryBuilder.html
+//! [`DtabBuilder`]: struct.DtabBuilder.html
+use std::{convert, error, fmt};
+
+use super::{Dentry, Dtab, NameTree, Prefix, Weighted};
+use super::path::PathError;
+use super::validate::{Validation, ValidationError};
+use super::weight::{IntoWeight, Weight};
+
+/// A builder for a [`Dentry`].
+///
+/// The destination is built as an alternation of branches. Each call to
+/// [`alt`] adds a new branch, while consecutive calls to [`weighted`] add
+/// members to a union in a single branch.
+///
+/// [`Dentry`]: ../struct.Dentry.html
+/// [`alt`]: #method.alt
+/// [`weighted`]: #method.weighted
+#[derive(Clone, Debug, Default)]
+pub struct DentryBuilder {
+    prefix: Option<Prefix>
+  , branches: Vec<Branch>
+}
+
+#[derive(Clone, Debug)]
+enum Branch {
+    Tree(NameTree<String>)
+  , Union(Vec<Weighted<String>>)
+}
+
+/// A builder for a [`Dtab`].
+///
+/// When the dtab is built, every dentry is validated, and the dtab is
+/// rejected if two dentries have the same prefix, or if a dentry is
+/// shadowed by a later dentry whose prefix matches every path it matches.
+///
+/// [`Dtab`]: ../struct.Dtab.html
+#[derive(Clone, Debug, Default)]
+pub struct DtabBuilder {
+    dentries: Vec<Dentry>
+}
+
+/// An error building a [`Dentry`] or [`Dtab`].
+///
+/// [`Dentry`]: ../struct.Dentry.html
+/// [`Dtab`]: ../struct.Dtab.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// No prefix was given.
+    MissingPrefix
+  , /// No destination was given.
+    MissingDestination
+  , /// A prefix was not valid.
+    InvalidPrefix(PathError)
+  , /// A canary weight was not between zero and one.
+    InvalidWeight(String)
+  , /// A canary was added for a prefix with no existing rule.
+    NoSuchRule { prefix: Prefix }
+  , /// Two dentries have the same prefix.
+    DuplicatePrefix { prefix: Prefix, first: usize, second: usize }
+  , /// A dentry is shadowed by a later dentry with a more general prefix.
+    Shadowed { entry: usize, by: usize }
+  , /// A dentry failed validation.
+    Invalid(ValidationError)
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::MissingPrefix => f.write_str("dentry has no prefix")
+          , BuildError::MissingDestination =>
+                f.write_str("dentry has no destination")
+          , BuildError::InvalidPrefix(ref error) =>
+                write!(f, "invalid prefix: {}", error)
+          , BuildError::InvalidWeight(ref weight) =>
+                write!(f, "canary weight {} is not between 0 and 1", weight)
+          , BuildError::NoSuchRule { ref prefix } =>
+                write!(f, "no rule for {} to canary", prefix)
+          , BuildError::DuplicatePrefix { ref prefix, first, second } =>
+                write!( f, "entries {} and {} have the same prefix {}"
+                      , first, second, prefix)
+          , BuildError::Shadowed { entry, by } =>
+                write!(f, "entry {} is shadowed by entry {}", entry, by)
+          , BuildError::Invalid(ref error) => write!(f, "invalid dentry: {}", error)
+        }
+    }
+}
+
+impl error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            BuildError::InvalidPrefix(ref error) => Some(error)
+          , BuildError::Invalid(ref error) => Some(error)
+          , _ => None
+        }
+    }
+}
+
+impl DentryBuilder {
+    /// Returns a new builder with no prefix or destination.
+    #[inline] pub fn new() -> Self { DentryBuilder::default() }
+
+    /// Set the prefix of the dentry, returning an error if it is not a valid
+    /// [`Prefix`].
+    ///
+    /// [`Prefix`]: ../prefix/struct.Prefix.html
+    pub fn prefix(self, prefix: &str) -> Result<Self, PathError> {
+        Prefix::read(prefix).map(|prefix| self.with_prefix(prefix))
+    }
+
+    /// Set the prefix of the dentry.
+    #[inline] pub fn with_prefix(mut self, prefix: Prefix) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    /// Add a branch to the destination, to be tried if the previous branches
+    /// fail to bind.
+    pub fn alt<R>(mut self, dst: R) -> Self
+    where R: convert::Into<NameTree<String>> {
+        self.branches.push(Branch::Tree(dst.into()));
+        self
+    }
+
+    /// Add a weighted member to a union in the destination.
+    ///
+    /// If the previous call was also to `weighted`, the member is added to
+    /// the same union; otherwise, it starts a new branch. A union with only
+    /// one member is built as that member alone, and its weight is dropped.
+    pub fn weighted<W, R>(mut self, weight: W, dst: R) -> Self
+    where W: IntoWeight
+        , R: convert::Into<NameTree<String>> {
+        let member = dst.into().weighted(weight);
+        if let Some(&mut Branch::Union(ref mut members)) = self.branches.last_mut() {
+            members.push(member);
+            return self
+        }
+        self.branches.push(Branch::Union(vec![member]));
+        self
+    }
+
+    /// Build the dentry, validating it with the default [`Validation`].
+    ///
+    /// [`Validation`]: ../validate/struct.Validation.html
+    #[inline] pub fn build(self) -> Result<Dentry, BuildError> {
+        self.build_with(&Validation::default())
+    }
+
+    /// Build the dentry, validating it with the given [`Validation`].
+    ///
+    /// [`Validation`]: ../validate/struct.Validation.html
+    pub fn build_with(self, validation: &Validation)
+                      -> Result<Dentry, BuildError> {
+        let prefix = self.prefix.ok_or(BuildError::MissingPrefix)?;
+        if self.branches.is_empty() {
+            return Err(BuildError::MissingDestination)
+        }
+        let dst = NameTree::alts(self.branches.into_iter().map(Branch::into_tree));
+        validation.dentry(prefix, dst).map_err(BuildError::Invalid)
+    }
+}
+
+impl DtabBuilder {
+    /// Returns a new builder with no dentries.
+    #[inline] pub fn new() -> Self { DtabBuilder::default() }
+
+    /// Add a dentry delegating `prefix` to `dst`.
+    ///
+    /// Returns an error if the prefix is invalid, or if the dentry fails the
+    /// default [`Validation`].
+    ///
+    /// [`Validation`]: ../validate/struct.Validation.html
+    pub fn rule<R>(self, prefix: &str, dst: R) -> Result<Self, BuildError>
+    where R: convert::Into<NameTree<String>> {
+        let dentry = DentryBuilder::new()
+            .prefix(prefix).map_err(BuildError::InvalidPrefix)?
+            .alt(dst)
+            .build()?;
+        Ok(self.dentry(dentry))
+    }
+
+    /// Add a dentry.
+    #[inline] pub fn dentry(mut self, dentry: Dentry) -> Self {
+        self.dentries.push(dentry);
+        self
+    }
+
+    /// Send a fraction of the traffic for an existing rule to `dst`.
+    ///
+    /// The destination of the last rule added for `prefix` is replaced by a
+    /// union of its current destination and `dst`, with `dst` receiving
+    /// `weight` of the traffic. Returns an error if there is no rule for
+    /// `prefix`, or if `weight` is not between zero and one.
+    pub fn canary<R>(mut self, prefix: &str, dst: R, weight: f64)
+                     -> Result<Self, BuildError>
+    where R: convert::Into<NameTree<String>> {
+        let prefix = Prefix::read(prefix).map_err(BuildError::InvalidPrefix)?;
+        let weight = Weight::from_f64(weight)
+            .filter(|weight| *weight <= Weight::ONE)
+            .ok_or_else(|| BuildError::InvalidWeight(weight.to_string()))?;
+        let rest = Weight::ratio(weight.denom() - weight.numer(), weight.denom());
+        let dentry = match self.dentries.iter_mut().rev()
+                                .find(|dentry| dentry.prefix == prefix) {
+            Some(dentry) => dentry
+          , None => return Err(BuildError::NoSuchRule { prefix })
+        };
+        let stable = ::std::mem::replace(&mut dentry.dst, NameTree::Neg);
+        dentry.dst = stable.weighted(rest) & dst.into().weighted(weight);
+        Validation::default().check_dentry(dentry).map_err(BuildError::Invalid)?;
+        Ok(self)
+    }
+
+    /// Add a dentry splitting the traffic for `prefix` evenly between every
+    /// destination in `dsts`.
+    ///
+    /// The destination is built with [`NameTree::even_union`], giving every
+    /// destination a weight of `1`. Returns an error if `dsts` is empty, if the
+    /// prefix is invalid, or if the dentry fails the default
+    /// [`Validation`].
+    ///
+    /// [`NameTree::even_union`]: ../enum.NameTree.html#method.even_union
+    /// [`Validation`]: ../validate/struct.Validation.html
+    pub fn even_split<I>(self, prefix: &str, dsts: I) -> Result<Self, BuildError>
+    where I: IntoIterator
+        , I::Item: convert::Into<NameTree<String>> {
+        let mut dsts = dsts.into_iter().peekable();
+        if dsts.peek().is_none() {
+            return Err(BuildError::MissingDestination)
+        }
+        self.rule(prefix, NameTree::even_union(dsts))
+    }
+
+    /// Build the dtab, validating it with the default [`Validation`].
+    ///
+    /// [`Validation`]: ../validate/struct.Validation.html
+    #[inline] pub fn build(self) -> Result<Dtab, BuildError> {
+        self.build_with(&Validation::default())
+    }
+
+    /// Build the dtab, validating it with the given [`Validation`].
+    ///
+    /// [`Validation`]: ../validate/struct.Validation.html
+    pub fn build_with(self, validation: &Validation) -> Result<Dtab, BuildError> {
+        let dtab = Dtab::from(self.dentries);
+        validation.check_dtab(&dtab).map_err(BuildError::Invalid)?;
+        for (first, dentry) in dtab.iter().enumerate() {
+            for (second, later) in dtab.iter().enumerate().skip(first + 1) {
+                if later.prefix == dentry.prefix {
+                    return Err(BuildError::DuplicatePrefix {
+                        prefix: dentry.prefix.clone(), first, second
+                    })
+                }
+                if later.prefix.covers(&dentry.prefix) {
+                    return Err(BuildError::Shadowed { entry: first, by: second })
+                }
+            }
+        }
+        Ok(dtab)
+    }
+}
+
+impl Branch {
+    fn into_tree(self) -> NameTree<String> {
+        match self {
+            Branch::Tree(tree) => tree
+          , Branch::Union(members) => NameTree::union(members)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build() {
+        let dentry = Dentry::builder()
+            .prefix("/iceCreamStore").unwrap()
+            .alt("/humphrys")
+            .alt("/smitten")
+            .build()
+            .unwrap();
+        assert_eq!(dentry, dentry!("/iceCreamStore" =>
+            NameTree::from("/humphrys") | "/smitten"));
+    }
+
+    #[test]
+    fn weighted_unions() {
+        let dentry = Dentry::builder()
+            .prefix("/svc").unwrap()
+            .weighted(0.5, "/a")
+            .weighted(0.25, "/b")
+            .weighted(0.25, "/c")
+            .alt("/d")
+            .weighted(1.0, "/e")
+            .build()
+            .unwrap();
+        assert_eq!( &dentry.to_string()
+                  , "/svc => 0.75 * (0.5 * /a & 0.25 * /b) & 0.25 * /c | /d | /e;");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Dentry::builder().prefix("svc").is_err());
+        assert_eq!( Dentry::builder().alt("/a").build()
+                  , Err(BuildError::MissingPrefix));
+        assert_eq!( Dentry::builder().prefix("/svc").unwrap().build()
+                  , Err(BuildError::MissingDestination));
+        match Dentry::builder().prefix("/svc").unwrap().alt("/a b").build() {
+            Err(BuildError::Invalid(ValidationError::InvalidDestination { .. })) => {}
+          , other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn build_dtab() {
+        let dtab = DtabBuilder::new()
+            .rule("/iceCreamStore", NameTree::from("/humphrys") | "/smitten").unwrap()
+            .rule("/smitten", "/USA/CA/SF/Harrison/2790").unwrap()
+            .canary("/iceCreamStore", "/birite", 0.1).unwrap()
+            .build()
+            .unwrap();
+        assert_eq!( &dtab.to_string()
+                  , "/iceCreamStore => 0.9 * (/humphrys | /smitten) & 0.1 * /birite;\n\
+                     /smitten => /USA/CA/SF/Harrison/2790;\n");
+    }
+
+    #[test]
+    fn even_split() {
+        let dtab = DtabBuilder::new()
+            .even_split("/svc", vec!["/a", "/b"]).unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(&dtab.to_string(), "/svc => 1 * /a & 1 * /b;\n");
+        assert_eq!( DtabBuilder::new().even_split("/svc", Vec::<&str>::new()).unwrap_err()
+                  , BuildError::MissingDestination);
+    }
+
+    #[test]
+    fn dtab_errors() {
+        assert_eq!( DtabBuilder::new().canary("/svc", "/srv", 0.1).unwrap_err()
+                  , BuildError::NoSuchRule { prefix: Prefix::read("/svc").unwrap() });
+        assert_eq!( DtabBuilder::new().rule("/svc", "/srv").unwrap()
+                        .canary("/svc", "/srv2", 1.5).unwrap_err()
+                  , BuildError::InvalidWeight("1.5".to_string()));
+        assert_eq!( DtabBuilder::new()
+                        .rule("/svc", "/a").unwrap()
+                        .rule("/svc", "/b").unwrap()
+                        .build()
+                  , Err(BuildError::DuplicatePrefix {
+                        prefix: Prefix::read("/svc").unwrap(), first: 0, second: 1
+                    }));
+        assert_eq!( DtabBuilder::new()
+                        .rule("/svc/users", "/a").unwrap()
+                        .rule("/svc/*", "/b").unwrap()
+                        .build()
+                  , Err(BuildError::Shadowed { entry: 0, by: 1 }));
+        assert!( DtabBuilder::new()
+                    .rule("/svc", "/a").unwrap()
+                    .rule("/svc/users", "/b").unwrap()
+                    .build().is_ok());
+    }
+}
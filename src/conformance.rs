@@ -0,0 +1,308 @@
+//! Cross-implementation conformance fixtures.
+//!
+//! This module loads golden files in a simple JSON format shared with other
+//! dtab implementations, and runs them against this crate, so that parity
+//! with Finagle and linkerd can be checked mechanically.
+//!
+//! A fixture file contains a list of cases. Each case has an input dtab and
+//! either the expected canonical form of that dtab, or `"error": true` if
+//! the input should be rejected. A case may also list paths to evaluate
+//! against the dtab, along with the name tree each is expected to produce:
+//!
+//! ```json
+//! { "name": "basics"
+//! , "cases":
+//!   [ { "name": "alternation"
+//!     , "input": "/iceCreamStore=>/humphrys|/smitten"
+//!     , "canonical": "/iceCreamStore => /humphrys | /smitten;"
+//!     , "eval": [ { "path": "/iceCreamStore/sf", "result": "/humphrys/sf | /smitten/sf" } ]
+//!     }
+//!   , { "name": "missing arrow", "input": "/a /b", "error": true }
+//!   ]
+//! }
+//! ```
+//!
+//! Since implementations differ in how they lay out their output,
+//! canonical forms and evaluation results are compared ignoring whitespace.
+//!
+//! Paths are evaluated by the [`Runner`]'s [`Evaluator`], which delegates
+//! them with [`Dtab::delegate`] by default. A path which fails to delegate
+//! fails its case.
+//!
+//! This module requires the `conformance` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::conformance::{Fixture, Runner};
+//!
+//! let fixture: Fixture = r#"
+//!   { "name": "example"
+//!   , "cases":
+//!     [ { "name": "simple", "input": "/a=>/b", "canonical": "/a => /b;" }
+//!     , { "name": "alternation"
+//!       , "input": "/iceCreamStore=>/humphrys|/smitten"
+//!       , "eval": [ { "path": "/iceCreamStore/sf", "result": "/humphrys/sf | /smitten/sf" } ]
+//!       }
+//!     ]
+//!   }"#.parse().unwrap();
+//!
+//! let report = Runner::default().run(&fixture);
+//! assert!(report.is_success(), "{}", report);
+//! ```
+//!
+//! [`Evaluator`]: type.Evaluator.html
+//! [`Runner`]: struct.Runner.html
+//! [`Dtab::delegate`]: ../struct.Dtab.html#method.delegate
+use std::{error, fmt, io, str};
+
+use serde_json;
+
+use super::{Dtab, NameTree};
+use super::delegate::DelegateError;
+use super::name::Name;
+use super::parse::{self, ParseOptions};
+use super::path::Path;
+
+/// A file of conformance cases.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The name of this fixture.
+    pub name: String
+  , /// The cases in this fixture.
+    pub cases: Vec<Case>
+}
+
+/// A single conformance case.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Case {
+    /// The name of this case.
+    pub name: String
+  , /// The dtab to parse.
+    pub input: String
+  , /// The expected canonical form of the dtab.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<String>
+  , /// Whether the input is expected to be rejected.
+    #[serde(default)]
+    pub error: bool
+  , /// Paths to evaluate, and their expected results.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub eval: Vec<Eval>
+}
+
+/// An evaluation to perform against a case's dtab.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Eval {
+    /// The path to evaluate.
+    pub path: String
+  , /// The expected result, as a name tree.
+    pub result: String
+}
+
+/// An error loading a fixture.
+#[derive(Debug)]
+pub struct LoadError(serde_json::Error);
+
+impl fmt::Display for LoadError {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid conformance fixture: {}", self.0)
+    }
+}
+
+impl error::Error for LoadError {
+    #[inline] fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl Fixture {
+    /// Load a fixture from a reader.
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Self, LoadError> {
+        serde_json::from_reader(reader).map_err(LoadError)
+    }
+}
+
+impl str::FromStr for Fixture {
+    type Err = LoadError;
+    #[inline] fn from_str(s: &str) -> Result<Self, LoadError> {
+        serde_json::from_str(s).map_err(LoadError)
+    }
+}
+
+/// A function which evaluates a path against a dtab.
+pub type Evaluator = fn(&Dtab, &Path) -> Result<NameTree<Name>, DelegateError>;
+
+/// Runs fixtures against this crate.
+#[derive(Clone, Debug)]
+pub struct Runner {
+    /// Options used to parse inputs.
+    pub options: ParseOptions
+  , /// The function used to evaluate paths, which is [`Dtab::delegate`] by
+    /// default.
+    ///
+    /// [`Dtab::delegate`]: ../struct.Dtab.html#method.delegate
+    pub evaluator: Evaluator
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Runner { options: ParseOptions::default(), evaluator: Dtab::delegate }
+    }
+}
+
+/// The outcome of a single case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed
+  , Failed(String)
+}
+
+/// The results of running a fixture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// The name of the fixture.
+    pub fixture: String
+  , /// Each case's name, and its outcome.
+    pub outcomes: Vec<(String, Outcome)>
+}
+
+impl Report {
+    /// Returns true if no case failed.
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns the names of failed cases, and why they failed.
+    pub fn failures(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.outcomes.iter().filter_map(|(name, outcome)|
+            match *outcome {
+                Outcome::Failed(ref why) => Some((name.as_str(), why.as_str()))
+              , _ => None
+            })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "fixture {}:", self.fixture)?;
+        for (name, outcome) in &self.outcomes {
+            match *outcome {
+                Outcome::Passed => writeln!(f, "  {}: ok", name)?
+              , Outcome::Failed(ref why) => writeln!(f, "  {}: FAILED: {}", name, why)?
+            }
+        }
+        Ok(())
+    }
+}
+
+fn strip_whitespace(s: &str) -> String {
+    s.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+impl Runner {
+    /// Run every case in a fixture.
+    pub fn run(&self, fixture: &Fixture) -> Report {
+        Report { fixture: fixture.name.clone()
+               , outcomes: fixture.cases.iter()
+                    .map(|case| (case.name.clone(), self.run_case(case)))
+                    .collect()
+               }
+    }
+
+    /// Run a single case.
+    pub fn run_case(&self, case: &Case) -> Outcome {
+        let dtab = match (parse::dtab(&case.input, &self.options), case.error) {
+            (Ok(dtab), false) => dtab
+          , (Err(_), true) => return Outcome::Passed
+          , (Ok(dtab), true) => return Outcome::Failed(format!(
+                "expected an error, but parsed `{}`", dtab.to_string().trim()))
+          , (Err(e), false) => return Outcome::Failed(format!(
+                "failed to parse input: {}", e))
+        };
+
+        if let Some(ref canonical) = case.canonical {
+            let actual = dtab.to_string();
+            if strip_whitespace(&actual) != strip_whitespace(canonical) {
+                return Outcome::Failed(format!(
+                    "expected canonical form `{}`, got `{}`"
+                  , canonical, actual.trim()))
+            }
+        }
+
+        for eval in &case.eval {
+            let path = match Path::read_with(&eval.path, self.options.labels) {
+                Ok(path) => path
+              , Err(e) => return Outcome::Failed(format!(
+                    "invalid evaluation path {:?}: {}", eval.path, e))
+            };
+            let actual = match (self.evaluator)(&dtab, &path) {
+                Ok(tree) => tree.to_string()
+              , Err(e) => return Outcome::Failed(format!(
+                    "failed to evaluate {}: {}", eval.path, e))
+            };
+            if strip_whitespace(&actual) != strip_whitespace(&eval.result) {
+                return Outcome::Failed(format!(
+                    "expected {} to evaluate to `{}`, got `{}`"
+                  , eval.path, eval.result, actual))
+            }
+        }
+        Outcome::Passed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"
+        { "name": "test"
+        , "cases":
+          [ { "name": "alternation"
+            , "input": "/iceCreamStore=>/humphrys|/smitten"
+            , "canonical": "/iceCreamStore => /humphrys | /smitten;"
+            , "eval": [ { "path": "/iceCreamStore", "result": "/humphrys | /smitten" } ]
+            }
+          , { "name": "missing arrow", "input": "/a /b", "error": true }
+          , { "name": "wrong canonical", "input": "/a=>/b", "canonical": "/a=>/c" }
+          , { "name": "unexpected error", "input": "/a=>", "canonical": "/a=>/c" }
+          , { "name": "wrong result"
+            , "input": "/a=>/b;/b=>/c"
+            , "eval": [ { "path": "/a/x", "result": "/b/x" } ]
+            }
+          , { "name": "cycle"
+            , "input": "/a=>/b;/b=>/a"
+            , "eval": [ { "path": "/a", "result": "/a" } ]
+            }
+          ]
+        }"#;
+
+    fn first_leaf(dtab: &Dtab, _: &Path) -> Result<NameTree<Name>, DelegateError> {
+        Ok(dtab[0].dst().leaves().next()
+            .map(|leaf| NameTree::Leaf(Name::Path(Path::read(leaf).unwrap())))
+            .unwrap_or(NameTree::Neg))
+    }
+
+    #[test]
+    fn run_fixture() {
+        let fixture: Fixture = FIXTURE.parse().unwrap();
+        let report = Runner::default().run(&fixture);
+        assert_eq!(report.outcomes[0].1, Outcome::Passed);
+        assert_eq!(report.outcomes[1].1, Outcome::Passed);
+        assert_eq!( report.failures().map(|(name, _)| name).collect::<Vec<_>>()
+                  , vec!["wrong canonical", "unexpected error", "wrong result", "cycle"]);
+        assert_eq!( report.failures().skip(2).collect::<Vec<_>>()
+                  , vec![ ("wrong result", "expected /a/x to evaluate to `/b/x`, got `/c/x`")
+                        , ("cycle", "failed to evaluate /a: delegation cycle: /a -> /b -> /a") ]);
+
+        let runner = Runner { evaluator: first_leaf, ..Runner::default() };
+        assert_eq!( runner.run_case(&fixture.cases[0])
+                  , Outcome::Failed("expected /iceCreamStore to evaluate to `/humphrys | \
+                                     /smitten`, got `/humphrys`".to_string()));
+    }
+
+    #[test]
+    fn invalid_fixture() {
+        assert!("{ \"name\": \"no cases\" }".parse::<Fixture>().is_err());
+    }
+}
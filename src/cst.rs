@@ -0,0 +1,314 @@
+//! A lossless syntax tree for editing dtab source files.
+//!
+//! Parsing a dtab into a [`Dtab`] discards its comments and layout. A
+//! [`Document`] keeps every byte of the source: the text of each dentry, and
+//! the whitespace, comments, and `;` separators around it. Writing an
+//! unedited document reproduces its source exactly, and editing an entry
+//! changes only the text of that entry, so tools can rewrite dtab files
+//! without disturbing the parts they didn't touch.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::cst::Document;
+//! use dtab::parse::ParseOptions;
+//!
+//! let source = "# services\n/svc=>/srv;  # legacy\n\t/srv => /#/io.l5d.fs\n";
+//! let mut document = Document::parse(source, &ParseOptions::default()).unwrap();
+//! assert_eq!(&document.to_string(), source);
+//!
+//! document.replace(0, dentry!("/svc" => "/srv/v2"));
+//! document.push(dentry!("/admin" => "/srv/admin"));
+//! assert_eq!( &document.to_string()
+//!           , "# services\n/svc => /srv/v2;  # legacy\n\t/srv => /#/io.l5d.fs;\n\
+//!              /admin => /srv/admin;\n");
+//! # }
+//! ```
+//!
+//! [`Dtab`]: ../struct.Dtab.html
+//! [`Document`]: struct.Document.html
+use std::{fmt, slice};
+
+use super::{Dentry, Dtab};
+use super::parse::{self, ParseError, ParseOptions};
+
+/// The source of a dtab, with every comment and all of its layout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Document {
+    /// Whitespace and comments before the first entry.
+    leading: String
+  , entries: Vec<Entry>
+}
+
+/// A dentry in a [`Document`], and the source text around it.
+///
+/// [`Document`]: struct.Document.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    dentry: Dentry
+  , /// The source of the dentry, excluding its `;`.
+    text: String
+  , /// Everything between the end of the dentry and the start of the next:
+    /// whitespace, comments, and the `;`, if there is one.
+    trailing: String
+}
+
+impl Entry {
+    /// Returns the dentry.
+    #[inline] pub fn dentry(&self) -> &Dentry { &self.dentry }
+
+    /// Returns the source text of the dentry, excluding its `;`.
+    #[inline] pub fn text(&self) -> &str { &self.text }
+
+    /// Returns the source text between this dentry and the next, including
+    /// the `;` that ends this dentry, if there is one.
+    #[inline] pub fn trailing(&self) -> &str { &self.trailing }
+}
+
+impl Document {
+    /// Parse a document from dtab source text.
+    pub fn parse(source: &str, options: &ParseOptions) -> Result<Self, ParseError> {
+        let dentries = parse::spanned_dtab(source, options)?;
+        let leading = dentries.first()
+            .map_or(source, |first| &source[..first.span.start])
+            .to_string();
+        let entries = dentries.iter().enumerate()
+            .map(|(i, spanned)| {
+                let end = dentries.get(i + 1)
+                    .map_or(source.len(), |next| next.span.start);
+                Entry { dentry: spanned.dentry.clone()
+                      , text: source[spanned.span.start..spanned.span.end].to_string()
+                      , trailing: source[spanned.span.end..end].to_string()
+                      }
+            })
+            .collect();
+        Ok(Document { leading, entries })
+    }
+
+    /// Returns the number of entries in this document.
+    #[inline] pub fn len(&self) -> usize { self.entries.len() }
+
+    /// Returns true if this document has no entries.
+    #[inline] pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    /// Returns the entries of this document, in order.
+    #[inline] pub fn entries(&self) -> &[Entry] { &self.entries }
+
+    /// Returns an iterator over the entries of this document.
+    #[inline] pub fn iter(&self) -> slice::Iter<'_, Entry> { self.entries.iter() }
+
+    /// Returns the dtab this document describes.
+    pub fn to_dtab(&self) -> Dtab {
+        self.entries.iter().map(|entry| entry.dentry.clone()).collect()
+    }
+
+    /// Replace the dentry at `index`, returning the old one.
+    ///
+    /// The new dentry is written in the canonical form, and the text around
+    /// it is unchanged. Comments within the old dentry's text are lost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn replace(&mut self, index: usize, dentry: Dentry) -> Dentry {
+        let entry = &mut self.entries[index];
+        entry.text = render(&dentry);
+        ::std::mem::replace(&mut entry.dentry, dentry)
+    }
+
+    /// Insert a dentry before the entry at `index`, on a line of its own
+    /// with the same indentation, if that entry begins a line.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of entries.
+    pub fn insert(&mut self, index: usize, dentry: Dentry) {
+        if index == self.entries.len() {
+            return self.push(dentry)
+        }
+        let separator = match line_start(self.before(index)) {
+            Some(indent) => format!("\n{}", indent)
+          , None => " ".to_string()
+        };
+        let entry = Entry { text: render(&dentry)
+                          , trailing: format!(";{}", separator)
+                          , dentry };
+        self.entries.insert(index, entry);
+    }
+
+    /// Add a dentry to the end of this document, on a new line.
+    ///
+    /// If the last entry has no `;`, one is added.
+    pub fn push(&mut self, dentry: Dentry) {
+        let before = match self.entries.last_mut() {
+            Some(last) => {
+                if semi(&last.trailing).is_none() {
+                    last.trailing.insert(0, ';');
+                }
+                &mut last.trailing
+            }
+          , None => &mut self.leading
+        };
+        if !before.is_empty() && !before.ends_with('\n') {
+            before.push('\n');
+        }
+        let entry = Entry { text: render(&dentry), trailing: ";\n".to_string(), dentry };
+        self.entries.push(entry);
+    }
+
+    /// Remove the entry at `index`, returning its dentry.
+    ///
+    /// The rest of the entry's line, including its `;` and any comment
+    /// after it, is removed with it. If the entry was on a line of its own,
+    /// the whole line is removed. Comments on later lines are kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Dentry {
+        let entry = self.entries.remove(index);
+        let trailing = &entry.trailing;
+        // the end of the line containing the entry's `;`.
+        let semi = semi(trailing).unwrap_or(0);
+        let line_end = trailing[semi..].find('\n').map(|end| semi + end);
+        let before = self.before_mut(index);
+        let rest = match (line_start(before), line_end) {
+            (Some(indent), _) => {
+                let len = before.len() - indent.len();
+                before.truncate(len);
+                line_end.map_or("", |end| &trailing[end + 1..])
+            }
+          , (None, Some(end)) => {
+                let len = before.trim_end_matches(&[' ', '\t'][..]).len();
+                before.truncate(len);
+                &trailing[end..]
+            }
+          , (None, None) => ""
+        };
+        before.push_str(rest);
+        entry.dentry
+    }
+
+    /// Returns the text before the entry at `index`.
+    fn before(&self, index: usize) -> &str {
+        match index {
+            0 => &self.leading
+          , i => &self.entries[i - 1].trailing
+        }
+    }
+
+    fn before_mut(&mut self, index: usize) -> &mut String {
+        match index {
+            0 => &mut self.leading
+          , i => &mut self.entries[i - 1].trailing
+        }
+    }
+}
+
+impl fmt::Display for Document {
+    /// Writes the document's source text, including every edit.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.leading)?;
+        for entry in &self.entries {
+            f.write_str(&entry.text)?;
+            f.write_str(&entry.trailing)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a Document {
+    type Item = &'a Entry;
+    type IntoIter = slice::Iter<'a, Entry>;
+    #[inline] fn into_iter(self) -> Self::IntoIter { self.iter() }
+}
+
+/// Returns the source text of a dentry, without its `;`.
+fn render(dentry: &Dentry) -> String {
+    format!("{} => {}", dentry.prefix(), dentry.dst())
+}
+
+/// If `before` ends at the start of a line, possibly after some
+/// indentation, returns the indentation.
+///
+/// The text before the first entry is assumed to start a line.
+fn line_start(before: &str) -> Option<&str> {
+    let indent = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    if indent.chars().all(char::is_whitespace) { Some(indent) } else { None }
+}
+
+/// Returns the offset of the `;` in the text between two entries, skipping
+/// comments.
+fn semi(trailing: &str) -> Option<usize> {
+    let mut comment = false;
+    for (i, ch) in trailing.char_indices() {
+        match ch {
+            '\n' => comment = false
+          , '#' => comment = true
+          , ';' if !comment => return Some(i)
+          , _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Document {
+        Document::parse(source, &ParseOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn lossless() {
+        let sources = [ ""
+                      , "  \n# only a comment"
+                      , "\u{feff}/a=>/b"
+                      , "# header\n\n/a  =>\t/b | # why\n  /c ; # trailing\n\n\t/d=>(~)"
+                      , "/a => /b;/c => /d;\n# end\n" ];
+        for source in &sources {
+            let document = parse(source);
+            assert_eq!(&document.to_string(), source);
+            assert_eq!(document.to_dtab(), Dtab::read(source).unwrap());
+        }
+        let document = parse("/a => /b | # why\n  /c ; # trailing\n");
+        assert_eq!(document.entries()[0].text(), "/a => /b | # why\n  /c");
+        assert_eq!(document.entries()[0].trailing(), " ; # trailing\n");
+    }
+
+    #[test]
+    fn edits() {
+        let mut document = parse("# header\n  /a => /b; # on a\n  /c => /d # on d\n");
+        assert_eq!(document.replace(1, dentry!("/c" => "/e")), dentry!("/c" => "/d"));
+        document.insert(1, dentry!("/x" => "/y"));
+        document.insert(0, dentry!("/w" => "/v"));
+        assert_eq!( &document.to_string()
+                  , "# header\n  /w => /v;\n  /a => /b; # on a\n  /x => /y;\n  \
+                     /c => /e # on d\n");
+        document.push(dentry!("/z" => "/y"));
+        assert_eq!( &document.to_string()
+                  , "# header\n  /w => /v;\n  /a => /b; # on a\n  /x => /y;\n  \
+                     /c => /e; # on d\n/z => /y;\n");
+        assert_eq!(document.remove(1), dentry!("/a" => "/b"));
+        assert_eq!(document.remove(3), dentry!("/z" => "/y"));
+        assert_eq!( &document.to_string()
+                  , "# header\n  /w => /v;\n  /x => /y;\n  /c => /e; # on d\n");
+        assert_eq!( document.to_dtab()
+                  , dtab!["/w" => "/v"; "/x" => "/y"; "/c" => "/e";]);
+
+        let mut inline = parse("/a => /b;/c => /d;\n# trailer\n");
+        inline.insert(1, dentry!("/x" => "/y"));
+        assert_eq!(&inline.to_string(), "/a => /b;/x => /y; /c => /d;\n# trailer\n");
+        inline.remove(2);
+        inline.remove(0);
+        assert_eq!(&inline.to_string(), "/x => /y;\n# trailer\n");
+
+        let mut empty = parse("# nothing yet");
+        empty.push(dentry!("/a" => "/b"));
+        assert_eq!(&empty.to_string(), "# nothing yet\n/a => /b;\n");
+        assert_eq!(parse(&empty.to_string()), empty);
+    }
+}
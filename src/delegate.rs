@@ -0,0 +1,1060 @@
+//! Delegating paths through a dtab.
+//!
+//! [`Dtab::lookup`] rewrites a path once, with every dentry that matches
+//! it. Delegation repeats this: each path in the result is looked up again,
+//! and replaced by its own rewrites, until no dentry matches any of the
+//! remaining paths. Those paths are the names the original path delegates
+//! to, and are the leaves of the resulting tree, alongside any `~`, `!` or
+//! `$` that the dentries rewrote to.
+//!
+//! Paths which begin with `/$` or `/#`, and which no dentry matches, are
+//! instead looked up by a [`Namer`], if it knows them, and replaced by the
+//! names they are bound to. `/$/nil` and `/$/fail` are always bound to `$`
+//! and `!`, as in Finagle. Paths of the form `/#/<id>/...` name the namer
+//! registered as `id`, so dentries never rewrite them; they are only ever
+//! bound by the namer.
+//!
+//! The leaves of the result are [`Name`]s: a path which no dentry matches
+//! and no namer binds is a [`Name::Path`], which is still symbolic, while
+//! the names a namer binds paths to are [`Name::Bound`].
+//!
+//! A dtab which rewrites a path back to itself, such as `/a => /b; /b =>
+//! /a`, would rewrite it forever, so delegation returns an error carrying
+//! the cycle of paths instead. As in Finagle, a path may also only be
+//! rewritten a limited number of times in a row, so that dtabs which
+//! rewrite paths forever without repeating them, such as `/a => /a/b`, also
+//! return an error rather than never finishing. The [`ResolutionLimits`]
+//! set this depth, and can also limit the number of names a path delegates
+//! to, and the number of lookups delegating it may take, so that a dtab
+//! whose rewrites fan out exponentially fails quickly. A dentry whose
+//! destination has a leaf which isn't a valid path, which is only possible
+//! in a dentry built from unvalidated strings, is also an error when it
+//! matches.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::NameTree;
+//! use dtab::path::Path;
+//!
+//! let dtab = dtab![ "/srv" => "/#/io.l5d.k8s/default/http";
+//!                   "/svc" => "/srv";
+//!                   "/svc/users" => NameTree::from("/srv/users-v2") | "/srv/users"; ];
+//! let path = Path::read("/svc/users/get").unwrap();
+//! assert_eq!( &dtab.delegate(&path).unwrap().to_string()
+//!           , "/#/io.l5d.k8s/default/http/users-v2/get \
+//!              | /#/io.l5d.k8s/default/http/users/get \
+//!              | /#/io.l5d.k8s/default/http/users/get");
+//! # }
+//! ```
+//!
+//! [`Dtab::lookup`]: ../struct.Dtab.html#method.lookup
+//! [`Namer`]: ../namer/trait.Namer.html
+//! [`ResolutionLimits`]: struct.ResolutionLimits.html
+//! [`Name`]: ../name/enum.Name.html
+//! [`Name::Path`]: ../name/enum.Name.html#variant.Path
+//! [`Name::Bound`]: ../name/enum.Name.html#variant.Bound
+use std::{error, fmt};
+use std::collections::HashMap;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use super::{Dentry, Dtab, NameTree};
+use super::nametree::Parts;
+use super::name::Name;
+use super::namer::{self, Namer};
+use super::observe::DelegationObserver;
+use super::path::{Path, PathError};
+
+/// The default maximum number of times a path may be rewritten in a row,
+/// as in Finagle.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// Options controlling delegation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DelegateOptions {
+    /// Limits on the work delegating a single path may do.
+    pub limits: ResolutionLimits
+}
+
+/// Limits on the work delegating a single path may do, beyond which
+/// delegation fails.
+///
+/// Only the depth is limited by default.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::NameTree;
+/// use dtab::delegate::{self, DelegateError, DelegateOptions, ResolutionLimits};
+/// use dtab::path::Path;
+///
+/// let dtab = dtab!["/a" => NameTree::from("/b") | "/c"; "/b" => NameTree::from("/d") & "/e";];
+/// let limits = ResolutionLimits { max_width: Some(2), ..ResolutionLimits::default() };
+/// let options = DelegateOptions { limits };
+/// let path = Path::read("/a").unwrap();
+/// assert_eq!( delegate::delegate(&dtab, &path, &options, (), ())
+///           , Err(DelegateError::TooWide { path: Path::read("/c").unwrap(), max: 2 }));
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResolutionLimits {
+    /// The maximum number of times a path may be rewritten in a row.
+    ///
+    /// By default, this is [`DEFAULT_MAX_DEPTH`], as in Finagle.
+    ///
+    /// [`DEFAULT_MAX_DEPTH`]: constant.DEFAULT_MAX_DEPTH.html
+    pub max_depth: usize
+  , /// The maximum number of names, including those a namer binds paths
+    /// to, in the tree a path delegates to.
+    pub max_width: Option<usize>
+  , /// The maximum number of lookups delegating a path may take, counting
+    /// every path reached along the way.
+    pub max_steps: Option<usize>
+}
+
+impl Default for ResolutionLimits {
+    fn default() -> Self {
+        ResolutionLimits { max_depth: DEFAULT_MAX_DEPTH, max_width: None, max_steps: None }
+    }
+}
+
+/// An error delegating a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DelegateError {
+    /// `path` was reached after rewriting the original path `max` times,
+    /// and would have been rewritten again.
+    TooDeep { path: Path, max: usize }
+  , /// Delegating `path` would have made the tree the original path
+    /// delegates to contain more than `max` names.
+    TooWide { path: Path, max: usize }
+  , /// Looking up `path` would have taken more than `max` lookups.
+    TooManySteps { path: Path, max: usize }
+  , /// Each path in `cycle` was rewritten to the next, and the last is the
+    /// same as the first, so the first would be rewritten forever.
+    Cycle { cycle: Vec<Path> }
+  , /// The dentry at `index` matched `path`, but a leaf of its destination
+    /// isn't a valid path.
+    InvalidDestination { path: Path, index: usize, error: PathError }
+}
+
+impl fmt::Display for DelegateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DelegateError::TooDeep { ref path, max } =>
+                write!(f, "delegation exceeded {} rewrites, at {}", max, path)
+          , DelegateError::TooWide { ref path, max } =>
+                write!(f, "delegation exceeded {} names, at {}", max, path)
+          , DelegateError::TooManySteps { ref path, max } =>
+                write!(f, "delegation exceeded {} lookups, at {}", max, path)
+          , DelegateError::Cycle { ref cycle } => {
+                f.write_str("delegation cycle: ")?;
+                for (i, path) in cycle.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" -> ")?;
+                    }
+                    write!(f, "{}", path)?;
+                }
+                Ok(())
+            }
+          , DelegateError::InvalidDestination { ref path, index, ref error } =>
+                write!(f, "entry {} rewrote {} to an invalid path: {}", index, path, error)
+        }
+    }
+}
+
+impl error::Error for DelegateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            DelegateError::InvalidDestination { ref error, .. } => Some(error)
+          , _ => None
+        }
+    }
+}
+
+/// Errors are serialized as objects with the fields `kind` (one of
+/// `"too-deep"`, `"too-wide"`, `"too-many-steps"`, `"cycle"` or
+/// `"invalid-destination"`), the variant's fields, with paths and path
+/// errors as strings, and `message`, the error's `Display` form.
+impl Serialize for DelegateError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        let len = match *self {
+            DelegateError::Cycle { .. } => 3
+          , DelegateError::InvalidDestination { .. } => 5
+          , _ => 4
+        };
+        let mut error = serializer.serialize_struct("DelegateError", len)?;
+        match *self {
+            DelegateError::TooDeep { ref path, max }
+          | DelegateError::TooWide { ref path, max }
+          | DelegateError::TooManySteps { ref path, max } => {
+                error.serialize_field("kind", match *self {
+                    DelegateError::TooDeep { .. } => "too-deep"
+                  , DelegateError::TooWide { .. } => "too-wide"
+                  , _ => "too-many-steps"
+                })?;
+                error.serialize_field("path", &path.to_string())?;
+                error.serialize_field("max", &max)?;
+            }
+          , DelegateError::Cycle { ref cycle } => {
+                error.serialize_field("kind", "cycle")?;
+                let cycle = cycle.iter().map(Path::to_string).collect::<Vec<_>>();
+                error.serialize_field("cycle", &cycle)?;
+            }
+          , DelegateError::InvalidDestination { ref path, index, error: ref path_error } => {
+                error.serialize_field("kind", "invalid-destination")?;
+                error.serialize_field("path", &path.to_string())?;
+                error.serialize_field("index", &index)?;
+                error.serialize_field("error", &path_error.to_string())?;
+            }
+        }
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+/// Every lookup made while delegating a path, explaining how the path
+/// came to delegate to its result.
+///
+/// This is returned by [`trace`]. Its `Display` form lists each lookup,
+/// indented by the number of rewrites which led to it:
+///
+/// ```text
+/// /svc/users
+///   1: /svc => /srv;  =>  /srv/users
+///   /srv/users
+///     0: /srv => /#/io.l5d.k8s/http;  =>  /#/io.l5d.k8s/http/users
+///     /#/io.l5d.k8s/http/users is a name
+/// = /#/io.l5d.k8s/http/users
+/// ```
+///
+/// A path bound by the namer is shown as `{path} is bound to {tree}`.
+///
+/// Traces are serialized as objects with the fields `path`, `steps` and
+/// `result`. Each step is an object with the fields `path`, `depth`,
+/// `rewrites` and `bound` (a string, or `null`), and each rewrite an
+/// object with the fields `index`, `dentry` (a dentry object), `residual`
+/// and `tree`. Paths and trees are strings, and the result is an object
+/// with either the field `tree`, or the field `error`, an error object.
+///
+/// [`trace`]: fn.trace.html
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DelegationTrace {
+    /// The path which was delegated.
+    #[serde(serialize_with = "serialize_display")]
+    pub path: Path
+  , /// Each path which was looked up, in the order it was looked up.
+    pub steps: Vec<Step>
+  , /// The result of delegation.
+    #[serde(serialize_with = "serialize_result")]
+    pub result: Result<NameTree<Name>, DelegateError>
+}
+
+/// A lookup of a single path during delegation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Step {
+    /// The path which was looked up.
+    #[serde(serialize_with = "serialize_display")]
+    pub path: Path
+  , /// The number of rewrites which led to this path.
+    pub depth: usize
+  , /// The rewrite of the path by each dentry which matched it, in
+    /// precedence order. If none did, the path is a name.
+    pub rewrites: Vec<Rewrite>
+  , /// The tree the path was bound to, by the namer or as one of the
+    /// built in paths `/$/nil` and `/$/fail`, if no dentry matched it.
+    #[serde(serialize_with = "serialize_bound")]
+    pub bound: Option<NameTree<Path>>
+}
+
+/// A path's rewrite by a single dentry.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Rewrite {
+    /// The index of the dentry in the dtab.
+    pub index: usize
+  , /// The dentry.
+    pub dentry: Dentry
+  , /// The rest of the path after the dentry's prefix.
+    #[serde(serialize_with = "serialize_display")]
+    pub residual: Path
+  , /// The dentry's destination, with the residual appended to each leaf.
+    #[serde(serialize_with = "serialize_display")]
+    pub tree: NameTree<Path>
+}
+
+/// Serialize a path or name tree as its `Display` form.
+pub(crate) fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where T: fmt::Display
+    , S: Serializer {
+    serializer.collect_str(value)
+}
+
+fn serialize_bound<S>(bound: &Option<NameTree<Path>>, serializer: S) -> Result<S::Ok, S::Error>
+where S: Serializer {
+    match *bound {
+        Some(ref tree) => serializer.serialize_some(&tree.to_string())
+      , None => serializer.serialize_none()
+    }
+}
+
+/// Serialize the result of delegation as an object with either the field
+/// `tree`, the name tree as a string, or the field `error`.
+pub(crate) fn serialize_result<S>( result: &Result<NameTree<Name>, DelegateError>
+                                 , serializer: S)
+                                 -> Result<S::Ok, S::Error>
+where S: Serializer {
+    let mut object = serializer.serialize_struct("Result", 1)?;
+    match *result {
+        Ok(ref tree) => object.serialize_field("tree", &tree.to_string())?
+      , Err(ref error) => object.serialize_field("error", error)?
+    }
+    object.end()
+}
+
+impl DelegationTrace {
+    /// Explain how the path was delegated in prose, with one numbered
+    /// sentence for each dentry which matched a path, and for each path
+    /// which no dentry matched, followed by the result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::NameTree;
+    /// use dtab::path::Path;
+    ///
+    /// let dtab = dtab![ "/srv" => "/#/io.l5d.k8s/http";
+    ///                   "/svc" => NameTree::from("/srv") | "/env"; ];
+    /// let trace = dtab.trace(&Path::read("/svc/users").unwrap());
+    /// assert_eq!( &trace.explain()
+    ///           , "1. `/svc/users` matched `/svc => /srv | /env;` at entry 1, \
+    ///                 with residual `/users`, and was rewritten to `/srv/users | /env/users`\n\
+    ///              2. `/srv/users` matched `/srv => /#/io.l5d.k8s/http;` at entry 0, \
+    ///                 with residual `/users`, and was rewritten to `/#/io.l5d.k8s/http/users`\n\
+    ///              3. `/#/io.l5d.k8s/http/users` matched no entries, so it is a name\n\
+    ///              4. `/env/users` matched no entries, so it is a name\n\
+    ///              `/svc/users` delegates to `/#/io.l5d.k8s/http/users | /env/users`\n");
+    /// # }
+    /// ```
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+        let mut number = 0;
+        for step in &self.steps {
+            let mut sentence = |text: fmt::Arguments| {
+                number += 1;
+                out.push_str(&format!("{}. `{}` {}\n", number, step.path, text));
+            };
+            if let Some(ref tree) = step.bound {
+                sentence(format_args!("matched no entries, and was bound to `{}`", tree));
+            } else if step.rewrites.is_empty() {
+                sentence(format_args!("matched no entries, so it is a name"));
+            }
+            for (i, rewrite) in step.rewrites.iter().enumerate() {
+                sentence(format_args!( "{} `{}` at entry {}, with residual `{}`, and was \
+                                        rewritten to `{}`"
+                                     , if i == 0 { "matched" } else { "also matched" }
+                                     , rewrite.dentry, rewrite.index, rewrite.residual
+                                     , rewrite.tree));
+            }
+        }
+        match self.result {
+            Ok(ref tree) => out.push_str(&format!("`{}` delegates to `{}`\n", self.path, tree))
+          , Err(ref error) =>
+                out.push_str(&format!("`{}` does not delegate: {}\n", self.path, error))
+        }
+        out
+    }
+}
+
+impl fmt::Display for DelegationTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            let indent = "  ".repeat(step.depth);
+            if let Some(ref tree) = step.bound {
+                writeln!(f, "{}{} is bound to {}", indent, step.path, tree)?;
+                continue
+            }
+            if step.rewrites.is_empty() {
+                writeln!(f, "{}{} is a name", indent, step.path)?;
+                continue
+            }
+            writeln!(f, "{}{}", indent, step.path)?;
+            for rewrite in &step.rewrites {
+                writeln!( f, "{}  {}: {}  =>  {}"
+                        , indent, rewrite.index, rewrite.dentry, rewrite.tree)?;
+            }
+        }
+        match self.result {
+            Ok(ref tree) => write!(f, "= {}", tree)
+          , Err(ref error) => write!(f, "error: {}", error)
+        }
+    }
+}
+
+/// Delegate `path` through `dtab`, binding paths which begin with `/$` or
+/// `/#` with `namer`, and notifying `observer` of each lookup.
+///
+/// Returns a tree whose leaves are the paths which no dentry matches, as
+/// [`Name::Path`]s, and the names `namer` bound paths to, as
+/// [`Name::Bound`]s. A path which no dentry matches, and which `namer`
+/// doesn't know, delegates to itself, except for `/$/nil` and `/$/fail`,
+/// which delegate to `$` and `!`.
+///
+/// [`Name::Path`]: ../name/enum.Name.html#variant.Path
+/// [`Name::Bound`]: ../name/enum.Name.html#variant.Bound
+pub fn delegate<N, O>( dtab: &Dtab, path: &Path, options: &DelegateOptions, namer: N
+                     , observer: O)
+                     -> Result<NameTree<Name>, DelegateError>
+where N: Namer
+    , O: DelegationObserver {
+    run(dtab, path, options, &namer, &observer, None)
+}
+
+/// Delegate `path` through `dtab`, as [`delegate`] does, recording every
+/// lookup.
+///
+/// Since the trace includes the result, this returns a trace even if
+/// delegation fails, showing the lookups which led to the failure.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::delegate::{self, DelegateOptions};
+/// use dtab::path::Path;
+///
+/// let dtab = dtab!["/srv" => "/#/io.l5d.k8s/http"; "/svc" => "/srv";];
+/// let path = Path::read("/svc/users").unwrap();
+/// let trace = delegate::trace(&dtab, &path, &DelegateOptions::default(), (), ());
+/// assert_eq!(trace.steps.len(), 3);
+/// assert_eq!(trace.steps[0].rewrites[0].index, 1);
+/// assert_eq!(trace.steps[1].path, Path::read("/srv/users").unwrap());
+/// assert_eq!(trace.steps[1].rewrites[0].residual, Path::read("/users").unwrap());
+/// assert!(trace.steps[2].rewrites.is_empty());
+/// # }
+/// ```
+///
+/// [`delegate`]: fn.delegate.html
+pub fn trace<N, O>( dtab: &Dtab, path: &Path, options: &DelegateOptions, namer: N
+                  , observer: O)
+                  -> DelegationTrace
+where N: Namer
+    , O: DelegationObserver {
+    let mut steps = Vec::new();
+    let result = run(dtab, path, options, &namer, &observer, Some(&mut steps));
+    DelegationTrace { path: path.clone(), steps, result }
+}
+
+/// A dtab which remembers the result of delegating each path through it.
+///
+/// Delegating a path through a large dtab means matching every dentry
+/// against every path it is rewritten to. Services which delegate the same
+/// paths again and again can use a cache instead, which delegates each path
+/// once, and returns the same result for it until the dtab is changed with
+/// [`set_dtab`] or [`update`], which forget every result.
+///
+/// The cache does not know when the names a namer binds paths to change, so
+/// it should be [`clear`]ed when they do. It holds every result until then,
+/// however many paths are delegated.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::delegate::DelegationCache;
+/// use dtab::path::Path;
+///
+/// let mut cache = DelegationCache::new(dtab!["/svc" => "/srv";]);
+/// let path = Path::read("/svc/users").unwrap();
+/// assert_eq!(&cache.delegate(&path).unwrap().to_string(), "/srv/users");
+/// assert_eq!(cache.len(), 1);
+///
+/// cache.update(|dtab| dtab.push(dentry!("/svc/users" => "/srv/users-v2")));
+/// assert!(cache.is_empty());
+/// assert_eq!(&cache.delegate(&path).unwrap().to_string(), "/srv/users-v2 | /srv/users");
+/// # }
+/// ```
+///
+/// [`set_dtab`]: #method.set_dtab
+/// [`update`]: #method.update
+/// [`clear`]: #method.clear
+#[derive(Clone, Debug)]
+pub struct DelegationCache<N = ()> {
+    dtab: Dtab
+  , options: DelegateOptions
+  , namer: N
+  , results: HashMap<Path, Result<NameTree<Name>, DelegateError>>
+}
+
+impl DelegationCache {
+    /// Returns an empty cache for `dtab`, which delegates with the default
+    /// [`DelegateOptions`] and no namer.
+    ///
+    /// [`DelegateOptions`]: struct.DelegateOptions.html
+    #[inline] pub fn new(dtab: Dtab) -> Self {
+        DelegationCache::with_namer(dtab, DelegateOptions::default(), ())
+    }
+}
+
+impl<N> DelegationCache<N>
+where N: Namer {
+    /// Returns an empty cache for `dtab`, which delegates with `options`,
+    /// binding paths with `namer`.
+    pub fn with_namer(dtab: Dtab, options: DelegateOptions, namer: N) -> Self {
+        DelegationCache { dtab, options, namer, results: HashMap::new() }
+    }
+
+    /// Delegate `path` through the dtab, as [`delegate`] does, or return
+    /// the result of delegating it before, if the dtab hasn't changed since.
+    ///
+    /// [`delegate`]: fn.delegate.html
+    pub fn delegate(&mut self, path: &Path) -> Result<NameTree<Name>, DelegateError> {
+        if let Some(result) = self.results.get(path) {
+            return result.clone()
+        }
+        let result = run(&self.dtab, path, &self.options, &self.namer, &(), None);
+        self.results.insert(path.clone(), result.clone());
+        result
+    }
+
+    /// Returns the dtab.
+    #[inline] pub fn dtab(&self) -> &Dtab { &self.dtab }
+
+    /// Replace the dtab, forgetting every result, and return the previous
+    /// dtab.
+    pub fn set_dtab(&mut self, dtab: Dtab) -> Dtab {
+        self.results.clear();
+        ::std::mem::replace(&mut self.dtab, dtab)
+    }
+
+    /// Change the dtab with `f`, forgetting every result.
+    pub fn update<F>(&mut self, f: F)
+    where F: FnOnce(&mut Dtab) {
+        self.results.clear();
+        f(&mut self.dtab);
+    }
+
+    /// Forget every result, so that each path is delegated again.
+    #[inline] pub fn clear(&mut self) { self.results.clear() }
+
+    /// Returns the number of paths whose result is remembered.
+    #[inline] pub fn len(&self) -> usize { self.results.len() }
+
+    /// Returns true if no results are remembered.
+    #[inline] pub fn is_empty(&self) -> bool { self.results.is_empty() }
+}
+
+fn run<N, O>( dtab: &Dtab, path: &Path, options: &DelegateOptions, namer: &N
+            , observer: &O, steps: Option<&mut Vec<Step>>)
+            -> Result<NameTree<Name>, DelegateError>
+where N: Namer
+    , O: DelegationObserver {
+    let mut delegation = Delegation { dtab, limits: &options.limits, namer, observer, steps
+                                    , chain: Vec::new(), deepest: 0, lookups: 0, width: 0 };
+    let tree = delegation.path(path, 0)?;
+    if tree == NameTree::Neg {
+        observer.neg(path);
+    }
+    observer.depth_reached(path, delegation.deepest);
+    Ok(tree)
+}
+
+/// The state of a single delegation.
+struct Delegation<'a, N: 'a, O: 'a> {
+    dtab: &'a Dtab
+  , limits: &'a ResolutionLimits
+  , namer: &'a N
+  , observer: &'a O
+  , /// Where to record each lookup, if tracing.
+    steps: Option<&'a mut Vec<Step>>
+  , /// The paths being rewritten, each of which was rewritten to the next.
+    chain: Vec<Path>
+  , /// The most times any path has been rewritten so far.
+    deepest: usize
+  , /// The number of paths looked up so far.
+    lookups: usize
+  , /// The number of names in the result so far.
+    width: usize
+}
+
+impl<'a, N, O> Delegation<'a, N, O>
+where N: Namer
+    , O: DelegationObserver {
+    /// Delegate `path`, which was reached after `depth` rewrites.
+    fn path(&mut self, path: &Path, depth: usize) -> Result<NameTree<Name>, DelegateError> {
+        self.lookups += 1;
+        if let Some(max) = self.limits.max_steps.filter(|&max| self.lookups > max) {
+            return Err(DelegateError::TooManySteps { path: path.clone(), max })
+        }
+        let mut rewrites = Vec::new();
+        // paths which name a namer are bound by it, not rewritten.
+        let dentries: &[Dentry] = if namer::namer_id(path).is_some() { &[] } else { self.dtab };
+        for (index, dentry) in dentries.iter().enumerate().rev() {
+            if let Some(residual) = dentry.prefix().matches(path) {
+                self.observer.dentry_fired(path, index, dentry);
+                let tree = super::rewrite(dentry.dst(), &residual)
+                    .map_err(|error| DelegateError::InvalidDestination {
+                        path: path.clone(), index, error
+                    })?;
+                if let Some(ref mut steps) = self.steps {
+                    if rewrites.is_empty() {
+                        steps.push(Step { path: path.clone(), depth, rewrites: Vec::new()
+                                        , bound: None });
+                    }
+                    let step = steps.last_mut().expect("step");
+                    step.rewrites.push(Rewrite { index, dentry: dentry.clone(), residual
+                                               , tree: tree.clone() });
+                }
+                rewrites.push(tree);
+            }
+        }
+        if rewrites.is_empty() {
+            self.observer.lookup_miss(path);
+            let bound = if namer::is_namer_path(path) {
+                namer::builtin(path).or_else(|| self.namer.lookup(path))
+            } else {
+                None
+            };
+            if let Some(ref mut steps) = self.steps {
+                steps.push(Step { path: path.clone(), depth, rewrites: Vec::new()
+                                , bound: bound.clone() });
+            }
+            let tree = match bound {
+                Some(tree) => tree.map(Name::Bound)
+              , None => NameTree::Leaf(Name::Path(path.clone()))
+            };
+            self.width += tree.leaves().count();
+            if let Some(max) = self.limits.max_width.filter(|&max| self.width > max) {
+                return Err(DelegateError::TooWide { path: path.clone(), max })
+            }
+            return Ok(tree)
+        }
+        self.observer.lookup_hit(path, rewrites.len());
+        if let Some(start) = self.chain.iter().position(|p| p == path) {
+            let mut cycle = self.chain.split_off(start);
+            cycle.push(path.clone());
+            return Err(DelegateError::Cycle { cycle })
+        }
+        if depth >= self.limits.max_depth {
+            return Err(DelegateError::TooDeep { path: path.clone(), max: self.limits.max_depth })
+        }
+        self.deepest = self.deepest.max(depth + 1);
+        self.chain.push(path.clone());
+        let tree = self.tree(NameTree::alts(rewrites), depth + 1)?;
+        self.chain.pop();
+        Ok(tree)
+    }
+
+    /// Delegate every leaf of `tree`, which was reached after `depth`
+    /// rewrites.
+    fn tree(&mut self, tree: NameTree<Path>, depth: usize)
+            -> Result<NameTree<Name>, DelegateError> {
+        Ok(match tree.into_parts() {
+            Parts::Leaf(path) => self.path(&path, depth)?
+          , Parts::Alt(left, right) => {
+                let left = self.tree(*left, depth)?;
+                NameTree::Alt(Box::new(left), Box::new(self.tree(*right, depth)?))
+            }
+          , Parts::Union(left, right) => {
+                let (left_weight, right_weight) = (left.weight(), right.weight());
+                let left = self.tree(left.into_tree(), depth)?.weighted(left_weight);
+                NameTree::Union(left, self.tree(right.into_tree(), depth)?.weighted(right_weight))
+            }
+          , Parts::Neg => NameTree::Neg
+          , Parts::Empty => NameTree::Empty
+          , Parts::Fail => NameTree::Fail
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use Dentry;
+    use nametree::W;
+
+    fn path(s: &str) -> Path { Path::read(s).unwrap() }
+
+    fn delegated(dtab: &Dtab, p: &str) -> String {
+        dtab.delegate(&path(p)).unwrap().to_string()
+    }
+
+    fn limited(limits: ResolutionLimits) -> DelegateOptions { DelegateOptions { limits } }
+
+    #[test]
+    fn delegation() {
+        let dtab = dtab![ "/a" => "/b";
+                          "/b" => NameTree::from("/c") | "!";
+                          "/b/x" => (W(3.0) * "/d") & (W(1.0) * "/e/f");
+                          "/e" => "~"; ];
+        assert_eq!(&delegated(&dtab, "/a/y"), "/c/y | !");
+        assert_eq!(&delegated(&dtab, "/a/x/z"), "3 * /d/z & 1 * ~ | (/c/x/z | !)");
+        assert_eq!(&delegated(&dtab, "/unmatched"), "/unmatched");
+        assert_eq!(&delegated(&Dtab::new(), "/"), "/");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let dtab = dtab!["/a" => "/a/b";];
+        let options = limited(ResolutionLimits { max_depth: 3, ..ResolutionLimits::default() });
+        assert_eq!( delegate(&dtab, &path("/a"), &options, (), ())
+                  , Err(DelegateError::TooDeep { path: path("/a/b/b/b"), max: 3 }));
+        // exactly `max_depth` rewrites are allowed.
+        let dtab = dtab!["/a" => "/b"; "/b" => "/c"; "/c" => "/d";];
+        assert_eq!( delegate(&dtab, &path("/a"), &options, (), ()).map(|tree| tree.to_string())
+                  , Ok("/d".to_string()));
+        let options = limited(ResolutionLimits { max_depth: 2, ..ResolutionLimits::default() });
+        assert_eq!( delegate(&dtab, &path("/a"), &options, (), ())
+                  , Err(DelegateError::TooDeep { path: path("/c"), max: 2 }));
+    }
+
+    #[test]
+    fn width_and_step_limits() {
+        // each rewrite doubles the number of names.
+        let dtab = dtab![ "/l0" => NameTree::from("/l1") & "/l1";
+                          "/l1" => NameTree::from("/l2") & "/l2";
+                          "/l2" => NameTree::from("/l3") & "/l3"; ];
+        let width = |max| limited(ResolutionLimits { max_width: max, ..Default::default() });
+        let tree = delegate(&dtab, &path("/l0"), &width(Some(8)), (), ()).unwrap();
+        assert_eq!(tree.leaves().count(), 8);
+        assert_eq!( delegate(&dtab, &path("/l0"), &width(Some(7)), (), ())
+                  , Err(DelegateError::TooWide { path: path("/l3"), max: 7 }));
+
+        // delegating `/l0` looks up 1 + 2 + 4 + 8 paths.
+        let steps = |max| limited(ResolutionLimits { max_steps: max, ..Default::default() });
+        assert!(delegate(&dtab, &path("/l0"), &steps(Some(15)), (), ()).is_ok());
+        assert_eq!( delegate(&dtab, &path("/l0"), &steps(Some(14)), (), ())
+                  , Err(DelegateError::TooManySteps { path: path("/l3"), max: 14 }));
+        assert_eq!( delegate(&dtab, &path("/l2"), &steps(Some(1)), (), ())
+                  , Err(DelegateError::TooManySteps { path: path("/l3"), max: 1 }));
+        assert!(delegate(&dtab, &path("/l0"), &steps(None), (), ()).is_ok());
+        assert_eq!( &DelegateError::TooManySteps { path: path("/a"), max: 10 }.to_string()
+                  , "delegation exceeded 10 lookups, at /a");
+        assert_eq!( &DelegateError::TooWide { path: path("/a"), max: 10 }.to_string()
+                  , "delegation exceeded 10 names, at /a");
+    }
+
+    #[test]
+    fn cycles() {
+        let options = DelegateOptions::default();
+        let cycle = |dtab: &Dtab, p| match delegate(dtab, &path(p), &options, (), ()) {
+            Err(DelegateError::Cycle { cycle }) =>
+                cycle.iter().map(Path::to_string).collect::<Vec<_>>()
+          , result => panic!("expected a cycle, got {:?}", result)
+        };
+        let dtab = dtab!["/a" => "/b"; "/b" => "/a";];
+        assert_eq!(cycle(&dtab, "/a"), vec!["/a", "/b", "/a"]);
+        let dtab = dtab!["/x" => "/a/c"; "/a" => NameTree::from("/d") | "/b"; "/b" => "/a";];
+        assert_eq!(cycle(&dtab, "/x"), vec!["/a/c", "/b/c", "/a/c"]);
+        let dtab = dtab!["/a" => "/a";];
+        assert_eq!(cycle(&dtab, "/a"), vec!["/a", "/a"]);
+        assert_eq!( &DelegateError::Cycle { cycle: vec![path("/a"), path("/b"), path("/a")] }
+                        .to_string()
+                  , "delegation cycle: /a -> /b -> /a");
+        // paths which are reached more than once, but not from themselves,
+        // aren't cycles.
+        let dtab = dtab!["/a" => NameTree::from("/b") | "/c"; "/b" => "/c";];
+        assert_eq!(&delegated(&dtab, "/a"), "/c | /c");
+    }
+
+    #[test]
+    fn escaped_destinations() {
+        use parse::{self, ParseOptions};
+        use path::LabelPolicy;
+
+        let finagle = parse::dtab("/a => /b\\xff | /c\\x41", &ParseOptions::finagle()).unwrap();
+        let escaped = Path::read_with("/b\\xff/x", LabelPolicy::Finagle).unwrap();
+        assert_eq!( finagle.lookup(&path("/a/x"))
+                  , Ok(NameTree::Leaf(escaped.clone()) | NameTree::Leaf(path("/cA/x"))));
+        assert_eq!( finagle.delegate(&path("/a/x"))
+                  , Ok(NameTree::Leaf(Name::Path(escaped)) | NameTree::Leaf(Name::Path(path("/cA/x")))));
+
+        let utf8 = ParseOptions { labels: LabelPolicy::Utf8, ..ParseOptions::default() };
+        let utf8 = parse::dtab("/a => /café", &utf8).unwrap();
+        assert_eq!(&delegated(&utf8, "/a"), "/caf\\xc3\\xa9");
+
+        // leaves which aren't paths are errors, not failures.
+        let invalid = dtab!["/a" => "b"; "/c" => "/d";];
+        assert_eq!( invalid.delegate(&path("/a"))
+                  , Err(DelegateError::InvalidDestination {
+                        path: path("/a"), index: 0
+                      , error: PathError::MissingLeadingSlash { path: "b".to_string() }
+                    }));
+        assert!(invalid.lookup(&path("/a")).is_err());
+        assert_eq!(&delegated(&invalid, "/c"), "/d");
+        assert_eq!( &invalid.delegate(&path("/a")).unwrap_err().to_string()
+                  , "entry 0 rewrote /a to an invalid path: path \"b\" does not begin with '/'");
+    }
+
+    #[test]
+    fn explanations() {
+        let dtab = dtab![ "/svc" => "/#/k8s";
+                          "/svc/users" => NameTree::from("~") | "/svc/v1";
+                          "/a" => "/b"; "/b" => "/a"; ];
+        let explain = |p| dtab.trace(&path(p)).explain();
+        assert_eq!( &explain("/svc/users")
+                  , "1. `/svc/users` matched `/svc/users => ~ | /svc/v1;` at entry 1, with \
+                        residual `/`, and was rewritten to `~ | /svc/v1`\n\
+                     2. `/svc/users` also matched `/svc => /#/k8s;` at entry 0, with residual \
+                        `/users`, and was rewritten to `/#/k8s/users`\n\
+                     3. `/svc/v1` matched `/svc => /#/k8s;` at entry 0, with residual `/v1`, \
+                        and was rewritten to `/#/k8s/v1`\n\
+                     4. `/#/k8s/v1` matched no entries, so it is a name\n\
+                     5. `/#/k8s/users` matched no entries, so it is a name\n\
+                     `/svc/users` delegates to `~ | /#/k8s/v1 | /#/k8s/users`\n");
+        assert_eq!( &explain("/a")
+                  , "1. `/a` matched `/a => /b;` at entry 2, with residual `/`, and was \
+                        rewritten to `/b`\n\
+                     2. `/b` matched `/b => /a;` at entry 3, with residual `/`, and was \
+                        rewritten to `/a`\n\
+                     3. `/a` matched `/a => /b;` at entry 2, with residual `/`, and was \
+                        rewritten to `/b`\n\
+                     `/a` does not delegate: delegation cycle: /a -> /b -> /a\n");
+
+        let namer: namer::MockNamer = vec![(path("/#/k8s"), NameTree::Leaf(path("/10.0.0.1")))]
+            .into_iter().collect();
+        let trace = trace(&dtab, &path("/svc/orders"), &DelegateOptions::default(), &namer, ());
+        assert_eq!( &trace.explain()
+                  , "1. `/svc/orders` matched `/svc => /#/k8s;` at entry 0, with residual \
+                        `/orders`, and was rewritten to `/#/k8s/orders`\n\
+                     2. `/#/k8s/orders` matched no entries, and was bound to \
+                        `/10.0.0.1/orders`\n\
+                     `/svc/orders` delegates to `/10.0.0.1/orders`\n");
+        assert_eq!(dtab.explain(&path("/svc/orders")), dtab.trace(&path("/svc/orders")).explain());
+    }
+
+    #[test]
+    fn traces() {
+        let dtab = dtab![ "/srv" => "/#/io.l5d.k8s/http";
+                          "/svc" => "/srv";
+                          "/svc/users" => NameTree::from("~") | "/srv/users-v2"; ];
+        let trace = |p| trace(&dtab, &path(p), &DelegateOptions::default(), (), ()).to_string();
+        assert_eq!( &trace("/svc/users")
+                  , "/svc/users\n\
+                     \x20 2: /svc/users => ~ | /srv/users-v2;  =>  ~ | /srv/users-v2\n\
+                     \x20 1: /svc => /srv;  =>  /srv/users\n\
+                     \x20 /srv/users-v2\n\
+                     \x20   0: /srv => /#/io.l5d.k8s/http;  =>  /#/io.l5d.k8s/http/users-v2\n\
+                     \x20   /#/io.l5d.k8s/http/users-v2 is a name\n\
+                     \x20 /srv/users\n\
+                     \x20   0: /srv => /#/io.l5d.k8s/http;  =>  /#/io.l5d.k8s/http/users\n\
+                     \x20   /#/io.l5d.k8s/http/users is a name\n\
+                     = ~ | /#/io.l5d.k8s/http/users-v2 | /#/io.l5d.k8s/http/users");
+        assert_eq!(&trace("/other"), "/other is a name\n= /other");
+
+        let dtab = dtab!["/a" => "/b"; "/b" => "/a";];
+        let trace = super::trace(&dtab, &path("/a"), &DelegateOptions::default(), (), ());
+        assert_eq!(trace.steps.len(), 3);
+        assert_eq!( &trace.to_string()
+                  , "/a\n  0: /a => /b;  =>  /b\n  /b\n    1: /b => /a;  =>  /a\n    \
+                     /a\n      0: /a => /b;  =>  /b\n\
+                     error: delegation cycle: /a -> /b -> /a");
+    }
+
+    /// Binds `/#/k8s/users` to two endpoints, and knows nothing else
+    /// under `/#/k8s`.
+    struct K8s;
+
+    impl Namer for K8s {
+        fn lookup(&self, p: &Path) -> Option<NameTree<Path>> {
+            match &*p.to_string() {
+                "/#/k8s/users" =>
+                    Some(NameTree::Leaf(path("/10.0.0.1")) & NameTree::Leaf(path("/10.0.0.2")))
+              , other if other.starts_with("/#/k8s/") => Some(NameTree::Neg)
+              , _ => None
+            }
+        }
+    }
+
+    #[test]
+    fn namers() {
+        let dtab = dtab![ "/svc" => NameTree::from("/#/k8s") | "/$/inet/fallback";
+                          "/#/k8s/admin" => "/#/k8s/users"; ];
+        let delegated = |p| delegate(&dtab, &path(p), &DelegateOptions::default(), K8s, ())
+            .unwrap()
+            .to_string();
+        assert_eq!( &delegated("/svc/users")
+                  , "0.5 * /10.0.0.1 & 0.5 * /10.0.0.2 | /$/inet/fallback/users");
+        assert_eq!(&delegated("/svc/orders"), "~ | /$/inet/fallback/orders");
+        // paths which name a namer are never rewritten.
+        assert_eq!(&delegated("/svc/admin"), "~ | /$/inet/fallback/admin");
+        // only paths beginning with `/$` or `/#` are looked up by the namer.
+        assert_eq!(&delegated("/k8s/users"), "/k8s/users");
+
+        let bound = |p| NameTree::Leaf(Name::Bound(path(p)));
+        assert_eq!( delegate(&dtab, &path("/svc/users"), &DelegateOptions::default(), K8s, ())
+                  , Ok((bound("/10.0.0.1") & bound("/10.0.0.2"))
+                       | NameTree::Leaf(Name::Path(path("/$/inet/fallback/users")))));
+
+        let trace = trace(&dtab, &path("/svc/users"), &DelegateOptions::default(), K8s, ());
+        assert_eq!( trace.steps[1].bound
+                  , Some(NameTree::Leaf(path("/10.0.0.1")) & NameTree::Leaf(path("/10.0.0.2"))));
+        assert_eq!( &trace.to_string()
+                  , "/svc/users\n\
+                     \x20 0: /svc => /#/k8s | /$/inet/fallback;  =>  \
+                     /#/k8s/users | /$/inet/fallback/users\n\
+                     \x20 /#/k8s/users is bound to 0.5 * /10.0.0.1 & 0.5 * /10.0.0.2\n\
+                     \x20 /$/inet/fallback/users is a name\n\
+                     = 0.5 * /10.0.0.1 & 0.5 * /10.0.0.2 | /$/inet/fallback/users");
+    }
+
+    #[test]
+    fn namer_ids() {
+        // without a namer, paths naming one are names, rather than being
+        // rewritten forever.
+        let dtab = dtab!["/" => "/#/io.l5d.fs";];
+        assert_eq!(&delegated(&dtab, "/users"), "/#/io.l5d.fs/users");
+
+        let mut fs = namer::MockNamer::new();
+        fs.insert(path("/users"), NameTree::Leaf(path("/$/inet/127.0.0.1/8080")));
+        let mut namers = namer::Namers::new();
+        namers.register("io.l5d.fs", fs);
+        let delegated = |p| delegate(&dtab, &path(p), &DelegateOptions::default(), &namers, ())
+            .unwrap()
+            .to_string();
+        assert_eq!(&delegated("/users"), "/$/inet/127.0.0.1/8080");
+        assert_eq!(&delegated("/orders"), "/#/io.l5d.fs/orders");
+        assert_eq!(&delegated("/#/io.l5d.k8s/users"), "/#/io.l5d.k8s/users");
+    }
+
+    #[test]
+    fn builtin_paths() {
+        let dtab = dtab![ "/cart" => "/$/nil";
+                          "/users" => "/$/inet/127.0.0.1/8080";
+                          "/orders" => NameTree::from("/$/fail/orders") | "/users"; ];
+        assert_eq!(&delegated(&dtab, "/cart/get"), "$");
+        assert_eq!(&delegated(&dtab, "/users/get"), "/$/inet/127.0.0.1/8080/get");
+        assert_eq!(&delegated(&dtab, "/orders"), "! | /$/inet/127.0.0.1/8080");
+
+        // built in paths are bound before the namer is consulted.
+        let namer: namer::MockNamer = vec![(path("/$"), NameTree::Leaf(path("/bound")))]
+            .into_iter().collect();
+        let delegated = |p| delegate(&dtab, &path(p), &DelegateOptions::default(), &namer, ())
+            .unwrap()
+            .to_string();
+        assert_eq!(&delegated("/cart"), "$");
+        assert_eq!(&delegated("/users/get"), "/bound/inet/127.0.0.1/8080/get");
+    }
+
+    #[test]
+    fn cache() {
+        /// Counts the paths it is asked to bind.
+        #[derive(Default)]
+        struct Counting(::std::cell::Cell<usize>);
+
+        impl Namer for Counting {
+            fn lookup(&self, _: &Path) -> Option<NameTree<Path>> {
+                self.0.set(self.0.get() + 1);
+                None
+            }
+        }
+
+        let dtab = dtab!["/svc" => "/#/k8s"; "/a" => "/b"; "/b" => "/a";];
+        let mut cache = DelegationCache::with_namer(dtab, DelegateOptions::default()
+                                                   , Counting::default());
+        for _ in 0..3 {
+            assert_eq!(&cache.delegate(&path("/svc/users")).unwrap().to_string(), "/#/k8s/users");
+            assert!(cache.delegate(&path("/a")).is_err());
+        }
+        assert_eq!(cache.namer.0.get(), 1);
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        cache.delegate(&path("/svc/users")).unwrap();
+        assert_eq!(cache.namer.0.get(), 2);
+
+        let old = cache.set_dtab(dtab!["/svc" => "/srv";]);
+        assert_eq!(old.len(), 3);
+        assert!(cache.is_empty());
+        assert_eq!(&cache.delegate(&path("/svc/users")).unwrap().to_string(), "/srv/users");
+        assert_eq!(cache.dtab(), &dtab!["/svc" => "/srv";]);
+    }
+
+    #[derive(Default)]
+    struct Events(RefCell<Vec<String>>);
+
+    impl DelegationObserver for Events {
+        fn lookup_hit(&self, path: &Path, dentries: usize) {
+            self.0.borrow_mut().push(format!("hit {} {}", path, dentries));
+        }
+        fn lookup_miss(&self, path: &Path) {
+            self.0.borrow_mut().push(format!("miss {}", path));
+        }
+        fn dentry_fired(&self, path: &Path, index: usize, _: &Dentry) {
+            self.0.borrow_mut().push(format!("fired {} {}", path, index));
+        }
+        fn neg(&self, path: &Path) {
+            self.0.borrow_mut().push(format!("neg {}", path));
+        }
+        fn depth_reached(&self, path: &Path, depth: usize) {
+            self.0.borrow_mut().push(format!("depth {} {}", path, depth));
+        }
+    }
+
+    #[test]
+    fn observed() {
+        let dtab = dtab!["/a" => "/b"; "/a/c" => "~"; "/b" => "/d";];
+        let events = Events::default();
+        delegate(&dtab, &path("/a/c"), &DelegateOptions::default(), (), &events).unwrap();
+        assert_eq!( *events.0.borrow()
+                  , vec![ "fired /a/c 1", "fired /a/c 0", "hit /a/c 2", "fired /b/c 2"
+                        , "hit /b/c 1", "miss /d/c", "depth /a/c 2" ]);
+        let events = Events::default();
+        delegate(&dtab!["/a" => "~";], &path("/a"), &DelegateOptions::default(), (), &events)
+            .unwrap();
+        assert_eq!(*events.0.borrow(), vec!["fired /a 0", "hit /a 1", "neg /a", "depth /a 1"]);
+    }
+
+    #[test]
+    fn serialize_trace() {
+        let dtab = dtab![ "/svc" => NameTree::from("/$/nil") | "/srv";
+                          "/srv/users" => "/srv/users"; ];
+        let trace = dtab.trace(&path("/svc/users"));
+        assert_eq!( ::serde_json::to_value(&trace).unwrap()
+                  , json!({
+                        "path": "/svc/users"
+                      , "steps": [
+                            { "path": "/svc/users", "depth": 0, "bound": null, "rewrites": [
+                                  { "index": 0
+                                  , "dentry": { "prefix": "/svc", "dst": "/$/nil | /srv" }
+                                  , "residual": "/users"
+                                  , "tree": "/$/nil/users | /srv/users" } ] }
+                          , { "path": "/$/nil/users", "depth": 1, "bound": "$", "rewrites": [] }
+                          , { "path": "/srv/users", "depth": 1, "bound": null, "rewrites": [
+                                  { "index": 1
+                                  , "dentry": { "prefix": "/srv/users", "dst": "/srv/users" }
+                                  , "residual": "/"
+                                  , "tree": "/srv/users" } ] }
+                          , { "path": "/srv/users", "depth": 2, "bound": null, "rewrites": [
+                                  { "index": 1
+                                  , "dentry": { "prefix": "/srv/users", "dst": "/srv/users" }
+                                  , "residual": "/"
+                                  , "tree": "/srv/users" } ] } ]
+                      , "result": { "error": { "kind": "cycle"
+                                             , "cycle": ["/srv/users", "/srv/users"]
+                                             , "message": "delegation cycle: \
+                                                           /srv/users -> /srv/users" } }
+                    }));
+
+        let trace = dtab.trace(&path("/$/nil"));
+        assert_eq!( ::serde_json::to_value(&trace).unwrap()
+                  , json!({
+                        "path": "/$/nil"
+                      , "steps": [ { "path": "/$/nil", "depth": 0, "bound": "$", "rewrites": [] } ]
+                      , "result": { "tree": "$" }
+                    }));
+        let error = DelegateError::TooDeep { path: path("/a"), max: 3 };
+        assert_eq!( ::serde_json::to_value(&error).unwrap()
+                  , json!({ "kind": "too-deep", "path": "/a", "max": 3
+                          , "message": "delegation exceeded 3 rewrites, at /a" }));
+    }
+}
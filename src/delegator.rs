@@ -0,0 +1,664 @@
+//! Simulating linkerd's delegator.
+//!
+//! linkerd's admin UI has a *delegator*, which shows how a path is
+//! delegated through a namespace's dtab: every dentry which rewrote each
+//! path along the way, what the namers bound the resulting paths to, and
+//! where delegation failed. Unlike [`delegate`], which fails as a whole if
+//! delegating any path fails, the delegator shows a partial resolution, in
+//! which the branches which failed are marked as failed, and every other
+//! branch is resolved as usual.
+//!
+//! [`simulate`] builds the same [`DelegateTree`], binding namer paths with a
+//! [`Namer`], such as a [`MockNamer`] of the bindings the namespace's namers
+//! would return. Its `Display` form is an indented outline, it serializes
+//! to a nested object for tools to render, and its [`result`] is the tree
+//! of names the path resolves to, with `!` in place of each branch which
+//! failed, so CI can assert how critical paths are routed whenever a dtab
+//! changes.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::NameTree;
+//! use dtab::delegate::DelegateOptions;
+//! use dtab::delegator;
+//! use dtab::namer::MockNamer;
+//! use dtab::path::Path;
+//!
+//! let path = |s| Path::read(s).unwrap();
+//! let mut namer = MockNamer::new();
+//! namer.insert(path("/#/k8s/users"), NameTree::Leaf(path("/$/inet/10.0.0.1/8080")));
+//!
+//! let dtab = dtab![ "/svc" => "/#/k8s";
+//!                   "/svc/users" => NameTree::from("/old") | "/svc/v1";
+//!                   "/old" => "/svc/users"; ];
+//! let options = DelegateOptions::default();
+//! let tree = delegator::simulate(&dtab, &path("/svc/users"), &options, &namer);
+//! assert_eq!( &tree.to_string()
+//!           , "/svc/users\n\
+//!              \x20 1: /svc/users => /old | /svc/v1;\n\
+//!              \x20   |\n\
+//!              \x20     /old\n\
+//!              \x20       2: /old => /svc/users;\n\
+//!              \x20         /svc/users failed: delegation cycle: \
+//!                          /svc/users -> /old -> /svc/users\n\
+//!              \x20     /svc/v1\n\
+//!              \x20       0: /svc => /#/k8s;\n\
+//!              \x20         /#/k8s/v1 is a name\n\
+//!              \x20 0: /svc => /#/k8s;\n\
+//!              \x20   /#/k8s/users is bound to /$/inet/10.0.0.1/8080\n");
+//! assert_eq!(&tree.result().to_string(), "! | /#/k8s/v1 | /$/inet/10.0.0.1/8080");
+//! # }
+//! ```
+//!
+//! [`delegate`]: ../delegate/fn.delegate.html
+//! [`simulate`]: fn.simulate.html
+//! [`DelegateTree`]: enum.DelegateTree.html
+//! [`result`]: enum.DelegateTree.html#method.result
+//! [`Namer`]: ../namer/trait.Namer.html
+//! [`MockNamer`]: ../namer/struct.MockNamer.html
+use std::fmt;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use super::{Dentry, Dtab, NameTree};
+use super::delegate::{DelegateError, DelegateOptions, ResolutionLimits};
+use super::name::Name;
+use super::namer::{self, Namer};
+use super::path::Path;
+
+/// How a path was delegated, as linkerd's delegator shows it.
+///
+/// Each node is serialized as an object with a `type`, one of
+/// `"delegate"`, `"bound"`, `"unbound"` or `"exception"`, the `path` it
+/// delegates as a string, the `fraction` of the traffic to the root path
+/// which reaches it, and the variant's other fields: the list of
+/// [`Rewritten`] objects `rewrites`, the `tree` a path was bound to as a
+/// string, or the `error` object a delegation failed with.
+///
+/// A node's `fraction` is the product of the normalized weights of every
+/// union member it's in, on the way down from the root, so the fractions of
+/// the members of a union add up to the fraction of the union. Alternatives
+/// don't split traffic, so each alternative gets the whole fraction of the
+/// tree it's in.
+///
+/// [`Rewritten`]: struct.Rewritten.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum DelegateTree {
+    /// `path` was rewritten by each dentry in `rewrites`, in precedence
+    /// order.
+    Delegate { path: Path, rewrites: Vec<Rewritten> }
+  , /// No dentry matched `path`, and the namer bound it to `tree`.
+    Bound { path: Path, tree: NameTree<Path> }
+  , /// No dentry matched `path`, and no namer bound it.
+    Unbound(Path)
+  , /// Delegating `path` failed, with `error`.
+    Exception { path: Path, error: DelegateError }
+}
+
+/// A path's rewrite by a single dentry, and how each path it was rewritten
+/// to was delegated in turn.
+///
+/// Rewrites are serialized as objects with the fields `index`, `dentry` (a
+/// dentry object) and `tree`, whose leaves are [`DelegateTree`] objects and
+/// whose operators are objects with the `type` `"alt"`, with the list of
+/// alternatives `trees`, or `"union"`, with the list of `members`, each an
+/// object with a `weight` string, the `fraction` of traffic which reaches
+/// it, as described for [`DelegateTree`], and a `tree`. `~`, `$` and `!`
+/// are objects with the `type` `"neg"`, `"empty"` and `"fail"`.
+///
+/// [`DelegateTree`]: enum.DelegateTree.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rewritten {
+    /// The index of the dentry in the dtab.
+    pub index: usize
+  , /// The dentry.
+    pub dentry: Dentry
+  , /// The dentry's destination, with each leaf replaced by the delegation
+    /// of the path it was rewritten to.
+    pub tree: NameTree<DelegateTree>
+}
+
+/// Delegate `path` through `dtab`, as linkerd's delegator does, binding
+/// paths which begin with `/$` or `/#` with `bindings`.
+///
+/// Paths are rewritten and bound exactly as [`delegate`] does, within the
+/// [`ResolutionLimits`] of `options`. Where delegating a path fails,
+/// because it is rewritten too many times or in a cycle, because it would
+/// exceed the limit on names or lookups, or because a dentry rewrote it to
+/// a leaf which isn't a valid path, the failure is recorded in
+/// its place in the tree, and delegation of the other paths carries on.
+///
+/// [`delegate`]: ../delegate/fn.delegate.html
+/// [`ResolutionLimits`]: ../delegate/struct.ResolutionLimits.html
+pub fn simulate<N>(dtab: &Dtab, path: &Path, options: &DelegateOptions, bindings: N)
+                   -> DelegateTree
+where N: Namer {
+    Simulation { dtab, namer: &bindings, limits: &options.limits, chain: Vec::new()
+               , lookups: 0, width: 0 }
+        .path(path, 0)
+}
+
+impl DelegateTree {
+    /// Returns the path this node delegates.
+    pub fn path(&self) -> &Path {
+        match *self {
+            DelegateTree::Delegate { ref path, .. } | DelegateTree::Bound { ref path, .. }
+          | DelegateTree::Unbound(ref path) | DelegateTree::Exception { ref path, .. } => path
+        }
+    }
+
+    /// Returns the tree of names the path resolves to, as [`delegate`]
+    /// would return it, with the failure `!` in place of each path whose
+    /// delegation failed.
+    ///
+    /// [`delegate`]: ../delegate/fn.delegate.html
+    pub fn result(&self) -> NameTree<Name> {
+        match *self {
+            DelegateTree::Delegate { ref rewrites, .. } =>
+                NameTree::alts(rewrites.iter().map(|rewrite| resolve(&rewrite.tree)))
+          , DelegateTree::Bound { ref tree, .. } => tree.clone().map(Name::Bound)
+          , DelegateTree::Unbound(ref path) => NameTree::Leaf(Name::Path(path.clone()))
+          , DelegateTree::Exception { .. } => NameTree::Fail
+        }
+    }
+
+    /// Returns every failure in this tree, in the order they occurred,
+    /// with the path whose delegation failed.
+    ///
+    /// If this is empty, the path resolved completely, and [`result`] is
+    /// what [`delegate`] would return.
+    ///
+    /// [`result`]: #method.result
+    /// [`delegate`]: ../delegate/fn.delegate.html
+    pub fn errors(&self) -> Vec<(&Path, &DelegateError)> {
+        let mut errors = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            match *node {
+                DelegateTree::Delegate { ref rewrites, .. } =>
+                    for rewrite in rewrites.iter().rev() {
+                        let before = stack.len();
+                        stack.extend(rewrite.tree.leaves());
+                        stack[before..].reverse();
+                    }
+              , DelegateTree::Exception { ref path, ref error } => errors.push((path, error))
+              , _ => {}
+            }
+        }
+        errors
+    }
+}
+
+/// Serialized as the root of a delegation, which all of the traffic
+/// reaches.
+impl Serialize for DelegateTree {
+    #[inline] fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        Delegation(self, 1.0).serialize(serializer)
+    }
+}
+
+/// Serialized as the root of a delegation, which all of the traffic
+/// reaches.
+impl Serialize for Rewritten {
+    #[inline] fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        Rewrite(self, 1.0).serialize(serializer)
+    }
+}
+
+/// Serializes a [`DelegateTree`] which the given fraction of traffic
+/// reaches.
+///
+/// [`DelegateTree`]: enum.DelegateTree.html
+struct Delegation<'a>(&'a DelegateTree, f64);
+
+/// Serializes a [`Rewritten`] which the given fraction of traffic reaches.
+///
+/// [`Rewritten`]: struct.Rewritten.html
+struct Rewrite<'a>(&'a Rewritten, f64);
+
+/// Serializes a rewritten tree which the given fraction of traffic
+/// reaches, as described for [`Rewritten`].
+///
+/// [`Rewritten`]: struct.Rewritten.html
+struct Node<'a>(&'a NameTree<DelegateTree>, f64);
+
+/// A weighted member of a union in a [`Node`].
+///
+/// [`Node`]: struct.Node.html
+#[derive(Serialize)]
+struct Member<'a> {
+    weight: String
+  , fraction: f64
+  , tree: Node<'a>
+}
+
+impl<'a> Serialize for Delegation<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        let Delegation(tree, fraction) = *self;
+        let (kind, len) = match *tree {
+            DelegateTree::Delegate { .. } => ("delegate", 4)
+          , DelegateTree::Bound { .. } => ("bound", 4)
+          , DelegateTree::Unbound(_) => ("unbound", 3)
+          , DelegateTree::Exception { .. } => ("exception", 4)
+        };
+        let mut node = serializer.serialize_struct("DelegateTree", len)?;
+        node.serialize_field("type", kind)?;
+        node.serialize_field("path", &tree.path().to_string())?;
+        node.serialize_field("fraction", &fraction)?;
+        match *tree {
+            DelegateTree::Delegate { ref rewrites, .. } => {
+                let rewrites = rewrites.iter()
+                    .map(|rewrite| Rewrite(rewrite, fraction))
+                    .collect::<Vec<_>>();
+                node.serialize_field("rewrites", &rewrites)?
+            }
+          , DelegateTree::Bound { ref tree, .. } =>
+                node.serialize_field("tree", &tree.to_string())?
+          , DelegateTree::Unbound(_) => {}
+          , DelegateTree::Exception { ref error, .. } => node.serialize_field("error", error)?
+        }
+        node.end()
+    }
+}
+
+impl<'a> Serialize for Rewrite<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        let Rewrite(rewrite, fraction) = *self;
+        let mut node = serializer.serialize_struct("Rewritten", 3)?;
+        node.serialize_field("index", &rewrite.index)?;
+        node.serialize_field("dentry", &rewrite.dentry)?;
+        node.serialize_field("tree", &Node(&rewrite.tree, fraction))?;
+        node.end()
+    }
+}
+
+impl<'a> Serialize for Node<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        let Node(tree, fraction) = *self;
+        let (mut trees, mut members) = (Vec::new(), Vec::new());
+        let kind = match *tree {
+            NameTree::Leaf(ref node) => return Delegation(node, fraction).serialize(serializer)
+          , NameTree::Alt(..) => {
+                // chains of alternations are a single list, as in `Display`.
+                let mut stack = vec![tree];
+                while let Some(tree) = stack.pop() {
+                    match *tree {
+                        NameTree::Alt(ref left, ref right) => {
+                            stack.push(right);
+                            stack.push(left);
+                        }
+                      , _ => trees.push(Node(tree, fraction))
+                    }
+                }
+                "alt"
+            }
+          , NameTree::Union(ref left, ref right) => {
+                let total = left.weight() + right.weight();
+                members.extend([left, right].iter().map(|member| {
+                    let share = if total.is_zero() {
+                        0.0
+                    } else {
+                        (member.weight() / total).to_f64()
+                    };
+                    Member { weight: member.weight().to_string()
+                           , fraction: fraction * share
+                           , tree: Node(member.tree(), fraction * share) }
+                }));
+                "union"
+            }
+          , NameTree::Neg => "neg"
+          , NameTree::Empty => "empty"
+          , NameTree::Fail => "fail"
+        };
+        let len = if trees.is_empty() && members.is_empty() { 1 } else { 2 };
+        let mut node = serializer.serialize_struct("Node", len)?;
+        node.serialize_field("type", kind)?;
+        if !trees.is_empty() {
+            node.serialize_field("trees", &trees)?;
+        } else if !members.is_empty() {
+            node.serialize_field("members", &members)?;
+        }
+        node.end()
+    }
+}
+
+/// Replace every leaf of `tree` with the names it resolves to.
+fn resolve(tree: &NameTree<DelegateTree>) -> NameTree<Name> {
+    match *tree {
+        NameTree::Leaf(ref node) => node.result()
+      , NameTree::Alt(ref left, ref right) =>
+            NameTree::Alt(Box::new(resolve(left)), Box::new(resolve(right)))
+      , NameTree::Union(ref left, ref right) =>
+            NameTree::Union( resolve(left.tree()).weighted(left.weight())
+                           , resolve(right.tree()).weighted(right.weight()))
+      , NameTree::Neg => NameTree::Neg
+      , NameTree::Empty => NameTree::Empty
+      , NameTree::Fail => NameTree::Fail
+    }
+}
+
+/// The state of a single simulation.
+struct Simulation<'a, N: 'a> {
+    dtab: &'a Dtab
+  , namer: &'a N
+  , limits: &'a ResolutionLimits
+  , /// The paths being rewritten, each of which was rewritten to the next.
+    chain: Vec<Path>
+  , /// The number of paths looked up so far.
+    lookups: usize
+  , /// The number of names in the result so far, not counting branches
+    /// which failed.
+    width: usize
+}
+
+impl<'a, N> Simulation<'a, N>
+where N: Namer {
+    /// Delegate `path`, which was reached after `depth` rewrites.
+    fn path(&mut self, path: &Path, depth: usize) -> DelegateTree {
+        let exception = |error| DelegateTree::Exception { path: path.clone(), error };
+        self.lookups += 1;
+        if let Some(max) = self.limits.max_steps.filter(|&max| self.lookups > max) {
+            return exception(DelegateError::TooManySteps { path: path.clone(), max })
+        }
+        let dtab = self.dtab;
+        // paths which name a namer are bound by it, not rewritten.
+        let dentries: &[Dentry] = if namer::namer_id(path).is_some() { &[] } else { dtab };
+        let rewrites = dentries.iter().enumerate().rev()
+            .filter_map(|(index, dentry)| match dentry.rewrite(path) {
+                Ok(tree) => tree.map(|tree| Ok((index, dentry, tree)))
+              , Err(error) => Some(Err(DelegateError::InvalidDestination {
+                    path: path.clone(), index, error
+                }))
+            })
+            .collect::<Result<Vec<_>, _>>();
+        let rewrites = match rewrites {
+            Ok(rewrites) => rewrites
+          , Err(error) => return exception(error)
+        };
+        if rewrites.is_empty() {
+            let bound = if namer::is_namer_path(path) {
+                namer::builtin(path).or_else(|| self.namer.lookup(path))
+            } else {
+                None
+            };
+            let width = self.width + bound.as_ref().map_or(1, |tree| tree.leaves().count());
+            if let Some(max) = self.limits.max_width.filter(|&max| width > max) {
+                return exception(DelegateError::TooWide { path: path.clone(), max })
+            }
+            self.width = width;
+            return match bound {
+                Some(tree) => DelegateTree::Bound { path: path.clone(), tree }
+              , None => DelegateTree::Unbound(path.clone())
+            }
+        }
+        if let Some(start) = self.chain.iter().position(|p| p == path) {
+            let mut cycle = self.chain[start..].to_vec();
+            cycle.push(path.clone());
+            return exception(DelegateError::Cycle { cycle })
+        }
+        if depth >= self.limits.max_depth {
+            return exception(DelegateError::TooDeep { path: path.clone()
+                                                    , max: self.limits.max_depth })
+        }
+        self.chain.push(path.clone());
+        let rewrites = rewrites.into_iter().map(|(index, dentry, tree)| {
+            let tree = tree.map(|rewritten| self.path(&rewritten, depth + 1));
+            Rewritten { index, dentry: dentry.clone(), tree }
+        }).collect();
+        self.chain.pop();
+        DelegateTree::Delegate { path: path.clone(), rewrites }
+    }
+}
+
+/// Writes the tree as an indented outline, one line per path, dentry, or
+/// operator, in the order they were delegated.
+impl fmt::Display for DelegateTree {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_node(f, self, 0, "")
+    }
+}
+
+fn write_node(f: &mut fmt::Formatter, node: &DelegateTree, indent: usize, prefix: &str)
+              -> fmt::Result {
+    let indent_str = "  ".repeat(indent);
+    match *node {
+        DelegateTree::Delegate { ref path, ref rewrites } => {
+            writeln!(f, "{}{}{}", indent_str, prefix, path)?;
+            for rewrite in rewrites {
+                writeln!(f, "{}  {}: {}", indent_str, rewrite.index, rewrite.dentry)?;
+                write_tree(f, &rewrite.tree, indent + 2, "")?;
+            }
+            Ok(())
+        }
+      , DelegateTree::Bound { ref path, ref tree } =>
+            writeln!(f, "{}{}{} is bound to {}", indent_str, prefix, path, tree)
+      , DelegateTree::Unbound(ref path) =>
+            writeln!(f, "{}{}{} is a name", indent_str, prefix, path)
+      , DelegateTree::Exception { ref path, ref error } =>
+            writeln!(f, "{}{}{} failed: {}", indent_str, prefix, path, error)
+    }
+}
+
+fn write_tree(f: &mut fmt::Formatter, tree: &NameTree<DelegateTree>, indent: usize, prefix: &str)
+              -> fmt::Result {
+    let indent_str = "  ".repeat(indent);
+    match *tree {
+        NameTree::Leaf(ref node) => write_node(f, node, indent, prefix)
+      , NameTree::Alt(..) => {
+            writeln!(f, "{}{}|", indent_str, prefix)?;
+            // chains of alternations are written as a single `|`.
+            let mut stack = vec![tree];
+            while let Some(tree) = stack.pop() {
+                match *tree {
+                    NameTree::Alt(ref left, ref right) => {
+                        stack.push(right);
+                        stack.push(left);
+                    }
+                  , _ => write_tree(f, tree, indent + 1, "")?
+                }
+            }
+            Ok(())
+        }
+      , NameTree::Union(ref left, ref right) => {
+            writeln!(f, "{}{}&", indent_str, prefix)?;
+            for member in &[left, right] {
+                write_tree(f, member.tree(), indent + 1, &format!("{} * ", member.weight()))?;
+            }
+            Ok(())
+        }
+      , NameTree::Neg => writeln!(f, "{}{}~", indent_str, prefix)
+      , NameTree::Empty => writeln!(f, "{}{}$", indent_str, prefix)
+      , NameTree::Fail => writeln!(f, "{}{}!", indent_str, prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delegate;
+    use namer::MockNamer;
+    use nametree::W;
+
+    fn path(s: &str) -> Path { Path::read(s).unwrap() }
+
+    #[test]
+    fn matches_delegation() {
+        let namer: MockNamer = vec![(path("/#/k8s/users"), NameTree::Leaf(path("/10.0.0.1")))]
+            .into_iter().collect();
+        let dtab = dtab![ "/svc" => NameTree::from("/#/k8s") | "/$/inet/fallback";
+                          "/svc/cart" => (W(0.9) * "/$/nil") & (W(0.1) * "/srv/cart");
+                          "/srv" => "~";
+                          "/srv/cart" => NameTree::from("!") | "/$/fail"; ];
+        for p in &["/svc/users", "/svc/orders", "/svc/cart", "/srv/cart", "/other", "/"] {
+            let tree = simulate(&dtab, &path(p), &DelegateOptions::default(), &namer);
+            assert!(tree.errors().is_empty());
+            assert_eq!(tree.path(), &path(p));
+            assert_eq!( Ok(tree.result())
+                      , delegate::delegate(&dtab, &path(p), &DelegateOptions::default(), &namer
+                                          , ()));
+        }
+    }
+
+    #[test]
+    fn partial_resolution() {
+        let dtab = dtab![ "/svc" => NameTree::from("/a") | "/srv";
+                          "/a" => "/b";
+                          "/b" => "/a"; ];
+        assert!(delegate::delegate(&dtab, &path("/svc"), &DelegateOptions::default(), (), ())
+            .is_err());
+        let tree = simulate(&dtab, &path("/svc"), &DelegateOptions::default(), ());
+        assert_eq!(&tree.result().to_string(), "! | /srv");
+        assert_eq!( tree.errors()
+                  , vec![(&path("/a"), &DelegateError::Cycle { cycle: vec![ path("/a")
+                                                                           , path("/b")
+                                                                           , path("/a") ] })]);
+        assert_eq!( &tree.to_string()
+                  , "/svc\n\
+                     \x20 0: /svc => /a | /srv;\n\
+                     \x20   |\n\
+                     \x20     /a\n\
+                     \x20       1: /a => /b;\n\
+                     \x20         /b\n\
+                     \x20           2: /b => /a;\n\
+                     \x20             /a failed: delegation cycle: /a -> /b -> /a\n\
+                     \x20     /srv is a name\n");
+
+        let dtab = dtab!["/svc" => NameTree::from("/a") | "/srv"; "/a" => "a";];
+        let tree = simulate(&dtab, &path("/svc"), &DelegateOptions::default(), ());
+        assert_eq!(&tree.result().to_string(), "! | /srv");
+        assert_eq!( tree.errors().iter().map(|&(_, error)| error.clone()).collect::<Vec<_>>()
+                  , vec![delegate::delegate(&dtab, &path("/a"), &DelegateOptions::default(), (), ())
+                             .unwrap_err()]);
+    }
+
+    #[test]
+    fn unions() {
+        let dtab = dtab!["/svc" => (W(0.75) * "/a") & (W(0.25) * "~");];
+        let tree = simulate(&dtab, &path("/svc"), &DelegateOptions::default(), ());
+        assert_eq!( &tree.to_string()
+                  , "/svc\n  0: /svc => 0.75 * /a & 0.25 * ~;\n    &\n      \
+                     0.75 * /a is a name\n      0.25 * ~\n");
+    }
+
+    #[test]
+    fn limits() {
+        let dtab = dtab![ "/svc" => NameTree::from("/a") | "/b" | "/c";
+                          "/a" => NameTree::from("/x") | "/y";
+                          "/deep" => "/deep/more"; ];
+        let limited = |limits| DelegateOptions { limits };
+        let errors = |tree: &DelegateTree| tree.errors().into_iter()
+            .map(|(path, error)| (path.to_string(), error.clone()))
+            .collect::<Vec<_>>();
+
+        let wide = limited(ResolutionLimits { max_width: Some(2), ..ResolutionLimits::default() });
+        let tree = simulate(&dtab, &path("/svc"), &wide, ());
+        assert_eq!(&tree.result().to_string(), "/x | /y | ! | !");
+        assert_eq!( errors(&tree)
+                  , vec![ ("/b".to_string(), DelegateError::TooWide { path: path("/b"), max: 2 })
+                        , ("/c".to_string(), DelegateError::TooWide { path: path("/c"), max: 2 })
+                        ]);
+
+        let steps = limited(ResolutionLimits { max_steps: Some(3), ..ResolutionLimits::default() });
+        let tree = simulate(&dtab, &path("/svc"), &steps, ());
+        assert_eq!(&tree.result().to_string(), "/x | ! | ! | !");
+        assert_eq!( errors(&tree)[0]
+                  , ("/y".to_string(), DelegateError::TooManySteps { path: path("/y"), max: 3 }));
+
+        let shallow = limited(ResolutionLimits { max_depth: 3, ..ResolutionLimits::default() });
+        let tree = simulate(&dtab, &path("/deep"), &shallow, ());
+        assert_eq!(&tree.result().to_string(), "!");
+        assert_eq!( errors(&tree)
+                  , vec![( "/deep/more/more/more".to_string()
+                         , DelegateError::TooDeep { path: path("/deep/more/more/more"), max: 3 })]);
+        for options in &[wide, steps, shallow] {
+            for p in &["/svc", "/deep"] {
+                let tree = simulate(&dtab, &path(p), options, ());
+                let delegated = delegate::delegate(&dtab, &path(p), options, (), ());
+                assert_eq!( tree.errors().first().map(|&(_, error)| error)
+                          , delegated.err().as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn serialize() {
+        let namer: MockNamer = vec![(path("/#/k8s/users"), NameTree::Leaf(path("/10.0.0.1")))]
+            .into_iter().collect();
+        let dtab = dtab![ "/svc" => (W(0.75) * "/#/k8s") & (W(0.25) * "~");
+                          "/svc/users" => NameTree::from("/a") | "/b" | "!";
+                          "/a" => "/a"; ];
+        let tree = simulate(&dtab, &path("/svc/users"), &DelegateOptions::default(), &namer);
+        assert_eq!( ::serde_json::to_value(&tree).unwrap()
+                  , json!({
+                        "type": "delegate"
+                      , "path": "/svc/users"
+                      , "fraction": 1.0
+                      , "rewrites": [
+                            { "index": 1
+                            , "dentry": { "prefix": "/svc/users", "dst": "/a | /b | !" }
+                            , "tree": { "type": "alt", "trees": [
+                                  { "type": "delegate", "path": "/a", "fraction": 1.0
+                                  , "rewrites": [
+                                      { "index": 2
+                                      , "dentry": { "prefix": "/a", "dst": "/a" }
+                                      , "tree": { "type": "exception", "path": "/a"
+                                                , "fraction": 1.0
+                                                , "error": { "kind": "cycle"
+                                                           , "cycle": ["/a", "/a"]
+                                                           , "message": "delegation cycle: \
+                                                                         /a -> /a" } }
+                                      } ] }
+                                , { "type": "unbound", "path": "/b", "fraction": 1.0 }
+                                , { "type": "fail" } ] }
+                            }
+                          , { "index": 0
+                            , "dentry": { "prefix": "/svc", "dst": "0.75 * /#/k8s & 0.25 * ~" }
+                            , "tree": { "type": "union", "members": [
+                                  { "weight": "0.75", "fraction": 0.75
+                                  , "tree": { "type": "bound", "path": "/#/k8s/users"
+                                            , "fraction": 0.75, "tree": "/10.0.0.1" } }
+                                , { "weight": "0.25", "fraction": 0.25
+                                  , "tree": { "type": "neg" } } ] }
+                            } ]
+                    }));
+    }
+
+    #[test]
+    fn serialize_fractions() {
+        let dtab = dtab![ "/a" => (W(3.0) * "/b") & (W(1.0) * "/c");
+                          "/b" => (W(1.0) * "/d") & (W(1.0) * (NameTree::from("/e") | "/f"));
+                          "/c" => (W(0.0) * "/d") & (W(0.0) * "/e"); ];
+        let tree = simulate(&dtab, &path("/a"), &DelegateOptions::default(), MockNamer::new());
+        let json = ::serde_json::to_value(&tree).unwrap();
+        let fraction = |pointer: &str| json.pointer(pointer).and_then(|f| f.as_f64());
+        let union = "/rewrites/0/tree/members";
+        assert_eq!(fraction("/fraction"), Some(1.0));
+        assert_eq!(fraction(&format!("{}/0/fraction", union)), Some(0.75));
+        assert_eq!(fraction(&format!("{}/1/fraction", union)), Some(0.25));
+        let b = format!("{}/0/tree/rewrites/0", union);
+        assert_eq!(fraction(&format!("{}/tree/members/0/tree/fraction", b)), Some(0.375));
+        assert_eq!(fraction(&format!("{}/tree/members/1/fraction", b)), Some(0.375));
+        assert_eq!( fraction(&format!("{}/tree/members/1/tree/trees/1/fraction", b))
+                  , Some(0.375));
+        // unions whose weights are all zero get no traffic.
+        let c = format!("{}/1/tree/rewrites/0", union);
+        assert_eq!(fraction(&format!("{}/tree/members/0/fraction", c)), Some(0.0));
+        assert_eq!(fraction(&format!("{}/tree/members/1/tree/fraction", c)), Some(0.0));
+
+        // a `Rewritten` on its own is the root of its traffic.
+        let rewritten = match tree {
+            DelegateTree::Delegate { ref rewrites, .. } => &rewrites[0]
+          , _ => panic!("expected a delegation, got {:?}", tree)
+        };
+        assert_eq!( ::serde_json::to_value(rewritten).unwrap().pointer("/tree/members/0/fraction")
+                  , Some(&json!(0.75)));
+    }
+}
@@ -0,0 +1,130 @@
+//! Binding `/$/inet` paths with DNS.
+//!
+//! Finagle's `inet` namer binds paths of the form `/$/inet/<host>/<port>`
+//! to the addresses `host` resolves to. [`DnsNamer`] does the same, using
+//! the system's resolver, so that delegating a path through a dtab can
+//! produce the actual endpoints it would be sent to. Each address is a leaf
+//! of the form `/$/inet/<ip>/<port>`, which [`socket_addr`] converts back
+//! into a `SocketAddr`.
+//!
+//! This module requires the `dns` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::delegate::{self, DelegateOptions};
+//! use dtab::dns::{self, DnsNamer};
+//! use dtab::name::Name;
+//! use dtab::path::Path;
+//!
+//! let dtab = dtab!["/svc/users" => "/$/inet/127.0.0.1/8080";];
+//! let path = Path::read("/svc/users").unwrap();
+//! let tree = delegate::delegate(&dtab, &path, &DelegateOptions::default(), DnsNamer, ())
+//!     .unwrap();
+//! let addrs: Vec<_> = tree.leaves()
+//!     .filter_map(Name::bound)
+//!     .filter_map(dns::socket_addr)
+//!     .collect();
+//! assert_eq!(addrs, vec!["127.0.0.1:8080".parse().unwrap()]);
+//! # }
+//! ```
+//!
+//! [`DnsNamer`]: struct.DnsNamer.html
+//! [`socket_addr`]: fn.socket_addr.html
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::str;
+
+use super::NameTree;
+use super::namer::Namer;
+use super::path::Path;
+
+/// A namer which resolves `/$/inet/<host>/<port>` paths with the system's
+/// resolver.
+///
+/// A path is bound to an evenly weighted union of the addresses its host
+/// resolves to, with the rest of the path after the port appended to each.
+/// A host which doesn't resolve is bound to the negation `~`, and a path
+/// without a host and a valid port fails, with `!`. Paths which don't
+/// begin with `/$/inet` are left to the dtab.
+///
+/// Resolution blocks the calling thread.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DnsNamer;
+
+impl Namer for DnsNamer {
+    fn lookup(&self, path: &Path) -> Option<NameTree<Path>> {
+        let mut labels = path.labels();
+        if labels.next() != Some(b"$") || labels.next() != Some(b"inet") {
+            return None
+        }
+        let host = labels.next().and_then(|host| str::from_utf8(host).ok());
+        let port = labels.next()
+            .and_then(|port| str::from_utf8(port).ok())
+            .and_then(|port| port.parse::<u16>().ok());
+        let (host, port) = match (host, port) {
+            (Some(host), Some(port)) => (host, port)
+          , _ => return Some(NameTree::Fail)
+        };
+        let mut addrs = match (host, port).to_socket_addrs() {
+            Ok(addrs) => addrs.collect::<Vec<_>>()
+          , Err(_) => return Some(NameTree::Neg)
+        };
+        addrs.sort();
+        addrs.dedup();
+        let residual: Vec<&[u8]> = labels.collect();
+        Some(NameTree::even_union(addrs.into_iter().map(|addr| {
+            let mut leaf = vec![ b"$".to_vec(), b"inet".to_vec()
+                               , addr.ip().to_string().into_bytes()
+                               , addr.port().to_string().into_bytes() ];
+            leaf.extend(residual.iter().map(|label| label.to_vec()));
+            NameTree::Leaf(Path::from_labels(leaf))
+        })))
+    }
+}
+
+/// Returns the socket address named by a path of the form
+/// `/$/inet/<ip>/<port>`, ignoring the rest of the path, or `None` if the
+/// path is not of that form.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::dns;
+/// use dtab::path::Path;
+///
+/// let path = Path::read("/$/inet/::1/4140/users").unwrap();
+/// assert_eq!(dns::socket_addr(&path), Some("[::1]:4140".parse().unwrap()));
+/// assert_eq!(dns::socket_addr(&Path::read("/$/inet/localhost/4140").unwrap()), None);
+/// ```
+pub fn socket_addr(path: &Path) -> Option<SocketAddr> {
+    let mut labels = path.labels();
+    if labels.next() != Some(b"$") || labels.next() != Some(b"inet") {
+        return None
+    }
+    let ip = str::from_utf8(labels.next()?).ok()?.parse::<IpAddr>().ok()?;
+    let port = str::from_utf8(labels.next()?).ok()?.parse::<u16>().ok()?;
+    Some(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound(s: &str) -> Option<String> {
+        DnsNamer.lookup(&Path::read(s).unwrap()).map(|tree| tree.to_string())
+    }
+
+    #[test]
+    fn dns_namer() {
+        assert_eq!(bound("/$/inet/127.0.0.1/8080"), Some("/$/inet/127.0.0.1/8080".to_string()));
+        assert_eq!( bound("/$/inet/::1/8080/users/get")
+                  , Some("/$/inet/::1/8080/users/get".to_string()));
+        assert_eq!(bound("/$/inet/127.0.0.1/http"), Some("!".to_string()));
+        assert_eq!(bound("/$/inet/127.0.0.1"), Some("!".to_string()));
+        assert_eq!(bound("/$/inet"), Some("!".to_string()));
+        assert_eq!(bound("/$/nil"), None);
+        assert_eq!(bound("/#/io.l5d.k8s/default"), None);
+    }
+}
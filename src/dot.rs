@@ -0,0 +1,296 @@
+//! Drawing name trees and delegations as Graphviz graphs.
+//!
+//! [`name_tree`] writes a name tree in Graphviz's DOT language, and
+//! [`trace`] writes a [`DelegationTrace`], showing how a path fans out
+//! through the dentries that rewrote it, the same picture linkerd's dtab
+//! playground draws. The output can be rendered with `dot -Tsvg`.
+//!
+//! In both, alternations are drawn as `|` nodes, whose edges are numbered
+//! in the order the alternatives are tried, and unions as `&` nodes, whose
+//! edges are labelled with each member's weight. Chains of alternations,
+//! such as `/a | /b | /c`, are drawn as a single node. Paths are drawn as
+//! boxes, and `~`, `!` and `$` as plain text.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::NameTree;
+//! use dtab::dot;
+//!
+//! let tree = NameTree::from("/srv/users-v2") | "/srv/users";
+//! assert_eq!( dot::name_tree(&tree)
+//!           , "digraph {\n\
+//!              \x20 n0 [label=\"|\", shape=circle];\n\
+//!              \x20 n1 [label=\"/srv/users-v2\", shape=box];\n\
+//!              \x20 n0 -> n1 [label=\"1\"];\n\
+//!              \x20 n2 [label=\"/srv/users\", shape=box];\n\
+//!              \x20 n0 -> n2 [label=\"2\"];\n\
+//!              }\n");
+//! ```
+//!
+//! [`name_tree`]: fn.name_tree.html
+//! [`trace`]: fn.trace.html
+//! [`DelegationTrace`]: ../delegate/struct.DelegationTrace.html
+use std::fmt::{self, Write};
+
+use super::NameTree;
+use super::delegate::{DelegationTrace, Step};
+use super::path::Path;
+
+/// Write `tree` as a DOT graph.
+pub fn name_tree<T>(tree: &NameTree<T>) -> String
+where T: fmt::Display {
+    let mut graph = Graph::new();
+    graph.tree(tree, &mut |graph, leaf| graph.node(leaf, "shape=box"));
+    graph.finish()
+}
+
+/// Write `trace` as a DOT graph.
+///
+/// Each path which was looked up is drawn as a box, with an edge labelled
+/// with each dentry which rewrote it to the tree it was rewritten to, whose
+/// paths are drawn in turn. A path which no dentry rewrote, and so is a
+/// name in the result, is drawn in bold, and one which a namer bound has
+/// an edge labelled `bound` to the tree of names it was bound to. If
+/// delegation failed, the graph is labelled with the error.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::dot;
+/// use dtab::path::Path;
+///
+/// let dtab = dtab!["/svc" => "/srv";];
+/// let trace = dtab.trace(&Path::read("/svc/users").unwrap());
+/// assert_eq!( dot::trace(&trace)
+///           , "digraph {\n\
+///              \x20 n0 [label=\"/svc/users\", shape=box];\n\
+///              \x20 n1 [label=\"/srv/users\", shape=box, style=bold];\n\
+///              \x20 n0 -> n1 [label=\"0: /svc => /srv;\"];\n\
+///              }\n");
+/// # }
+/// ```
+pub fn trace(trace: &DelegationTrace) -> String {
+    let mut graph = Graph::new();
+    let mut steps = trace.steps.iter().peekable();
+    match steps.next() {
+        Some(step) => graph.step(step, &mut steps)
+      , None => graph.node(&trace.path, "shape=box")
+    };
+    if let Err(ref error) = trace.result {
+        writeln!(graph.out, "  label=\"error: {}\";", Escaped(error)).expect("write to string");
+    }
+    graph.finish()
+}
+
+/// A DOT graph being written.
+struct Graph {
+    out: String
+  , /// The number of nodes written so far.
+    nodes: usize
+}
+
+type Steps<'a> = ::std::iter::Peekable<::std::slice::Iter<'a, Step>>;
+
+impl Graph {
+    fn new() -> Self {
+        Graph { out: String::from("digraph {\n"), nodes: 0 }
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("}\n");
+        self.out
+    }
+
+    /// Write a node labelled `label`, with `attrs`, returning its id.
+    fn node<L>(&mut self, label: L, attrs: &str) -> usize
+    where L: fmt::Display {
+        let id = self.nodes;
+        self.nodes += 1;
+        writeln!(self.out, "  n{} [label=\"{}\", {}];", id, Escaped(label), attrs)
+            .expect("write to string");
+        id
+    }
+
+    /// Write an edge from `from` to `to`, labelled `label`.
+    fn edge<L>(&mut self, from: usize, to: usize, label: L)
+    where L: fmt::Display {
+        writeln!(self.out, "  n{} -> n{} [label=\"{}\"];", from, to, Escaped(label))
+            .expect("write to string");
+    }
+
+    /// Write `tree`, using `leaf` to write each of its leaves, returning the
+    /// id of its root.
+    fn tree<T, F>(&mut self, tree: &NameTree<T>, leaf: &mut F) -> usize
+    where T: fmt::Display
+        , F: FnMut(&mut Graph, &T) -> usize {
+        match *tree {
+            NameTree::Leaf(ref value) => leaf(self, value)
+          , NameTree::Alt(..) => {
+                let id = self.node("|", "shape=circle");
+                let mut alts = Vec::new();
+                let mut stack = vec![tree];
+                while let Some(tree) = stack.pop() {
+                    match *tree {
+                        NameTree::Alt(ref left, ref right) => {
+                            stack.push(right);
+                            stack.push(left);
+                        }
+                      , _ => alts.push(tree)
+                    }
+                }
+                for (i, alt) in alts.into_iter().enumerate() {
+                    let child = self.tree(alt, leaf);
+                    self.edge(id, child, i + 1);
+                }
+                id
+            }
+          , NameTree::Union(ref left, ref right) => {
+                let id = self.node("&", "shape=circle");
+                for member in &[left, right] {
+                    let child = self.tree(member.tree(), leaf);
+                    self.edge(id, child, member.weight());
+                }
+                id
+            }
+          , NameTree::Neg => self.node("~", "shape=plaintext")
+          , NameTree::Fail => self.node("!", "shape=plaintext")
+          , NameTree::Empty => self.node("$", "shape=plaintext")
+        }
+    }
+
+    /// Write `step`, and the steps which delegated each path it was
+    /// rewritten to, which follow it in `steps`, returning its id.
+    fn step(&mut self, step: &Step, steps: &mut Steps) -> usize {
+        if step.rewrites.is_empty() && step.bound.is_none() {
+            return self.node(&step.path, "shape=box, style=bold")
+        }
+        let id = self.node(&step.path, "shape=box");
+        if let Some(ref bound) = step.bound {
+            let child = self.tree(bound, &mut |graph, name| {
+                graph.node(name, "shape=box, style=bold")
+            });
+            self.edge(id, child, "bound");
+            return id
+        }
+        let mut delegated = |graph: &mut Graph, path: &Path| {
+            // the path is delegated by the next step, unless delegation
+            // failed before reaching it.
+            let is_next = steps.peek()
+                .is_some_and(|next| next.depth == step.depth + 1 && next.path == *path);
+            match steps.next_if(|_| is_next) {
+                Some(next) => graph.step(next, steps)
+              , None => graph.node(path, "shape=box, style=dashed")
+            }
+        };
+        for rewrite in &step.rewrites {
+            let child = self.tree(&rewrite.tree, &mut delegated);
+            self.edge(id, child, format_args!("{}: {}", rewrite.index, rewrite.dentry));
+        }
+        id
+    }
+}
+
+/// Escapes `"`, `\` and newlines in a value's `Display` form, so that it can be
+/// written in a quoted DOT string.
+struct Escaped<T>(T);
+
+impl<T> fmt::Display for Escaped<T>
+where T: fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = self.0.to_string();
+        for ch in text.chars() {
+            match ch {
+                '"' | '\\' => write!(f, "\\{}", ch)?
+              , '\n' => f.write_str("\\n")?
+              , ch => f.write_char(ch)?
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nametree::W;
+    use Dtab;
+    use delegate::{self, DelegateOptions};
+    use namer::MockNamer;
+
+    fn path(s: &str) -> Path { Path::read(s).unwrap() }
+
+    #[test]
+    fn name_trees() {
+        let tree = NameTree::from("/a") | ((W(0.75) * "/b") & (W(0.25) * "~")) | "!";
+        assert_eq!( name_tree(&tree)
+                  , "digraph {\n  \
+                     n0 [label=\"|\", shape=circle];\n  \
+                     n1 [label=\"/a\", shape=box];\n  \
+                     n0 -> n1 [label=\"1\"];\n  \
+                     n2 [label=\"&\", shape=circle];\n  \
+                     n3 [label=\"/b\", shape=box];\n  \
+                     n2 -> n3 [label=\"0.75\"];\n  \
+                     n4 [label=\"~\", shape=plaintext];\n  \
+                     n2 -> n4 [label=\"0.25\"];\n  \
+                     n0 -> n2 [label=\"2\"];\n  \
+                     n5 [label=\"!\", shape=plaintext];\n  \
+                     n0 -> n5 [label=\"3\"];\n\
+                     }\n");
+        assert_eq!(&Escaped("/a\\x22\"\n").to_string(), "/a\\\\x22\\\"\\n");
+    }
+
+    #[test]
+    fn traces() {
+        let dtab = dtab![ "/srv" => "/#/k8s";
+                          "/svc" => "/srv";
+                          "/svc/users" => NameTree::from("~") | "/srv/users-v2"; ];
+        let trace = dtab.trace(&path("/svc/users"));
+        assert_eq!( super::trace(&trace)
+                  , "digraph {\n  \
+                     n0 [label=\"/svc/users\", shape=box];\n  \
+                     n1 [label=\"|\", shape=circle];\n  \
+                     n2 [label=\"~\", shape=plaintext];\n  \
+                     n1 -> n2 [label=\"1\"];\n  \
+                     n3 [label=\"/srv/users-v2\", shape=box];\n  \
+                     n4 [label=\"/#/k8s/users-v2\", shape=box, style=bold];\n  \
+                     n3 -> n4 [label=\"0: /srv => /#/k8s;\"];\n  \
+                     n1 -> n3 [label=\"2\"];\n  \
+                     n0 -> n1 [label=\"2: /svc/users => ~ | /srv/users-v2;\"];\n  \
+                     n5 [label=\"/srv/users\", shape=box];\n  \
+                     n6 [label=\"/#/k8s/users\", shape=box, style=bold];\n  \
+                     n5 -> n6 [label=\"0: /srv => /#/k8s;\"];\n  \
+                     n0 -> n5 [label=\"1: /svc => /srv;\"];\n\
+                     }\n");
+
+        let trace = dtab!["/a" => "/b"; "/b" => "/a";].trace(&path("/a"));
+        assert_eq!( super::trace(&trace)
+                  , "digraph {\n  \
+                     n0 [label=\"/a\", shape=box];\n  \
+                     n1 [label=\"/b\", shape=box];\n  \
+                     n2 [label=\"/a\", shape=box];\n  \
+                     n3 [label=\"/b\", shape=box, style=dashed];\n  \
+                     n2 -> n3 [label=\"0: /a => /b;\"];\n  \
+                     n1 -> n2 [label=\"1: /b => /a;\"];\n  \
+                     n0 -> n1 [label=\"0: /a => /b;\"];\n  \
+                     label=\"error: delegation cycle: /a -> /b -> /a\";\n\
+                     }\n");
+        assert_eq!( super::trace(&Dtab::new().trace(&Path::empty()))
+                  , "digraph {\n  n0 [label=\"/\", shape=box, style=bold];\n}\n");
+
+        let namer: MockNamer = vec![(path("/#/k8s"), NameTree::Leaf(path("/10.0.0.1")))]
+            .into_iter().collect();
+        let trace = delegate::trace( &dtab!["/svc" => "/#/k8s";], &path("/svc/users")
+                                   , &DelegateOptions::default(), &namer, ());
+        assert_eq!( super::trace(&trace)
+                  , "digraph {\n  \
+                     n0 [label=\"/svc/users\", shape=box];\n  \
+                     n1 [label=\"/#/k8s/users\", shape=box];\n  \
+                     n2 [label=\"/10.0.0.1/users\", shape=box, style=bold];\n  \
+                     n1 -> n2 [label=\"bound\"];\n  \
+                     n0 -> n1 [label=\"0: /svc => /#/k8s;\"];\n\
+                     }\n");
+    }
+}
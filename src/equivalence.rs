@@ -0,0 +1,220 @@
+//! Checking that two dtabs behave the same way.
+//!
+//! Before replacing a dtab with a rewritten one, it's worth checking that
+//! the rewrite routes requests the same way. [`behaves_like`] delegates
+//! every path in a corpus through both dtabs, and reports each path whose
+//! result differs, along with the dentry on each side that the path
+//! matched.
+//!
+//! Paths are delegated with [`Dtab::delegate`]. For each path which
+//! delegates differently, both delegations are traced to find the first path
+//! looked up differently along the way, which may be the original path or
+//! one it was rewritten to, and the entry which won that lookup on each
+//! side.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::path::Path;
+//!
+//! let old = dtab!["/svc" => "/srv"; "/srv/admin" => "/internal";];
+//! let new = dtab!["/svc" => "/srv";];
+//! let paths = vec![ Path::read("/svc/users").unwrap()
+//!                 , Path::read("/svc/admin").unwrap() ];
+//!
+//! let report = old.behaves_like(&new, &paths);
+//! assert!(!report.is_equivalent());
+//! let difference = &report.differences[0];
+//! assert_eq!(difference.path, paths[1]);
+//! assert_eq!(difference.diverged_at, Path::read("/srv/admin").unwrap());
+//! assert_eq!(difference.left_dentry, Some(1));
+//! assert_eq!(difference.right_dentry, None);
+//! # }
+//! ```
+//!
+//! [`behaves_like`]: fn.behaves_like.html
+//! [`Dtab::delegate`]: ../struct.Dtab.html#method.delegate
+use std::fmt;
+
+use super::{Dtab, NameTree};
+use super::delegate::{self, DelegateError, Step};
+use super::name::Name;
+use super::path::Path;
+
+/// The result of comparing two dtabs over a corpus of paths.
+///
+/// Reports are serialized as objects with the fields `paths` and
+/// `differences`. Each difference is an object with the fields `path`,
+/// `left`, `right`, `diverged_at`, `left_dentry` and `right_dentry`, where
+/// paths are strings, the results `left` and `right` are serialized as a
+/// [`DelegationTrace`]'s result is, and the dentries are indices, or
+/// `null`.
+///
+/// [`DelegationTrace`]: ../delegate/struct.DelegationTrace.html
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EquivalenceReport {
+    /// The number of paths compared.
+    pub paths: usize
+  , /// Every path whose result differs, in corpus order.
+    pub differences: Vec<Difference>
+}
+
+/// A path which delegates differently under two dtabs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Difference {
+    /// The path.
+    #[serde(serialize_with = "delegate::serialize_display")]
+    pub path: Path
+  , /// The result of delegating the path with the left-hand dtab.
+    #[serde(serialize_with = "delegate::serialize_result")]
+    pub left: Result<NameTree<Name>, DelegateError>
+  , /// The result of delegating the path with the right-hand dtab.
+    #[serde(serialize_with = "delegate::serialize_result")]
+    pub right: Result<NameTree<Name>, DelegateError>
+  , /// The first path, in the order delegation looks paths up, whose lookup
+    /// differs between the two dtabs.
+    #[serde(serialize_with = "delegate::serialize_display")]
+    pub diverged_at: Path
+  , /// The index of the highest-precedence dentry in the left-hand dtab
+    /// which matches `diverged_at`, if any.
+    pub left_dentry: Option<usize>
+  , /// The index of the highest-precedence dentry in the right-hand dtab
+    /// which matches `diverged_at`, if any.
+    pub right_dentry: Option<usize>
+}
+
+impl EquivalenceReport {
+    /// Returns true if every path delegated to the same result.
+    #[inline] pub fn is_equivalent(&self) -> bool { self.differences.is_empty() }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = |result: &Result<NameTree<Name>, DelegateError>| match *result {
+            Ok(ref tree) => format!("`{}`", tree)
+          , Err(ref error) => format!("error: {}", error)
+        };
+        let entry = |dentry: Option<usize>| match dentry {
+            Some(i) => format!("entry {}", i)
+          , None => "no entry".to_string()
+        };
+        write!( f, "{}: {} vs. {}; {} matched {} vs. {}"
+              , self.path, result(&self.left), result(&self.right), self.diverged_at
+              , entry(self.left_dentry), entry(self.right_dentry))
+    }
+}
+
+impl fmt::Display for EquivalenceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!( f, "{} of {} paths differ"
+                , self.differences.len(), self.paths)?;
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the result of delegating each of `paths` under `left` and
+/// `right`.
+pub fn behaves_like(left: &Dtab, right: &Dtab, paths: &[Path]) -> EquivalenceReport {
+    let differences = paths.iter()
+        .filter_map(|path| {
+            let (left_result, right_result) = (left.delegate(path), right.delegate(path));
+            if left_result == right_result {
+                return None
+            }
+            let diverged_at = diverged_at(&left.trace(path).steps, &right.trace(path).steps)
+                .unwrap_or(path)
+                .clone();
+            Some(Difference { path: path.clone()
+                            , left: left_result
+                            , right: right_result
+                            , left_dentry: left.winning_entry(&diverged_at).map(|(i, _)| i)
+                            , right_dentry: right.winning_entry(&diverged_at).map(|(i, _)| i)
+                            , diverged_at
+                            })
+        })
+        .collect();
+    EquivalenceReport { paths: paths.len(), differences }
+}
+
+/// Returns the path of the first lookup which differs between two traces:
+/// one which looked up a different path, rewrote it to different trees, or
+/// bound it differently.
+fn diverged_at<'a>(left: &'a [Step], right: &'a [Step]) -> Option<&'a Path> {
+    let same = |left: &Step, right: &Step| {
+        left.path == right.path && left.bound == right.bound
+            && left.rewrites.len() == right.rewrites.len()
+            && left.rewrites.iter().zip(&right.rewrites).all(|(l, r)| l.tree == r.tree)
+    };
+    left.iter().zip(right)
+        .find(|&(left, right)| !same(left, right))
+        .map(|(left, _)| &left.path)
+        .or_else(|| left.get(right.len()).or_else(|| right.get(left.len())).map(|step| &step.path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<Path> {
+        paths.iter().map(|path| Path::read(path).unwrap()).collect()
+    }
+
+    #[test]
+    fn report() {
+        let left = dtab!["/svc" => "/srv"; "/svc/*/v2" => "/canary";];
+        let right = dtab!["/svc/*/v2" => "/canary"; "/svc" => "/srv";];
+        let paths = paths(&["/svc/a", "/svc/a/v2", "/other"]);
+        let report = behaves_like(&left, &right, &paths);
+        assert_eq!( &report.to_string()
+                  , "1 of 3 paths differ\n  \
+                     /svc/a/v2: `/canary | /srv/a/v2` vs. `/srv/a/v2 | /canary`; \
+                     /svc/a/v2 matched entry 1 vs. entry 1\n");
+        assert!(behaves_like(&left, &left, &paths).is_equivalent());
+        let empty = behaves_like(&left, &Dtab::new(), &paths[..1]);
+        assert_eq!(empty.differences[0].right_dentry, None);
+    }
+
+    #[test]
+    fn reports_where_delegation_diverged() {
+        let left = dtab!["/svc" => "/srv"; "/srv" => "/#/io.l5d.k8s/http";];
+        let right = dtab![ "/svc" => "/srv"; "/srv" => "/#/io.l5d.k8s/http";
+                           "/srv/users" => "/svc/users"; ];
+        let paths = paths(&["/svc/users", "/svc/admin"]);
+        let report = behaves_like(&left, &right, &paths);
+        assert_eq!(report.differences.len(), 1);
+        let difference = &report.differences[0];
+        assert_eq!(difference.diverged_at, Path::read("/srv/users").unwrap());
+        assert_eq!((difference.left_dentry, difference.right_dentry), (Some(1), Some(2)));
+        assert!(difference.left.is_ok());
+        match difference.right {
+            Err(DelegateError::Cycle { .. }) => {}
+          , ref other => panic!("expected a cycle, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn serialize_report() {
+        let left = dtab!["/svc" => "/srv"; "/srv/admin" => "/srv/admin";];
+        let right = dtab!["/svc" => "/srv";];
+        let report = behaves_like(&left, &right, &paths(&["/svc/users", "/svc/admin"]));
+        assert_eq!( ::serde_json::to_value(&report).unwrap()
+                  , json!({
+                        "paths": 2
+                      , "differences": [
+                            { "path": "/svc/admin"
+                            , "left": { "error": { "kind": "cycle"
+                                                 , "cycle": ["/srv/admin", "/srv/admin"]
+                                                 , "message": "delegation cycle: \
+                                                               /srv/admin -> /srv/admin" } }
+                            , "right": { "tree": "/srv/admin" }
+                            , "diverged_at": "/srv/admin"
+                            , "left_dentry": 1
+                            , "right_dentry": null } ]
+                    }));
+    }
+}
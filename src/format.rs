@@ -0,0 +1,191 @@
+//! Formatting dtab source files.
+//!
+//! Where `Dtab`'s `Display` implementation writes one dentry per line,
+//! [`format`] rewrites a dtab's source text while keeping its layout
+//! recognizable: groups of dentries separated by blank lines stay separate,
+//! and the arrows within each group can be aligned. Spacing within each
+//! dentry is normalized to the canonical form.
+//!
+//! Comments are preserved. A comment on the same line as the end of a
+//! dentry stays at the end of that line, and every other comment is written
+//! on a line of its own. Comments within a dentry are moved to the lines
+//! before it.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::format::{self, FormatOptions};
+//!
+//! let source = "# services\n\
+//!               /svc=>/srv;/svc/users  =>  /srv/users|/legacy; # until v2\n\n\n\
+//!               /srv=>/#/io.l5d.fs";
+//! assert_eq!( format::format(source, &FormatOptions::default()).unwrap()
+//!           , "# services\n\
+//!              /svc       => /srv;\n\
+//!              /svc/users => /srv/users | /legacy; # until v2\n\
+//!              \n\
+//!              /srv => /#/io.l5d.fs;\n");
+//! ```
+//!
+//! [`format`]: fn.format.html
+use super::parse::{self, ParseError, ParseOptions, Span};
+
+/// Options controlling the formatter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FormatOptions {
+    /// Options used to parse the source.
+    pub parse: ParseOptions
+  , /// Whether to align the arrows of every dentry in a group.
+    pub align_arrows: bool
+  , /// Whether to keep blank lines between groups of dentries. At most one
+    /// blank line is kept between any two dentries.
+    pub blank_lines: bool
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions { parse: ParseOptions::default()
+                      , align_arrows: true
+                      , blank_lines: true
+                      }
+    }
+}
+
+/// Format dtab source text.
+///
+/// Returns an error if the source is not a valid dtab.
+pub fn format(source: &str, options: &FormatOptions) -> Result<String, ParseError> {
+    let dentries = parse::spanned_dtab(source, &options.parse)?;
+
+    // Every line of output, in source order, with the span of source it
+    // was formatted from, which is used to find blank lines between them.
+    let mut lines: Vec<(Span, Line)> = Vec::new();
+    let mut comments = parse::comments(source).into_iter().peekable();
+    for (i, spanned) in dentries.iter().enumerate() {
+        let span = spanned.span;
+        while let Some(comment) = comments.next_if(|comment| comment.start < span.end) {
+            let at = if comment.start < span.start { comment } else {
+                Span { start: span.start, end: span.start }
+            };
+            lines.push((at, Line::Comment(comment)));
+        }
+        let next = dentries.get(i + 1).map_or(source.len(), |next| next.span.start);
+        let trailing = comments.next_if(|comment| comment.start < next &&
+            !source[span.end..comment.start].contains('\n'));
+        let end = trailing.map_or(span.end, |comment| comment.end);
+        lines.push((Span { start: span.start, end }, Line::Dentry(i, trailing)));
+    }
+    lines.extend(comments.map(|comment| (comment, Line::Comment(comment))));
+
+    let mut groups: Vec<&[(Span, Line)]> = Vec::new();
+    let mut start = 0;
+    for i in 1..lines.len() {
+        let gap = &source[lines[i - 1].0.end..lines[i].0.start.max(lines[i - 1].0.end)];
+        if options.blank_lines && gap.matches('\n').count() > 1 {
+            groups.push(&lines[start..i]);
+            start = i;
+        }
+    }
+    if start < lines.len() {
+        groups.push(&lines[start..]);
+    }
+
+    let mut output = String::new();
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+        let width = if options.align_arrows {
+            group.iter()
+                .filter_map(|(_, line)| match *line {
+                    Line::Dentry(i, _) => Some(dentries[i].dentry.prefix().to_string().len())
+                  , Line::Comment(_) => None
+                })
+                .max()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        for (_, line) in group.iter() {
+            match *line {
+                Line::Comment(comment) => {
+                    output.push_str(&source[comment.start..comment.end]);
+                }
+              , Line::Dentry(i, trailing) => {
+                    let dentry = &dentries[i].dentry;
+                    output.push_str(&format!( "{:width$} => {};"
+                                            , dentry.prefix().to_string(), dentry.dst()
+                                            , width = width));
+                    if let Some(comment) = trailing {
+                        output.push(' ');
+                        output.push_str(&source[comment.start..comment.end]);
+                    }
+                }
+            }
+            output.push('\n');
+        }
+    }
+    Ok(output)
+}
+
+/// A line of formatted output.
+enum Line {
+    /// A comment on a line of its own.
+    Comment(Span)
+  , /// A dentry, by index, and the comment at the end of its line, if any.
+    Dentry(usize, Option<Span>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        let source = "\n\n/a=>/b;\n/long/prefix=>~;\n \n/c=>/d;\n";
+        let unaligned = FormatOptions { align_arrows: false, ..FormatOptions::default() };
+        assert_eq!( format(source, &unaligned).unwrap()
+                  , "/a => /b;\n/long/prefix => ~;\n\n/c => /d;\n");
+        let ungrouped = FormatOptions { blank_lines: false, ..FormatOptions::default() };
+        assert_eq!( format(source, &ungrouped).unwrap()
+                  , "/a           => /b;\n\
+                     /long/prefix => ~;\n\
+                     /c           => /d;\n");
+        assert_eq!(format("", &FormatOptions::default()).unwrap(), "");
+        assert!(format("/a => ", &FormatOptions::default()).is_err());
+
+        let formatted = format(source, &FormatOptions::default()).unwrap();
+        assert_eq!(format(&formatted, &FormatOptions::default()).unwrap(), formatted);
+    }
+
+    #[test]
+    fn comments() {
+        let source = "# header\n\n\
+                      /a=>/b;/c=>/d; # on c\n\
+                      # before e\n\
+                      /e => # inside e\n  /f;\n\
+                      \n# trailer\n";
+        let formatted = format(source, &FormatOptions::default()).unwrap();
+        assert_eq!( formatted
+                  , "# header\n\
+                     \n\
+                     /a => /b;\n\
+                     /c => /d; # on c\n\
+                     # before e\n\
+                     # inside e\n\
+                     /e => /f;\n\
+                     \n\
+                     # trailer\n");
+        assert_eq!(format(&formatted, &FormatOptions::default()).unwrap(), formatted);
+        assert_eq!(format("# only\n", &FormatOptions::default()).unwrap(), "# only\n");
+    }
+
+    #[test]
+    fn stray_semicolons() {
+        let source = ";\n/a=>/b;;\n\n# on c\n/c=>/d; ;\n;";
+        assert!(format(source, &FormatOptions::default()).is_err());
+        let lenient = FormatOptions { parse: ParseOptions::lenient(), ..FormatOptions::default() };
+        assert_eq!( format(source, &lenient).unwrap()
+                  , "/a => /b;\n\n# on c\n/c => /d;\n");
+    }
+}
@@ -0,0 +1,222 @@
+//! Random dtabs and path corpora, for benchmarks and load tests.
+//!
+//! A [`Generator`] produces valid dtabs of a configurable size and shape,
+//! and request paths which match their prefixes. Generation is driven by a
+//! small deterministic pseudo-random number generator, so the same
+//! [`GenConfig`] always produces the same dtabs and paths, making
+//! benchmarks reproducible.
+//!
+//! Labels are drawn from a fixed vocabulary of random words, so that
+//! destination paths frequently match the prefixes of other dentries, as
+//! they do in real dtabs.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::generate::{GenConfig, Generator};
+//!
+//! let config = GenConfig { dentries: 1_000, ..GenConfig::default() };
+//! let mut generator = Generator::new(config);
+//! let dtab = generator.dtab();
+//! assert_eq!(dtab.len(), 1_000);
+//!
+//! let paths = generator.paths(&dtab, 10_000);
+//! assert_eq!(paths.len(), 10_000);
+//! ```
+//!
+//! [`Generator`]: struct.Generator.html
+//! [`GenConfig`]: struct.GenConfig.html
+use super::{Dentry, Dtab, NameTree};
+use super::path::Path;
+use super::prefix::{Elem, Prefix};
+use super::weight::Weight;
+
+/// Configures the size and shape of generated dtabs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenConfig {
+    /// The number of dentries in each generated dtab.
+    pub dentries: usize
+  , /// The length of each label, in bytes.
+    pub label_len: usize
+  , /// The number of distinct labels to draw from.
+    pub vocabulary: usize
+  , /// The maximum number of labels in a prefix, or in a destination path.
+    pub max_labels: usize
+  , /// The maximum depth of a destination tree. Trees of depth zero are
+    /// single paths.
+    pub tree_depth: usize
+  , /// The probability that each element of a prefix after the first is a
+    /// wildcard, between zero and one.
+    pub wildcard_rate: f64
+  , /// The seed for the pseudo-random number generator.
+    pub seed: u64
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        GenConfig { dentries: 100
+                  , label_len: 8
+                  , vocabulary: 64
+                  , max_labels: 4
+                  , tree_depth: 2
+                  , wildcard_rate: 0.1
+                  , seed: 0x5eed
+                  }
+    }
+}
+
+/// Generates random dtabs and paths.
+#[derive(Clone, Debug)]
+pub struct Generator {
+    config: GenConfig
+  , words: Vec<String>
+  , state: u64
+}
+
+impl Generator {
+    /// Returns a new generator with the given configuration.
+    ///
+    /// # Panics
+    ///
+    /// If `label_len`, `vocabulary` or `max_labels` is zero.
+    pub fn new(config: GenConfig) -> Self {
+        assert!( config.label_len > 0 && config.vocabulary > 0 && config.max_labels > 0
+               , "label_len, vocabulary and max_labels must be non-zero");
+        let mut generator = Generator { state: config.seed
+                                      , words: Vec::with_capacity(config.vocabulary)
+                                      , config
+                                      };
+        for _ in 0..generator.config.vocabulary {
+            let word = (0..generator.config.label_len)
+                .map(|_| (b'a' + generator.below(26) as u8) as char)
+                .collect();
+            generator.words.push(word);
+        }
+        generator
+    }
+
+    /// Returns this generator's configuration.
+    #[inline] pub fn config(&self) -> &GenConfig { &self.config }
+
+    /// Generate a dtab.
+    pub fn dtab(&mut self) -> Dtab {
+        (0..self.config.dentries).map(|_| self.dentry()).collect()
+    }
+
+    /// Generate a dentry.
+    pub fn dentry(&mut self) -> Dentry {
+        let len = 1 + self.below(self.config.max_labels);
+        let elems = (0..len)
+            .map(|i| if i > 0 && self.chance(self.config.wildcard_rate) {
+                Elem::AnyElem
+            } else {
+                Elem::Label(self.word().as_bytes().to_vec())
+            })
+            .collect();
+        let prefix = Prefix::new(elems).expect("generated labels are never empty");
+        let depth = self.config.tree_depth;
+        Dentry::new(prefix, self.tree(depth))
+    }
+
+    /// Generate `n` paths, each of which matches the prefix of a dentry in
+    /// `dtab`, followed by up to `max_labels` further labels.
+    ///
+    /// If `dtab` is empty, the paths are entirely random.
+    pub fn paths(&mut self, dtab: &Dtab, n: usize) -> Vec<Path> {
+        (0..n).map(|_| self.path(dtab)).collect()
+    }
+
+    /// Generate a path which matches the prefix of a dentry in `dtab`.
+    pub fn path(&mut self, dtab: &Dtab) -> Path {
+        let mut path = String::new();
+        if !dtab.is_empty() {
+            let dentry = &dtab[self.below(dtab.len())];
+            for elem in dentry.prefix().elems() {
+                path.push('/');
+                match *elem {
+                    Elem::Label(ref label) =>
+                        path.push_str(&String::from_utf8_lossy(label))
+                  , Elem::AnyElem => path.push_str(self.word())
+                }
+            }
+        }
+        let residual = self.below(self.config.max_labels + 1);
+        for _ in 0..residual {
+            path.push('/');
+            path.push_str(self.word());
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        Path::read(&path).expect("generated paths are always valid")
+    }
+
+    fn tree(&mut self, depth: usize) -> NameTree<String> {
+        if depth == 0 || self.below(3) == 0 {
+            let len = 1 + self.below(self.config.max_labels);
+            let mut leaf = String::new();
+            for _ in 0..len {
+                leaf.push('/');
+                leaf.push_str(self.word());
+            }
+            return NameTree::Leaf(leaf)
+        }
+        let left = self.tree(depth - 1);
+        let right = self.tree(depth - 1);
+        if self.below(2) == 0 {
+            left | right
+        } else {
+            let left_weight = Weight::per_mille(1 + self.below(1000) as u64);
+            let right_weight = Weight::per_mille(1 + self.below(1000) as u64);
+            NameTree::Union(left.weighted(left_weight), right.weighted(right_weight))
+        }
+    }
+
+    fn word(&mut self) -> &str {
+        let i = self.below(self.words.len());
+        &self.words[i]
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// SplitMix64.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::{self, ParseOptions};
+
+    #[test]
+    fn deterministic_and_valid() {
+        let config = GenConfig { dentries: 200, wildcard_rate: 0.5, ..GenConfig::default() };
+        let dtab = Generator::new(config.clone()).dtab();
+        assert_eq!(dtab, Generator::new(config.clone()).dtab());
+        assert!(dtab.iter().any(|dentry| dentry.prefix().has_wildcards()));
+
+        let parsed = parse::dtab(&dtab.to_string(), &ParseOptions::default()).unwrap();
+        assert_eq!(parsed.to_string(), dtab.to_string());
+
+        let mut generator = Generator::new(GenConfig { seed: 1, ..config });
+        let paths = generator.paths(&dtab, 100);
+        assert_ne!(dtab, generator.dtab());
+        assert_eq!(generator.paths(&Dtab::new(), 50).len(), 50);
+        for path in paths {
+            assert!( dtab.iter().any(|dentry| dentry.prefix().covers(&Prefix::from(&path)))
+                   , "{} matches no prefix", path);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Golden-file behavior tests for dtabs.
+//!
+//! A fixture file lists request paths, and the name tree each path is
+//! expected to delegate to under a particular dtab, one per line:
+//!
+//! ```text
+//! # routing for the users service
+//! /svc/users => /#/io.l5d.k8s/default/http/users
+//! /svc/users/canary => /#/io.l5d.k8s/canary/http/users | /#/io.l5d.k8s/default/http/users
+//! ```
+//!
+//! Blank lines, and lines beginning with `#`, are ignored. Keeping a fixture
+//! file alongside each dtab file gives a regression suite for routing: any
+//! change to the dtab that changes where one of the listed paths goes will
+//! be reported as a [`Failure`].
+//!
+//! Paths are delegated with [`Dtab::delegate`] by [`Fixtures::check`], or
+//! with a function supplied to [`Fixtures::check_with`], such as one which
+//! binds paths with a [`Namer`].
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::golden::Fixtures;
+//!
+//! let fixtures: Fixtures = "
+//!     /svc/users => /srv/users
+//!     /svc/admin => /srv/admin
+//! ".parse().unwrap();
+//!
+//! assert!(fixtures.check(&dtab!["/svc" => "/srv";]).is_ok());
+//!
+//! let failures = fixtures.check(&dtab!["/svc" => "/legacy";]).unwrap_err();
+//! assert_eq!(failures.len(), 2);
+//! assert_eq!(failures[0].line, 2);
+//! assert_eq!(&failures[0].actual.as_ref().unwrap().to_string(), "/legacy/users");
+//! # }
+//! ```
+//!
+//! [`Failure`]: struct.Failure.html
+//! [`Dtab::delegate`]: ../struct.Dtab.html#method.delegate
+//! [`Fixtures::check`]: struct.Fixtures.html#method.check
+//! [`Fixtures::check_with`]: struct.Fixtures.html#method.check_with
+//! [`Namer`]: ../namer/trait.Namer.html
+use std::{error, fmt, io, str};
+
+use super::{Dtab, NameTree};
+use super::delegate::DelegateError;
+use super::name::Name;
+use super::parse::{self, ParseError, ParseOptions};
+use super::path::{Path, PathError};
+
+/// A set of expected delegations, loaded from a fixture file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fixtures {
+    expectations: Vec<Expectation>
+}
+
+/// A path, and the name tree it is expected to delegate to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expectation {
+    /// The line of the fixture file on which this expectation appears,
+    /// starting at 1.
+    pub line: usize
+  , /// The path to delegate.
+    pub path: Path
+  , /// The name tree the path is expected to delegate to.
+    pub expected: NameTree<String>
+}
+
+/// An expectation which was not met.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    /// The line of the fixture file on which the expectation appears.
+    pub line: usize
+  , /// The path that was delegated.
+    pub path: Path
+  , /// The name tree the path was expected to delegate to.
+    pub expected: NameTree<String>
+  , /// The result of actually delegating the path.
+    pub actual: Result<NameTree<Name>, DelegateError>
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!( f, "line {}: expected {} to delegate to `{}`, "
+              , self.line, self.path, self.expected)?;
+        match self.actual {
+            Ok(ref tree) => write!(f, "got `{}`", tree)
+          , Err(ref error) => write!(f, "got error: {}", error)
+        }
+    }
+}
+
+/// An error loading a fixture file.
+#[derive(Debug)]
+pub enum FixtureError {
+    /// The fixture file could not be read.
+    Io(io::Error)
+  , /// A line did not have the form `path => tree`.
+    MissingArrow { line: usize }
+  , /// The path on a line was invalid.
+    InvalidPath { line: usize, error: PathError }
+  , /// The expected name tree on a line could not be parsed.
+    InvalidTree { line: usize, error: ParseError }
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FixtureError::Io(ref error) =>
+                write!(f, "could not read fixtures: {}", error)
+          , FixtureError::MissingArrow { line } =>
+                write!(f, "line {}: expected `path => tree`", line)
+          , FixtureError::InvalidPath { line, ref error } =>
+                write!(f, "line {}: invalid path: {}", line, error)
+          , FixtureError::InvalidTree { line, ref error } =>
+                write!(f, "line {}: invalid expected tree: {}", line, error)
+        }
+    }
+}
+
+impl error::Error for FixtureError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            FixtureError::Io(ref error) => Some(error)
+          , FixtureError::MissingArrow { .. } => None
+          , FixtureError::InvalidPath { ref error, .. } => Some(error)
+          , FixtureError::InvalidTree { ref error, .. } => Some(error)
+        }
+    }
+}
+
+impl Fixtures {
+    /// Parse fixtures, using the given options for paths and trees.
+    pub fn parse_with(input: &str, options: &ParseOptions)
+                      -> Result<Self, FixtureError> {
+        let mut expectations = Vec::new();
+        for (i, text) in input.lines().enumerate() {
+            let line = i + 1;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue
+            }
+            let arrow = text.find("=>")
+                .ok_or(FixtureError::MissingArrow { line })?;
+            let path = Path::read_with(text[..arrow].trim(), options.labels)
+                .map_err(|error| FixtureError::InvalidPath { line, error })?;
+            let expected = parse::name_tree(&text[arrow + 2..], options)
+                .map_err(|error| FixtureError::InvalidTree { line, error })?;
+            expectations.push(Expectation { line, path, expected });
+        }
+        Ok(Fixtures { expectations })
+    }
+
+    /// Load fixtures from a reader, using the default options.
+    pub fn from_reader<R: io::Read>(mut reader: R) -> Result<Self, FixtureError> {
+        let mut input = String::new();
+        reader.read_to_string(&mut input).map_err(FixtureError::Io)?;
+        input.parse()
+    }
+
+    /// Returns every expectation, in the order they appear in the file.
+    #[inline] pub fn expectations(&self) -> &[Expectation] {
+        &self.expectations
+    }
+
+    /// Check every expectation against `dtab`, delegating each path with
+    /// [`Dtab::delegate`].
+    ///
+    /// Returns every expectation that was not met, in order.
+    ///
+    /// [`Dtab::delegate`]: ../struct.Dtab.html#method.delegate
+    #[inline] pub fn check(&self, dtab: &Dtab) -> Result<(), Vec<Failure>> {
+        self.check_with(dtab, Dtab::delegate)
+    }
+
+    /// Check every expectation against `dtab`, using `delegate` to delegate
+    /// each path.
+    ///
+    /// Returns every expectation that was not met, in order.
+    pub fn check_with<F>(&self, dtab: &Dtab, delegate: F) -> Result<(), Vec<Failure>>
+    where F: Fn(&Dtab, &Path) -> Result<NameTree<Name>, DelegateError> {
+        let failures = self.expectations.iter()
+            .filter_map(|expectation| {
+                let actual = delegate(dtab, &expectation.path);
+                let met = actual.as_ref().ok()
+                    .map(|tree| tree.clone().map(|name| name.to_string()));
+                if met.as_ref() == Some(&expectation.expected) {
+                    return None
+                }
+                Some(Failure { line: expectation.line
+                             , path: expectation.path.clone()
+                             , expected: expectation.expected.clone()
+                             , actual
+                             })
+            })
+            .collect::<Vec<_>>();
+        if failures.is_empty() { Ok(()) } else { Err(failures) }
+    }
+}
+
+impl str::FromStr for Fixtures {
+    type Err = FixtureError;
+    #[inline] fn from_str(s: &str) -> Result<Self, FixtureError> {
+        Fixtures::parse_with(s, &ParseOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delegate::{self, DelegateOptions};
+
+    #[test]
+    fn load() {
+        let fixtures: Fixtures = "
+            # comment
+            /svc/a => /#/io.l5d.fs/a
+
+            /svc/b => /srv/b | ~
+        ".parse().unwrap();
+        assert_eq!( fixtures.expectations().iter()
+                        .map(|e| (e.line, e.path.to_string(), e.expected.to_string()))
+                        .collect::<Vec<_>>()
+                  , vec![ (3, "/svc/a".to_string(), "/#/io.l5d.fs/a".to_string())
+                        , (5, "/svc/b".to_string(), "/srv/b | ~".to_string()) ]);
+
+        let err = "/svc/a => /a\n/svc/b /b".parse::<Fixtures>().unwrap_err();
+        assert_eq!(&err.to_string(), "line 2: expected `path => tree`");
+        match "\n/svc/* => /a".parse::<Fixtures>() {
+            Err(FixtureError::InvalidPath { line: 2, .. }) => {}
+          , other => panic!("expected an invalid path, got {:?}", other)
+        }
+        match "/svc => /a |".parse::<Fixtures>() {
+            Err(FixtureError::InvalidTree { line: 1, .. }) => {}
+          , other => panic!("expected an invalid tree, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn check() {
+        let fixtures: Fixtures = "
+            /svc/users => /#/io.l5d.k8s/http/users | /env/users
+            /svc/cycle => /svc/cycle
+        ".parse().unwrap();
+        let dtab = dtab![ "/srv" => "/#/io.l5d.k8s/http";
+                          "/svc" => NameTree::from("/srv") | "/env";
+                          "/svc/cycle" => "/svc/cycle"; ];
+        let failures = fixtures.check(&dtab).unwrap_err();
+        assert_eq!(failures.iter().map(|f| f.line).collect::<Vec<_>>(), vec![3]);
+        assert_eq!( &failures[0].to_string()
+                  , "line 3: expected /svc/cycle to delegate to `/svc/cycle`, \
+                     got error: delegation cycle: /svc/cycle -> /svc/cycle");
+
+        // a custom delegation, with a namer that binds nothing.
+        let options = DelegateOptions::default();
+        let delegate = |dtab: &Dtab, path: &Path| delegate::delegate(dtab, path, &options, (), ());
+        assert_eq!(fixtures.check_with(&dtab, delegate).unwrap_err(), failures);
+    }
+}
@@ -0,0 +1,512 @@
+//! Reading and writing dtabs in request headers.
+//!
+//! Override dtabs are sent with requests in the compact form used by the
+//! `l5d-dtab` header. Proxies cap the size of a header, typically at 8 KiB,
+//! and an oversized override is rejected at the proxy with little
+//! explanation. [`encode`] checks the size of the encoded dtab before it is
+//! sent, and applies an [`Overflow`] strategy when it doesn't fit.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::header::{self, HeaderOptions, Overflow};
+//!
+//! let dtab = dtab![
+//!     "/svc" => "/srv";
+//!     "/svc/users" => "/srv/users-v2";
+//! ];
+//! let options = HeaderOptions { limit: 32, overflow: Overflow::Spill };
+//! let encoded = header::encode(&dtab, &options).unwrap();
+//! assert_eq!(encoded.headers, vec!["/svc=>/srv", "/svc/users=>/srv/users-v2"]);
+//!
+//! let options = HeaderOptions { limit: 32, overflow: Overflow::DropLowest };
+//! let encoded = header::encode(&dtab, &options).unwrap();
+//! assert_eq!(encoded.headers, vec!["/svc/users=>/srv/users-v2"]);
+//! assert_eq!(encoded.dropped, vec![0]);
+//! # }
+//! ```
+//!
+//! # Propagation
+//!
+//! Finagle services propagate dtabs in the `dtab-local` header, while
+//! linkerd uses `l5d-ctx-dtab` for the dtab propagated with a request and
+//! `l5d-dtab` for overrides added by the caller. During a migration, a
+//! fleet may contain both. [`read`] combines every dtab header on a request
+//! with the correct precedence, and a [`Propagation`] mode selects which
+//! headers are emitted on egress:
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::header::{self, HeaderOptions, Propagation};
+//! use dtab::parse::ParseOptions;
+//!
+//! let incoming = vec![ ("L5d-Dtab", "/svc=>/srv/v2")
+//!                    , ("Dtab-Local", "/svc=>/srv/v1")
+//!                    , ("Content-Type", "text/plain") ];
+//! let dtab = header::read(incoming, &ParseOptions::untrusted()).unwrap();
+//! assert_eq!(dtab, dtab!["/svc" => "/srv/v1"; "/svc" => "/srv/v2";]);
+//!
+//! let encoded = dtab.to_headers(&HeaderOptions::default()).unwrap();
+//! assert_eq!( Propagation::Both.headers(&encoded)
+//!           , vec![ ("dtab-local", "/svc=>/srv/v1;/svc=>/srv/v2".to_string())
+//!                 , ("l5d-ctx-dtab", "/svc=>/srv/v1;/svc=>/srv/v2".to_string()) ]);
+//! # }
+//! ```
+//!
+//! Headers are sometimes percent-encoded in transit; [`decode`] decodes a
+//! single header value before parsing it.
+//!
+//! [`decode`]: fn.decode.html
+//! [`encode`]: fn.encode.html
+//! [`Overflow`]: enum.Overflow.html
+//! [`read`]: fn.read.html
+//! [`Propagation`]: enum.Propagation.html
+use std::{error, fmt};
+
+use super::Dtab;
+use super::parse::{self, ErrorKind, ParseError, ParseOptions};
+
+/// The header used by Finagle to propagate dtabs.
+pub const DTAB_LOCAL: &str = "dtab-local";
+/// The header used by linkerd to propagate dtabs between proxies.
+pub const L5D_CTX_DTAB: &str = "l5d-ctx-dtab";
+/// The header used by callers to add overrides to linkerd's dtab.
+pub const L5D_DTAB: &str = "l5d-dtab";
+
+/// Every dtab header, in increasing order of precedence.
+const PRECEDENCE: [&str; 3] = [DTAB_LOCAL, L5D_CTX_DTAB, L5D_DTAB];
+
+/// What to do when an encoded dtab is larger than the limit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Overflow {
+    /// Return an error.
+    Error
+  , /// Drop the lowest-precedence dentries, from the start of the dtab,
+    /// until the rest fit in a single header.
+    DropLowest
+  , /// Split the dtab across as many headers as necessary. Each header
+    /// holds a run of consecutive dentries, so concatenating the headers in
+    /// order restores the dtab.
+    Spill
+}
+
+impl Default for Overflow {
+    #[inline] fn default() -> Self { Overflow::Error }
+}
+
+/// Options controlling header encoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HeaderOptions {
+    /// The maximum length of a header value, in bytes.
+    pub limit: usize
+  , /// What to do when the encoded dtab is longer than `limit`.
+    pub overflow: Overflow
+}
+
+impl Default for HeaderOptions {
+    /// The default options allow 8 KiB headers, and return an error for
+    /// larger dtabs.
+    fn default() -> Self {
+        HeaderOptions { limit: 8 * 1024, overflow: Overflow::default() }
+    }
+}
+
+/// A dtab encoded as one or more header values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Encoded {
+    /// The header values, in order. An empty dtab is encoded as a single
+    /// empty value.
+    pub headers: Vec<String>
+  , /// The indices of dentries that were dropped to fit the limit.
+    pub dropped: Vec<usize>
+}
+
+/// An error encoding a dtab as headers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The encoded dtab is longer than the limit.
+    TooLarge { len: usize, limit: usize }
+  , /// A single dentry is longer than the limit, so it can't be sent in any
+    /// header.
+    DentryTooLarge { entry: usize, len: usize, limit: usize }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderError::TooLarge { len, limit } =>
+                write!(f, "encoded dtab is {} bytes, over the {} byte limit", len, limit)
+          , HeaderError::DentryTooLarge { entry, len, limit } =>
+                write!( f, "entry {} is {} bytes, over the {} byte limit"
+                      , entry, len, limit)
+        }
+    }
+}
+
+impl error::Error for HeaderError {}
+
+/// Encode `dtab` in the compact header form, applying `options.overflow`
+/// if it is longer than `options.limit`.
+pub fn encode(dtab: &Dtab, options: &HeaderOptions) -> Result<Encoded, HeaderError> {
+    let limit = options.limit;
+    let dentries = dtab.iter()
+        .map(|dentry| format!("{:#}", dentry))
+        .collect::<Vec<_>>();
+    // The length of a header holding a run of dentries.
+    let len = |dentries: &[String]| dentries.iter().map(String::len).sum::<usize>()
+        + dentries.len().saturating_sub(1);
+    let too_large = |entry: usize| HeaderError::DentryTooLarge {
+        entry, len: dentries[entry].len(), limit
+    };
+
+    let total = len(&dentries);
+    if total <= limit {
+        return Ok(Encoded { headers: vec![dentries.join(";")], dropped: Vec::new() })
+    }
+    match options.overflow {
+        Overflow::Error => Err(HeaderError::TooLarge { len: total, limit })
+      , Overflow::DropLowest => {
+            let mut start = 0;
+            while len(&dentries[start..]) > limit {
+                start += 1;
+            }
+            if start == dentries.len() {
+                return Err(too_large(dentries.len() - 1))
+            }
+            Ok(Encoded { headers: vec![dentries[start..].join(";")]
+                       , dropped: (0..start).collect()
+                       })
+        }
+      , Overflow::Spill => {
+            let mut headers = Vec::new();
+            let mut start = 0;
+            for end in 1..=dentries.len() {
+                if dentries[end - 1].len() > limit {
+                    return Err(too_large(end - 1))
+                }
+                if len(&dentries[start..end]) > limit {
+                    headers.push(dentries[start..end - 1].join(";"));
+                    start = end - 1;
+                }
+            }
+            headers.push(dentries[start..].join(";"));
+            Ok(Encoded { headers, dropped: Vec::new() })
+        }
+    }
+}
+
+/// Which headers to emit when propagating a dtab.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Propagation {
+    /// Emit `dtab-local`, for Finagle services.
+    Legacy
+  , /// Emit `l5d-ctx-dtab`, for linkerd.
+    Linkerd
+  , /// Emit both `dtab-local` and `l5d-ctx-dtab`.
+    Both
+}
+
+impl Default for Propagation {
+    #[inline] fn default() -> Self { Propagation::Linkerd }
+}
+
+impl Propagation {
+    /// Returns the names of the headers to emit, in order.
+    pub fn names(self) -> &'static [&'static str] {
+        match self {
+            Propagation::Legacy => &PRECEDENCE[..1]
+          , Propagation::Linkerd => &PRECEDENCE[1..2]
+          , Propagation::Both => &PRECEDENCE[..2]
+        }
+    }
+
+    /// Returns the `(name, value)` pairs to emit for an encoded dtab. Each
+    /// value is emitted under every name, and empty values are omitted.
+    pub fn headers(self, encoded: &Encoded) -> Vec<(&'static str, String)> {
+        self.names().iter()
+            .flat_map(|&name| encoded.headers.iter()
+                .filter(|value| !value.is_empty())
+                .map(move |value| (name, value.clone())))
+            .collect()
+    }
+}
+
+/// An error reading a dtab from a header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadError {
+    /// The name of the header, in lower case.
+    pub header: &'static str
+  , /// The error parsing its value.
+    pub error: ParseError
+}
+
+impl fmt::Display for ReadError {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {} header: {}", self.header, self.error)
+    }
+}
+
+impl error::Error for ReadError {
+    #[inline] fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Read the dtab propagated with a request from its headers, given as
+/// `(name, value)` pairs. Header names are compared case-insensitively, and
+/// headers other than dtab headers are ignored.
+///
+/// Dtabs from `dtab-local`, then `l5d-ctx-dtab`, then `l5d-dtab` are
+/// concatenated, so that a caller's `l5d-dtab` overrides take precedence
+/// over the propagated dtab, which in turn takes precedence over a dtab
+/// from a legacy Finagle service. Repeated headers with the same name are
+/// concatenated in the order they appear.
+///
+/// The limits on input length and on the number of entries in `options`
+/// apply to all of the headers together, so that a request can't exceed
+/// them by repeating a header.
+pub fn read<'a, I>(headers: I, options: &ParseOptions) -> Result<Dtab, ReadError>
+where I: IntoIterator<Item = (&'a str, &'a str)> {
+    let mut values: [Vec<&str>; 3] = Default::default();
+    for (name, value) in headers {
+        if let Some(i) = PRECEDENCE.iter().position(|h| h.eq_ignore_ascii_case(name)) {
+            values[i].push(value);
+        }
+    }
+    let mut dtab = Dtab::new();
+    let mut read_bytes = 0;
+    for (&header, values) in PRECEDENCE.iter().zip(&values) {
+        for value in values {
+            // what's left of the limits after the headers already read.
+            let remaining = ParseOptions {
+                max_input_bytes: options.max_input_bytes
+                    .map(|max| max.saturating_sub(read_bytes))
+              , max_entries: options.max_entries
+                    .map(|max| max.saturating_sub(dtab.len()))
+              , ..*options
+            };
+            let parsed = parse::dtab(value, &remaining).map_err(|error| {
+                let kind = match error.kind {
+                    ErrorKind::InputTooLong { len, .. } => ErrorKind::InputTooLong {
+                        max: options.max_input_bytes.unwrap_or(len)
+                      , len: read_bytes + len
+                    }
+                  , ErrorKind::TooManyEntries { .. } => ErrorKind::TooManyEntries {
+                        max: options.max_entries.unwrap_or(dtab.len())
+                    }
+                  , kind => kind
+                };
+                ReadError { header, error: ParseError { kind, ..error } }
+            })?;
+            read_bytes += value.len();
+            dtab.extend(parsed);
+        }
+    }
+    Ok(dtab)
+}
+
+/// An error decoding a dtab from a header value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes decoded from the percent-encoded sequence at this offset
+    /// were not valid UTF-8.
+    InvalidUtf8 { at: usize }
+  , /// The decoded value was not a valid dtab. The error's offset is in the
+    /// value as it was received, before decoding.
+    Parse(ParseError)
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::InvalidUtf8 { at } =>
+                write!(f, "percent-encoded bytes at byte {} are not valid UTF-8", at)
+          , DecodeError::Parse(ref e) => fmt::Display::fmt(e, f)
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            DecodeError::InvalidUtf8 { .. } => None
+          , DecodeError::Parse(ref e) => Some(e)
+        }
+    }
+}
+
+/// Decode a dtab from a single header value, which may be percent-encoded.
+///
+/// Every `%` followed by two hexadecimal digits is decoded before the value
+/// is parsed; since `%` may also appear in labels, any other `%` is left as
+/// it is. Entries are separated by `;`, and whitespace may appear between
+/// any two tokens. Offsets in errors are in the value as received.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::NameTree;
+/// use dtab::header::{self, DecodeError};
+/// use dtab::parse::ParseOptions;
+///
+/// let options = ParseOptions::untrusted();
+/// let value = "/svc%20%3D%3E%20/srv%7C/fallback;%20/a=>/50%";
+/// assert_eq!( header::decode(value, &options).unwrap()
+///           , dtab!["/svc" => NameTree::from("/srv") | "/fallback"; "/a" => "/50%";]);
+///
+/// match header::decode("/svc%3D%3E%20/srv%20%7C", &options) {
+///     Err(DecodeError::Parse(e)) => assert_eq!(e.at, 23)
+///   , other => panic!("expected a parse error, got {:?}", other)
+/// }
+/// # }
+/// ```
+pub fn decode(value: &str, options: &ParseOptions) -> Result<Dtab, DecodeError> {
+    match percent_decode(value)? {
+        None => parse::dtab(value, options).map_err(DecodeError::Parse)
+      , Some((text, offsets)) => parse::dtab(&text, options).map_err(|e| {
+            let at = offsets[e.at.min(text.len())];
+            DecodeError::Parse(ParseError { at, ..e })
+        })
+    }
+}
+
+/// Percent-decode a header value. If it contained any escapes, returns the
+/// decoded text and, for each byte of the text and for its end, the
+/// corresponding offset in `value`.
+fn percent_decode(value: &str) -> Result<Option<(String, Vec<usize>)>, DecodeError> {
+    if !value.contains('%') {
+        return Ok(None)
+    }
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut offsets = Vec::with_capacity(bytes.len() + 1);
+    let mut i = 0;
+    while i < bytes.len() {
+        offsets.push(i);
+        let escaped = value.get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => { decoded.push(byte); i += 3 }
+          , None => { decoded.push(bytes[i]); i += 1 }
+        }
+    }
+    offsets.push(bytes.len());
+    match String::from_utf8(decoded) {
+        Ok(text) => Ok(Some((text, offsets)))
+      , Err(e) => Err(DecodeError::InvalidUtf8 { at: offsets[e.utf8_error().valid_up_to()] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(limit: usize, overflow: Overflow) -> HeaderOptions {
+        HeaderOptions { limit, overflow }
+    }
+
+    #[test]
+    fn within_limit() {
+        let dtab = dtab!["/a" => "/b"; "/c" => "/d";];
+        for &overflow in &[Overflow::Error, Overflow::DropLowest, Overflow::Spill] {
+            assert_eq!( encode(&dtab, &options(13, overflow)).unwrap()
+                      , Encoded { headers: vec!["/a=>/b;/c=>/d".to_string()]
+                                , dropped: Vec::new()
+                                });
+        }
+        assert_eq!( encode(&Dtab::new(), &HeaderOptions::default()).unwrap().headers
+                  , vec![String::new()]);
+    }
+
+    #[test]
+    fn overflow() {
+        let dtab = dtab!["/a" => "/b"; "/c" => "/d"; "/e" => "/f";];
+        assert_eq!( encode(&dtab, &options(13, Overflow::Error)).unwrap_err()
+                  , HeaderError::TooLarge { len: 20, limit: 13 });
+        assert_eq!( &encode(&dtab, &options(13, Overflow::Error)).unwrap_err().to_string()
+                  , "encoded dtab is 20 bytes, over the 13 byte limit");
+
+        let dropped = encode(&dtab, &options(13, Overflow::DropLowest)).unwrap();
+        assert_eq!(dropped.headers, vec!["/c=>/d;/e=>/f"]);
+        assert_eq!(dropped.dropped, vec![0]);
+
+        let spilled = encode(&dtab, &options(13, Overflow::Spill)).unwrap();
+        assert_eq!(spilled.headers, vec!["/a=>/b;/c=>/d", "/e=>/f"]);
+        assert_eq!(spilled.headers.join(";"), format!("{:#}", dtab));
+
+        assert_eq!( encode(&dtab, &options(5, Overflow::DropLowest)).unwrap_err()
+                  , HeaderError::DentryTooLarge { entry: 2, len: 6, limit: 5 });
+        assert_eq!( encode(&dtab, &options(5, Overflow::Spill)).unwrap_err()
+                  , HeaderError::DentryTooLarge { entry: 0, len: 6, limit: 5 });
+    }
+
+    #[test]
+    fn decoding() {
+        let options = ParseOptions::default();
+        assert_eq!( decode("  /a => /b ;/c=>/d;", &options).unwrap()
+                  , dtab!["/a" => "/b"; "/c" => "/d";]);
+        assert_eq!( decode("%2Fa%3d%3e%2F%23%2Fb", &options).unwrap()
+                  , dtab!["/a" => "/#/b";]);
+        assert_eq!(decode("/a=>/100%;/b=>/%zz", &options).unwrap().len(), 2);
+        assert_eq!(decode("", &options).unwrap(), Dtab::new());
+        assert_eq!( decode("/a=>/b%C3%A9", &options).unwrap_err()
+                  , DecodeError::Parse(parse::dtab("/a=>/bé", &options).unwrap_err()));
+        assert_eq!(decode("/a=>%FF", &options), Err(DecodeError::InvalidUtf8 { at: 4 }));
+        assert_eq!(decode("/a=>%C3", &options), Err(DecodeError::InvalidUtf8 { at: 4 }));
+        match decode("%2Fa%20%3D%3E%20%7E%20%3F", &options) {
+            Err(DecodeError::Parse(e)) => {
+                assert_eq!(e.at, 22);
+                assert_eq!(e.kind, parse::ErrorKind::InvalidCharacter('?'));
+            }
+          , other => panic!("expected a parse error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn propagation() {
+        let options = ParseOptions::default();
+        let headers = vec![ ("l5d-dtab", "/a=>/user")
+                          , ("l5d-ctx-dtab", "/a=>/ctx1")
+                          , ("dtab-local", "/a=>/legacy")
+                          , ("L5D-CTX-DTAB", "/a=>/ctx2") ];
+        assert_eq!( read(headers, &options).unwrap()
+                  , dtab![ "/a" => "/legacy"; "/a" => "/ctx1"
+                         ; "/a" => "/ctx2"; "/a" => "/user"; ]);
+        assert_eq!(read(vec![("dtab-local", "")], &options).unwrap(), Dtab::new());
+        let error = read(vec![("l5d-dtab", "/a=>")], &options).unwrap_err();
+        assert_eq!(error.header, L5D_DTAB);
+        assert!(error.to_string().starts_with("invalid l5d-dtab header: "));
+
+        let spilled = Encoded { headers: vec!["/a=>/b".to_string(), "/c=>/d".to_string()]
+                              , dropped: Vec::new() };
+        assert_eq!( Propagation::Legacy.headers(&spilled)
+                  , vec![ (DTAB_LOCAL, "/a=>/b".to_string())
+                        , (DTAB_LOCAL, "/c=>/d".to_string()) ]);
+        let empty = encode(&Dtab::new(), &HeaderOptions::default()).unwrap();
+        assert!(Propagation::default().headers(&empty).is_empty());
+    }
+
+    #[test]
+    fn limits_span_headers() {
+        let options = ParseOptions { max_input_bytes: Some(16), max_entries: Some(3)
+                                   , ..ParseOptions::default() };
+        let headers = vec![("l5d-dtab", "/a=>/b;/c=>/d"), ("l5d-dtab", "/e=>/f;/g=>/h")];
+        let entries = ParseOptions { max_input_bytes: None, ..options };
+        assert_eq!( read(headers, &entries).unwrap_err().error
+                  , ParseError { kind: ErrorKind::TooManyEntries { max: 3 }, at: 7 });
+        let headers = vec![("dtab-local", "/a=>/b;/c=>/d"), ("l5d-dtab", "/e=>/f")];
+        assert_eq!( read(headers, &options).unwrap_err()
+                  , ReadError { header: L5D_DTAB
+                              , error: ParseError { kind: ErrorKind::InputTooLong {
+                                                        max: 16, len: 19 }
+                                                  , at: 3 } });
+        let headers = vec![("dtab-local", "/a=>/b"), ("l5d-dtab", "/e=>/f")];
+        assert_eq!(read(headers, &options).unwrap().len(), 2);
+    }
+}
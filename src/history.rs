@@ -0,0 +1,180 @@
+//! A history of changes to a dtab.
+//!
+//! A [`DtabHistory`] records each successive version of a dtab as a
+//! [`VersionedDtab`], along with when it was made, who made it, and why.
+//! The history is append-only: rolling back to an earlier version records a
+//! new version with the earlier contents, so the history always answers
+//! "who changed routing, and when".
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::history::DtabHistory;
+//!
+//! let mut history = DtabHistory::new();
+//! history.record(dtab!["/svc" => "/srv/v1";], "eliza", "initial routing");
+//! history.record(dtab!["/svc" => "/srv/v2";], "eliza", "deploy v2");
+//!
+//! let rolled_back = history.rollback_to(1, "oncall").unwrap();
+//! assert_eq!(rolled_back.version, 3);
+//! assert_eq!(&rolled_back.comment, "rollback to version 1");
+//! assert_eq!(rolled_back.dtab, dtab!["/svc" => "/srv/v1";]);
+//! # }
+//! ```
+//!
+//! [`DtabHistory`]: struct.DtabHistory.html
+//! [`VersionedDtab`]: struct.VersionedDtab.html
+use std::{error, fmt};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Dtab;
+
+/// A version of a dtab, and the metadata of the change which produced it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VersionedDtab {
+    /// The version number. The first version recorded is version 1.
+    pub version: u64
+  , /// The dtab.
+    pub dtab: Dtab
+  , /// When this version was recorded.
+    pub timestamp: SystemTime
+  , /// Who made the change.
+    pub author: String
+  , /// Why the change was made.
+    pub comment: String
+}
+
+/// An append-only history of a dtab's versions.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DtabHistory {
+    versions: Vec<VersionedDtab>
+}
+
+/// An error indicating that a version is not in a [`DtabHistory`].
+///
+/// [`DtabHistory`]: struct.DtabHistory.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnknownVersion(pub u64);
+
+impl fmt::Display for UnknownVersion {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "version {} is not in the history", self.0)
+    }
+}
+
+impl error::Error for UnknownVersion {}
+
+impl DtabHistory {
+    /// Returns a new, empty, history.
+    #[inline] pub fn new() -> Self { DtabHistory::default() }
+
+    /// Record a new version of the dtab, made now.
+    #[inline]
+    pub fn record<A, C>(&mut self, dtab: Dtab, author: A, comment: C)
+                        -> &VersionedDtab
+    where A: Into<String>
+        , C: Into<String> {
+        self.record_at(dtab, SystemTime::now(), author, comment)
+    }
+
+    /// Record a new version of the dtab, made at the given time.
+    pub fn record_at<A, C>( &mut self, dtab: Dtab, timestamp: SystemTime
+                          , author: A, comment: C) -> &VersionedDtab
+    where A: Into<String>
+        , C: Into<String> {
+        let version = self.versions.last().map_or(1, |last| last.version + 1);
+        self.versions.push(VersionedDtab {
+            version, dtab, timestamp
+          , author: author.into()
+          , comment: comment.into()
+        });
+        self.versions.last().expect("a version was just recorded")
+    }
+
+    /// Record a new version with the contents of an earlier `version`.
+    ///
+    /// Returns an error if `version` is not in this history.
+    pub fn rollback_to<A>(&mut self, version: u64, author: A)
+                          -> Result<&VersionedDtab, UnknownVersion>
+    where A: Into<String> {
+        let dtab = self.get(version)
+            .ok_or(UnknownVersion(version))?
+            .dtab.clone();
+        let comment = format!("rollback to version {}", version);
+        Ok(self.record(dtab, author, comment))
+    }
+
+    /// Returns the most recent version, if any has been recorded.
+    #[inline] pub fn current(&self) -> Option<&VersionedDtab> {
+        self.versions.last()
+    }
+
+    /// Returns the given version, if it is in this history.
+    pub fn get(&self, version: u64) -> Option<&VersionedDtab> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+
+    /// Returns every version in this history, oldest first.
+    #[inline] pub fn versions(&self) -> &[VersionedDtab] { &self.versions }
+}
+
+/// Versions are displayed as an audit log entry: a header line with the
+/// version, the time in seconds since the Unix epoch, the author and the
+/// comment, followed by the dtab in its compact form.
+impl fmt::Display for VersionedDtab {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.timestamp.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        writeln!( f, "version {} at {} by {}: {}"
+                , self.version, secs, self.author, self.comment)?;
+        write!(f, "  {:#}", self.dtab)
+    }
+}
+
+/// Histories are displayed as an audit log, with one entry per version.
+impl fmt::Display for DtabHistory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for version in &self.versions {
+            writeln!(f, "{}", version)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn audit_log() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut history = DtabHistory::new();
+        history.record_at(dtab!["/svc" => "/srv/v1";], at(100), "eliza", "initial");
+        history.record_at( dtab!["/svc" => "/srv/v1"; "/svc/a" => "/srv/a";]
+                         , at(200), "kevin", "add a");
+        assert_eq!( &history.to_string()
+                  , "version 1 at 100 by eliza: initial\n  \
+                       /svc=>/srv/v1\n\
+                     version 2 at 200 by kevin: add a\n  \
+                       /svc=>/srv/v1;/svc/a=>/srv/a\n");
+    }
+
+    #[test]
+    fn rollback() {
+        let mut history = DtabHistory::new();
+        assert_eq!(history.current(), None);
+        assert_eq!(history.rollback_to(1, "eliza"), Err(UnknownVersion(1)));
+
+        history.record(dtab!["/svc" => "/srv/v1";], "eliza", "v1");
+        history.record(dtab!["/svc" => "/srv/v2";], "eliza", "v2");
+        let version = history.rollback_to(1, "oncall").unwrap().version;
+        assert_eq!(version, 3);
+        assert_eq!(history.versions().len(), 3);
+        assert_eq!(history.get(3).unwrap().dtab, history.get(1).unwrap().dtab);
+        assert_eq!(&history.current().unwrap().author, "oncall");
+    }
+}
@@ -0,0 +1,261 @@
+//! Composing a dtab from several files with `@include` directives.
+//!
+//! Large deployments often share a common set of dentries between many
+//! dtabs. A dtab file may include another with a directive on a line of its
+//! own:
+//!
+//! ```text
+//! @include "common.dtab"
+//! /svc/users => /srv/users-v2;
+//! ```
+//!
+//! The included file's dentries take the place of the directive, so later
+//! dentries in the including file still take precedence over them. Paths
+//! are relative to the directory of the including file. [`load`] reads a
+//! file and everything it includes into a single [`Dtab`], recording the
+//! file and span each dentry came from, and reports errors with the file in
+//! which they occurred.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use std::io;
+//! use std::path::Path;
+//! use dtab::include;
+//! use dtab::parse::ParseOptions;
+//!
+//! let read = |path: &Path| match path.to_str() {
+//!     Some("dtabs/prod.dtab") =>
+//!         Ok("@include \"common.dtab\"\n/svc/users => /srv/users-v2;\n".to_string())
+//!   , Some("dtabs/common.dtab") => Ok("/svc => /srv;\n".to_string())
+//!   , _ => Err(io::Error::new(io::ErrorKind::NotFound, "no such file"))
+//! };
+//! let loaded = include::load_with("dtabs/prod.dtab", &ParseOptions::default(), read)
+//!     .unwrap();
+//! assert_eq!(loaded.dtab, dtab!["/svc" => "/srv"; "/svc/users" => "/srv/users-v2";]);
+//! assert_eq!(loaded.origins[0].file, Path::new("dtabs/common.dtab"));
+//! assert_eq!(loaded.origins[1].file, Path::new("dtabs/prod.dtab"));
+//! # }
+//! ```
+//!
+//! [`load`]: fn.load.html
+//! [`Dtab`]: ../struct.Dtab.html
+use std::{error, fmt, fs, io};
+use std::path::{Path, PathBuf};
+
+use super::Dtab;
+use super::parse::{self, ParseError, ParseOptions, Span};
+
+/// A dtab read from several files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loaded {
+    /// The combined dtab.
+    pub dtab: Dtab
+  , /// Where each dentry in `dtab` came from, by index.
+    pub origins: Vec<Origin>
+}
+
+/// The file a dentry was read from, and its span in that file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Origin {
+    /// The file, as it was named by the including file.
+    pub file: PathBuf
+  , /// The span of the dentry in the file.
+    pub span: Span
+}
+
+/// An error reading a dtab and the files it includes.
+#[derive(Debug)]
+pub enum IncludeError {
+    /// A file could not be read.
+    Io { file: PathBuf, error: io::Error }
+  , /// The directive at this offset in `file` was not understood, or
+    /// appeared in the middle of a dentry.
+    InvalidDirective { file: PathBuf, at: usize }
+  , /// The directive at this offset in `file` includes a file which is
+    /// already being read, and so would include itself.
+    Cycle { file: PathBuf, at: usize }
+  , /// A file was not a valid dtab.
+    Parse { file: PathBuf, error: ParseError }
+}
+
+impl IncludeError {
+    /// Returns the file in which the error occurred.
+    pub fn file(&self) -> &Path {
+        match *self {
+            IncludeError::Io { ref file, .. }
+          | IncludeError::InvalidDirective { ref file, .. }
+          | IncludeError::Cycle { ref file, .. }
+          | IncludeError::Parse { ref file, .. } => file
+        }
+    }
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IncludeError::Io { ref file, ref error } =>
+                write!(f, "could not read {}: {}", file.display(), error)
+          , IncludeError::InvalidDirective { ref file, at } =>
+                write!(f, "{}: invalid directive at byte {}", file.display(), at)
+          , IncludeError::Cycle { ref file, at } =>
+                write!(f, "{}: file includes itself at byte {}", file.display(), at)
+          , IncludeError::Parse { ref file, ref error } =>
+                write!(f, "{}: {}", file.display(), error)
+        }
+    }
+}
+
+impl error::Error for IncludeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            IncludeError::Io { ref error, .. } => Some(error)
+          , IncludeError::Parse { ref error, .. } => Some(error)
+          , _ => None
+        }
+    }
+}
+
+/// Read the dtab in `file`, and every file it includes, from the file
+/// system.
+#[inline] pub fn load<P>(file: P, options: &ParseOptions) -> Result<Loaded, IncludeError>
+where P: AsRef<Path> {
+    load_with(file, options, |path: &Path| fs::read_to_string(path))
+}
+
+/// Read the dtab in `file`, and every file it includes, using `read` to
+/// read each file.
+///
+/// Each file is parsed on its own, so limits such as `max_entries` apply to
+/// each file separately.
+pub fn load_with<P, F>(file: P, options: &ParseOptions, mut read: F)
+                       -> Result<Loaded, IncludeError>
+where P: AsRef<Path>
+    , F: FnMut(&Path) -> io::Result<String> {
+    let mut loaded = Loaded { dtab: Dtab::new(), origins: Vec::new() };
+    let mut stack = Vec::new();
+    load_into(file.as_ref(), options, &mut read, &mut stack, &mut loaded)?;
+    Ok(loaded)
+}
+
+fn load_into<F>( file: &Path, options: &ParseOptions, read: &mut F
+               , stack: &mut Vec<PathBuf>, loaded: &mut Loaded)
+               -> Result<(), IncludeError>
+where F: FnMut(&Path) -> io::Result<String> {
+    let source = read(file).map_err(|error| IncludeError::Io {
+        file: file.to_path_buf(), error
+    })?;
+    let mut includes = Vec::new();
+    let blanked = directives(&source, &mut includes)
+        .map_err(|at| IncludeError::InvalidDirective { file: file.to_path_buf(), at })?;
+    let dentries = parse::spanned_dtab(&blanked, options)
+        .map_err(|error| IncludeError::Parse { file: file.to_path_buf(), error })?;
+
+    stack.push(file.to_path_buf());
+    let mut dentries = dentries.into_iter().peekable();
+    for (at, included) in includes {
+        while let Some(spanned) = dentries.next_if(|spanned| spanned.span.start < at) {
+            if spanned.span.end > at {
+                return Err(IncludeError::InvalidDirective { file: file.to_path_buf(), at })
+            }
+            loaded.dtab.push(spanned.dentry);
+            loaded.origins.push(Origin { file: file.to_path_buf(), span: spanned.span });
+        }
+        let included = file.parent().unwrap_or_else(|| Path::new("")).join(included);
+        if stack.contains(&included) {
+            return Err(IncludeError::Cycle { file: file.to_path_buf(), at })
+        }
+        load_into(&included, options, read, stack, loaded)?;
+    }
+    for spanned in dentries {
+        loaded.dtab.push(spanned.dentry);
+        loaded.origins.push(Origin { file: file.to_path_buf(), span: spanned.span });
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Find the `@include` directives in `source`, adding the offset and path
+/// of each to `includes`. Returns the source with each directive replaced
+/// by spaces, or the offset of an invalid directive.
+fn directives<'a>(source: &'a str, includes: &mut Vec<(usize, &'a str)>)
+                  -> Result<String, usize> {
+    let mut blanked = String::with_capacity(source.len());
+    for line in source.split_inclusive('\n') {
+        let directive = line.trim();
+        if !directive.starts_with('@') {
+            blanked.push_str(line);
+            continue
+        }
+        let at = blanked.len() + line.find('@').expect("directive");
+        let path = directive.strip_prefix("@include")
+            .map(str::trim_start)
+            .and_then(|path| path.strip_prefix('"'))
+            .and_then(|path| path.strip_suffix('"'))
+            .filter(|path| !path.is_empty() && !path.contains('"'))
+            .ok_or(at)?;
+        includes.push((at, path));
+        blanked.extend(line.chars().map(|ch| if ch == '\n' { ch } else { ' ' }));
+    }
+    Ok(blanked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::ErrorKind;
+
+    fn files(files: Vec<(&'static str, &'static str)>)
+             -> impl FnMut(&Path) -> io::Result<String> {
+        move |path: &Path| files.iter()
+            .find(|&&(name, _)| Path::new(name) == path)
+            .map(|&(_, source)| source.to_string())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+    }
+
+    #[test]
+    fn includes() {
+        let read = files(vec![ ("main.dtab", "/a => /b;\n  @include \"lib/x.dtab\"  \n/c => /d;")
+                          , ("lib/x.dtab", "@include \"y.dtab\"\n/x => /y;")
+                          , ("lib/y.dtab", "# nothing here\n") ]);
+        let loaded = load_with("main.dtab", &ParseOptions::default(), read).unwrap();
+        assert_eq!(loaded.dtab, dtab!["/a" => "/b"; "/x" => "/y"; "/c" => "/d";]);
+        assert_eq!( loaded.origins
+                  , vec![ Origin { file: "main.dtab".into(), span: Span { start: 0, end: 8 } }
+                        , Origin { file: "lib/x.dtab".into(), span: Span { start: 18, end: 26 } }
+                        , Origin { file: "main.dtab".into(), span: Span { start: 36, end: 44 } }
+                        ]);
+    }
+
+    #[test]
+    fn errors() {
+        let options = ParseOptions::default();
+        let load = |files| load_with("main.dtab", &options, files);
+        match load(files(vec![("main.dtab", "/a => /b;\n@include \"missing.dtab\"\n")])) {
+            Err(IncludeError::Io { ref file, .. }) if file == Path::new("missing.dtab") => {}
+          , result => panic!("expected an I/O error, got {:?}", result)
+        }
+        match load(files(vec![ ("main.dtab", "@include \"a.dtab\"")
+                          , ("a.dtab", "/a => /b;\n@include \"main.dtab\"") ])) {
+            Err(IncludeError::Cycle { ref file, at: 10 }) if file == Path::new("a.dtab") => {}
+          , result => panic!("expected a cycle, got {:?}", result)
+        }
+        for &(source, at) in &[ ("/a =>\n@include \"b.dtab\"\n/b;", 6)
+                              , ("@import \"b.dtab\"", 0)
+                              , ("  @include b.dtab", 2) ] {
+            match load(files(vec![("main.dtab", source), ("b.dtab", "")])) {
+                Err(IncludeError::InvalidDirective { at: actual, .. }) => assert_eq!(actual, at)
+              , result => panic!("expected an invalid directive, got {:?}", result)
+            }
+        }
+        match load(files(vec![("main.dtab", "@include \"a.dtab\""), ("a.dtab", "/a => ")])) {
+            Err(IncludeError::Parse { ref file, ref error }) => {
+                assert_eq!(file, Path::new("a.dtab"));
+                assert_eq!(error.kind, ErrorKind::UnexpectedEof { expected: "a name tree" });
+            }
+          , result => panic!("expected a parse error, got {:?}", result)
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Indexing a dtab's prefixes for fast lookups.
+//!
+//! [`Dtab::lookup`] matches every dentry's prefix against the path it is
+//! given, which is slow for generated dtabs with tens of thousands of
+//! dentries. A [`DtabIndex`] stores the prefixes in a trie instead, with a
+//! branch for each label and one for the wildcard `*`, so that a lookup
+//! only visits the dentries whose prefixes share labels with the path.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::path::Path;
+//!
+//! let dtab = dtab![ "/svc" => "/srv";
+//!                   "/svc/*/users" => "/srv/users";
+//!                   "/srv" => "/#/io.l5d.k8s/default/http"; ];
+//! let index = dtab.index();
+//! let path = Path::read("/svc/v2/users").unwrap();
+//! let matching: Vec<usize> = index.matching(&path).iter().map(|m| m.0).collect();
+//! assert_eq!(matching, vec![1, 0]);
+//! assert_eq!(index.lookup(&path), dtab.lookup(&path));
+//! # }
+//! ```
+//!
+//! [`Dtab::lookup`]: ../struct.Dtab.html#method.lookup
+//! [`DtabIndex`]: struct.DtabIndex.html
+use std::cmp;
+use std::collections::HashMap;
+
+use super::{Dentry, Dtab, NameTree};
+use super::path::{Path, PathError};
+use super::prefix::Elem;
+
+/// A trie of a dtab's prefixes.
+///
+/// This is returned by [`Dtab::index`]. It borrows the dtab, so the dtab
+/// can't change while it is indexed.
+///
+/// [`Dtab::index`]: ../struct.Dtab.html#method.index
+#[derive(Clone, Debug)]
+pub struct DtabIndex<'a> {
+    dtab: &'a Dtab
+  , root: Node
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    /// The indices of the dentries whose prefixes end at this node.
+    dentries: Vec<usize>
+  , labels: HashMap<Vec<u8>, Node>
+  , wildcard: Option<Box<Node>>
+}
+
+impl<'a> DtabIndex<'a> {
+    /// Index the prefixes of every dentry in `dtab`.
+    pub fn new(dtab: &'a Dtab) -> Self {
+        let mut root = Node::default();
+        for (index, dentry) in dtab.iter().enumerate() {
+            let mut node = &mut root;
+            for elem in dentry.prefix().elems() {
+                node = match *elem {
+                    Elem::Label(ref label) => node.labels.entry(label.clone()).or_default()
+                  , Elem::AnyElem => node.wildcard.get_or_insert_with(Box::default)
+                };
+            }
+            node.dentries.push(index);
+        }
+        DtabIndex { dtab, root }
+    }
+
+    /// Returns the indexed dtab.
+    #[inline] pub fn dtab(&self) -> &'a Dtab { self.dtab }
+
+    /// Returns the index of each dentry whose prefix matches `path`, along
+    /// with the dentry itself, in precedence order: later dentries first.
+    pub fn matching(&self, path: &Path) -> Vec<(usize, &'a Dentry)> {
+        self.matches(path).into_iter().map(|(index, _)| (index, &self.dtab[index])).collect()
+    }
+
+    /// Rewrite `path` with every dentry whose prefix matches it, exactly as
+    /// [`Dtab::lookup`] does.
+    ///
+    /// [`Dtab::lookup`]: ../struct.Dtab.html#method.lookup
+    pub fn lookup(&self, path: &Path) -> Result<NameTree<Path>, PathError> {
+        let labels: Vec<&[u8]> = path.labels().collect();
+        let rewrites = self.matches(path).into_iter().map(|(index, len)| {
+            let residual = Path::from_labels(labels[len..].iter().map(|l| l.to_vec()).collect());
+            super::rewrite(self.dtab[index].dst(), &residual)
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(NameTree::alts(rewrites))
+    }
+
+    /// Returns the index and prefix length of each dentry whose prefix
+    /// matches `path`, in precedence order.
+    fn matches(&self, path: &Path) -> Vec<(usize, usize)> {
+        let labels: Vec<&[u8]> = path.labels().collect();
+        let mut matches = Vec::new();
+        let mut stack = vec![(&self.root, 0)];
+        while let Some((node, len)) = stack.pop() {
+            matches.extend(node.dentries.iter().map(|&index| (index, len)));
+            if let Some(&label) = labels.get(len) {
+                if let Some(child) = node.labels.get(label) {
+                    stack.push((child, len + 1));
+                }
+                if let Some(ref wildcard) = node.wildcard {
+                    stack.push((wildcard, len + 1));
+                }
+            }
+        }
+        matches.sort_unstable_by_key(|&(index, _)| cmp::Reverse(index));
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::{GenConfig, Generator};
+
+    #[test]
+    fn matches_lookup() {
+        let config = GenConfig { dentries: 2_000, wildcard_rate: 0.3, ..GenConfig::default() };
+        let mut generator = Generator::new(config);
+        let dtab = generator.dtab();
+        let index = dtab.index();
+        let paths = generator.paths(&dtab, 500);
+        for path in paths.iter().chain(Some(&Path::empty())) {
+            assert_eq!(index.lookup(path), dtab.lookup(path), "looking up {}", path);
+            let expected: Vec<usize> = (0..dtab.len()).rev()
+                .filter(|&i| dtab[i].prefix().matches(path).is_some())
+                .collect();
+            let actual: Vec<usize> = index.matching(path).iter().map(|m| m.0).collect();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn empty_prefix() {
+        let dtab = dtab!["/" => "/a"; "/*" => "/b"; "/c" => "/d";];
+        let index = dtab.index();
+        let lookup = |p| index.lookup(&Path::read(p).unwrap()).unwrap().to_string();
+        assert_eq!(&lookup("/"), "/a");
+        assert_eq!(&lookup("/c/e"), "/d/e | /b/e | /a/c/e");
+        assert_eq!(&lookup("/x"), "/b | /a/x");
+    }
+}
@@ -0,0 +1,205 @@
+//! Filling in `${VAR}` placeholders in dtab templates.
+//!
+//! A single dtab often differs between environments only in a few labels,
+//! such as the name of the environment itself. Rather than generating dtab
+//! text with string templating, a dtab can be written once with `${NAME}`
+//! placeholders, and [`dtab`] fills them in from a lookup function, then
+//! parses the result. Errors are reported at their offsets in the template,
+//! so they point at the text that was actually written.
+//!
+//! Placeholder names are made of ASCII letters, digits, and `_`, and don't
+//! start with a digit. A `$` which isn't followed by `{` is the empty name
+//! tree, as usual, and placeholders within comments are left alone.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::interpolate;
+//! use dtab::parse::ParseOptions;
+//!
+//! let template = "/svc => /env/${DEPLOY_ENV}/svc; # served by ${nobody}\n\
+//!                 /svc/users => /env/${DEPLOY_ENV}/users | $;";
+//! let lookup = |name: &str| match name {
+//!     "DEPLOY_ENV" => Some("staging".to_string())
+//!   , _ => None
+//! };
+//! let dtab = interpolate::dtab(template, &ParseOptions::default(), lookup).unwrap();
+//! assert_eq!( dtab
+//!           , dtab![ "/svc" => "/env/staging/svc"
+//!                  ; "/svc/users" => dtab::NameTree::from("/env/staging/users")
+//!                                    | dtab::NameTree::Empty; ]);
+//! # }
+//! ```
+//!
+//! To fill placeholders from the process's environment, look them up with
+//! `|name| std::env::var(name).ok()`.
+//!
+//! [`dtab`]: fn.dtab.html
+use std::{error, fmt};
+
+use super::Dtab;
+use super::parse::{self, ParseError, ParseOptions};
+
+/// An error filling in a dtab template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InterpolateError {
+    /// The placeholder at this offset has no closing `}`.
+    Unclosed { at: usize }
+  , /// The placeholder at this offset has an invalid name.
+    InvalidName { name: String, at: usize }
+  , /// The lookup function had no value for the placeholder at this offset.
+    Undefined { name: String, at: usize }
+  , /// The filled-in template was not a valid dtab. The error's offset is in
+    /// the template; errors within a substituted value are reported at the
+    /// start of its placeholder.
+    Parse(ParseError)
+}
+
+impl fmt::Display for InterpolateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InterpolateError::Unclosed { at } =>
+                write!(f, "unclosed placeholder at byte {}", at)
+          , InterpolateError::InvalidName { ref name, at } =>
+                write!(f, "invalid placeholder name {:?} at byte {}", name, at)
+          , InterpolateError::Undefined { ref name, at } =>
+                write!(f, "undefined variable {:?} at byte {}", name, at)
+          , InterpolateError::Parse(ref e) => fmt::Display::fmt(e, f)
+        }
+    }
+}
+
+impl error::Error for InterpolateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            InterpolateError::Parse(ref e) => Some(e)
+          , _ => None
+        }
+    }
+}
+
+/// Fill in the placeholders in `template` using `lookup`, and parse the
+/// result as a dtab.
+pub fn dtab<F>(template: &str, options: &ParseOptions, lookup: F)
+               -> Result<Dtab, InterpolateError>
+where F: FnMut(&str) -> Option<String> {
+    let (text, offsets) = fill(template, lookup)?;
+    parse::dtab(&text, options).map_err(|e| {
+        let at = offsets[e.at.min(text.len())];
+        InterpolateError::Parse(ParseError { at, ..e })
+    })
+}
+
+/// Fill in the placeholders in `template` using `lookup`, returning the
+/// resulting dtab text without parsing it.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::interpolate::{self, InterpolateError};
+///
+/// let lookup = |name: &str| if name == "ENV" { Some("prod".to_string()) } else { None };
+/// assert_eq!( interpolate::expand("/svc => /${ENV}/svc", lookup).unwrap()
+///           , "/svc => /prod/svc");
+/// assert_eq!( interpolate::expand("/svc => /${REGION}/svc", lookup)
+///           , Err(InterpolateError::Undefined { name: "REGION".to_string(), at: 9 }));
+/// ```
+pub fn expand<F>(template: &str, lookup: F) -> Result<String, InterpolateError>
+where F: FnMut(&str) -> Option<String> {
+    fill(template, lookup).map(|(text, _)| text)
+}
+
+/// Fill in the placeholders in `template`. Returns the filled-in text and,
+/// for each byte of the text and for its end, the corresponding offset in
+/// `template`.
+fn fill<F>(template: &str, mut lookup: F) -> Result<(String, Vec<usize>), InterpolateError>
+where F: FnMut(&str) -> Option<String> {
+    let mut text = String::with_capacity(template.len());
+    let mut offsets = Vec::with_capacity(template.len() + 1);
+    // whether the scanner is within a path, where `#` isn't a comment.
+    let mut in_path = false;
+    let mut rest = template;
+    while let Some(ch) = rest.chars().next() {
+        let at = template.len() - rest.len();
+        let len = match ch {
+            '$' if rest.starts_with("${") => {
+                let end = rest.find('}').ok_or(InterpolateError::Unclosed { at })?;
+                let name = &rest[2..end];
+                if !is_name(name) {
+                    return Err(InterpolateError::InvalidName { name: name.to_string(), at })
+                }
+                let value = lookup(name).ok_or_else(|| InterpolateError::Undefined {
+                    name: name.to_string(), at
+                })?;
+                text.push_str(&value);
+                offsets.resize(text.len(), at);
+                rest = &rest[end + 1..];
+                continue
+            }
+          , '#' if !in_path => rest.find('\n').unwrap_or(rest.len())
+          , _ => {
+                in_path = match ch {
+                    '/' => true
+                  , ch => in_path && !(ch.is_whitespace() || ";|&()=".contains(ch))
+                };
+                ch.len_utf8()
+            }
+        };
+        text.push_str(&rest[..len]);
+        offsets.extend(at..at + len);
+        rest = &rest[len..];
+    }
+    offsets.push(template.len());
+    Ok((text, offsets))
+}
+
+/// Returns true if `name` is a valid placeholder name.
+pub(crate) fn is_name(name: &str) -> bool {
+    name.bytes().next().is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::ErrorKind;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "ENV" => Some("staging".to_string())
+          , "EMPTY" => Some(String::new())
+          , "BAD" => Some("a b".to_string())
+          , _ => None
+        }
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!( expand("/a/${ENV}${EMPTY}/b#${ENV} => /c # ${UNSET}\n", lookup).unwrap()
+                  , "/a/staging/b#staging => /c # ${UNSET}\n");
+        assert_eq!( expand("/a => $ | /${ENV}", lookup).unwrap()
+                  , "/a => $ | /staging");
+        assert_eq!( expand("/a => /${ENV", lookup)
+                  , Err(InterpolateError::Unclosed { at: 7 }));
+        assert_eq!( expand("/a => /${1ENV}", lookup)
+                  , Err(InterpolateError::InvalidName { name: "1ENV".to_string(), at: 7 }));
+        assert_eq!( expand("/a => /${}", lookup)
+                  , Err(InterpolateError::InvalidName { name: String::new(), at: 7 }));
+    }
+
+    #[test]
+    fn errors_are_located_in_the_template() {
+        let options = ParseOptions::default();
+        let error = |template: &str| match dtab(template, &options, lookup) {
+            Err(InterpolateError::Parse(e)) => e
+          , result => panic!("expected a parse error, got {:?}", result)
+        };
+        assert_eq!(error("/${ENV} => /${ENV} /b").at, 19);
+        assert_eq!(error("/${ENV} => /${BAD}").at, 12);
+        assert_eq!( error("/${ENV} => /${ENV} |")
+                  , ParseError { kind: ErrorKind::UnexpectedEof { expected: "a name tree" }
+                               , at: 20 });
+    }
+}
@@ -11,17 +11,19 @@ extern crate pretty_assertions;
 
 extern crate serde;
 #[macro_use] extern crate serde_derive;
-//
-// #[macro_use] extern crate nom;
+#[cfg(any(test, feature = "conformance", feature = "lsp"))]
+#[macro_use] extern crate serde_json;
+#[cfg(feature = "linkerd-config")]
+extern crate serde_yaml;
+#[cfg(feature = "rand")]
+extern crate rand;
+#[cfg(feature = "futures")]
+extern crate futures;
 
-// extern crate regex;
+use std::{convert, fmt, fs, io, iter, ops, slice, str, vec};
 
-use std::fmt;
-
-pub mod nametree;
-pub mod path;
-
-pub use self::nametree::*;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
 
 
 /// Macro for constructing a [`Dentry`].
@@ -34,6 +36,14 @@ pub use self::nametree::*;
 /// The macro also allows the use of `=>` rather than `>>`, as to
 /// more closely match the dtab synbtax.
 ///
+/// The prefix may be a string or a [`Prefix`]. The destination may be a
+/// [`NameTree`], or anything which can be converted into one, such as a
+/// string or a [`Path`].
+///
+/// # Panics
+///
+/// If the prefix is a string which is not a valid [`Prefix`].
+///
 /// # Examples
 ///
 /// ```
@@ -51,15 +61,82 @@ pub use self::nametree::*;
 /// # }
 /// ```
 ///
+/// Within the destination, string literals are converted into `NameTree`s,
+/// and a numeric literal followed by `*` is a weight, so the destination
+/// can be written much as it would be in a dtab:
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// let dentry = dentry!("/svc/a" => 0.9 * "/svc/a-v1" & 0.1 * "/svc/a-v2" | "/svc/b");
+///
+/// assert_eq!( "/svc/a => 0.9 * /svc/a-v1 & 0.1 * /svc/a-v2 | /svc/b;"
+///           , &dentry.to_string());
+/// # }
+/// ```
+///
+/// [`Dentry`]: struct.Dentry.html
+/// [`NameTree`]: enum.NameTree.html
+/// [`Path`]: path/struct.Path.html
+/// [`Prefix`]: prefix/struct.Prefix.html
 #[macro_export]
 macro_rules! dentry {
-  ($src: expr => $dst: expr ) => ($crate::Dentry {
-      prefix: $crate::NameTree::from($src), dst: $dst
+  ($src: expr => $($dst: tt)+ ) => ($crate::Dentry::new(
+      <$crate::Prefix as ::std::convert::TryFrom<_>>::try_from($src)
+          .expect("invalid dentry prefix")
+    , $crate::NameTree::<String>::from($crate::__dtab_dst!([] $($dst)+))
+  ))
+}
+
+/// Macro for constructing a [`Prefix`] from a string literal.
+///
+/// The literal is validated at compile time, so the resulting `Prefix` can be
+/// used with the `>>` operator to construct a [`Dentry`] without any error
+/// handling.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::NameTree;
+///
+/// let dentry = prefix!("/iceCreamStore") >> (NameTree::from("/humphrys") | "/smitten");
+/// assert_eq!( "/iceCreamStore => /humphrys | /smitten;"
+///           , &dentry.to_string());
+/// # }
+/// ```
+///
+/// Invalid prefixes are compile errors:
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// let prefix = prefix!("/ice cream store");
+/// # }
+/// ```
+///
+/// [`Dentry`]: struct.Dentry.html
+/// [`Prefix`]: prefix/struct.Prefix.html
+#[macro_export]
+macro_rules! prefix {
+  ($src: expr) => ({
+      const _: () = assert!( $crate::prefix::is_valid($src)
+                           , "invalid prefix literal");
+      $crate::Prefix::read($src)
+          .expect("prefix literal was validated at compile time")
   })
 }
 
 /// Convenience macro for making [`Dtab`]s.
 ///
+/// As with [`dentry!`], destinations which are plain strings or paths don't
+/// need to be wrapped in a [`NameTree`]. Since each destination is parsed as
+/// a single Rust expression, the weight syntax understood by [`dentry!`]
+/// isn't available here; use `W` or `NameTree::weighted` instead. To write
+/// a dtab in the dtab syntax, checked at compile time, use `dtab_static!`
+/// from the [`dtab-macros`] crate.
+///
 /// # Examples
 ///
 /// ```
@@ -68,7 +145,7 @@ macro_rules! dentry {
 /// use dtab::NameTree;
 ///
 /// let dtab = dtab![
-///   "/smitten"       => NameTree::from("/USA/CA/SF/Harrison/2790");
+///   "/smitten"       => "/USA/CA/SF/Harrison/2790";
 ///   "/iceCreamStore" => NameTree::from("/humphrys") | "/smitten";
 /// ];
 ///
@@ -80,38 +157,1225 @@ macro_rules! dentry {
 /// # }
 /// ```
 ///
-/// [`Dtab`]: type.Dtab.html
+/// [`Dtab`]: struct.Dtab.html
+/// [`dentry!`]: macro.dentry.html
+/// [`NameTree`]: enum.NameTree.html
+/// [`dtab-macros`]: https://docs.rs/dtab-macros
 #[macro_export]
 macro_rules! dtab {
+  () => ($crate::Dtab::default());
   ($($src: expr => $dst: expr ;)+) => (
-    $crate::Dtab(vec![ $(dentry!($src => $dst)),+ ])
+    $crate::Dtab::from(vec![ $($crate::dentry!($src => $dst)),+ ])
   )
 }
+
+/// Rewrites the destination side of a [`dentry!`] into a `NameTree`
+/// expression, wrapping string literals in `NameTree::from` and weight
+/// literals in `Weight::saturating_from_f64`.
+///
+/// [`dentry!`]: macro.dentry.html
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __dtab_dst {
+  ([$($out: tt)*]) => ($($out)*);
+  ([$($out: tt)*] $weight: literal * $($rest: tt)*) => (
+    $crate::__dtab_dst!(
+      [$($out)* $crate::weight::Weight::saturating_from_f64(f64::from($weight)) *] $($rest)*)
+  );
+  ([$($out: tt)*] $leaf: literal $($rest: tt)*) => (
+    $crate::__dtab_dst!(
+      [$($out)* $crate::NameTree::<String>::from($leaf)] $($rest)*)
+  );
+  ([$($out: tt)*] $f: ident ( $($args: tt)* ) $($rest: tt)*) => (
+    $crate::__dtab_dst!([$($out)* $f ( $($args)* )] $($rest)*)
+  );
+  ([$($out: tt)*] ( $($group: tt)+ ) $($rest: tt)*) => (
+    $crate::__dtab_dst!(
+      [$($out)* ($crate::NameTree::<String>::from(
+        $crate::__dtab_dst!([] $($group)+)))] $($rest)*)
+  );
+  ([$($out: tt)*] $other: tt $($rest: tt)*) => (
+    $crate::__dtab_dst!([$($out)* $other] $($rest)*)
+  );
+}
+
+#[cfg(feature = "futures")]
+pub mod async_namer;
+pub mod builder;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod cst;
+pub mod delegate;
+pub mod delegator;
+pub mod dot;
+#[cfg(feature = "dns")]
+pub mod dns;
+pub mod equivalence;
+pub mod format;
+pub mod generate;
+pub mod golden;
+pub mod header;
+pub mod history;
+pub mod include;
+pub mod index;
+pub mod interpolate;
+#[cfg(feature = "linkerd-config")]
+pub mod linkerd;
+pub mod lint;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod merge;
+pub mod minimize;
+pub mod name;
+pub mod namer;
+pub mod namespace;
+pub mod nametree;
+pub mod observe;
+pub mod parse;
+pub mod path;
+pub mod prefix;
+pub mod profile;
+#[cfg(feature = "rand")]
+pub mod sample;
+#[cfg(feature = "cli")]
+pub mod repl;
+pub mod template;
+pub mod validate;
+pub mod watch;
+pub mod weight;
+
+pub use self::nametree::*;
+pub use self::prefix::{DelegateTo, Prefix};
+
 /// A `dtab` (delegation table) comprises a sequence of delegation rules.
-#[derive(Debug, Clone, Serialize)]
-pub struct Dtab(pub Vec<Dentry>);
+///
+/// The default dtab is the empty table, so `Dtab`s can be used as fields of
+/// configuration structs which derive `Default`. Together with
+/// [`is_default`], this also allows empty tables to be left out when a
+/// configuration is serialized:
+///
+/// ```
+/// # extern crate dtab;
+/// # extern crate serde;
+/// # #[macro_use] extern crate serde_derive;
+/// use dtab::Dtab;
+///
+/// #[derive(Default, Serialize)]
+/// struct RouterConfig {
+///     label: String
+///   , #[serde(skip_serializing_if = "Dtab::is_default")]
+///     dtab: Dtab
+/// }
+/// # fn main() {
+/// let config = RouterConfig::default();
+/// assert!(config.dtab.is_default());
+/// # }
+/// ```
+///
+/// A dtab dereferences to a slice of its dentries, so they can be read with
+/// the usual slice methods. It can only be modified through methods such as
+/// [`push`], [`insert`], and [`remove`], so that future versions can
+/// maintain invariants over its contents:
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// let mut dtab = dtab![
+///   "/smitten" => "/USA/CA/SF/Harrison/2790";
+/// ];
+/// dtab.push(dentry!("/iceCreamStore" => "/smitten"));
+/// assert_eq!(dtab.len(), 2);
+/// assert_eq!(&dtab[1].to_string(), "/iceCreamStore => /smitten;");
+///
+/// let removed = dtab.remove(0);
+/// assert_eq!(&removed.to_string(), "/smitten => /USA/CA/SF/Harrison/2790;");
+/// # }
+/// ```
+///
+/// [`is_default`]: #method.is_default
+/// [`push`]: #method.push
+/// [`insert`]: #method.insert
+/// [`remove`]: #method.remove
+#[derive(Debug, Clone, Default, PartialEq)]
+#[allow(deprecated)]
+pub struct Dtab(
+    #[deprecated( since = "0.0.3"
+                , note = "read dentries through `Deref<Target=[Dentry]>`, and \
+                          modify them with `Dtab::push`, `insert` and `remove`")]
+    pub Vec<Dentry>
+);
 
-impl fmt::Display for Dtab {
-    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.iter()
-            .fold( Ok(())
-                 , |r, ref entry| r.and_then(|_| {
-                        write!(f, "{}\n", entry)
-                    }))
+#[allow(deprecated)]
+impl Dtab {
+    /// The empty dtab, which rewrites no paths.
+    ///
+    /// This is the identity of [`concat`]: concatenating it with any dtab
+    /// returns that dtab.
+    ///
+    /// [`concat`]: #method.concat
+    pub const EMPTY: Dtab = Dtab(Vec::new());
+
+    /// Returns a new, empty, dtab.
+    #[inline] pub fn new() -> Self { Dtab(Vec::new()) }
+
+    /// Parse a dtab from its text form, using the default [`ParseOptions`].
+    ///
+    /// Both the multi-line form written by `Display` and the compact form
+    /// written by `{:#}` are accepted, so a dtab can be read back after it
+    /// has been displayed:
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::{Dtab, NameTree};
+    ///
+    /// let dtab = dtab![
+    ///   "/smitten"       => "/USA/CA/SF/Harrison/2790";
+    ///   "/iceCreamStore" => NameTree::from("/humphrys") | "/smitten";
+    /// ];
+    /// assert_eq!(Dtab::read(&dtab.to_string()).unwrap(), dtab);
+    /// assert_eq!(format!("{:#}", dtab).parse::<Dtab>().unwrap(), dtab);
+    /// # }
+    /// ```
+    ///
+    /// A dtab's text form is always ASCII: labels in prefixes and
+    /// destinations alike are written with `\xNN` escapes for any bytes which
+    /// aren't showable. The default options only accept labels of showable
+    /// ASCII characters, so a dtab whose labels needed escaping must be read
+    /// back with the label policy it was read with, or with the [`Any`]
+    /// policy, which reads back every label, on both sides of each dentry:
+    ///
+    /// ```
+    /// use dtab::Dtab;
+    /// use dtab::parse::ParseOptions;
+    /// use dtab::path::LabelPolicy;
+    ///
+    /// let utf8 = ParseOptions { labels: LabelPolicy::Utf8, ..ParseOptions::default() };
+    /// let dtab = Dtab::read_with("/café => /crêperie", &utf8).unwrap();
+    /// assert_eq!(&dtab.to_string(), "/caf\\xc3\\xa9 => /cr\\xc3\\xaaperie;\n");
+    /// assert!(Dtab::read(&dtab.to_string()).is_err());
+    /// assert_eq!(Dtab::read_with(&dtab.to_string(), &utf8), Ok(dtab.clone()));
+    ///
+    /// let any = ParseOptions { labels: LabelPolicy::Any, ..ParseOptions::default() };
+    /// assert_eq!(Dtab::read_with(&dtab.to_string(), &any), Ok(dtab));
+    /// ```
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    /// [`Any`]: path/enum.LabelPolicy.html#variant.Any
+    #[inline] pub fn read(s: &str) -> Result<Self, parse::ParseError> {
+        Dtab::read_with(s, &parse::ParseOptions::default())
+    }
+
+    /// Parse a dtab from its text form, using the given [`ParseOptions`].
+    /// See [`parse::dtab`].
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    /// [`parse::dtab`]: parse/fn.dtab.html
+    #[inline]
+    pub fn read_with(s: &str, options: &parse::ParseOptions)
+                     -> Result<Self, parse::ParseError> {
+        parse::dtab(s, options)
+    }
+
+    /// Parse as many dentries as possible from the start of `s`, using the
+    /// default [`ParseOptions`], returning them and the rest of `s`. See
+    /// [`parse::dtab_partial`].
+    ///
+    /// ```
+    /// use dtab::Dtab;
+    ///
+    /// let (dtab, rest) = Dtab::read_partial("/svc => /srv;\n}\nnext: 1");
+    /// assert_eq!(&dtab.to_string(), "/svc => /srv;\n");
+    /// assert_eq!(rest, "\n}\nnext: 1");
+    /// ```
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    /// [`parse::dtab_partial`]: parse/fn.dtab_partial.html
+    #[inline] pub fn read_partial(s: &str) -> (Self, &str) {
+        parse::dtab_partial(s, &parse::ParseOptions::default())
+    }
+
+    /// Build a dtab from the `--dtab` overrides in a list of command-line
+    /// arguments, using the default [`ParseOptions`]. See [`parse::args`].
+    ///
+    /// ```
+    /// use dtab::Dtab;
+    ///
+    /// let args = vec!["serve", "--dtab", "/svc => /srv", "--dtab=/svc/a => /srv/b"];
+    /// let dtab = Dtab::from_args(args).unwrap();
+    /// assert_eq!(&format!("{:#}", dtab), "/svc=>/srv;/svc/a=>/srv/b");
+    /// ```
+    ///
+    /// In a binary, pass it `std::env::args().skip(1)`.
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    /// [`parse::args`]: parse/fn.args.html
+    #[inline]
+    pub fn from_args<I, S>(args: I) -> Result<Self, parse::ArgsError>
+    where I: IntoIterator<Item = S>
+        , S: AsRef<str> {
+        parse::args(args, &parse::ParseOptions::default())
+    }
+
+    /// Parse a dtab from its text form, using the default [`ParseOptions`],
+    /// skipping invalid dentries. Returns the valid dentries, and an error
+    /// for every invalid one. See [`parse::dtab_lenient`].
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    /// [`parse::dtab_lenient`]: parse/fn.dtab_lenient.html
+    #[inline] pub fn read_lenient(s: &str) -> (Self, Vec<parse::ParseError>) {
+        parse::dtab_lenient(s, &parse::ParseOptions::default())
+    }
+
+    /// Read and parse a dtab from `reader`, using the default
+    /// [`ParseOptions`].
+    ///
+    /// The error distinguishes failures to read the input, including input
+    /// which is not valid UTF-8, from input which is not a valid dtab.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::Dtab;
+    /// use dtab::parse::ReadError;
+    ///
+    /// let dtab = Dtab::from_reader(&b"/svc => /srv;\n/srv => /#/io.l5d.fs;\n"[..]).unwrap();
+    /// assert_eq!(dtab.len(), 2);
+    ///
+    /// match Dtab::from_reader(&b"/svc => /srv |"[..]) {
+    ///     Err(ReadError::Parse(e)) => assert_eq!(e.at, 14)
+    ///   , other => panic!("expected a parse error, got {:?}", other)
+    /// }
+    /// match Dtab::from_reader(&b"/svc => /\xff;"[..]) {
+    ///     Err(ReadError::Io(_)) => {}
+    ///   , other => panic!("expected an I/O error, got {:?}", other)
+    /// }
+    /// ```
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    #[inline]
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Self, parse::ReadError> {
+        Dtab::from_reader_with(reader, &parse::ParseOptions::default())
+    }
+
+    /// Read and parse a dtab from `reader`, using the given
+    /// [`ParseOptions`].
+    ///
+    /// The input is parsed as it is read, one dentry at a time, so the
+    /// input length limit applies to each dentry rather than to the whole
+    /// input. See [`DentryReader`].
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    /// [`DentryReader`]: parse/struct.DentryReader.html
+    pub fn from_reader_with<R: io::Read>(reader: R, options: &parse::ParseOptions)
+                                         -> Result<Self, parse::ReadError> {
+        parse::DentryReader::new(io::BufReader::new(reader), *options).collect()
+    }
+
+    /// Read and parse the dtab in the file at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use std::env;
+    /// use dtab::Dtab;
+    /// use dtab::parse::FileError;
+    ///
+    /// let path = env::temp_dir().join(format!("from_file-{}.dtab", std::process::id()));
+    /// let dtab = dtab!["/svc" => "/srv"; "/srv" => "/#/io.l5d.fs";];
+    /// dtab.to_file(&path).unwrap();
+    /// assert_eq!(Dtab::from_file(&path).unwrap(), dtab);
+    ///
+    /// std::fs::write(&path, "/svc => /srv |").unwrap();
+    /// match Dtab::from_file(&path) {
+    ///     Err(FileError::Parse { path: ref p, ref error }) => {
+    ///         assert_eq!(p, &path);
+    ///         assert_eq!(error.at, 14);
+    ///     }
+    ///   , other => panic!("expected a parse error, got {:?}", other)
+    /// }
+    /// std::fs::remove_file(&path).unwrap();
+    /// assert!(Dtab::from_file(&path).is_err());
+    /// # }
+    /// ```
+    #[inline]
+    pub fn from_file<P: AsRef<::std::path::Path>>(path: P) -> Result<Self, parse::FileError> {
+        Dtab::from_file_with(path, &parse::ParseOptions::default())
+    }
+
+    /// Read and parse the dtab in the file at `path`, using the given
+    /// [`ParseOptions`].
+    ///
+    /// [`ParseOptions`]: parse/struct.ParseOptions.html
+    pub fn from_file_with<P>(path: P, options: &parse::ParseOptions)
+                             -> Result<Self, parse::FileError>
+    where P: AsRef<::std::path::Path> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| parse::FileError::Io {
+            path: path.to_path_buf(), error
+        })?;
+        Dtab::read_with(&source, options).map_err(|error| parse::FileError::Parse {
+            path: path.to_path_buf(), error
+        })
+    }
+
+    /// Write this dtab to the file at `path`, replacing it atomically.
+    ///
+    /// The dtab is written to a temporary file in the same directory, which
+    /// is then renamed over `path`, so readers of `path` see either its old
+    /// contents or the whole of the new dtab, and never part of it. Each
+    /// write has its own temporary file, so concurrent writers, in this
+    /// process or others, each replace `path` with a whole dtab.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use std::{env, thread};
+    /// use dtab::Dtab;
+    ///
+    /// let dir = env::temp_dir().join(format!("to_file-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("concurrent.dtab");
+    /// let dtabs = (0..8).map(|i| dtab!["/svc" => &format!("/srv/{}", i)[..];])
+    ///     .collect::<Vec<_>>();
+    /// let writers = dtabs.iter().cloned().map(|dtab| {
+    ///     let path = path.clone();
+    ///     thread::spawn(move || for _ in 0..20 { dtab.to_file(&path).unwrap() })
+    /// }).collect::<Vec<_>>();
+    /// for writer in writers {
+    ///     writer.join().unwrap();
+    /// }
+    /// assert!(dtabs.contains(&Dtab::from_file(&path).unwrap()));
+    /// // no temporary files are left behind.
+    /// assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// # }
+    /// ```
+    pub fn to_file<P: AsRef<::std::path::Path>>(&self, path: P) -> Result<(), parse::FileError> {
+        use std::io::Write;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        // distinguishes the temporary files of writes in this process.
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+        let path = path.as_ref();
+        let io_error = |error| parse::FileError::Io { path: path.to_path_buf(), error };
+        let name = path.file_name().ok_or_else(|| io_error(io::Error::new(
+            io::ErrorKind::InvalidInput, "path does not name a file")))?;
+        // a temporary file left behind by a crashed process with the same id
+        // is never reused, so a name that's taken is skipped.
+        let (temp, mut file) = loop {
+            let mut temp_name = ::std::ffi::OsString::from(".");
+            temp_name.push(name);
+            temp_name.push(format!( ".{}.{}.tmp", ::std::process::id()
+                                  , WRITES.fetch_add(1, Ordering::Relaxed)));
+            let temp = path.with_file_name(temp_name);
+            match fs::OpenOptions::new().write(true).create_new(true).open(&temp) {
+                Ok(file) => break (temp, file)
+              , Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue
+              , Err(e) => return Err(io_error(e))
+            }
+        };
+        let written = file.write_all(self.to_string().as_bytes())
+            .and_then(|_| file.sync_all());
+        drop(file);
+        let written = written.and_then(|_| fs::rename(&temp, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        written.map_err(io_error)
+    }
+
+    /// Returns true if this is the default, empty, dtab.
+    #[inline] pub fn is_default(&self) -> bool { self.0.is_empty() }
+
+    /// Append a dentry to the end of this dtab, where it takes precedence
+    /// over every existing dentry.
+    #[inline] pub fn push(&mut self, dentry: Dentry) { self.0.push(dentry) }
+
+    /// Returns this dtab followed by every dentry of `other`, as Finagle's
+    /// `++` does.
+    ///
+    /// Since later dentries take precedence, the dentries of `other`
+    /// override those of this dtab wherever both match a path, as
+    /// `Dtab.local` overrides `Dtab.base` in Finagle. The `+` operator does
+    /// the same.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::Dtab;
+    /// use dtab::path::Path;
+    ///
+    /// let base = dtab!["/svc" => "/#/io.l5d.k8s/default/http";];
+    /// let local = dtab!["/svc/users" => "/#/io.l5d.k8s/canary/http/users";];
+    /// let dtab = base.concat(&local);
+    /// assert_eq!(dtab.len(), 2);
+    /// assert_eq!( &dtab.lookup(&Path::read("/svc/users").unwrap()).unwrap().to_string()
+    ///           , "/#/io.l5d.k8s/canary/http/users | /#/io.l5d.k8s/default/http/users");
+    ///
+    /// assert_eq!(base.clone() + local, dtab);
+    /// assert_eq!(&base + &Dtab::EMPTY, base);
+    /// # }
+    /// ```
+    pub fn concat(&self, other: &Dtab) -> Dtab {
+        self.iter().chain(other.iter()).cloned().collect()
+    }
+
+    /// Merge `overrides` over this dtab, as [`concat`] does, except that
+    /// each dentry of this dtab with the same prefix as an override is
+    /// replaced by it, rather than shadowed. Returns the merged dtab, and
+    /// each dentry which was replaced. See [`merge`].
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// let base = dtab!["/svc" => "/srv"; "/env" => "/prod";];
+    /// let (dtab, replaced) = base.override_with(&dtab!["/env" => "/staging";]);
+    /// assert_eq!(dtab, dtab!["/svc" => "/srv"; "/env" => "/staging";]);
+    /// assert_eq!(replaced[0].dentry, base[1]);
+    /// # }
+    /// ```
+    ///
+    /// [`concat`]: #method.concat
+    /// [`merge`]: merge/index.html
+    #[inline]
+    pub fn override_with(&self, overrides: &Dtab) -> (Dtab, Vec<merge::Replacement>) {
+        merge::override_with(self, overrides)
+    }
+
+    /// Insert a dentry at position `index`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is greater than the number of dentries.
+    #[inline] pub fn insert(&mut self, index: usize, dentry: Dentry) {
+        self.0.insert(index, dentry)
+    }
+
+    /// Remove and return the dentry at position `index`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    #[inline] pub fn remove(&mut self, index: usize) -> Dentry {
+        self.0.remove(index)
+    }
+
+    /// Consume this dtab, returning its dentries.
+    #[inline] pub fn into_dentries(self) -> Vec<Dentry> { self.0 }
+
+    /// Run every [lint] against this dtab.
+    ///
+    /// [lint]: lint/index.html
+    #[inline] pub fn lint(&self) -> Vec<lint::Finding> {
+        lint::lint(self)
+    }
+
+    /// Look up `path` in this dtab, rewriting it with every dentry whose
+    /// prefix matches it.
+    ///
+    /// The rewrites are combined into an alternation in reverse entry
+    /// order, with one alternative for each matching dentry, so later
+    /// dentries take precedence over earlier ones, and an earlier dentry is
+    /// only used if the names from later ones fail to bind. If no dentry
+    /// matches, the result is the negation `~`. This is
+    /// one step of delegation, as in Finagle's `Dtab.lookup`; the resulting
+    /// paths aren't looked up again. See [`Dentry::rewrite`], which returns
+    /// the error if a matching dentry has a leaf which isn't a valid path.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::NameTree;
+    /// use dtab::path::Path;
+    ///
+    /// let dtab = dtab![ "/svc" => NameTree::from("/srv/east") | "/srv/west";
+    ///                   "/svc/users" => "/srv/users-v2";
+    ///                   "/svc/*/admin" => "!"; ];
+    /// let lookup = |path| dtab.lookup(&Path::read(path).unwrap()).unwrap().to_string();
+    /// assert_eq!( &lookup("/svc/users/get")
+    ///           , "/srv/users-v2/get | (/srv/east/users/get | /srv/west/users/get)");
+    /// assert_eq!(&lookup("/svc/web/admin"), "! | (/srv/east/web/admin | /srv/west/web/admin)");
+    /// assert_eq!(&lookup("/svc"), "/srv/east | /srv/west");
+    /// assert_eq!(dtab.lookup(&Path::read("/other").unwrap()), Ok(NameTree::Neg));
+    /// # }
+    /// ```
+    ///
+    /// [`Dentry::rewrite`]: struct.Dentry.html#method.rewrite
+    pub fn lookup(&self, path: &path::Path)
+                  -> Result<NameTree<path::Path>, path::PathError> {
+        let rewrites = self.iter().rev()
+            .filter_map(|dentry| dentry.rewrite(path).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(NameTree::alts(rewrites))
+    }
+
+    /// Returns the dentry which takes precedence for `path`, and its index:
+    /// the last dentry whose prefix matches it, or `None` if none does.
+    ///
+    /// This is the first alternative of [`lookup`], so it shows whether a
+    /// dentry appended to override another actually shadows it for a path.
+    /// Earlier matching dentries are still tried if the names it rewrites
+    /// the path to fail to bind.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::path::Path;
+    ///
+    /// let dtab = dtab![ "/svc" => "/srv";
+    ///                   "/svc/users" => "/srv/users-v2";
+    ///                   "/svc/*/admin" => "!"; ];
+    /// let winner = |path| dtab.winning_entry(&Path::read(path).unwrap()).map(|(i, _)| i);
+    /// assert_eq!(winner("/svc/users/get"), Some(1));
+    /// // the override only shadows the dentry for paths it matches.
+    /// assert_eq!(winner("/svc/users/admin"), Some(2));
+    /// assert_eq!(winner("/svc/orders"), Some(0));
+    /// assert_eq!(winner("/other"), None);
+    /// assert_eq!(dtab.winning_entry(&Path::read("/svc").unwrap()), Some((0, &dtab[0])));
+    /// # }
+    /// ```
+    ///
+    /// [`lookup`]: #method.lookup
+    pub fn winning_entry(&self, path: &path::Path) -> Option<(usize, &Dentry)> {
+        self.iter().enumerate().rev().find(|&(_, dentry)| dentry.prefix().matches(path).is_some())
+    }
+
+    /// Index this dtab's prefixes in a trie, for faster lookups in large
+    /// dtabs. See [`index`].
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::path::Path;
+    ///
+    /// let dtab = dtab!["/svc" => "/srv"; "/svc/*/users" => "/srv/users";];
+    /// let index = dtab.index();
+    /// let path = Path::read("/svc/v2/users").unwrap();
+    /// assert_eq!(&index.lookup(&path).unwrap().to_string(), "/srv/users | /srv/v2/users");
+    /// # }
+    /// ```
+    ///
+    /// [`index`]: index/index.html
+    #[inline] pub fn index(&self) -> index::DtabIndex<'_> {
+        index::DtabIndex::new(self)
+    }
+
+    /// Delegate `path` through this dtab, looking up each rewritten path
+    /// again until no dentry matches, using the default
+    /// [`DelegateOptions`] and no [`Namer`]. See [`delegate`].
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::path::Path;
+    ///
+    /// let dtab = dtab!["/srv" => "/$/inet/127.1/8080"; "/svc" => "/srv";];
+    /// let tree = dtab.delegate(&Path::read("/svc").unwrap()).unwrap();
+    /// assert_eq!(&tree.to_string(), "/$/inet/127.1/8080");
+    /// # }
+    /// ```
+    ///
+    /// [`DelegateOptions`]: delegate/struct.DelegateOptions.html
+    /// [`delegate`]: delegate/index.html
+    /// [`Namer`]: namer/trait.Namer.html
+    #[inline]
+    pub fn delegate(&self, path: &path::Path)
+                    -> Result<NameTree<name::Name>, delegate::DelegateError> {
+        delegate::delegate(self, path, &delegate::DelegateOptions::default(), (), ())
+    }
+
+    /// Delegate `path` through this dtab, as [`Dtab::delegate`] does,
+    /// recording every lookup to explain the result. See
+    /// [`delegate::trace`].
+    ///
+    /// [`Dtab::delegate`]: #method.delegate
+    /// [`delegate::trace`]: delegate/fn.trace.html
+    #[inline] pub fn trace(&self, path: &path::Path) -> delegate::DelegationTrace {
+        delegate::trace(self, path, &delegate::DelegateOptions::default(), (), ())
+    }
+
+    /// Explain how `path` delegates through this dtab, in numbered
+    /// sentences naming each entry which matched a path along the way. See
+    /// [`DelegationTrace::explain`].
+    ///
+    /// [`DelegationTrace::explain`]: delegate/struct.DelegationTrace.html#method.explain
+    #[inline] pub fn explain(&self, path: &path::Path) -> String {
+        self.trace(path).explain()
+    }
+
+    /// Check that every dentry in this dtab is [valid].
+    ///
+    /// [valid]: validate/index.html
+    #[inline]
+    pub fn validate(&self, validation: &validate::Validation)
+                    -> Result<(), validate::ValidationError> {
+        validation.check_dtab(self)
+    }
+
+    /// Apply a [`WeightPolicy`] to the destination of every dentry in this
+    /// dtab, before it is written out for a particular consumer.
+    ///
+    /// If the policy is `Require`, the error identifies the first dentry
+    /// containing a union whose weights don't sum to one.
+    ///
+    /// [`WeightPolicy`]: weight/enum.WeightPolicy.html
+    pub fn apply_weight_policy(&mut self, policy: weight::WeightPolicy)
+                               -> Result<(), weight::WeightSumError> {
+        for (i, dentry) in self.0.iter_mut().enumerate() {
+            dentry.dst.apply_weight_policy(policy)
+                .map_err(|error| weight::WeightSumError { entry: Some(i), ..error })?;
+        }
+        Ok(())
+    }
+
+    /// Remove and merge dentries without changing the result of delegating
+    /// any of `paths`. See [`minimize`].
+    ///
+    /// [`minimize`]: minimize/fn.minimize.html
+    #[inline]
+    pub fn minimize(&self, paths: &[path::Path]) -> minimize::Minimized {
+        minimize::minimize(self, paths)
+    }
+
+    /// Compare the result of delegating each of `paths` under this dtab and
+    /// `other`. See [`behaves_like`].
+    ///
+    /// [`behaves_like`]: equivalence/fn.behaves_like.html
+    #[inline]
+    pub fn behaves_like(&self, other: &Dtab, paths: &[path::Path])
+                        -> equivalence::EquivalenceReport {
+        equivalence::behaves_like(self, other, paths)
+    }
+
+    /// Encode this dtab as one or more header values. See [`encode`].
+    ///
+    /// [`encode`]: header/fn.encode.html
+    #[inline]
+    pub fn to_headers(&self, options: &header::HeaderOptions)
+                      -> Result<header::Encoded, header::HeaderError> {
+        header::encode(self, options)
+    }
+
+    /// Returns a hash of this dtab's contents.
+    ///
+    /// The hash is computed from the dtab's compact form (`{:#}`), so it
+    /// doesn't depend on how the dtab was originally formatted, and it is
+    /// stable across processes, platforms and versions of this crate. This
+    /// makes it suitable as an etag, or for detecting drift between a
+    /// stored dtab and the dtab a proxy is actually serving.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate dtab;
+    /// # fn main() {
+    /// use dtab::parse::{self, ParseOptions};
+    ///
+    /// let options = ParseOptions::default();
+    /// let stored = parse::dtab("/svc => /a | /b;", &options).unwrap();
+    /// let served = parse::dtab("/svc=>/a|/b", &options).unwrap();
+    /// assert_eq!(stored.content_hash(), served.content_hash());
+    ///
+    /// let etag = format!("\"{:016x}\"", stored.content_hash());
+    /// # assert_eq!(etag.len(), 18);
+    /// # }
+    /// ```
+    pub fn content_hash(&self) -> u64 {
+        // 64-bit FNV-1a, which (unlike `std`'s `DefaultHasher`) is
+        // guaranteed to produce the same hash everywhere.
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+        format!("{:#}", self).bytes()
+            .fold(OFFSET_BASIS, |hash, byte|
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+    }
+}
+
+/// Converts pairs of prefixes and destination trees, in text form, into a
+/// dtab, using the default [`ParseOptions`]. See [`parse::pairs`].
+///
+/// [`ParseOptions`]: parse/struct.ParseOptions.html
+/// [`parse::pairs`]: parse/fn.pairs.html
+impl<P, D> convert::TryFrom<Vec<(P, D)>> for Dtab
+where P: AsRef<str>
+    , D: AsRef<str> {
+    type Error = parse::PairError;
+    #[inline] fn try_from(pairs: Vec<(P, D)>) -> Result<Self, Self::Error> {
+        parse::pairs(pairs, &parse::ParseOptions::default())
+    }
+}
+
+impl str::FromStr for Dtab {
+    type Err = parse::ParseError;
+    #[inline] fn from_str(s: &str) -> Result<Self, parse::ParseError> {
+        Dtab::read(s)
+    }
+}
+
+#[allow(deprecated)]
+impl ops::Deref for Dtab {
+    type Target = [Dentry];
+    #[inline] fn deref(&self) -> &[Dentry] { &self.0 }
+}
+
+impl convert::From<Vec<Dentry>> for Dtab {
+    #[inline] fn from(dentries: Vec<Dentry>) -> Self { Dtab(dentries) }
+}
+
+impl iter::FromIterator<Dentry> for Dtab {
+    #[inline] fn from_iter<I>(iter: I) -> Self
+    where I: IntoIterator<Item = Dentry> {
+        Dtab(iter.into_iter().collect())
+    }
+}
 
+#[allow(deprecated)]
+impl iter::Extend<Dentry> for Dtab {
+    #[inline] fn extend<I>(&mut self, iter: I)
+    where I: IntoIterator<Item = Dentry> {
+        self.0.extend(iter)
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Concatenate two dtabs. See [`Dtab::concat`].
+///
+/// [`Dtab::concat`]: struct.Dtab.html#method.concat
+impl ops::Add for Dtab {
+    type Output = Dtab;
+    #[inline] fn add(mut self, rhs: Dtab) -> Dtab {
+        self.extend(rhs);
+        self
+    }
+}
+
+/// Concatenate two dtabs. See [`Dtab::concat`].
+///
+/// [`Dtab::concat`]: struct.Dtab.html#method.concat
+impl<'a> ops::Add<&'a Dtab> for &Dtab {
+    type Output = Dtab;
+    #[inline] fn add(self, rhs: &'a Dtab) -> Dtab { self.concat(rhs) }
+}
+
+impl ops::AddAssign for Dtab {
+    #[inline] fn add_assign(&mut self, rhs: Dtab) { self.extend(rhs) }
+}
+
+#[allow(deprecated)]
+impl IntoIterator for Dtab {
+    type Item = Dentry;
+    type IntoIter = vec::IntoIter<Dentry>;
+    #[inline] fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
+}
+
+impl<'a> IntoIterator for &'a Dtab {
+    type Item = &'a Dentry;
+    type IntoIter = slice::Iter<'a, Dentry>;
+    #[inline] fn into_iter(self) -> Self::IntoIter { self.iter() }
+}
+
+/// Exports a dtab as pairs of prefixes and destination trees, in text form.
+///
+/// This is the inverse of the `TryFrom<Vec<(P, D)>>` conversion, for
+/// systems which store dtabs as flat pairs.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::NameTree;
+///
+/// let dtab = dtab![
+///   "/iceCreamStore" => NameTree::from("/humphrys") | "/smitten";
+/// ];
+/// let pairs = Vec::<(String, String)>::from(&dtab);
+/// assert_eq!( pairs
+///           , vec![( "/iceCreamStore".to_string()
+///                  , "/humphrys | /smitten".to_string())]);
+/// # }
+/// ```
+impl<'a> convert::From<&'a Dtab> for Vec<(String, String)> {
+    fn from(dtab: &'a Dtab) -> Self {
+        dtab.iter()
+            .map(|dentry| (dentry.prefix.to_string(), dentry.dst.to_string()))
+            .collect()
+    }
+}
+
+/// Dtabs are displayed with one dentry per line. The alternate flag (`{:#}`)
+/// selects the compact form instead, which has no whitespace and separates
+/// dentries with `;`, as in Finagle's `Dtab.show` and the `l5d-dtab` header:
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// let dtab = dtab![
+///   "/smitten"       => "/USA/CA/SF/Harrison/2790";
+///   "/iceCreamStore" => dtab::NameTree::from("/humphrys") | "/smitten";
+/// ];
+/// assert_eq!( &format!("{:#}", dtab)
+///           , "/smitten=>/USA/CA/SF/Harrison/2790;/iceCreamStore=>/humphrys|/smitten");
+/// # }
+/// ```
+impl fmt::Display for Dtab {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            for (i, entry) in self.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(";")?;
+                }
+                write!(f, "{:#}", entry)?;
+            }
+            return Ok(())
+        }
+        for entry in self {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// A delegation rule, rewriting paths beginning with `prefix` to `dst`.
+///
+/// The default dentry is `/ => ~`, which delegates every path to the
+/// negation, and so has no effect on any lookup.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
 pub struct Dentry {
-    #[serde(serialize_with ="nametree::serialize")]
-    pub prefix: NameTree<String>
+    #[serde(serialize_with ="prefix::serialize")]
+    prefix: Prefix
   , #[serde(serialize_with ="nametree::serialize")]
-    pub dst: NameTree<String>
+    dst: NameTree<String>
+}
+
+impl Dentry {
+    /// Returns a new dentry delegating `prefix` to `dst`.
+    #[inline]
+    pub fn new<R>(prefix: Prefix, dst: R) -> Self
+    where R: convert::Into<NameTree<String>> {
+        Dentry { prefix, dst: dst.into() }
+    }
+
+    /// Returns the prefix matched by this dentry.
+    #[inline] pub fn prefix(&self) -> &Prefix { &self.prefix }
+
+    /// Returns the destination of this dentry.
+    #[inline] pub fn dst(&self) -> &NameTree<String> { &self.dst }
+
+    /// Returns a mutable reference to the destination of this dentry.
+    #[inline] pub fn dst_mut(&mut self) -> &mut NameTree<String> { &mut self.dst }
+
+    /// Consume this dentry, returning its prefix and destination.
+    #[inline] pub fn into_parts(self) -> (Prefix, NameTree<String>) {
+        (self.prefix, self.dst)
+    }
+
+    /// Returns a [`DentryBuilder`] for constructing a dentry from runtime
+    /// data.
+    ///
+    /// [`DentryBuilder`]: builder/struct.DentryBuilder.html
+    #[inline] pub fn builder() -> builder::DentryBuilder {
+        builder::DentryBuilder::new()
+    }
+
+    /// Check that this dentry is [valid].
+    ///
+    /// [valid]: validate/index.html
+    #[inline]
+    pub fn validate(&self, validation: &validate::Validation)
+                    -> Result<(), validate::ValidationError> {
+        validation.check_dentry(self)
+    }
+
+    /// Rewrite `path` with this dentry, if its prefix matches the path.
+    ///
+    /// As in Finagle, the matched prefix is replaced by the destination:
+    /// the rest of the path after the prefix is appended to every leaf of
+    /// the destination, and `~`, `!` and `$` are left as they are. Returns
+    /// `None` if the prefix doesn't [match] the path.
+    ///
+    /// Leaves keep the text they were written with (with any non-ASCII
+    /// characters escaped, if they were parsed), so they're read as
+    /// paths here, accepting the labels of every [`LabelPolicy`]: escaped
+    /// labels are rewritten too. A leaf which isn't a valid path under any
+    /// policy, which is only possible in a dentry built from unvalidated
+    /// strings, is an error.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate dtab;
+    /// # fn main() {
+    /// use dtab::path::Path;
+    ///
+    /// let dentry = dentry!("/svc/*" => "/srv/east" | "/srv/west" | "~");
+    /// let rewritten = dentry.rewrite(&Path::read("/svc/users/v2").unwrap()).unwrap().unwrap();
+    /// assert_eq!(&rewritten.to_string(), "/srv/east/v2 | /srv/west/v2 | ~");
+    /// assert_eq!(dentry.rewrite(&Path::read("/svc").unwrap()), Ok(None));
+    ///
+    /// let dentry = dentry!("/svc" => "srv");
+    /// assert!(dentry.rewrite(&Path::read("/svc").unwrap()).is_err());
+    /// # }
+    /// ```
+    ///
+    /// [match]: prefix/struct.Prefix.html#method.matches
+    /// [`LabelPolicy`]: path/enum.LabelPolicy.html
+    pub fn rewrite(&self, path: &path::Path)
+                   -> Result<Option<NameTree<path::Path>>, path::PathError> {
+        self.prefix.matches(path)
+            .map(|residual| rewrite(&self.dst, &residual))
+            .transpose()
+    }
+}
+
+/// Read the text of a destination leaf as a path.
+///
+/// Leaves keep the text they were parsed from, with any non-ASCII characters
+/// escaped, which may have been read under any label policy, so they're read with the [`Any`] policy.
+///
+/// [`Any`]: path/enum.LabelPolicy.html#variant.Any
+#[inline]
+pub(crate) fn read_leaf(leaf: &str) -> Result<path::Path, path::PathError> {
+    path::Path::read_with(leaf, path::LabelPolicy::Any)
+}
+
+/// Append `residual` to every leaf of `tree`.
+fn rewrite(tree: &NameTree<String>, residual: &path::Path)
+           -> Result<NameTree<path::Path>, path::PathError> {
+    Ok(match *tree {
+        NameTree::Leaf(ref leaf) => NameTree::Leaf(read_leaf(leaf)?.concat(residual))
+      , NameTree::Alt(ref left, ref right) =>
+            NameTree::Alt( Box::new(rewrite(left, residual)?)
+                         , Box::new(rewrite(right, residual)?))
+      , NameTree::Union(ref left, ref right) => NameTree::Union(
+            rewrite(left.tree(), residual)?.weighted(left.weight())
+          , rewrite(right.tree(), residual)?.weighted(right.weight()))
+      , NameTree::Neg => NameTree::Neg
+      , NameTree::Empty => NameTree::Empty
+      , NameTree::Fail => NameTree::Fail
+    })
+}
+
+/// Parses a single dentry from its text form, `prefix => dst`, with an
+/// optional trailing `;`, using the default [`ParseOptions`]. See
+/// [`parse::dentry`].
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use dtab::Dentry;
+/// use dtab::parse::ErrorKind;
+///
+/// let dentry: Dentry = "/svc/foo => /srv/foo;".parse().unwrap();
+/// assert_eq!(&dentry.to_string(), "/svc/foo => /srv/foo;");
+/// assert_eq!(Dentry::try_from("/svc/foo=>/srv/foo").unwrap(), dentry);
+///
+/// let error = "/svc/foo => /srv/foo; /svc/bar => /srv/bar".parse::<Dentry>().unwrap_err();
+/// assert_eq!(error.at, 22);
+/// match error.kind {
+///     ErrorKind::Unexpected { .. } => {}
+///   , other => panic!("unexpected error {:?}", other)
+/// }
+/// ```
+///
+/// [`ParseOptions`]: parse/struct.ParseOptions.html
+/// [`parse::dentry`]: parse/fn.dentry.html
+impl str::FromStr for Dentry {
+    type Err = parse::ParseError;
+    #[inline] fn from_str(s: &str) -> Result<Self, parse::ParseError> {
+        parse::dentry(s, &parse::ParseOptions::default())
+    }
+}
+
+impl<'a> convert::TryFrom<&'a str> for Dentry {
+    type Error = parse::ParseError;
+    #[inline] fn try_from(s: &'a str) -> Result<Self, parse::ParseError> {
+        s.parse()
+    }
+}
+
+/// Dentries can be deserialized either from their text form, `prefix =>
+/// dst;`, or from the structure they are serialized as, with `prefix` and
+/// `dst` fields. The text is parsed with the default [`ParseOptions`],
+/// except that labels are read with the [`Any`] policy, so that every
+/// serialized dentry reads back.
+///
+/// Formats which aren't human-readable, such as bincode, don't describe the
+/// types of the values they hold, so they are only read as the structure
+/// that was written:
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # extern crate bincode;
+/// # fn main() {
+/// use dtab::Dentry;
+///
+/// let dentry = dentry!("/svc" => "/srv");
+/// let bytes = bincode::serialize(&dentry).unwrap();
+/// assert_eq!(bincode::deserialize::<Dentry>(&bytes).unwrap(), dentry);
+/// # }
+/// ```
+///
+/// [`ParseOptions`]: parse/struct.ParseOptions.html
+/// [`Any`]: path/enum.LabelPolicy.html#variant.Any
+impl<'de> Deserialize<'de> for Dentry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(with = "prefix")]
+            prefix: Prefix
+          , #[serde(with = "nametree")]
+            dst: NameTree<String>
+        }
+
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Dentry;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a dentry")
+            }
+            fn visit_str<E>(self, v: &str) -> Result<Dentry, E>
+            where E: de::Error {
+                parse::dentry(v, &parse::ParseOptions::serialized())
+                    .map_err(E::custom)
+            }
+            fn visit_map<A>(self, map: A) -> Result<Dentry, A::Error>
+            where A: de::MapAccess<'de> {
+                let Fields { prefix, dst } =
+                    Fields::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(Dentry { prefix, dst })
+            }
+            fn visit_seq<A>(self, seq: A) -> Result<Dentry, A::Error>
+            where A: de::SeqAccess<'de> {
+                let Fields { prefix, dst } =
+                    Fields::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(Dentry { prefix, dst })
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(Visitor)
+        } else {
+            deserializer.deserialize_struct("Dentry", &["prefix", "dst"], Visitor)
+        }
+    }
+}
+
+/// Dtabs can be deserialized either from their text form, as a string, or
+/// from a sequence of dentries. As with [dentries], labels are read with the
+/// [`Any`] policy. This allows a dtab to be embedded directly in a
+/// configuration struct:
+///
+/// ```
+/// # extern crate dtab;
+/// # extern crate serde;
+/// # extern crate serde_json;
+/// # #[macro_use] extern crate serde_derive;
+/// use dtab::Dtab;
+///
+/// #[derive(Deserialize)]
+/// struct RouterConfig {
+///     label: String
+///   , #[serde(default)]
+///     dtab: Dtab
+/// }
+/// # fn main() {
+/// let config: RouterConfig = serde_json::from_str(r#"{
+///     "label": "http",
+///     "dtab": "/svc => /srv; /srv => /#/io.l5d.fs;"
+/// }"#).unwrap();
+/// assert_eq!(config.dtab.len(), 2);
+///
+/// let config: RouterConfig = serde_json::from_str(r#"{
+///     "label": "http",
+///     "dtab": ["/svc => /srv;", {"prefix": "/srv", "dst": "/#/io.l5d.fs"}]
+/// }"#).unwrap();
+/// assert_eq!(config.dtab.len(), 2);
+/// # }
+/// ```
+///
+/// Formats which aren't human-readable are only read as a sequence, as
+/// dtabs are serialized:
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # extern crate bincode;
+/// # fn main() {
+/// use dtab::Dtab;
+///
+/// let dtab = dtab!["/svc" => "/srv"; "/srv" => "/#/io.l5d.fs";];
+/// let bytes = bincode::serialize(&dtab).unwrap();
+/// assert_eq!(bincode::deserialize::<Dtab>(&bytes).unwrap(), dtab);
+/// # }
+/// ```
+///
+/// [dentries]: struct.Dentry.html#impl-Deserialize%3C'de%3E-for-Dentry
+/// [`Any`]: path/enum.LabelPolicy.html#variant.Any
+impl<'de> Deserialize<'de> for Dtab {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Dtab;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a dtab")
+            }
+            fn visit_str<E>(self, v: &str) -> Result<Dtab, E>
+            where E: de::Error {
+                parse::dtab(v, &parse::ParseOptions::serialized()).map_err(E::custom)
+            }
+            fn visit_seq<A>(self, mut seq: A) -> Result<Dtab, A::Error>
+            where A: de::SeqAccess<'de> {
+                let mut dtab = Dtab::new();
+                while let Some(dentry) = seq.next_element()? {
+                    dtab.push(dentry);
+                }
+                Ok(dtab)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(Visitor)
+        } else {
+            deserializer.deserialize_seq(Visitor)
+        }
+    }
+}
+
+/// Dtabs are serialized as a sequence of dentries, each with `prefix` and
+/// `dst` fields holding their text forms. This is the representation used
+/// by namerd's HTTP API, so dtabs can be exchanged with namerd directly:
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # extern crate serde_json;
+/// # fn main() {
+/// use dtab::{Dtab, NameTree};
+///
+/// let dtab = dtab![
+///     "/svc" => "/#/io.l5d.fs";
+///     "/svc/users" => NameTree::from("/srv/users-v2") | "/srv/users";
+/// ];
+/// let json = r#"[{"prefix":"/svc","dst":"/#/io.l5d.fs"},{"prefix":"/svc/users","dst":"/srv/users-v2 | /srv/users"}]"#;
+/// assert_eq!(serde_json::to_string(&dtab).unwrap(), json);
+/// assert_eq!(serde_json::from_str::<Dtab>(json).unwrap(), dtab);
+/// # }
+/// ```
+impl Serialize for Dtab {
+    #[inline] fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        serializer.collect_seq(self.iter())
+    }
 }
 
+/// Dentries are displayed as `prefix => dst;`. The alternate flag (`{:#}`)
+/// selects the compact form, `prefix=>dst`, with no whitespace or trailing
+/// `;`.
 impl fmt::Display for Dentry {
-    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{}=>", self.prefix)?;
+            return nametree::write_compact(f, &self.dst)
+        }
         write!(f, "{} => {};", self.prefix, self.dst)
     }
 }
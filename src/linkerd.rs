@@ -0,0 +1,147 @@
+//! Loading dtabs from linkerd configuration files.
+//!
+//! A linkerd configuration file gives each router its own dtab, under the
+//! router's `dtab:` key. [`router_dtabs`] extracts and parses every router's
+//! dtab, so that a configuration can be validated before it is deployed.
+//!
+//! This module requires the `linkerd-config` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::linkerd;
+//! use dtab::parse::ParseOptions;
+//!
+//! let config = r#"
+//! admin:
+//!   port: 9990
+//! routers:
+//! - protocol: http
+//!   label: outgoing
+//!   dtab: |
+//!     /svc => /#/io.l5d.fs;
+//!     /svc/users => /#/io.l5d.fs/users-v2;
+//!   servers:
+//!   - port: 4140
+//! - protocol: thrift
+//! "#;
+//! let routers = linkerd::router_dtabs(config, &ParseOptions::default()).unwrap();
+//! assert_eq!(routers[0].label, "outgoing");
+//! assert_eq!( routers[0].dtab
+//!           , dtab![ "/svc" => "/#/io.l5d.fs"
+//!                  ; "/svc/users" => "/#/io.l5d.fs/users-v2"; ]);
+//! assert_eq!(routers[1].label, "thrift");
+//! assert!(routers[1].dtab.is_empty());
+//! # }
+//! ```
+//!
+//! [`router_dtabs`]: fn.router_dtabs.html
+use std::{error, fmt};
+
+use serde_yaml;
+
+use super::Dtab;
+use super::parse::{self, ParseError, ParseOptions};
+
+/// The dtab of one router in a linkerd configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouterDtab {
+    /// The index of the router in the configuration's `routers` list.
+    pub router: usize
+  , /// The router's label, which linkerd defaults to its protocol.
+    pub label: String
+  , /// The router's dtab, which is empty if the router has none.
+    pub dtab: Dtab
+}
+
+/// An error loading dtabs from a linkerd configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration was not valid YAML, or didn't have the structure
+    /// of a linkerd configuration.
+    Yaml(serde_yaml::Error)
+  , /// A router's dtab could not be parsed. Offsets in the error are
+    /// relative to the start of the dtab.
+    InvalidDtab { router: usize, label: String, error: ParseError }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Yaml(ref e) => write!(f, "invalid linkerd config: {}", e)
+          , ConfigError::InvalidDtab { router, ref label, ref error } =>
+                write!(f, "invalid dtab in router {} ({}): {}", router, label, error)
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ConfigError::Yaml(ref e) => Some(e)
+          , ConfigError::InvalidDtab { ref error, .. } => Some(error)
+        }
+    }
+}
+
+/// The parts of a linkerd configuration which hold dtabs.
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    routers: Vec<Router>
+}
+
+#[derive(Deserialize)]
+struct Router {
+    protocol: String
+  , label: Option<String>
+  , dtab: Option<String>
+}
+
+/// Parse the dtab of every router in a linkerd configuration, in the
+/// order the routers appear.
+///
+/// Returns an error for the first router whose dtab is invalid.
+pub fn router_dtabs(config: &str, options: &ParseOptions)
+                    -> Result<Vec<RouterDtab>, ConfigError> {
+    let config: Config = serde_yaml::from_str(config).map_err(ConfigError::Yaml)?;
+    config.routers.into_iter().enumerate()
+        .map(|(router, Router { protocol, label, dtab })| {
+            let label = label.unwrap_or(protocol);
+            let dtab = match dtab {
+                Some(dtab) => parse::dtab(&dtab, options)
+                    .map_err(|error| ConfigError::InvalidDtab {
+                        router, label: label.clone(), error
+                    })?
+              , None => Dtab::new()
+            };
+            Ok(RouterDtab { router, label, dtab })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        let options = ParseOptions::default();
+        let config = "routers:\n\
+                      - protocol: http\n  dtab: /a => /b\n\
+                      - protocol: h2\n  label: h2-in\n  dtab: /a => \n";
+        match router_dtabs(config, &options).unwrap_err() {
+            ConfigError::InvalidDtab { router, label, error } => {
+                assert_eq!((router, label.as_str()), (1, "h2-in"));
+                assert_eq!(error, parse::dtab("/a =>", &options).unwrap_err());
+            }
+          , e => panic!("expected an invalid dtab, got {:?}", e)
+        }
+        assert!(router_dtabs("routers: [", &options).unwrap_err().to_string()
+                    .starts_with("invalid linkerd config: "));
+        assert!(router_dtabs("routers:\n- label: no-protocol\n", &options).is_err());
+        assert_eq!(router_dtabs("admin: {port: 9990}\n", &options).unwrap(), Vec::new());
+    }
+}
@@ -0,0 +1,341 @@
+//! Static analysis of dtabs.
+//!
+//! Lints detect dtabs which are well-formed, but probably don't do what their
+//! author intended, such as rules that have been pasted twice.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::NameTree;
+//! use dtab::lint::{self, Lint};
+//!
+//! let dtab = dtab![
+//!   "/iceCreamStore" => NameTree::from("/humphrys");
+//!   "/iceCreamStore" => NameTree::from("/smitten");
+//! ];
+//!
+//! let findings = lint::duplicate_prefixes(&dtab);
+//! assert_eq!(findings.len(), 1);
+//! assert_eq!(findings[0].lint, Lint::DuplicatePrefix);
+//! assert_eq!(findings[0].entries, vec![0, 1]);
+//! assert_eq!( &findings[0].suggestion.as_ref().unwrap().to_string()
+//!           , "/iceCreamStore => /smitten | /humphrys;");
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::ser::{Serialize, Serializer};
+
+use super::{Dentry, Dtab};
+use super::namer;
+
+/// The kinds of problem detected by the linter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// Two or more dentries have byte-identical prefixes.
+    ///
+    /// Later entries take precedence, with earlier entries consulted only if
+    /// the later ones fail to resolve; this is equivalent to (and easier to
+    /// read as) a single dentry whose destination is an alternation.
+    DuplicatePrefix
+  , /// A destination leaf is neither a namer path (beginning with `/$` or
+    /// `/#`) nor matched by the prefix of any other dentry, so delegation
+    /// ends at it as an unbound [`Name::Path`], which no namer will bind,
+    /// or it isn't a valid path at all.
+    ///
+    /// [`Name::Path`]: ../name/enum.Name.html#variant.Path
+    DanglingDestination
+}
+
+impl Lint {
+    /// Returns the name of this lint, as used in diagnostic output.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Lint::DuplicatePrefix => "duplicate-prefix"
+          , Lint::DanglingDestination => "dangling-destination"
+        }
+    }
+
+    /// Returns the default severity of this lint.
+    pub fn severity(&self) -> Severity {
+        match *self {
+            Lint::DuplicatePrefix => Severity::Warning
+          , Lint::DanglingDestination => Severity::Warning
+        }
+    }
+}
+
+/// Lints are serialized as their [name].
+///
+/// [name]: #method.name
+impl Serialize for Lint {
+    #[inline] fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl fmt::Display for Lint {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How serious a [`Finding`] is.
+///
+/// [`Finding`]: struct.Finding.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity { Warning, Error }
+
+/// Severities are serialized as `"warning"` or `"error"`.
+impl Serialize for Severity {
+    #[inline] fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        serializer.serialize_str(match *self {
+            Severity::Warning => "warning"
+          , Severity::Error => "error"
+        })
+    }
+}
+
+impl fmt::Display for Severity {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Warning => f.write_str("warning")
+          , Severity::Error => f.write_str("error")
+        }
+    }
+}
+
+/// A single problem found by the linter.
+///
+/// Findings are serialized as objects with the fields `lint` (the lint's
+/// [name]), `severity`, `entries`, `message` and `suggestion` (a dentry
+/// object, or `null`), so that tools can consume linter output.
+///
+/// [name]: enum.Lint.html#method.name
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Finding {
+    /// The lint that produced this finding.
+    pub lint: Lint
+  , /// How serious the problem is.
+    pub severity: Severity
+  , /// The indices of the affected dentries in the dtab.
+    pub entries: Vec<usize>
+  , /// A human-readable description of the problem.
+    pub message: String
+  , /// A dentry that may replace the affected entries, if there is one.
+    pub suggestion: Option<Dentry>
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.lint, self.message)?;
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, "\n  help: replace with `{}`", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every lint against `dtab`, returning all findings ordered by the
+/// index of the first affected entry.
+pub fn lint(dtab: &Dtab) -> Vec<Finding> {
+    let mut findings = duplicate_prefixes(dtab);
+    findings.extend(dangling_destinations(dtab));
+    findings.sort_by_key(|finding| finding.entries.first().cloned());
+    findings
+}
+
+/// Find dentries whose prefixes are byte-identical.
+///
+/// Each group of duplicates produces one finding, suggesting a single
+/// dentry whose destination alternates between the duplicates'
+/// destinations, in precedence order.
+pub fn duplicate_prefixes(dtab: &Dtab) -> Vec<Finding> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (i, dentry) in dtab.iter().enumerate() {
+        let prefix = dentry.prefix.to_string();
+        if let Some(&group) = index.get(&prefix) {
+            groups[group].1.push(i);
+            continue
+        }
+        index.insert(prefix.clone(), groups.len());
+        groups.push((prefix, vec![i]));
+    }
+
+    groups.into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .map(|(prefix, entries)| {
+            let dst = entries.iter().rev()
+                .map(|&i| dtab[i].dst.clone())
+                .fold(None, |alt, dst| match alt {
+                    None => Some(dst)
+                  , Some(alt) => Some(alt | dst)
+                })
+                .expect("duplicate group has at least two entries");
+            let suggestion = Dentry::new(dtab[entries[0]].prefix().clone(), dst);
+            let message = format!(
+                "prefix `{}` is repeated in entries {}"
+              , prefix
+              , entries.iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            Finding { lint: Lint::DuplicatePrefix
+                    , severity: Lint::DuplicatePrefix.severity()
+                    , entries
+                    , message
+                    , suggestion: Some(suggestion)
+                    }
+        })
+        .collect()
+}
+
+/// Find destination leaves that can never be resolved.
+///
+/// A leaf is considered resolvable if it is rooted at a namer (`/$` or
+/// `/#`), or if the prefix of some other dentry [matches] it, as it would
+/// when delegating. Each dangling leaf produces its own finding, as does
+/// each leaf which isn't a valid path, since delegation fails at it.
+///
+/// [matches]: ../prefix/struct.Prefix.html#method.matches
+pub fn dangling_destinations(dtab: &Dtab) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (i, dentry) in dtab.iter().enumerate() {
+        for leaf in dentry.dst.leaves() {
+            let message = match super::read_leaf(leaf) {
+                Ok(ref path) if namer::is_namer_path(path) => continue
+              , Ok(ref path) => {
+                    let matched = dtab.iter().enumerate()
+                        .any(|(j, other)| j != i && other.prefix.matches(path).is_some());
+                    if matched {
+                        continue
+                    }
+                    format!( "destination `{}` in entry {} is not matched by any \
+                              prefix, so delegation leaves it unbound"
+                           , leaf, i)
+                }
+              , Err(error) =>
+                    format!( "destination `{}` in entry {} is not a valid path: {}"
+                           , leaf, i, error)
+            };
+            findings.push(Finding {
+                lint: Lint::DanglingDestination
+              , severity: Lint::DanglingDestination.severity()
+              , entries: vec![i]
+              , message
+              , suggestion: None
+            })
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NameTree;
+
+    #[test]
+    fn serialize_findings() {
+        let dtab = dtab![
+            "/svc" => "/#/a";
+            "/svc" => "/#/b";
+        ];
+        assert_eq!( ::serde_json::to_value(lint(&dtab)).unwrap()
+                  , json!([{
+                        "lint": "duplicate-prefix"
+                      , "severity": "warning"
+                      , "entries": [0, 1]
+                      , "message": "prefix `/svc` is repeated in entries 0, 1"
+                      , "suggestion": { "prefix": "/svc", "dst": "/#/b | /#/a" }
+                    }]));
+    }
+
+    #[test]
+    fn no_findings() {
+        let dtab = dtab![
+            "/smitten" => NameTree::from("/$/inet/smitten.com/80");
+            "/iceCreamStore" => NameTree::from("/smitten");
+        ];
+        assert_eq!(lint(&dtab), vec![]);
+    }
+
+    #[test]
+    fn three_duplicates() {
+        let dtab = dtab![
+            "/iceCreamStore" => NameTree::from("/humphrys");
+            "/smitten" => NameTree::from("/USA/CA/SF/Harrison/2790");
+            "/iceCreamStore" => NameTree::from("/smitten");
+            "/iceCreamStore" => NameTree::from("/birite") | "/three-twins";
+        ];
+        let findings = duplicate_prefixes(&dtab);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].entries, vec![0, 2, 3]);
+        assert_eq!( &findings[0].to_string()
+                  , "warning[duplicate-prefix]: prefix `/iceCreamStore` is \
+                     repeated in entries 0, 2, 3\n  help: replace with \
+                     `/iceCreamStore => /birite | /three-twins | /smitten | \
+                     /humphrys;`");
+    }
+
+    #[test]
+    fn dangling_destinations_found() {
+        let dtab = dtab![
+            "/ice/*/store" => NameTree::from("/#/io.l5d.k8s/default/http");
+            "/iceCreamStore" => NameTree::from("/ice/cream/store/sf")
+                              | "/smiten";
+        ];
+        let findings = dangling_destinations(&dtab);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].entries, vec![1]);
+        assert_eq!( &findings[0].message
+                  , "destination `/smiten` in entry 1 is not matched by any \
+                     prefix, so delegation leaves it unbound");
+    }
+
+    #[test]
+    fn own_prefix_does_not_match() {
+        let dtab = dtab![
+            "/iceCreamStore" => NameTree::from("/iceCreamStore/sf");
+        ];
+        assert_eq!(dangling_destinations(&dtab).len(), 1);
+    }
+
+    #[test]
+    fn escaped_destinations() {
+        use parse::{self, ParseOptions};
+        use path::LabelPolicy;
+
+        // `\x2f` is part of a label, not a separator.
+        let finagle = ParseOptions::finagle();
+        let dtab = parse::dtab("/a/b => /#/c; /d => /a\\x2fb | /a/b; /e => /a\\x2fb/c", &finagle)
+            .unwrap();
+        let findings = dangling_destinations(&dtab);
+        assert_eq!( findings.iter().map(|f| f.message.as_str()).collect::<Vec<_>>()
+                  , vec![ "destination `/a\\x2fb` in entry 1 is not matched by any prefix, \
+                           so delegation leaves it unbound"
+                        , "destination `/a\\x2fb/c` in entry 2 is not matched by any prefix, \
+                           so delegation leaves it unbound" ]);
+
+        // escaped and raw labels match each other, as they do when delegating.
+        let dtab = parse::dtab("/s\\x41 => /#/c; /d => /s\\x41/x | /sA", &finagle).unwrap();
+        assert_eq!(dangling_destinations(&dtab), vec![]);
+        let utf8 = ParseOptions { labels: LabelPolicy::Utf8, ..ParseOptions::default() };
+        let dtab = parse::dtab("/caf\\xc3\\xa9 => /#/c; /d => /café/x", &utf8).unwrap();
+        assert_eq!(dangling_destinations(&dtab), vec![]);
+        assert_eq!( dtab.delegate(&"/d".parse().unwrap()).unwrap().to_string()
+                  , "/#/c/x");
+
+        let dtab = dtab!["/a" => "a";];
+        assert_eq!( &dangling_destinations(&dtab)[0].message
+                  , "destination `a` in entry 0 is not a valid path: \
+                     path \"a\" does not begin with '/'");
+    }
+}
@@ -0,0 +1,433 @@
+//! A Language Server Protocol backend for `.dtab` files.
+//!
+//! The [`Server`] speaks LSP over any reader and writer, and provides:
+//!
+//! - **diagnostics** for parse errors and [lint] findings, covering the
+//!   affected dentries;
+//! - **hover** over any path, listing the dentries whose prefixes match it,
+//!   in precedence order;
+//! - **go to definition** from a destination path to the dentries whose
+//!   prefixes match it;
+//! - **formatting** of the whole document, as by [`format::format`].
+//!
+//! Documents are synchronized in full on every change.
+//!
+//! This module requires the `lsp` feature. With the `cli` feature as well,
+//! `dtab lsp` runs a server over standard input and output.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate serde_json;
+//! # extern crate dtab;
+//! # fn main() {
+//! use dtab::lsp::Server;
+//!
+//! let mut server = Server::new();
+//! let sent = server.handle(&json!({
+//!     "jsonrpc": "2.0", "method": "textDocument/didOpen",
+//!     "params": { "textDocument": { "uri": "file:///prod.dtab", "text": "/svc => " } }
+//! }));
+//! assert_eq!(sent[0]["method"], "textDocument/publishDiagnostics");
+//! assert_eq!( sent[0]["params"]["diagnostics"][0]["message"]
+//!           , "expected a name tree, found end of input");
+//! # }
+//! ```
+//!
+//! [`Server`]: struct.Server.html
+//! [lint]: ../lint/index.html
+//! [`format::format`]: ../format/fn.format.html
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{self, Value};
+
+use super::Dtab;
+use super::format::{self, FormatOptions};
+use super::lint::{self, Severity};
+use super::parse::{self, ParseOptions, Span, SpannedDentry};
+use super::prefix::Prefix;
+
+/// JSON-RPC error code for unknown methods.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for invalid parameters.
+const INVALID_PARAMS: i64 = -32602;
+
+/// A language server for dtab documents.
+#[derive(Clone, Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, String>
+  , options: ParseOptions
+}
+
+impl Server {
+    /// Returns a new server, with no open documents.
+    #[inline] pub fn new() -> Self { Server::default() }
+
+    /// Returns a new server which parses documents with the given options.
+    #[inline] pub fn with_options(options: ParseOptions) -> Self {
+        Server { documents: HashMap::new(), options }
+    }
+
+    /// Serve requests read from `input`, writing responses to `output`,
+    /// until the client sends `exit` or closes `input`.
+    pub fn run<R, W>(&mut self, mut input: R, mut output: W) -> io::Result<()>
+    where R: BufRead
+        , W: Write {
+        while let Some(message) = read_message(&mut input)? {
+            if message["method"] == "exit" {
+                return Ok(())
+            }
+            for reply in self.handle(&message) {
+                let body = reply.to_string();
+                write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+            }
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Handle a single message, returning the responses and notifications
+    /// to send to the client.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = match message.get("id") {
+            Some(id) => id.clone()
+          , None => return self.notify(method, params)
+        };
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1
+                  , "hoverProvider": true
+                  , "definitionProvider": true
+                  , "documentFormattingProvider": true
+                }
+              , "serverInfo": { "name": "dtab" }
+            }))
+          , "shutdown" => Ok(Value::Null)
+          , "textDocument/hover" => self.hover(params)
+          , "textDocument/definition" => self.definition(params)
+          , "textDocument/formatting" => self.formatting(params)
+          , _ => Err((METHOD_NOT_FOUND, format!("unknown method {:?}", method)))
+        };
+        vec![match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result })
+          , Err((code, message)) => json!({
+                "jsonrpc": "2.0", "id": id
+              , "error": { "code": code, "message": message }
+            })
+        }]
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = match params["textDocument"]["uri"].as_str() {
+            Some(uri) => uri.to_string()
+          , None => return Vec::new()
+        };
+        let text = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str()
+          , "textDocument/didChange" => params["contentChanges"].as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str())
+          , "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish(&uri, Vec::new())]
+            }
+          , _ => None
+        };
+        match text {
+            Some(text) => {
+                let diagnostics = self.diagnostics(text);
+                self.documents.insert(uri.clone(), text.to_string());
+                vec![publish(&uri, diagnostics)]
+            }
+          , None => Vec::new()
+        }
+    }
+
+    fn diagnostics(&self, text: &str) -> Vec<Value> {
+        let dentries = match parse::spanned_dtab(text, &self.options) {
+            Ok(dentries) => dentries
+          , Err(e) => {
+                return vec![json!({
+                    "range": range(text, e.span(text))
+                  , "severity": 1
+                  , "source": "dtab"
+                  , "message": e.kind.to_string()
+                })]
+            }
+        };
+        let dtab = dentries.iter().map(|spanned| spanned.dentry.clone()).collect::<Dtab>();
+        lint::lint(&dtab).iter()
+            .flat_map(|finding| finding.entries.iter().map(move |&entry| (finding, entry)))
+            .map(|(finding, entry)| json!({
+                "range": range(text, dentries[entry].span)
+              , "severity": match finding.severity {
+                    Severity::Error => 1
+                  , Severity::Warning => 2
+                }
+              , "source": "dtab"
+              , "code": finding.lint.name()
+              , "message": finding.message
+            }))
+            .collect()
+    }
+
+    /// Find the document and offset referred to by a position request,
+    /// along with the document's dentries and the path under the cursor.
+    fn at_cursor<'a>(&'a self, params: &'a Value)
+                     -> Result<Option<Cursor<'a>>, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str()
+            .ok_or_else(|| (INVALID_PARAMS, "missing document".to_string()))?;
+        let text = self.documents.get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("unknown document {:?}", uri)))?;
+        let position = &params["position"];
+        let (line, character) = match (position["line"].as_u64(), position["character"].as_u64()) {
+            (Some(line), Some(character)) => (line as usize, character as usize)
+          , _ => return Err((INVALID_PARAMS, "missing position".to_string()))
+        };
+        let offset = offset(text, line, character);
+        let (span, path) = match parse::path_at(text, offset) {
+            Some(found) => found
+          , None => return Ok(None)
+        };
+        let dentries = match parse::spanned_dtab(text, &self.options) {
+            Ok(dentries) => dentries
+          , Err(_) => return Ok(None)
+        };
+        let prefix = match Prefix::read_with(path, self.options.labels) {
+            Ok(prefix) => prefix
+          , Err(_) => return Ok(None)
+        };
+        Ok(Some(Cursor { uri, text, span, path, prefix, dentries }))
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let cursor = match self.at_cursor(params)? {
+            Some(cursor) => cursor
+          , None => return Ok(Value::Null)
+        };
+        let matches = cursor.matches().collect::<Vec<_>>();
+        let contents = if matches.is_empty() {
+            format!("`{}` matches no dentries, and delegates to `~`", cursor.path)
+        } else {
+            let rules = matches.iter()
+                .map(|spanned| spanned.dentry.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!("`{}` is matched by, in precedence order:\n```dtab\n{}\n```"
+                   , cursor.path, rules)
+        };
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": contents }
+          , "range": range(cursor.text, cursor.span)
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let cursor = match self.at_cursor(params)? {
+            Some(cursor) => cursor
+          , None => return Ok(Value::Null)
+        };
+        Ok(cursor.matches()
+            .filter(|spanned| spanned.prefix != cursor.span)
+            .map(|spanned| json!({
+                "uri": cursor.uri
+              , "range": range(cursor.text, spanned.prefix)
+            }))
+            .collect())
+    }
+
+    fn formatting(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str()
+            .ok_or_else(|| (INVALID_PARAMS, "missing document".to_string()))?;
+        let text = self.documents.get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("unknown document {:?}", uri)))?;
+        let options = FormatOptions { parse: self.options, ..FormatOptions::default() };
+        let formatted = match format::format(text, &options) {
+            Ok(formatted) => formatted
+          , Err(_) => return Ok(json!([]))
+        };
+        Ok(json!([{
+            "range": range(text, Span { start: 0, end: text.len() })
+          , "newText": formatted
+        }]))
+    }
+}
+
+/// The path under the cursor in a position request.
+struct Cursor<'a> {
+    uri: &'a str
+  , text: &'a str
+  , span: Span
+  , path: &'a str
+  , prefix: Prefix
+  , dentries: Vec<SpannedDentry>
+}
+
+impl<'a> Cursor<'a> {
+    /// The dentries whose prefixes match the path under the cursor, in
+    /// precedence order.
+    fn matches(&self) -> impl Iterator<Item = &SpannedDentry> {
+        self.dentries.iter().rev()
+            .filter(move |spanned| spanned.dentry.prefix().covers(&self.prefix))
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0"
+      , "method": "textDocument/publishDiagnostics"
+      , "params": { "uri": uri, "diagnostics": diagnostics }
+    })
+}
+
+/// Convert a span into an LSP range.
+fn range(text: &str, span: Span) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// Convert a byte offset into an LSP position, whose character offsets are
+/// counted in UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character = before[start..].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+/// Convert an LSP position into a byte offset, clamping it to the text.
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let start = match line {
+        0 => 0
+      , _ => match text.match_indices('\n').nth(line - 1) {
+            Some((i, _)) => i + 1
+          , None => return text.len()
+        }
+    };
+    let mut units = 0;
+    for (i, ch) in text[start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return start + i
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+/// Read one message, framed with a `Content-Length` header.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None)
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break
+        }
+        let mut parts = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(||
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.dtab";
+
+    fn open(server: &mut Server, text: &str) -> Value {
+        server.handle(&json!({
+            "jsonrpc": "2.0", "method": "textDocument/didOpen"
+          , "params": { "textDocument": { "uri": URI, "text": text } }
+        })).remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, line: u64, character: u64) -> Value {
+        server.handle(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": method
+          , "params": { "textDocument": { "uri": URI }
+                      , "position": { "line": line, "character": character } }
+        })).remove(0)
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut server = Server::new();
+        let published = open(&mut server, "/a => /#/x;\n/a => /#/y;");
+        let diagnostics = &published["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 2);
+        assert_eq!(diagnostics[1]["code"], "duplicate-prefix");
+        assert_eq!(diagnostics[1]["severity"], 2);
+        assert_eq!( diagnostics[1]["range"]
+                  , json!({ "start": { "line": 1, "character": 0 }
+                          , "end": { "line": 1, "character": 10 } }));
+
+        let published = open(&mut server, "/a => /#/x;\n/a => é");
+        assert_eq!( published["params"]["diagnostics"][0]["range"]
+                  , json!({ "start": { "line": 1, "character": 6 }
+                          , "end": { "line": 1, "character": 7 } }));
+    }
+
+    #[test]
+    fn hover_and_definition() {
+        let mut server = Server::new();
+        open(&mut server, "/svc => /srv;\n/srv => /#/io.l5d.fs;\n/srv/* => /#/other;");
+        let hover = request(&mut server, "textDocument/hover", 0, 10);
+        assert_eq!( hover["result"]["contents"]["value"]
+                  , "`/srv` is matched by, in precedence order:\n\
+                     ```dtab\n/srv => /#/io.l5d.fs;\n```");
+        let definition = request(&mut server, "textDocument/definition", 0, 9);
+        assert_eq!( definition["result"]
+                  , json!([{ "uri": URI
+                           , "range": { "start": { "line": 1, "character": 0 }
+                                      , "end": { "line": 1, "character": 4 } } }]));
+        assert_eq!(request(&mut server, "textDocument/hover", 0, 5)["result"], Value::Null);
+        assert_eq!( request(&mut server, "textDocument/frobnicate", 0, 0)["error"]["code"]
+                  , METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn run() {
+        let frame = |message: Value| {
+            let body = message.to_string();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        };
+        let input = [ frame(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }))
+                    , frame(json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen"
+                                  , "params": { "textDocument": { "uri": URI
+                                                                , "text": "/a=>/b" } } }))
+                    , frame(json!({ "jsonrpc": "2.0", "id": 2
+                                  , "method": "textDocument/formatting"
+                                  , "params": { "textDocument": { "uri": URI } } }))
+                    , frame(json!({ "jsonrpc": "2.0", "method": "exit" }))
+                    ].concat();
+        let mut output = Vec::new();
+        Server::new().run(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut input = output.as_bytes();
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut input).unwrap() {
+            replies.push(reply);
+        }
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(replies[2]["result"][0]["newText"], "/a => /b;\n");
+    }
+}
@@ -0,0 +1,117 @@
+//! Layering one dtab over another.
+//!
+//! Concatenating a base dtab with an override dtab, as [`Dtab::concat`]
+//! does, shadows the base dentries: where an override matches a path, it
+//! takes precedence, but the base dentries which match it are still tried
+//! if its names fail to bind. Configuration layered from several files
+//! usually means something stronger: an override for a prefix replaces the
+//! base's rule for that prefix altogether. [`override_with`] merges dtabs in
+//! that way, and reports each base dentry it replaced.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::path::Path;
+//!
+//! let base = dtab!["/svc" => "/srv"; "/svc/users" => "/srv/users-v1";];
+//! let overrides = dtab!["/svc/users" => "/srv/users-v2";];
+//!
+//! let (dtab, replaced) = base.override_with(&overrides);
+//! assert_eq!(dtab, dtab!["/svc" => "/srv"; "/svc/users" => "/srv/users-v2";]);
+//! assert_eq!( &replaced[0].to_string()
+//!           , "entry 1, `/svc/users => /srv/users-v1;`, was replaced by override 0, \
+//!              `/svc/users => /srv/users-v2;`");
+//!
+//! // concatenating them would leave the base dentry as a fallback.
+//! let path = Path::read("/svc/users").unwrap();
+//! assert_eq!( &base.concat(&overrides).lookup(&path).unwrap().to_string()
+//!           , "/srv/users-v2 | /srv/users-v1 | /srv/users");
+//! assert_eq!(&dtab.lookup(&path).unwrap().to_string(), "/srv/users-v2 | /srv/users");
+//! # }
+//! ```
+//!
+//! [`Dtab::concat`]: ../struct.Dtab.html#method.concat
+//! [`override_with`]: fn.override_with.html
+use std::fmt;
+
+use super::{Dentry, Dtab};
+
+/// A base dentry which was replaced by an override.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replacement {
+    /// The index of the replaced dentry in the base dtab.
+    pub index: usize
+  , /// The replaced dentry.
+    pub dentry: Dentry
+  , /// The index of the dentry which replaced it in the override dtab.
+    pub by_index: usize
+  , /// The dentry which replaced it.
+    pub by: Dentry
+}
+
+impl fmt::Display for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!( f, "entry {}, `{}`, was replaced by override {}, `{}`"
+              , self.index, self.dentry, self.by_index, self.by)
+    }
+}
+
+/// Merge `overrides` over `base`, returning the merged dtab and every base
+/// dentry which was replaced, in base order.
+///
+/// Every base dentry whose prefix is identical to an override's is
+/// removed, and replaced by the last override with that prefix. The merged
+/// dtab is the remaining base dentries, in order, followed by every
+/// override, so that the overrides take precedence over the base dentries
+/// which remain, as with [`Dtab::concat`]. Prefixes which merely overlap,
+/// such as `/svc` and `/svc/users`, don't replace one another.
+///
+/// [`Dtab::concat`]: ../struct.Dtab.html#method.concat
+pub fn override_with(base: &Dtab, overrides: &Dtab) -> (Dtab, Vec<Replacement>) {
+    let mut merged = Dtab::new();
+    let mut replaced = Vec::new();
+    for (index, dentry) in base.iter().enumerate() {
+        let replacement = overrides.iter().enumerate().rev()
+            .find(|&(_, by)| by.prefix() == dentry.prefix());
+        match replacement {
+            Some((by_index, by)) =>
+                replaced.push(Replacement { index, dentry: dentry.clone()
+                                          , by_index, by: by.clone() })
+          , None => merged.push(dentry.clone())
+        }
+    }
+    merged.extend(overrides.iter().cloned());
+    (merged, replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NameTree;
+
+    #[test]
+    fn overrides() {
+        let base = dtab![ "/svc" => "/srv";
+                          "/svc/*" => "/srv/any";
+                          "/svc" => "/srv/old";
+                          "/env" => "/prod"; ];
+        let overrides = dtab![ "/svc" => "/srv/v2";
+                               "/svc/users" => "/srv/users";
+                               "/svc" => NameTree::from("/srv/v3") | "/srv/v2"; ];
+        let (merged, replaced) = base.override_with(&overrides);
+        assert_eq!(merged, dtab![ "/svc/*" => "/srv/any";
+                                  "/env" => "/prod";
+                                  "/svc" => "/srv/v2";
+                                  "/svc/users" => "/srv/users";
+                                  "/svc" => NameTree::from("/srv/v3") | "/srv/v2"; ]);
+        assert_eq!( replaced.iter().map(|r| (r.index, r.by_index)).collect::<Vec<_>>()
+                  , vec![(0, 2), (2, 2)]);
+        assert_eq!(replaced[1].dentry, base[2]);
+        assert_eq!(replaced[1].by, overrides[2]);
+
+        assert_eq!(base.override_with(&Dtab::new()), (base.clone(), Vec::new()));
+        assert_eq!(Dtab::new().override_with(&base), (base.clone(), Vec::new()));
+    }
+}
@@ -0,0 +1,181 @@
+//! Shrinking dtabs without changing their behavior.
+//!
+//! Dtabs that have been edited for years accumulate dentries that no
+//! longer affect any request. [`minimize`] removes and merges dentries
+//! while guaranteeing that every path in a corpus still delegates to
+//! exactly the same name tree, and reports what it changed.
+//!
+//! Paths are delegated with [`Dtab::delegate`], and a path which fails to
+//! delegate, such as one caught in a cycle, must still fail in the same way.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::NameTree;
+//! use dtab::path::Path;
+//!
+//! let dtab = dtab![
+//!     "/svc" => "/srv/old";
+//!     "/svc" => "/srv/new";
+//!     "/unused" => "/nowhere";
+//! ];
+//! let paths = vec![Path::read("/svc/users").unwrap()];
+//! let minimized = dtab.minimize(&paths);
+//! assert_eq!(minimized.dtab, dtab!["/svc" => NameTree::from("/srv/new") | "/srv/old";]);
+//! assert_eq!(minimized.removed, vec![2]);
+//! assert_eq!(minimized.merged, vec![vec![0, 1]]);
+//! # }
+//! ```
+//!
+//! [`minimize`]: fn.minimize.html
+//! [`Dtab::delegate`]: ../struct.Dtab.html#method.delegate
+use std::fmt;
+
+use super::{Dentry, Dtab, NameTree};
+use super::path::Path;
+
+/// The result of minimizing a dtab.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Minimized {
+    /// The minimized dtab.
+    pub dtab: Dtab
+  , /// The indices, in the original dtab, of dentries which were removed.
+    pub removed: Vec<usize>
+  , /// Groups of dentries, by index in the original dtab, which were merged
+    /// into a single dentry.
+    pub merged: Vec<Vec<usize>>
+  , /// The number of dentries in the original dtab.
+    pub before: usize
+}
+
+impl Minimized {
+    /// Returns the number of dentries in the minimized dtab.
+    #[inline] pub fn after(&self) -> usize { self.dtab.len() }
+}
+
+impl fmt::Display for Minimized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!( f, "reduced {} dentries to {}: removed {}, merged {} groups"
+              , self.before, self.after(), self.removed.len(), self.merged.len())
+    }
+}
+
+/// Minimize `dtab`, preserving the result of delegating each of `paths`.
+///
+/// First, each dentry is removed in turn, if doing so doesn't change the
+/// result for any path. Then, each group of remaining dentries with
+/// identical prefixes is merged into a single dentry alternating between
+/// their destinations, in precedence order, if doing so doesn't change the
+/// result for any path.
+///
+/// The guarantee extends only to the paths in the corpus: a path outside
+/// it may delegate differently under the minimized dtab.
+pub fn minimize(dtab: &Dtab, paths: &[Path]) -> Minimized {
+    let expected = paths.iter()
+        .map(|path| dtab.delegate(path))
+        .collect::<Vec<_>>();
+    let preserved = |candidate: &Dtab| paths.iter().zip(&expected)
+        .all(|(path, expected)| &candidate.delegate(path) == expected);
+
+    // Each remaining dentry, with the original indices it came from.
+    let mut entries = dtab.iter().cloned()
+        .enumerate()
+        .map(|(i, dentry)| (vec![i], dentry))
+        .collect::<Vec<(Vec<usize>, Dentry)>>();
+    let build = |entries: &[(Vec<usize>, Dentry)]| entries.iter()
+        .map(|(_, dentry)| dentry.clone())
+        .collect::<Dtab>();
+
+    let mut removed = Vec::new();
+    let mut i = 0;
+    while i < entries.len() {
+        let mut candidate = entries.clone();
+        candidate.remove(i);
+        if preserved(&build(&candidate)) {
+            removed.extend(entries.remove(i).0);
+        } else {
+            i += 1;
+        }
+    }
+
+    let mut merged = Vec::new();
+    let mut i = 0;
+    while i < entries.len() {
+        let group = (i..entries.len())
+            .filter(|&j| entries[j].1.prefix() == entries[i].1.prefix())
+            .collect::<Vec<_>>();
+        if group.len() < 2 {
+            i += 1;
+            continue
+        }
+        let dst = NameTree::alts(group.iter().rev().map(|&j| entries[j].1.dst().clone()));
+        let last = *group.last().expect("group has at least two entries");
+        let mut candidate = entries.clone();
+        let indices = group.iter()
+            .flat_map(|&j| entries[j].0.iter().cloned())
+            .collect::<Vec<_>>();
+        candidate[last] = (indices.clone(), Dentry::new(entries[i].1.prefix().clone(), dst));
+        for &j in group.iter().rev().skip(1) {
+            candidate.remove(j);
+        }
+        if preserved(&build(&candidate)) {
+            entries = candidate;
+            merged.push(indices);
+        } else {
+            i += 1;
+        }
+    }
+
+    removed.sort();
+    Minimized { dtab: build(&entries), removed, merged, before: dtab.len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_duplicates() {
+        let dtab = dtab![
+            "/svc" => "/a";
+            "/other" => "/b";
+            "/svc" => "/c";
+            "/svc/*/x" => "/d";
+        ];
+        let paths = vec![ Path::read("/svc/users").unwrap()
+                        , Path::read("/other").unwrap() ];
+        let minimized = minimize(&dtab, &paths);
+        assert_eq!( minimized.dtab
+                  , dtab![
+                        "/other" => "/b";
+                        "/svc" => NameTree::from("/c") | "/a";
+                    ]);
+        assert_eq!(minimized.removed, vec![3]);
+        assert_eq!(minimized.merged, vec![vec![0, 2]]);
+        assert_eq!(&minimized.to_string(), "reduced 4 dentries to 2: removed 1, merged 1 groups");
+        for path in &paths {
+            assert_eq!(minimized.dtab.delegate(path), dtab.delegate(path));
+        }
+    }
+
+    #[test]
+    fn preserves_failures() {
+        let dtab = dtab!["/a" => "/b"; "/b" => "/a"; "/c" => "/d"; "/b" => "/e";];
+        let paths = vec![Path::read("/a").unwrap()];
+        assert!(dtab.delegate(&paths[0]).is_err());
+        let minimized = minimize(&dtab, &paths);
+        assert_eq!(minimized.dtab, dtab!["/a" => "/b"; "/b" => "/a";]);
+        assert_eq!(minimized.removed, vec![2, 3]);
+        assert_eq!(minimized.dtab.delegate(&paths[0]), dtab.delegate(&paths[0]));
+    }
+
+    #[test]
+    fn empty_corpus() {
+        let dtab = dtab!["/a" => "/b"; "/c" => "/d";];
+        let minimized = minimize(&dtab, &[]);
+        assert_eq!(minimized.dtab, Dtab::new());
+        assert_eq!(minimized.removed, vec![0, 1]);
+    }
+}
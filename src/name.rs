@@ -0,0 +1,131 @@
+//! The names a path delegates to.
+//!
+//! Delegation rewrites a path until no dentry matches it, and then either
+//! leaves it as it is, or asks a [`Namer`] to bind it. As in Finagle, the
+//! leaves of the result are therefore one of two kinds of [`Name`]:
+//!
+//! + [`Name::Path`], a path which is still symbolic: nothing rewrote or
+//!   bound it, so it would need delegating through some other dtab, or
+//!   binding by some other namer, before it names anything.
+//! + [`Name::Bound`], a name a namer bound a path to. It is terminal: it is
+//!   never delegated again, and identifies an address, such as
+//!   `/$/inet/127.0.0.1/8080`, or an id which is only meaningful to the
+//!   namer which bound it.
+//!
+//! Both kinds are written as their paths, so a tree of names is written
+//! just as a tree of paths would be.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::NameTree;
+//! use dtab::delegate::{self, DelegateOptions};
+//! use dtab::name::Name;
+//! use dtab::namer::MockNamer;
+//! use dtab::path::Path;
+//!
+//! let path = |s| Path::read(s).unwrap();
+//! let mut namer = MockNamer::new();
+//! namer.insert(path("/#/k8s/users"), NameTree::Leaf(path("/$/inet/10.0.0.1/8080")));
+//!
+//! let dtab = dtab!["/svc" => NameTree::from("/#/k8s") | "/srv";];
+//! let tree = delegate::delegate(&dtab, &path("/svc/users"), &DelegateOptions::default()
+//!                              , &namer, ())
+//!     .unwrap();
+//! assert_eq!(&tree.to_string(), "/$/inet/10.0.0.1/8080 | /srv/users");
+//!
+//! let names: Vec<&Name> = tree.leaves().collect();
+//! assert_eq!(names, vec![ &Name::Bound(path("/$/inet/10.0.0.1/8080"))
+//!                       , &Name::Path(path("/srv/users")) ]);
+//! # }
+//! ```
+//!
+//! [`Namer`]: ../namer/trait.Namer.html
+//! [`Name`]: enum.Name.html
+//! [`Name::Path`]: enum.Name.html#variant.Path
+//! [`Name::Bound`]: enum.Name.html#variant.Bound
+use std::fmt;
+
+use super::path::Path;
+
+/// A leaf of the tree a path delegates to.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Name {
+    /// A path which no dentry rewrote and no namer bound, and so still
+    /// needs delegating.
+    Path(Path)
+  , /// The address or id a namer bound a path to, which is never delegated
+    /// again.
+    Bound(Path)
+}
+
+impl Name {
+    /// Returns the path this name is written as, whether or not it is
+    /// bound.
+    #[inline] pub fn path(&self) -> &Path {
+        match *self {
+            Name::Path(ref path) | Name::Bound(ref path) => path
+        }
+    }
+
+    /// Returns this name's path, whether or not it is bound.
+    #[inline] pub fn into_path(self) -> Path {
+        match self {
+            Name::Path(path) | Name::Bound(path) => path
+        }
+    }
+
+    /// Returns true if a namer bound this name.
+    #[inline] pub fn is_bound(&self) -> bool {
+        matches!(*self, Name::Bound(_))
+    }
+
+    /// Returns the address or id of this name, if a namer bound it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::name::Name;
+    /// use dtab::path::Path;
+    ///
+    /// let path = Path::read("/$/inet/127.0.0.1/8080").unwrap();
+    /// assert_eq!(Name::Bound(path.clone()).bound(), Some(&path));
+    /// assert_eq!(Name::Path(path).bound(), None);
+    /// ```
+    #[inline] pub fn bound(&self) -> Option<&Path> {
+        match *self {
+            Name::Bound(ref path) => Some(path)
+          , Name::Path(_) => None
+        }
+    }
+}
+
+impl fmt::Display for Name {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.path(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NameTree;
+
+    #[test]
+    fn names() {
+        let path = Path::read("/srv/users").unwrap();
+        let bound = Name::Bound(path.clone());
+        let unbound = Name::Path(path.clone());
+        assert!(bound.is_bound());
+        assert!(!unbound.is_bound());
+        assert_ne!(bound, unbound);
+        assert_eq!(bound.path(), unbound.path());
+        assert_eq!(&bound.to_string(), "/srv/users");
+        assert_eq!(unbound.into_path(), path);
+
+        let tree = NameTree::Leaf(bound) | NameTree::Leaf(Name::Path(Path::empty()));
+        assert_eq!(&tree.to_string(), "/srv/users | /");
+    }
+}
@@ -0,0 +1,343 @@
+//! Binding names with service discovery backends.
+//!
+//! Delegation rewrites a path with a dtab until no dentry matches it. By
+//! convention, paths which begin with `/$` or `/#` name a *namer*, which
+//! binds the rest of the path using some service discovery backend, such as
+//! DNS or the Kubernetes API: `/#/io.l5d.k8s/default/http/users` is bound by
+//! the namer linkerd configures as `io.l5d.k8s`, and
+//! `/$/inet/users.example.com/8080` by Finagle's built-in `inet` namer.
+//!
+//! A [`Namer`] lets the delegation engine consult such a backend. When no
+//! dentry matches a namer path, the namer looks it up, and the tree it
+//! returns takes the path's place in the result. The leaves of that tree are
+//! the names the path is bound to, and are not delegated any further. A
+//! namer which doesn't know a path returns `None`, leaving the path as a
+//! name in the result; the unit type, `()`, is a namer which knows no paths.
+//!
+//! As in Finagle, a few paths have a fixed meaning, and are bound by
+//! [`builtin`] before any namer is consulted: `/$/nil` is bound to the empty
+//! tree `$`, and `/$/fail` to the failure `!`. Paths under `/$/inet` name an
+//! address directly, and so are names, unless the namer resolves them.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::NameTree;
+//! use dtab::delegate::{self, DelegateOptions};
+//! use dtab::namer::Namer;
+//! use dtab::path::Path;
+//!
+//! /// Binds `/#/static/<service>` to two replicas of each service.
+//! struct Static;
+//!
+//! impl Namer for Static {
+//!     fn lookup(&self, path: &Path) -> Option<NameTree<Path>> {
+//!         let mut labels = path.labels().skip(1);
+//!         if labels.next() != Some(&b"static"[..]) {
+//!             return None
+//!         }
+//!         let service = String::from_utf8_lossy(labels.next()?).into_owned();
+//!         let replica = |n| Path::read(&format!("/{}-{}", service, n)).unwrap();
+//!         Some(NameTree::Leaf(replica(0)) & NameTree::Leaf(replica(1)))
+//!     }
+//! }
+//!
+//! let dtab = dtab!["/svc" => "/#/static";];
+//! let path = Path::read("/svc/users").unwrap();
+//! let tree = delegate::delegate(&dtab, &path, &DelegateOptions::default(), &Static, ())
+//!     .unwrap();
+//! assert_eq!(&tree.to_string(), "0.5 * /users-0 & 0.5 * /users-1");
+//! # }
+//! ```
+//!
+//! Tests of a dtab can bind names with a [`MockNamer`] instead, which
+//! binds paths from a map, without any network dependencies.
+//!
+//! A path of the form `/#/<id>/...` names the namer registered as `id`, so
+//! it is never rewritten by a dtab: dentries such as `/ => /#/io.l5d.fs`
+//! would otherwise rewrite it again and again. Delegation binds it with
+//! the namer instead, or leaves it as a name if the namer doesn't know it.
+//! [`Namers`] dispatches such paths to the namer registered with their id.
+//!
+//! [`Namer`]: trait.Namer.html
+//! [`MockNamer`]: struct.MockNamer.html
+//! [`Namers`]: struct.Namers.html
+//! [`builtin`]: fn.builtin.html
+use std::{fmt, str};
+use std::collections::BTreeMap;
+use std::iter::FromIterator;
+
+use super::NameTree;
+use super::path::Path;
+
+/// A service discovery backend, which binds paths beginning with `/$` or
+/// `/#`.
+pub trait Namer {
+    /// Bind `path`, returning the tree of names it is bound to, or `None` if
+    /// this namer doesn't know it.
+    ///
+    /// Delegation only asks a namer to bind paths which begin with `/$` or
+    /// `/#`. A namer registered with [`Namers`] is given the rest of the
+    /// path after `/#/<id>`, instead.
+    ///
+    /// A namer which knows `path`, but finds nothing bound to it, should
+    /// return the negation `~`, so that any alternatives are tried instead.
+    ///
+    /// [`Namers`]: struct.Namers.html
+    fn lookup(&self, path: &Path) -> Option<NameTree<Path>>;
+}
+
+/// Knows no paths.
+impl Namer for () {
+    #[inline] fn lookup(&self, _: &Path) -> Option<NameTree<Path>> { None }
+}
+
+impl<N> Namer for &N
+where N: Namer + ?Sized {
+    #[inline] fn lookup(&self, path: &Path) -> Option<NameTree<Path>> {
+        (**self).lookup(path)
+    }
+}
+
+/// Returns `true` if `path` begins with `/$` or `/#`, and so is bound by a
+/// namer rather than a dtab.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::namer;
+/// use dtab::path::Path;
+///
+/// assert!(namer::is_namer_path(&Path::read("/#/io.l5d.k8s/default").unwrap()));
+/// assert!(namer::is_namer_path(&Path::read("/$/inet/localhost/8080").unwrap()));
+/// assert!(!namer::is_namer_path(&Path::read("/svc/$").unwrap()));
+/// ```
+pub fn is_namer_path(path: &Path) -> bool {
+    matches!(path.labels().next(), Some(b"$") | Some(b"#"))
+}
+
+/// Returns the id of the namer named by a path of the form `/#/<id>/...`,
+/// or `None` if the path is not of that form.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::namer;
+/// use dtab::path::Path;
+///
+/// let path = Path::read("/#/io.l5d.k8s/default/http").unwrap();
+/// assert_eq!(namer::namer_id(&path), Some("io.l5d.k8s"));
+/// assert_eq!(namer::namer_id(&Path::read("/$/inet/127.1/80").unwrap()), None);
+/// assert_eq!(namer::namer_id(&Path::read("/#").unwrap()), None);
+/// ```
+pub fn namer_id(path: &Path) -> Option<&str> {
+    let mut labels = path.labels();
+    if labels.next() != Some(b"#") {
+        return None
+    }
+    labels.next().and_then(|id| str::from_utf8(id).ok())
+}
+
+/// Bind one of the paths with a fixed meaning in Finagle: `/$/nil`, and
+/// every path beneath it, is bound to the empty tree `$`, and `/$/fail` to
+/// the failure `!`. Returns `None` for any other path.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::NameTree;
+/// use dtab::namer;
+/// use dtab::path::Path;
+///
+/// let builtin = |s| namer::builtin(&Path::read(s).unwrap());
+/// assert_eq!(builtin("/$/nil"), Some(NameTree::Empty));
+/// assert_eq!(builtin("/$/fail/users"), Some(NameTree::Fail));
+/// assert_eq!(builtin("/$/inet/127.0.0.1/8080"), None);
+/// ```
+pub fn builtin(path: &Path) -> Option<NameTree<Path>> {
+    let mut labels = path.labels();
+    if labels.next() != Some(b"$") {
+        return None
+    }
+    match labels.next() {
+        Some(b"nil") => Some(NameTree::Empty)
+      , Some(b"fail") => Some(NameTree::Fail)
+      , _ => None
+    }
+}
+
+/// A namer which dispatches each path of the form `/#/<id>/...` to the
+/// namer registered as `id`, as linkerd does with its configured namers.
+///
+/// The registered namer is given the rest of the path after the id. Paths
+/// whose id has no namer registered, and paths of any other form, are
+/// unknown.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::NameTree;
+/// use dtab::namer::{MockNamer, Namer, Namers};
+/// use dtab::path::Path;
+///
+/// let path = |s| Path::read(s).unwrap();
+/// let mut k8s = MockNamer::new();
+/// k8s.insert(path("/default/http/users"), NameTree::Leaf(path("/$/inet/10.0.0.1/8080")));
+///
+/// let mut namers = Namers::new();
+/// namers.register("io.l5d.k8s", k8s);
+/// assert_eq!( namers.lookup(&path("/#/io.l5d.k8s/default/http/users"))
+///           , Some(NameTree::Leaf(path("/$/inet/10.0.0.1/8080"))));
+/// assert_eq!(namers.lookup(&path("/#/io.l5d.consul/users")), None);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Namers {
+    namers: BTreeMap<String, Box<dyn Namer>>
+}
+
+impl Namers {
+    /// Returns a new registry, with no namers registered.
+    #[inline] pub fn new() -> Self { Namers::default() }
+
+    /// Register `namer` as `id`, returning the namer previously registered
+    /// as `id`, if any.
+    pub fn register<I, N>(&mut self, id: I, namer: N) -> Option<Box<dyn Namer>>
+    where I: Into<String>
+        , N: Namer + 'static {
+        self.namers.insert(id.into(), Box::new(namer))
+    }
+
+    /// Remove the namer registered as `id`, returning it.
+    pub fn unregister(&mut self, id: &str) -> Option<Box<dyn Namer>> {
+        self.namers.remove(id)
+    }
+
+    /// Returns an iterator over the ids of the registered namers, in order.
+    pub fn ids(&self) -> impl Iterator<Item=&str> {
+        self.namers.keys().map(String::as_str)
+    }
+}
+
+impl Namer for Namers {
+    fn lookup(&self, path: &Path) -> Option<NameTree<Path>> {
+        let namer = self.namers.get(namer_id(path)?)?;
+        namer.lookup(&Path::from_labels(path.labels().skip(2).map(<[u8]>::to_vec).collect()))
+    }
+}
+
+impl fmt::Debug for Namers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Namers")
+            .field("ids", &self.namers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// A namer which binds paths from a map, for testing dtabs.
+///
+/// A path is bound by the longest path in the map which is a prefix of it,
+/// with the rest of the path appended to each leaf of the bound tree, as
+/// Finagle's namers do. Paths which have no prefix in the map are unknown.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dtab;
+/// # fn main() {
+/// use dtab::NameTree;
+/// use dtab::delegate::{self, DelegateOptions};
+/// use dtab::namer::MockNamer;
+/// use dtab::path::Path;
+///
+/// let path = |s| Path::read(s).unwrap();
+/// let mut namer = MockNamer::new();
+/// namer.insert( path("/#/io.l5d.k8s/default/http/users")
+///             , NameTree::Leaf(path("/10.0.0.1/8080")));
+/// namer.insert(path("/#/io.l5d.k8s/default/http/orders"), NameTree::Neg);
+///
+/// let dtab = dtab![ "/svc" => NameTree::from("/#/io.l5d.k8s/default/http")
+///                           | "/$/inet/fallback/8080"; ];
+/// let options = DelegateOptions::default();
+/// let delegate = |p| delegate::delegate(&dtab, &path(p), &options, &namer, ())
+///     .unwrap()
+///     .to_string();
+/// assert_eq!( &delegate("/svc/users/v2")
+///           , "/10.0.0.1/8080/v2 | /$/inet/fallback/8080/users/v2");
+/// assert_eq!(&delegate("/svc/orders"), "~ | /$/inet/fallback/8080/orders");
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MockNamer {
+    bindings: BTreeMap<Path, NameTree<Path>>
+}
+
+impl MockNamer {
+    /// Returns a new namer, which knows no paths.
+    #[inline] pub fn new() -> Self { MockNamer::default() }
+
+    /// Bind `path`, and every path it is a prefix of, to `tree`. Returns the
+    /// tree `path` was previously bound to, if any.
+    pub fn insert(&mut self, path: Path, tree: NameTree<Path>) -> Option<NameTree<Path>> {
+        self.bindings.insert(path, tree)
+    }
+
+    /// Unbind `path`, returning the tree it was bound to.
+    pub fn remove(&mut self, path: &Path) -> Option<NameTree<Path>> {
+        self.bindings.remove(path)
+    }
+}
+
+impl Namer for MockNamer {
+    fn lookup(&self, path: &Path) -> Option<NameTree<Path>> {
+        let labels: Vec<&[u8]> = path.labels().collect();
+        (0..=labels.len()).rev().find_map(|len| {
+            let prefix = Path::from_labels(labels[..len].iter().map(|l| l.to_vec()).collect());
+            self.bindings.get(&prefix).map(|tree| {
+                let residual = Path::from_labels(labels[len..].iter().map(|l| l.to_vec())
+                                                                   .collect());
+                tree.clone().append_residual(&residual)
+            })
+        })
+    }
+}
+
+impl FromIterator<(Path, NameTree<Path>)> for MockNamer {
+    fn from_iter<I>(iter: I) -> Self
+    where I: IntoIterator<Item=(Path, NameTree<Path>)> {
+        MockNamer { bindings: iter.into_iter().collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(s: &str) -> Path { Path::read(s).unwrap() }
+
+    #[test]
+    fn mock_namer() {
+        let namer: MockNamer = vec![
+            (path("/#/k8s"), NameTree::Fail)
+          , (path("/#/k8s/users"), NameTree::Leaf(path("/a")) | NameTree::Leaf(path("/b")))
+          , (path("/$/nil"), NameTree::Empty)
+        ].into_iter().collect();
+        let bound = |p| namer.lookup(&path(p)).map(|tree| tree.to_string());
+        assert_eq!(bound("/#/k8s/users"), Some("/a | /b".to_string()));
+        assert_eq!(bound("/#/k8s/users/get/1"), Some("/a/get/1 | /b/get/1".to_string()));
+        assert_eq!(bound("/#/k8s/orders"), Some("!".to_string()));
+        assert_eq!(bound("/$/nil/x"), Some("$".to_string()));
+        assert_eq!(bound("/#/consul/users"), None);
+        assert_eq!(bound("/#"), None);
+
+        let mut namer = namer;
+        assert_eq!( namer.remove(&path("/#/k8s/users"))
+                  , Some(NameTree::Leaf(path("/a")) | NameTree::Leaf(path("/b"))));
+        assert_eq!(namer.lookup(&path("/#/k8s/users")), Some(NameTree::Fail));
+        assert_eq!(namer.insert(path("/#/k8s"), NameTree::Neg), Some(NameTree::Fail));
+    }
+}
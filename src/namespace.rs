@@ -0,0 +1,207 @@
+//! Collections of dtabs, keyed by namespace.
+//!
+//! In a multi-tenant setup such as namerd, each namespace has its own dtab.
+//! A [`DtabMap`] holds those dtabs, and allows a namespace to extend a
+//! parent namespace: the effective dtab of a child namespace is its
+//! parent's effective dtab followed by its own dentries, so the child's
+//! rules take precedence.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::namespace::DtabMap;
+//!
+//! let mut namespaces = DtabMap::new();
+//! namespaces.insert("default", dtab!["/svc" => "/#/io.l5d.k8s/default/http";]);
+//! namespaces.insert("canary", dtab!["/svc/users" => "/#/io.l5d.k8s/canary/http/users";]);
+//! namespaces.set_parent("canary", "default").unwrap();
+//!
+//! assert_eq!( namespaces.effective("canary").unwrap()
+//!           , dtab![
+//!                 "/svc" => "/#/io.l5d.k8s/default/http";
+//!                 "/svc/users" => "/#/io.l5d.k8s/canary/http/users";
+//!             ]);
+//! # }
+//! ```
+//!
+//! [`DtabMap`]: struct.DtabMap.html
+use std::{error, fmt};
+use std::collections::BTreeMap;
+
+use super::Dtab;
+
+/// A map from namespace names to dtabs, in which namespaces may extend one
+/// another.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DtabMap {
+    namespaces: BTreeMap<String, Namespace>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Namespace {
+    dtab: Dtab
+  , parent: Option<String>
+}
+
+/// An error resolving or composing namespaces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NamespaceError {
+    /// The named namespace does not exist.
+    Unknown(String)
+  , /// Making a namespace extend a parent would create a cycle. The cycle is
+    /// listed from the namespace back to itself.
+    Cycle(Vec<String>)
+}
+
+impl fmt::Display for NamespaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NamespaceError::Unknown(ref name) =>
+                write!(f, "unknown namespace {:?}", name)
+          , NamespaceError::Cycle(ref names) =>
+                write!(f, "namespaces would form a cycle: {}", names.join(" -> "))
+        }
+    }
+}
+
+impl error::Error for NamespaceError {}
+
+impl DtabMap {
+    /// Returns a new, empty, map.
+    #[inline] pub fn new() -> Self { DtabMap::default() }
+
+    /// Set the dtab of a namespace, creating the namespace if it doesn't
+    /// exist. Returns the namespace's previous dtab, if any.
+    ///
+    /// Replacing a namespace's dtab does not change its parent.
+    pub fn insert<N>(&mut self, name: N, dtab: Dtab) -> Option<Dtab>
+    where N: Into<String> {
+        let name = name.into();
+        if let Some(namespace) = self.namespaces.get_mut(&name) {
+            return Some(::std::mem::replace(&mut namespace.dtab, dtab))
+        }
+        self.namespaces.insert(name, Namespace { dtab, parent: None });
+        None
+    }
+
+    /// Remove a namespace, returning its dtab.
+    ///
+    /// Namespaces which extended the removed namespace are not removed, but
+    /// resolving them will fail until it is replaced.
+    pub fn remove(&mut self, name: &str) -> Option<Dtab> {
+        self.namespaces.remove(name).map(|namespace| namespace.dtab)
+    }
+
+    /// Make the namespace `name` extend the namespace `parent`.
+    ///
+    /// Returns an error if either namespace does not exist, or if `parent`
+    /// already extends `name`.
+    pub fn set_parent(&mut self, name: &str, parent: &str)
+                      -> Result<(), NamespaceError> {
+        if !self.namespaces.contains_key(name) {
+            return Err(NamespaceError::Unknown(name.to_string()))
+        }
+        let mut cycle = vec![name.to_string()];
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            cycle.push(current.to_string());
+            if current == name {
+                return Err(NamespaceError::Cycle(cycle))
+            }
+            ancestor = self.namespaces.get(current)
+                .ok_or_else(|| NamespaceError::Unknown(current.to_string()))?
+                .parent.as_deref();
+        }
+        self.namespaces.get_mut(name).expect("namespace was checked above")
+            .parent = Some(parent.to_string());
+        Ok(())
+    }
+
+    /// Stop the namespace `name` from extending its parent, returning the
+    /// parent's name.
+    pub fn clear_parent(&mut self, name: &str) -> Option<String> {
+        self.namespaces.get_mut(name).and_then(|namespace| namespace.parent.take())
+    }
+
+    /// Returns the namespace's own dtab, without its parent's dentries.
+    pub fn get(&self, name: &str) -> Option<&Dtab> {
+        self.namespaces.get(name).map(|namespace| &namespace.dtab)
+    }
+
+    /// Returns the name of the namespace's parent, if it has one.
+    pub fn parent(&self, name: &str) -> Option<&str> {
+        self.namespaces.get(name)
+            .and_then(|namespace| namespace.parent.as_deref())
+    }
+
+    /// Returns an iterator over the names of every namespace, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.namespaces.keys().map(String::as_str)
+    }
+
+    /// Resolve the effective dtab of a namespace: the dentries of its most
+    /// distant ancestor first, followed by those of each descendant in turn,
+    /// ending with the namespace's own dentries.
+    pub fn effective(&self, name: &str) -> Result<Dtab, NamespaceError> {
+        let mut chain = Vec::new();
+        let mut current = Some(name);
+        while let Some(name) = current {
+            let namespace = self.namespaces.get(name)
+                .ok_or_else(|| NamespaceError::Unknown(name.to_string()))?;
+            chain.push(&namespace.dtab);
+            current = namespace.parent.as_deref();
+        }
+        Ok(chain.into_iter().rev()
+            .flat_map(|dtab| dtab.iter().cloned())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn namespaces() -> DtabMap {
+        let mut namespaces = DtabMap::new();
+        namespaces.insert("base", dtab!["/svc" => "/srv";]);
+        namespaces.insert("team", dtab!["/svc/a" => "/srv/team-a";]);
+        namespaces.insert("dev", dtab!["/svc/a" => "/srv/dev-a";]);
+        namespaces.set_parent("team", "base").unwrap();
+        namespaces.set_parent("dev", "team").unwrap();
+        namespaces
+    }
+
+    #[test]
+    fn effective() {
+        let namespaces = namespaces();
+        assert_eq!( namespaces.effective("dev").unwrap()
+                  , dtab![
+                        "/svc" => "/srv";
+                        "/svc/a" => "/srv/team-a";
+                        "/svc/a" => "/srv/dev-a";
+                    ]);
+        assert_eq!(namespaces.effective("base").unwrap(), dtab!["/svc" => "/srv";]);
+        assert_eq!(namespaces.parent("dev"), Some("team"));
+        assert_eq!(namespaces.names().collect::<Vec<_>>(), vec!["base", "dev", "team"]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut namespaces = namespaces();
+        assert_eq!( namespaces.set_parent("base", "dev")
+                  , Err(NamespaceError::Cycle(vec![ "base".to_string()
+                                                   , "dev".to_string()
+                                                   , "team".to_string()
+                                                   , "base".to_string()])));
+        assert_eq!( namespaces.set_parent("base", "prod")
+                  , Err(NamespaceError::Unknown("prod".to_string())));
+
+        namespaces.remove("team");
+        assert_eq!( namespaces.effective("dev")
+                  , Err(NamespaceError::Unknown("team".to_string())));
+        assert_eq!(namespaces.clear_parent("dev"), Some("team".to_string()));
+        assert!(namespaces.effective("dev").is_ok());
+    }
+}
@@ -20,10 +20,11 @@
 //! expression:
 //!
 //! ```
-//! use dtab::NameTree;
-//!
-//! let dentry = NameTree::from("/iceCreamStore") >> "/smitten";
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! let dentry = prefix!("/iceCreamStore") >> "/smitten";
 //! assert_eq!("/iceCreamStore => /smitten;", &dentry.to_string());
+//! # }
 //! ```
 //!
 //! Take note of the following:
@@ -36,6 +37,21 @@
 //!   other element, as `NameTree<String>` implements `convert::From<&str>`.
 //! + The `>>` operator is used in place of `=>` to construct a [`Dentry`].
 //!   `=>` is a reserved word in Rust, but `>>` is [an overridable operator].
+//!   The left hand side of `>>` is a [`Prefix`], so that constructing a
+//!   dentry can't fail. The [`prefix!`] macro validates string literals at
+//!   compile time, while [`DelegateTo`] reads a prefix which isn't known
+//!   until runtime, returning a `Result`:
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::DelegateTo;
+//!
+//! let dentry = prefix!("/iceCreamStore") >> "/smitten";
+//! assert_eq!(Ok(dentry), "/iceCreamStore".delegate_to("/smitten"));
+//! assert!("iceCreamStore".delegate_to("/smitten").is_err());
+//! # }
+//! ```
 //!
 //! The `|` operator can be used to programmatically construct alternation
 //! expressions. For example:
@@ -47,37 +63,46 @@
 //! becomes
 //!
 //! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
 //! use dtab::NameTree;
 //!
-//! let dentry = NameTree::from("/iceCreamStore") >>
+//! let dentry = prefix!("/iceCreamStore") >>
 //!              (NameTree::from("/humphrys") | "/smitten");
 //! assert_eq!("/iceCreamStore => /humphrys | /smitten;", &dentry.to_string());
+//! # }
 //! ```
 //!
 //! These alternation expressions can have any number of alternates, as in:
 //!
 //! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
 //! use dtab::NameTree;
 //!
 //! let dest = NameTree::from("/humphrys") | "/smitten" | "/birite"
 //!                   | "/three-twins";
-//! let dentry = NameTree::from("/iceCreamStore") >> dest;
+//! let dentry = prefix!("/iceCreamStore") >> dest;
 //! assert_eq!(
 //!   "/iceCreamStore => /humphrys | /smitten | /birite | /three-twins;"
 //! , &dentry.to_string()
 //! );
+//! # }
 //! ```
 //!
 //! Union expressions can be constructed using the `&` operator:
 //!
 //! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
 //! use dtab::NameTree;
 //!
 //! let dest = NameTree::from("/smitten") & "/humphrys";
-//! let dentry = NameTree::from("/iceCreamStore") >> dest;
+//! let dentry = prefix!("/iceCreamStore") >> dest;
 //!
 //! assert_eq!( "/iceCreamStore => 0.5 * /smitten & 0.5 * /humphrys;"
 //!            , &dentry.to_string());
+//! # }
 //! ```
 //!
 //! Note that if no weight is supplied, the value of [`DEFAULT_WEIGHT`], 0.5,
@@ -86,13 +111,15 @@
 //! Weighted unions can be constructed using the `*` operator:
 //!
 //! ```
-//! use dtab::NameTree;
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
 //! use dtab::nametree::W;
 //!
-//! let dest = W(0.7) * "/smitten" & W(0.3) * "/humphrys";
-//! let dentry = NameTree::from("/iceCreamStore") >> dest;
+//! let dest = (W(0.7) * "/smitten") & (W(0.3) * "/humphrys");
+//! let dentry = prefix!("/iceCreamStore") >> dest;
 //! assert_eq!( "/iceCreamStore => 0.7 * /smitten & 0.3 * /humphrys;"
 //!            , &dentry.to_string());
+//! # }
 //! ```
 //!
 //! [`W()`] is a [newtype] used to allow the implementation of custom operators
@@ -104,17 +131,20 @@
 //! failure, and empty `NameTree` nodes, rather than leaf nodes:
 //!
 //! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
 //! use dtab::NameTree;
 //!
-//! let dentry = NameTree::from("/iceCreamStore") >>
+//! let dentry = prefix!("/iceCreamStore") >>
 //!              (NameTree::from("~") | "/smitten");
 //! assert_eq!( "/iceCreamStore => ~ | /smitten;"
 //!            , &dentry.to_string());
 //!
-//! let dentry = NameTree::from("/iceCreamStore") >>
+//! let dentry = prefix!("/iceCreamStore") >>
 //!              (NameTree::from("/smitten") | "!");
 //! assert_eq!( "/iceCreamStore => /smitten | !;"
 //!            , &dentry.to_string());
+//! # }
 //! ```
 //!
 //! Note that this only works when the leaf type of the `NameTree` is `String`.
@@ -123,20 +153,49 @@
 //! explictly:
 //!
 //! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
 //! use dtab::NameTree;
 //!
-//! let dentry = NameTree::from("/iceCreamStore") >>
+//! let dentry = prefix!("/iceCreamStore") >>
 //!             (NameTree::Neg | "/smitten");
 //! assert_eq!( "/iceCreamStore => ~ | /smitten;"
 //!            , &dentry.to_string());
 //!
-//! let dentry = NameTree::from("/iceCreamStore") >>
+//! let dentry = prefix!("/iceCreamStore") >>
 //!              (NameTree::from("/smitten") | NameTree::Fail);
 //! assert_eq!( "/iceCreamStore => /smitten | !;"
 //!            , &dentry.to_string());
+//! # }
+//! ```
+//!
+//! Name trees are serialized as strings. To include a `NameTree` in a
+//! configuration struct, use this module with `serde(with = ...)`:
+//!
+//! ```
+//! # extern crate dtab;
+//! # extern crate serde;
+//! # extern crate serde_json;
+//! # #[macro_use] extern crate serde_derive;
+//! use dtab::NameTree;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Fallback {
+//!     #[serde(with = "dtab::nametree")]
+//!     dst: NameTree<String>
+//! }
+//! # fn main() {
+//! let fallback: Fallback = serde_json::from_str(r#"{"dst": "/a | ~"}"#).unwrap();
+//! assert_eq!(fallback.dst, NameTree::from("/a") | NameTree::Neg);
+//! assert_eq!( serde_json::to_string(&fallback).unwrap()
+//!           , r#"{"dst":"/a | ~"}"#);
+//! # }
 //! ```
 //!
 //! [`Dentry`]: ../struct.Dentry.html
+//! [`DelegateTo`]: ../prefix/trait.DelegateTo.html
+//! [`prefix!`]: ../macro.prefix.html
+//! [`Prefix`]: ../prefix/struct.Prefix.html
 //! [`NameTree`]: enum.NameTree.html
 //! [`W()`]: struct.W.html
 //! [Scala implementation]: https://github.com/twitter/finagle/blob/master/finagle-core/src/main/scala/com/twitter/finagle/NameTree.scala
@@ -144,14 +203,41 @@
 //! [an overridable operator]: https://doc.rust-lang.org/std/ops/trait.Shr.html
 //! [newtype]: https://aturon.github.io/features/types/newtype.html
 
-use std::{ops, convert, fmt};
+use std::{ops, convert, fmt, mem, ptr, str};
+use std::fmt::Write;
 use super::Dentry;
+use super::parse::{self, ParseOptions};
+use super::path::{EscapeNonAscii, Path};
+use super::prefix::Prefix;
+use super::weight::{IntoWeight, Weight, WeightPolicy, WeightSumError};
+#[cfg(feature = "rand")]
+use super::sample;
 use self::NameTree::*;
 pub const DEFAULT_WEIGHT: f64 = 0.5;
 
 /// Name trees represent a composite name whose interpretation is subject to
 /// Finagle's interpretation rules
-#[derive(Clone, PartialEq, Debug)]
+///
+/// The `Debug` form of a tree is its dtab syntax, with each union and
+/// alternation labelled with its variant name. With the alternate flag
+/// (`{:#?}`), each member is written on its own line, so that diffs of
+/// failed test assertions line up with the structure of the tree:
+///
+/// ```
+/// use dtab::NameTree;
+///
+/// let tree = NameTree::from("/humphrys") | NameTree::from("/smitten") & "/birite";
+/// assert_eq!( &format!("{:?}", tree)
+///           , r#"Alt("/humphrys" | Union(0.5 * "/smitten" & 0.5 * "/birite"))"#);
+/// assert_eq!( &format!("{:#?}", tree), r#"Alt(
+///     "/humphrys"
+///   | Union(
+///         0.5 * "/smitten"
+///       & 0.5 * "/birite"
+///     )
+/// )"#);
+/// ```
+#[derive(Clone, PartialEq)]
 pub enum NameTree<T> { Leaf(T)
                      , Union(Weighted<T>, Weighted<T>)
                      , Alt(Box<NameTree<T>>, Box<NameTree<T>>)
@@ -160,9 +246,773 @@ pub enum NameTree<T> { Leaf(T)
                      , Fail
                      }
 
+impl<T> Default for NameTree<T> {
+    /// The default tree is the negation, `~`.
+    #[inline] fn default() -> Self { Neg }
+}
+
+impl<T> Drop for NameTree<T> {
+    /// Drop the tree without recursing, by unlinking nested unions and
+    /// alternations onto a work list, so that arbitrarily deep trees can be
+    /// dropped without overflowing the stack.
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.unlink_branches(&mut stack);
+        while let Some(mut tree) = stack.pop() {
+            tree.unlink_branches(&mut stack);
+        }
+    }
+}
+
+/// A `NameTree` taken apart by value.
+///
+/// Since `NameTree` implements `Drop`, it can't be destructured by moving
+/// out of its variants; `NameTree::into_parts` does that instead.
+pub(crate) enum Parts<T> { Leaf(T)
+              , Union(Weighted<T>, Weighted<T>)
+              , Alt(Box<NameTree<T>>, Box<NameTree<T>>)
+              , Neg
+              , Empty
+              , Fail
+              }
+
 impl<T> NameTree<T> {
-    #[inline] pub fn weighted(self, weight: f64) -> Weighted<T> {
-        Weighted { weight: weight, tree: Box::new(self)}
+    /// Assign a weight to this tree, for use in a union.
+    ///
+    /// The weight may be an `f64` or an exact [`Weight`].
+    ///
+    /// [`Weight`]: ../weight/struct.Weight.html
+    #[inline] pub fn weighted<W>(self, weight: W) -> Weighted<T>
+    where W: IntoWeight {
+        Weighted { weight: weight.into_weight(), tree: Box::new(self)}
+    }
+
+    /// Construct an alternation of any number of trees, each of which is
+    /// tried in turn if the trees before it fail to bind.
+    ///
+    /// An empty alternation is the negation `~`, and an alternation of a
+    /// single tree is just that tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    ///
+    /// let stores = vec!["/humphrys", "/smitten", "/birite"];
+    /// let tree: NameTree<String> = NameTree::alts(stores);
+    /// assert_eq!(&tree.to_string(), "/humphrys | /smitten | /birite");
+    /// ```
+    pub fn alts<I>(trees: I) -> Self
+    where I: IntoIterator
+        , I::Item: convert::Into<NameTree<T>> {
+        trees.into_iter()
+            .map(convert::Into::into)
+            .fold(None, |alt, tree| Some(match alt {
+                None => tree
+              , Some(alt) => Alt(Box::new(alt), Box::new(tree))
+            }))
+            .unwrap_or(Neg)
+    }
+
+    /// Construct a union of any number of weighted trees.
+    ///
+    /// Members may be [`Weighted`] trees, or plain `NameTree`s, which are
+    /// given the [`DEFAULT_WEIGHT`]. Since a `NameTree` union has exactly two
+    /// members, the result is a chain of nested unions, in which each nested
+    /// union is weighted by the sum of its members' weights; this preserves
+    /// the relative weights of every member. Use [`normalize_weights`] to
+    /// rescale the weights so that they sum to one.
+    ///
+    /// An empty union is the negation `~`, and a union of a single tree is
+    /// just that tree, without its weight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    ///
+    /// let canaries = vec![(0.9, "/v1"), (0.05, "/v2"), (0.05, "/v3")];
+    /// let tree = NameTree::union(canaries.into_iter()
+    ///     .map(|(weight, dst)| NameTree::from(dst).weighted(weight)));
+    /// assert_eq!( &tree.to_string()
+    ///           , "0.95 * (0.9 * /v1 & 0.05 * /v2) & 0.05 * /v3");
+    /// ```
+    ///
+    /// [`DEFAULT_WEIGHT`]: constant.DEFAULT_WEIGHT.html
+    /// [`normalize_weights`]: #method.normalize_weights
+    /// [`Weighted`]: struct.Weighted.html
+    pub fn union<I>(members: I) -> Self
+    where I: IntoIterator
+        , I::Item: convert::Into<Weighted<T>> {
+        let mut members = members.into_iter().map(convert::Into::into);
+        let Weighted { mut weight, tree } = match members.next() {
+            Some(first) => first
+          , None => return Neg
+        };
+        let mut tree = *tree;
+        for member in members {
+            let next_weight = weight + member.weight;
+            tree = Union(tree.weighted(weight), member);
+            weight = next_weight;
+        }
+        tree
+    }
+
+    /// Construct a union splitting traffic evenly between any number of
+    /// trees.
+    ///
+    /// Every tree is given a weight of exactly `1`, and the union is built
+    /// as [`union`] builds it, so each nested union is weighted by the
+    /// number of trees it contains, and each of the `n` trees receives
+    /// exactly `1/n` of the traffic. The weights are whole numbers, so they
+    /// are written and read back exactly, and the text form is the same as
+    /// writing `1 * ` before each tree of a chain of unions. Use
+    /// [`normalize_weights`] to rescale the weights so that they sum to one.
+    /// The trees themselves are not modified.
+    ///
+    /// An empty union is the negation `~`, and a union of a single tree is
+    /// just that tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    ///
+    /// let tree: NameTree<String> = NameTree::even_union(vec!["/a", "/b", "/c"]);
+    /// assert_eq!(&tree.to_string(), "2 * (1 * /a & 1 * /b) & 1 * /c");
+    /// assert_eq!(tree, "1 * /a & 1 * /b & 1 * /c".parse().unwrap());
+    /// ```
+    ///
+    /// [`union`]: #method.union
+    /// [`normalize_weights`]: #method.normalize_weights
+    pub fn even_union<I>(trees: I) -> Self
+    where I: IntoIterator
+        , I::Item: convert::Into<NameTree<T>> {
+        NameTree::union(trees.into_iter().map(|tree| tree.into().weighted(Weight::ONE)))
+    }
+
+    /// Convert each leaf of this tree with `f`, keeping the tree's shape and
+    /// weights.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    ///
+    /// let tree = NameTree::from("/humphrys") | "/smitten";
+    /// let lengths: NameTree<usize> = tree.map(|leaf| leaf.len());
+    /// assert_eq!(&lengths.to_string(), "9 | 8");
+    /// ```
+    pub fn map<U, F>(self, mut f: F) -> NameTree<U>
+    where F: FnMut(T) -> U {
+        self.map_with(&mut f)
+    }
+
+    fn map_with<U, F>(self, f: &mut F) -> NameTree<U>
+    where F: FnMut(T) -> U {
+        /// A step of `map_with`: either a tree to convert, or the unions and
+        /// alternations to rebuild from the last two converted trees.
+        enum Step<T> { Tree(NameTree<T>), Union(Weight, Weight), Alt }
+
+        let mut steps = vec![Step::Tree(self)];
+        let mut done = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Tree(tree) => match tree.into_parts() {
+                    Parts::Leaf(leaf) => done.push(Leaf(f(leaf)))
+                  , Parts::Union(left, right) => {
+                        steps.push(Step::Union(left.weight, right.weight));
+                        steps.push(Step::Tree(right.into_tree()));
+                        steps.push(Step::Tree(left.into_tree()));
+                    }
+                  , Parts::Alt(left, right) => {
+                        steps.push(Step::Alt);
+                        steps.push(Step::Tree(*right));
+                        steps.push(Step::Tree(*left));
+                    }
+                  , Parts::Neg => done.push(Neg)
+                  , Parts::Empty => done.push(Empty)
+                  , Parts::Fail => done.push(Fail)
+                }
+              , Step::Union(left_weight, right_weight) => {
+                    let right = done.pop().expect("mapped right member");
+                    let left = done.pop().expect("mapped left member");
+                    done.push(Union(left.weighted(left_weight), right.weighted(right_weight)));
+                }
+              , Step::Alt => {
+                    let right = done.pop().expect("mapped right alternative");
+                    let left = done.pop().expect("mapped left alternative");
+                    done.push(Alt(Box::new(left), Box::new(right)));
+                }
+            }
+        }
+        done.pop().expect("mapped tree")
+    }
+
+    /// Take this tree apart, moving its leaf or members out of it.
+    pub(crate) fn into_parts(self) -> Parts<T> {
+        let tree = mem::ManuallyDrop::new(self);
+        // Each field is read exactly once, and `tree` is never dropped, so
+        // this moves the fields out just as destructuring `self` would if
+        // `NameTree` didn't implement `Drop`.
+        unsafe {
+            match *tree {
+                Leaf(ref leaf) => Parts::Leaf(ptr::read(leaf))
+              , Union(ref left, ref right) => Parts::Union(ptr::read(left), ptr::read(right))
+              , Alt(ref left, ref right) => Parts::Alt(ptr::read(left), ptr::read(right))
+              , Neg => Parts::Neg
+              , Empty => Parts::Empty
+              , Fail => Parts::Fail
+            }
+        }
+    }
+
+    /// Move this tree's unions and alternations onto `stack`, leaving `~` in
+    /// their place, so that dropping this tree won't recurse into them.
+    fn unlink_branches(&mut self, stack: &mut Vec<NameTree<T>>) {
+        let members: [&mut NameTree<T>; 2] = match *self {
+            Union(ref mut left, ref mut right) => [&mut *left.tree, &mut *right.tree]
+          , Alt(ref mut left, ref mut right) => [&mut **left, &mut **right]
+          , _ => return
+        };
+        for member in members {
+            if let Union(..) | Alt(..) = *member {
+                stack.push(mem::replace(member, Neg));
+            }
+        }
+    }
+
+    /// Append the alternatives of the alternation rooted at this tree to
+    /// `alts`, from left to right, flattening nested alternations.
+    fn flatten_alts(self, alts: &mut Vec<Self>) {
+        let mut stack = vec![self];
+        while let Some(tree) = stack.pop() {
+            match tree.into_parts() {
+                Parts::Alt(left, right) => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+              , parts => alts.push(NameTree::from_parts(parts))
+            }
+        }
+    }
+
+    /// Put a tree taken apart by `into_parts` back together.
+    fn from_parts(parts: Parts<T>) -> Self {
+        match parts {
+            Parts::Leaf(leaf) => Leaf(leaf)
+          , Parts::Union(left, right) => Union(left, right)
+          , Parts::Alt(left, right) => Alt(left, right)
+          , Parts::Neg => Neg
+          , Parts::Empty => Empty
+          , Parts::Fail => Fail
+        }
+    }
+
+    /// Rescale the weights of every union in this tree so that the weights
+    /// of each union's members sum to exactly one.
+    ///
+    /// Weights are stored as exact fractions, so normalizing a tree any
+    /// number of times always produces the same weights. Unions whose
+    /// weights are both zero are left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::nametree::W;
+    ///
+    /// let mut tree = W(3.0) * "/smitten" & W(1.0) * "/humphrys";
+    /// tree.normalize_weights();
+    /// assert_eq!(&tree.to_string(), "0.75 * /smitten & 0.25 * /humphrys");
+    /// ```
+    pub fn normalize_weights(&mut self) {
+        match *self {
+            Union(ref mut left, ref mut right) => {
+                let total = left.weight + right.weight;
+                if !total.is_zero() {
+                    left.weight = left.weight / total;
+                    right.weight = right.weight / total;
+                }
+                left.tree.normalize_weights();
+                right.tree.normalize_weights();
+            }
+          , Alt(ref mut left, ref mut right) => {
+                left.normalize_weights();
+                right.normalize_weights();
+            }
+          , _ => {}
+        }
+    }
+
+    /// Apply a [`WeightPolicy`] to every union in this tree.
+    ///
+    /// `Raw` leaves the tree unchanged, and `Normalize` is equivalent to
+    /// [`normalize_weights`]. `Require` leaves the tree unchanged, but returns
+    /// an error for the first union, from the left, whose members' weights
+    /// don't sum to one. Note that each nested union is checked separately,
+    /// just as a consumer parsing this tree's text form would see it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::nametree::W;
+    /// use dtab::weight::{Weight, WeightPolicy};
+    ///
+    /// let strict = WeightPolicy::Require { tolerance: Weight::ZERO };
+    /// let mut tree = W(3.0) * "/smitten" & W(1.0) * "/humphrys";
+    /// assert_eq!(tree.apply_weight_policy(strict).unwrap_err().sum.to_f64(), 4.0);
+    ///
+    /// tree.apply_weight_policy(WeightPolicy::Normalize).unwrap();
+    /// assert!(tree.apply_weight_policy(strict).is_ok());
+    /// ```
+    ///
+    /// [`WeightPolicy`]: ../weight/enum.WeightPolicy.html
+    /// [`normalize_weights`]: #method.normalize_weights
+    pub fn apply_weight_policy(&mut self, policy: WeightPolicy)
+                               -> Result<(), WeightSumError> {
+        let tolerance = match policy {
+            WeightPolicy::Raw => return Ok(())
+          , WeightPolicy::Normalize => {
+                self.normalize_weights();
+                return Ok(())
+            }
+          , WeightPolicy::Require { tolerance } => tolerance
+        };
+        let mut stack = vec![&*self];
+        while let Some(tree) = stack.pop() {
+            match *tree {
+                Union(ref left, ref right) => {
+                    let sum = left.weight + right.weight;
+                    if !policy.accepts(sum) {
+                        return Err(WeightSumError { entry: None, sum, tolerance })
+                    }
+                    stack.push(&right.tree);
+                    stack.push(&left.tree);
+                }
+              , Alt(ref left, ref right) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+              , _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a smaller tree which binds to the same destinations as this
+    /// one, following Finagle's `NameTree.simplified`.
+    ///
+    /// - Negations are removed from alternations, as are any alternatives
+    ///   after a failure, since they can never be tried. An alternation of a
+    ///   single tree becomes that tree, and one with no trees left becomes
+    ///   `~`.
+    /// - Members of unions which have zero weight, or which are `~` or `!`,
+    ///   are removed. A union with one member left becomes that member, and
+    ///   one with none left becomes `!` if any member failed, or `~`
+    ///   otherwise.
+    ///
+    /// When members of a nested union are removed, its weight in the
+    /// enclosing union is reduced accordingly, so every remaining leaf keeps
+    /// the same share of the traffic as it had before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    /// use dtab::nametree::W;
+    ///
+    /// let tree = NameTree::from("~") | (W(0.9) * "/v1" & W(0.1) * "!") | "/v2";
+    /// assert_eq!(&tree.simplified().to_string(), "/v1 | /v2");
+    ///
+    /// let tree = NameTree::from("/humphrys") | "!" | "/smitten";
+    /// assert_eq!(&tree.simplified().to_string(), "/humphrys | !");
+    /// ```
+    pub fn simplified(self) -> Self {
+        self.simplify().0
+    }
+
+    /// Simplify this tree, returning the simplified tree and the fraction of
+    /// its weight that remains, if it is a union.
+    fn simplify(self) -> (Self, Weight) {
+        /// A step of `simplify`: either a tree to simplify, a union to
+        /// rebuild from the last two simplified trees, or an alternation
+        /// whose last simplified alternative is to be added to `alts` before
+        /// the rest of its alternatives are simplified.
+        enum Step<T> { Tree(NameTree<T>)
+                     , Union(Weight, Weight)
+                     , Alts { alts: Vec<NameTree<T>>, rest: Vec<NameTree<T>> }
+                     }
+
+        let mut steps = vec![Step::Tree(self)];
+        let mut done = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Tree(tree) => match tree.into_parts() {
+                    Parts::Alt(left, right) => {
+                        let mut rest = Vec::new();
+                        left.flatten_alts(&mut rest);
+                        right.flatten_alts(&mut rest);
+                        rest.reverse();
+                        let first = rest.pop().expect("first alternative");
+                        steps.push(Step::Alts { alts: Vec::new(), rest });
+                        steps.push(Step::Tree(first));
+                    }
+                  , Parts::Union(left, right) => {
+                        steps.push(Step::Union(left.weight, right.weight));
+                        steps.push(Step::Tree(right.into_tree()));
+                        steps.push(Step::Tree(left.into_tree()));
+                    }
+                  , parts => done.push((NameTree::from_parts(parts), Weight::ONE))
+                }
+              , Step::Alts { mut alts, mut rest } => {
+                    match done.pop().expect("simplified alternative").0 {
+                        Neg => {}
+                        // Simplified alternations are already flat, so
+                        // their alternatives only need to be spliced in.
+                      , tree @ Alt(..) => tree.flatten_alts(&mut alts)
+                      , tree => alts.push(tree)
+                    }
+                    // alternatives after a failure can never be tried
+                    if let Some(&Fail) = alts.last() {
+                        rest.clear();
+                    }
+                    match rest.pop() {
+                        Some(next) => {
+                            steps.push(Step::Alts { alts, rest });
+                            steps.push(Step::Tree(next));
+                        }
+                      , None => done.push((NameTree::alts(alts), Weight::ONE))
+                    }
+                }
+              , Step::Union(left_weight, right_weight) => {
+                    let (right, right_kept) = done.pop().expect("simplified right member");
+                    let (left, left_kept) = done.pop().expect("simplified left member");
+                    let total = left_weight + right_weight;
+                    let (left_weight, right_weight) =
+                        (left_weight * left_kept, right_weight * right_kept);
+                    let keep = |tree: &Self, weight: Weight|
+                        !weight.is_zero() && !matches!(*tree, Neg | Fail);
+                    done.push(match (keep(&left, left_weight), keep(&right, right_weight)) {
+                        (true, true) =>
+                            ( left.weighted(left_weight) & right.weighted(right_weight)
+                            , (left_weight + right_weight) / total)
+                      , (true, false) => (left, left_weight / total)
+                      , (false, true) => (right, right_weight / total)
+                      , (false, false) => {
+                            let failed = |tree: &Self, weight: Weight|
+                                !weight.is_zero() && matches!(*tree, Fail);
+                            if failed(&left, left_weight) || failed(&right, right_weight) {
+                                (Fail, Weight::ONE)
+                            } else {
+                                (Neg, Weight::ONE)
+                            }
+                        }
+                    });
+                }
+            }
+        }
+        done.pop().expect("simplified tree")
+    }
+
+    /// Pick one of the leaves this tree binds to at random, as a load
+    /// balancer would, or return `None` if the tree doesn't bind to any.
+    ///
+    /// A union picks one of its members which binds, with probability
+    /// proportional to its weight; members which are `~` or `!`, or which
+    /// have zero weight, are never picked. An alternation takes its first
+    /// member which isn't `~`. The negation `~`, the failure `!`, and the
+    /// empty tree `$` bind to no leaves.
+    ///
+    /// Sampling is deterministic: unions are visited from the root down,
+    /// and each picks a member using exact integer arithmetic on the
+    /// members' weights and whole `u64`s drawn from `rng`, so the result
+    /// depends only on the values `rng` produces. Use a [`SeededRng`] for
+    /// results which are the same on every run.
+    ///
+    /// This method requires the `rand` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate dtab;
+    /// # extern crate rand;
+    /// # fn main() {
+    /// use dtab::NameTree;
+    /// use dtab::nametree::W;
+    ///
+    /// let tree = NameTree::from("~") | (W(0.9) * "/v1" & W(0.1) * "/v2") | "/v0";
+    /// let mut rng = rand::thread_rng();
+    /// let leaf = tree.sample(&mut rng).unwrap();
+    /// assert!(leaf == "/v1" || leaf == "/v2");
+    ///
+    /// let tree = NameTree::from("!") | "/v0";
+    /// assert_eq!(tree.sample(&mut rng), None);
+    /// # }
+    /// ```
+    ///
+    /// [`SeededRng`]: ../sample/struct.SeededRng.html
+    #[cfg(feature = "rand")]
+    pub fn sample<R>(&self, rng: &mut R) -> Option<&T>
+    where R: ::rand::RngCore + ?Sized {
+        match *self {
+            Leaf(ref leaf) => Some(leaf)
+          , Alt(ref left, ref right) => match left.binding() {
+                Binding::Neg => right.sample(rng)
+              , _ => left.sample(rng)
+            }
+          , Union(ref left, ref right) => match (left.binds(), right.binds()) {
+                (true, true) => {
+                    let p = left.weight / (left.weight + right.weight);
+                    if sample::below(rng, p.denom()) < p.numer() {
+                        left.tree.sample(rng)
+                    } else {
+                        right.tree.sample(rng)
+                    }
+                }
+              , (true, false) => left.tree.sample(rng)
+              , (false, true) => right.tree.sample(rng)
+              , (false, false) => None
+            }
+          , Neg | Fail | Empty => None
+        }
+    }
+
+    /// Returns whether this tree binds, without binding it.
+    #[cfg(feature = "rand")]
+    fn binding(&self) -> Binding {
+        match *self {
+            Leaf(_) | Empty => Binding::Bound
+          , Neg => Binding::Neg
+          , Fail => Binding::Fail
+          , Alt(ref left, ref right) => match left.binding() {
+                Binding::Neg => right.binding()
+              , binding => binding
+            }
+          , Union(ref left, ref right) => {
+                if left.binds() || right.binds() {
+                    Binding::Bound
+                } else if [left, right].iter()
+                    .any(|m| !m.weight.is_zero() && m.tree.binding() == Binding::Fail) {
+                    Binding::Fail
+                } else {
+                    Binding::Neg
+                }
+            }
+        }
+    }
+
+    /// The weight given to this tree when it is added to a union without an
+    /// explicit weight.
+    fn member_weight(&self) -> Weight {
+        match *self {
+            Union(ref left, ref right) => left.weight + right.weight
+          , _ => DEFAULT_WEIGHT.into_weight()
+        }
+    }
+
+    /// Returns an iterator over references to the leaves of this tree, from
+    /// left to right.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    ///
+    /// let tree = NameTree::from("/humphrys") | "~" | "/smitten";
+    /// let leaves: Vec<&String> = tree.leaves().collect();
+    /// assert_eq!(leaves, vec!["/humphrys", "/smitten"]);
+    /// ```
+    #[inline] pub fn leaves(&self) -> Leaves<'_, T> {
+        Leaves { stack: vec![self] }
+    }
+
+    /// Measure the size and shape of this tree.
+    ///
+    /// This does not recurse, so it is safe to call on arbitrarily deep
+    /// trees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    ///
+    /// let tree = NameTree::from("/humphrys") & "/smitten" & "/birite"
+    ///          | "/three-twins";
+    /// let complexity = tree.complexity();
+    /// assert_eq!(complexity.depth, 4);
+    /// assert_eq!(complexity.union_width, 3);
+    /// assert_eq!(complexity.leaves, 4);
+    /// ```
+    pub fn complexity(&self) -> Complexity {
+        let mut complexity = Complexity { depth: 0, union_width: 0, leaves: 0 };
+        // (subtree, depth, whether the subtree is a member of a union)
+        let mut stack = vec![(self, 1, false)];
+        while let Some((tree, depth, in_union)) = stack.pop() {
+            complexity.depth = complexity.depth.max(depth);
+            match *tree {
+                Leaf(_) => complexity.leaves += 1
+              , Union(ref left, ref right) => {
+                    if !in_union {
+                        complexity.union_width =
+                            complexity.union_width.max(tree.union_width());
+                    }
+                    stack.push((&right.tree, depth + 1, true));
+                    stack.push((&left.tree, depth + 1, true));
+                }
+              , Alt(ref left, ref right) => {
+                    stack.push((right, depth + 1, false));
+                    stack.push((left, depth + 1, false));
+                }
+              , Neg | Empty | Fail => {}
+            }
+        }
+        complexity
+    }
+
+    /// Count the members of the union rooted at this tree, flattening
+    /// directly nested unions.
+    fn union_width(&self) -> usize {
+        let mut width = 0;
+        let mut stack = vec![self];
+        while let Some(tree) = stack.pop() {
+            match *tree {
+                Union(ref left, ref right) => {
+                    stack.push(&right.tree);
+                    stack.push(&left.tree);
+                }
+              , _ => width += 1
+            }
+        }
+        width
+    }
+}
+
+impl NameTree<Path> {
+    /// Append `residual` to every leaf of this tree.
+    ///
+    /// This is how a dentry's destination is applied to a path: the rest of
+    /// the path after the dentry's prefix is appended to each destination.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::NameTree;
+    /// use dtab::path::Path;
+    ///
+    /// let path = |s| Path::read(s).unwrap();
+    /// let tree = NameTree::Leaf(path("/srv/v2")) | NameTree::Leaf(path("/srv/v1"))
+    ///          | NameTree::Neg;
+    /// assert_eq!( &tree.append_residual(&path("/users/get")).to_string()
+    ///           , "/srv/v2/users/get | /srv/v1/users/get | ~");
+    /// ```
+    pub fn append_residual(self, residual: &Path) -> Self {
+        if residual.is_empty() {
+            return self
+        }
+        self.map(|leaf| leaf.concat(residual))
+    }
+}
+
+/// The size and shape of a [`NameTree`], as returned by
+/// [`NameTree::complexity`].
+///
+/// [`NameTree`]: enum.NameTree.html
+/// [`NameTree::complexity`]: enum.NameTree.html#method.complexity
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Complexity {
+    /// The greatest number of nodes on any path from the root to a leaf.
+    pub depth: usize
+  , /// The greatest number of members of any union, counting the members
+    /// of directly nested unions as members of the outer union.
+    pub union_width: usize
+  , /// The total number of leaves.
+    pub leaves: usize
+}
+
+/// Whether a tree binds to any leaves, when it is sampled.
+#[cfg(feature = "rand")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Binding { Bound, Neg, Fail }
+
+/// Iterator over references to the leaves of a [`NameTree`].
+///
+/// This is returned by [`NameTree::leaves`].
+///
+/// [`NameTree`]: enum.NameTree.html
+/// [`NameTree::leaves`]: enum.NameTree.html#method.leaves
+#[derive(Clone, Debug)]
+pub struct Leaves<'a, T: 'a> { stack: Vec<&'a NameTree<T>> }
+
+impl<'a, T> Iterator for Leaves<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        while let Some(tree) = self.stack.pop() {
+            match *tree {
+                Leaf(ref value) => return Some(value)
+              , Union(ref left, ref right) => {
+                    self.stack.push(&right.tree);
+                    self.stack.push(&left.tree);
+                }
+              , Alt(ref left, ref right) => {
+                    self.stack.push(right);
+                    self.stack.push(left);
+                }
+              , Neg | Empty | Fail => {}
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> IntoIterator for &'a NameTree<T> {
+    type Item = &'a T;
+    type IntoIter = Leaves<'a, T>;
+    #[inline] fn into_iter(self) -> Leaves<'a, T> { self.leaves() }
+}
+
+/// An iterator over the owned leaves of a [`NameTree`], from left to right.
+///
+/// This is returned by `NameTree`'s `IntoIterator` implementation, so the
+/// leaves of a tree can be collected without cloning them:
+///
+/// ```
+/// use dtab::NameTree;
+///
+/// let tree = NameTree::from("/humphrys") | "~" | NameTree::from("/smitten") & "/birite";
+/// let leaves: Vec<String> = tree.into_iter().collect();
+/// assert_eq!(leaves, vec!["/humphrys", "/smitten", "/birite"]);
+/// ```
+///
+/// [`NameTree`]: enum.NameTree.html
+#[derive(Clone, Debug)]
+pub struct IntoLeaves<T> { stack: Vec<NameTree<T>> }
+
+impl<T> Iterator for IntoLeaves<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        while let Some(tree) = self.stack.pop() {
+            match tree.into_parts() {
+                Parts::Leaf(value) => return Some(value)
+              , Parts::Union(left, right) => {
+                    self.stack.push(*right.tree);
+                    self.stack.push(*left.tree);
+                }
+              , Parts::Alt(left, right) => {
+                    self.stack.push(*right);
+                    self.stack.push(*left);
+                }
+              , Parts::Neg | Parts::Empty | Parts::Fail => {}
+            }
+        }
+        None
+    }
+}
+
+impl<T> IntoIterator for NameTree<T> {
+    type Item = T;
+    type IntoIter = IntoLeaves<T>;
+    #[inline] fn into_iter(self) -> IntoLeaves<T> {
+        IntoLeaves { stack: vec![self] }
     }
 }
 
@@ -178,30 +1028,331 @@ impl<'a> convert::From<&'a str> for NameTree<String> {
     }
 }
 
+impl convert::From<String> for NameTree<String> {
+    #[inline] fn from(s: String) -> Self {
+        match NameTree::from(s.as_str()) {
+            Leaf(_) => Leaf(s)
+          , tree => tree
+        }
+    }
+}
+
+/// A path is converted into a leaf, without first having to be formatted as
+/// a string by the caller.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::NameTree;
+/// use dtab::path::Path;
+///
+/// let path = Path::read("/USA/CA/SF").unwrap() / "Harrison" / "2790";
+/// let dst = NameTree::from(&path) | "/smitten";
+/// assert_eq!(&dst.to_string(), "/USA/CA/SF/Harrison/2790 | /smitten");
+/// ```
+impl<'a> convert::From<&'a Path> for NameTree<String> {
+    #[inline] fn from(path: &'a Path) -> Self { Leaf(path.to_string()) }
+}
+
+impl convert::From<Path> for NameTree<String> {
+    #[inline] fn from(path: Path) -> Self { NameTree::from(&path) }
+}
+
+/// Parses a tree from its text form, using the default [`ParseOptions`].
+/// See [`parse::name_tree`].
+///
+/// Unlike the `From<&str>` conversion, which treats its input as a single
+/// leaf, this parses weighted unions, alternations, and parentheses, with
+/// `&` binding more tightly than `|`:
+///
+/// ```
+/// use dtab::NameTree;
+/// use dtab::nametree::W;
+///
+/// let tree: NameTree<String> = "0.7 * /a & 0.3 * /b | ~".parse().unwrap();
+/// assert_eq!(tree, (W(0.7) * "/a" & W(0.3) * "/b") | NameTree::Neg);
+/// assert!("/a |".parse::<NameTree<String>>().is_err());
+/// ```
+///
+/// [`ParseOptions`]: ../parse/struct.ParseOptions.html
+/// [`parse::name_tree`]: ../parse/fn.name_tree.html
+impl str::FromStr for NameTree<String> {
+    type Err = parse::ParseError;
+    #[inline] fn from_str(s: &str) -> Result<Self, parse::ParseError> {
+        parse::name_tree(s, &ParseOptions::default())
+    }
+}
+
+/// Trees are displayed in dtab syntax, with parentheses only where they are
+/// needed. The alternate flag (`{:#}`) adds parentheses around every nested
+/// union or alternation, making the grouping explicit:
+///
+/// ```
+/// use dtab::NameTree;
+///
+/// let tree = NameTree::from("/humphrys") | "/smitten" | "/birite";
+/// assert_eq!(&format!("{}", tree), "/humphrys | /smitten | /birite");
+/// assert_eq!(&format!("{:#}", tree), "(/humphrys | /smitten) | /birite");
+/// ```
+///
+/// Like the labels of a [`Path`], leaves are written with each non-ASCII
+/// character replaced by `\xNN` escapes of its UTF-8 bytes, so the text form
+/// of a tree is always ASCII:
+///
+/// ```
+/// use dtab::NameTree;
+///
+/// let tree = NameTree::from("/crêperie") | "/café";
+/// assert_eq!(&tree.to_string(), "/cr\\xc3\\xaaperie | /caf\\xc3\\xa9");
+/// ```
+///
+/// [`Path`]: ../path/struct.Path.html
 impl<T> fmt::Display for NameTree<T>
 where T: fmt::Display {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Leaf(ref value) => write!(f, "{}", value)
-          , Union(ref left, ref right) => write!(f, "{} & {}", left, right)
-          , Alt(ref left, ref right) => write!(f, "{} | {}", left, right)
-          , Fail => write!(f, "!")
-          , Neg => write!(f, "~")
-          , Empty => write!(f, "$")
-        }
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let style = if f.alternate() { Style::Parenthesized } else { Style::Normal };
+        write_tree(f, Piece::Tree(self), style)
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
-pub struct Weighted<T> { weight: f64, tree: Box<NameTree<T>> }
+/// How `write_tree` lays out a tree.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Style {
+    /// Spaces around operators, and parentheses only where needed.
+    Normal
+  , /// Parentheses around every nested union or alternation.
+    Parenthesized
+  , /// No whitespace, and parentheses only where needed.
+    Compact
+}
 
-impl<T> fmt::Display for Weighted<T>
+impl Style {
+    fn text(self, spaced: &'static str, compact: &'static str) -> &'static str {
+        if self == Style::Compact { compact } else { spaced }
+    }
+}
+
+/// Write a tree in the compact form, without any whitespace.
+#[inline]
+pub(crate) fn write_compact<T>(f: &mut fmt::Formatter, tree: &NameTree<T>)
+                               -> fmt::Result
+where T: fmt::Display {
+    write_tree(f, Piece::Tree(tree), Style::Compact)
+}
+
+/// A piece of output still to be written by `write_tree`.
+enum Piece<'a, T: 'a> {
+    Tree(&'a NameTree<T>)
+  , Weighted(&'a Weighted<T>)
+  , Text(&'static str)
+}
+
+/// Write a tree without recursing, so that arbitrarily deep trees can be
+/// displayed without overflowing the stack.
+///
+/// Parentheses are inserted wherever they are needed for the output to be
+/// parsed back into the same tree: around unions and alternations that are
+/// members of a union, and around alternations that are the right-hand side
+/// of another alternation. In the parenthesized style, they are also
+/// inserted around unions and alternations that are the left-hand side of
+/// an alternation.
+fn write_tree<T>(f: &mut fmt::Formatter, root: Piece<T>, style: Style)
+                 -> fmt::Result
 where T: fmt::Display {
+    let mut stack = vec![root];
+    while let Some(piece) = stack.pop() {
+        match piece {
+            Piece::Text(text) => f.write_str(text)?
+          , Piece::Weighted(weighted) => {
+                write!(f, "{}{}", weighted.weight, style.text(" * ", "*"))?;
+                match *weighted.tree {
+                    Union(..) | Alt(..) => {
+                        f.write_str("(")?;
+                        stack.push(Piece::Text(")"));
+                    }
+                  , _ => {}
+                }
+                stack.push(Piece::Tree(&weighted.tree));
+            }
+          , Piece::Tree(tree) => match *tree {
+                Leaf(ref value) => write!(EscapeNonAscii(&mut *f), "{}", value)?
+              , Union(ref left, ref right) => {
+                    stack.push(Piece::Weighted(right));
+                    stack.push(Piece::Text(style.text(" & ", "&")));
+                    stack.push(Piece::Weighted(left));
+                }
+              , Alt(ref left, ref right) => {
+                    let nested = |tree: &NameTree<T>| match *tree {
+                        Union(..) | Alt(..) => style == Style::Parenthesized
+                      , _ => false
+                    };
+                    if let Alt(..) = **right {
+                        stack.push(Piece::Text(")"));
+                        stack.push(Piece::Tree(right));
+                        stack.push(Piece::Text(style.text(" | (", "|(")));
+                    } else if nested(right) {
+                        stack.push(Piece::Text(")"));
+                        stack.push(Piece::Tree(right));
+                        stack.push(Piece::Text(" | ("));
+                    } else {
+                        stack.push(Piece::Tree(right));
+                        stack.push(Piece::Text(style.text(" | ", "|")));
+                    }
+                    if nested(left) {
+                        stack.push(Piece::Text(")"));
+                        stack.push(Piece::Tree(left));
+                        stack.push(Piece::Text("("));
+                    } else {
+                        stack.push(Piece::Tree(left));
+                    }
+                }
+              , Fail => f.write_str("!")?
+              , Neg => f.write_str("~")?
+              , Empty => f.write_str("$")?
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<T> fmt::Debug for NameTree<T>
+where T: fmt::Debug {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        debug_tree(f, self, None)
+    }
+}
+
+impl<T> fmt::Debug for Weighted<T>
+where T: fmt::Debug {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        debug_tree(f, &self.tree, Some(self.weight))
+    }
+}
+
+/// A piece of output still to be written by `debug_tree`, at the given
+/// indentation for the alternate form.
+enum DebugPiece<'a, T: 'a> {
+    Tree(&'a NameTree<T>, Option<Weight>, usize)
+  , Op(&'static str, usize)
+  , Close(usize)
+}
 
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} * {}", self.weight, self.tree)
+/// Write the `Debug` form of a tree, with an optional weight, without
+/// recursing, as `write_tree` does.
+fn debug_tree<T>(f: &mut fmt::Formatter, tree: &NameTree<T>, weight: Option<Weight>)
+                 -> fmt::Result
+where T: fmt::Debug {
+    let mut stack = vec![DebugPiece::Tree(tree, weight, 0)];
+    while let Some(piece) = stack.pop() {
+        let (tree, weight, indent) = match piece {
+            DebugPiece::Tree(tree, weight, indent) => (tree, weight, indent)
+          , DebugPiece::Op(op, indent) => {
+                if f.alternate() {
+                    f.write_str("\n")?;
+                    write_indent(f, indent + 2)?;
+                    write!(f, "{} ", op)?;
+                } else {
+                    write!(f, " {} ", op)?;
+                }
+                continue
+            }
+          , DebugPiece::Close(indent) => {
+                if f.alternate() {
+                    f.write_str("\n")?;
+                    write_indent(f, indent)?;
+                    f.write_str(")")?;
+                } else {
+                    f.write_str(")")?;
+                }
+                continue
+            }
+        };
+        if let Some(weight) = weight {
+            write!(f, "{} * ", weight)?;
+        }
+        let (name, op, (left_weight, left), (right_weight, right)) = match *tree {
+            Leaf(ref value) => {
+                write!(f, "{:?}", value)?;
+                continue
+            }
+          , Neg => {
+                f.write_str("Neg")?;
+                continue
+            }
+          , Fail => {
+                f.write_str("Fail")?;
+                continue
+            }
+          , Empty => {
+                f.write_str("Empty")?;
+                continue
+            }
+          , Union(ref left, ref right) =>
+                ( "Union", "&"
+                , (Some(left.weight), &*left.tree)
+                , (Some(right.weight), &*right.tree))
+          , Alt(ref left, ref right) => ("Alt", "|", (None, &**left), (None, &**right))
+        };
+        if f.alternate() {
+            writeln!(f, "{}(", name)?;
+            write_indent(f, indent + 4)?;
+        } else {
+            write!(f, "{}(", name)?;
+        }
+        stack.push(DebugPiece::Close(indent));
+        stack.push(DebugPiece::Tree(right, right_weight, indent + 4));
+        stack.push(DebugPiece::Op(op, indent));
+        stack.push(DebugPiece::Tree(left, left_weight, indent + 4));
     }
+    Ok(())
+}
 
+/// Write `indent` spaces, which may be more than a formatting width allows.
+fn write_indent(f: &mut fmt::Formatter, mut indent: usize) -> fmt::Result {
+    const SPACES: &str = "                                ";
+    while indent > 0 {
+        let n = indent.min(SPACES.len());
+        f.write_str(&SPACES[..n])?;
+        indent -= n;
+    }
+    Ok(())
+}
+
+/// A `NameTree` with an associated weight, as a member of a union.
+#[derive(Clone, PartialEq)]
+pub struct Weighted<T> { weight: Weight, tree: Box<NameTree<T>> }
+
+impl<T> Weighted<T> {
+    /// Returns true if this member of a union may be picked by
+    /// `NameTree::sample`.
+    #[cfg(feature = "rand")]
+    fn binds(&self) -> bool {
+        !self.weight.is_zero() && self.tree.binding() == Binding::Bound
+    }
+
+    /// Returns the exact weight of this tree.
+    #[inline] pub fn weight(&self) -> Weight { self.weight }
+
+    /// Returns the weighted tree.
+    #[inline] pub fn tree(&self) -> &NameTree<T> { &self.tree }
+
+    /// Consume this weighted tree, returning the tree without its weight.
+    #[inline] pub fn into_tree(self) -> NameTree<T> { *self.tree }
+}
+
+impl<T> convert::From<NameTree<T>> for Weighted<T> {
+    /// Weight a tree with the [`DEFAULT_WEIGHT`].
+    ///
+    /// [`DEFAULT_WEIGHT`]: constant.DEFAULT_WEIGHT.html
+    #[inline] fn from(tree: NameTree<T>) -> Self { tree.weighted(DEFAULT_WEIGHT) }
+}
+
+impl<T> fmt::Display for Weighted<T>
+where T: fmt::Display {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let style = if f.alternate() { Style::Parenthesized } else { Style::Normal };
+        write_tree(f, Piece::Weighted(self), style)
+    }
 }
 //
 // pub trait NameTree {
@@ -228,6 +1379,32 @@ impl<T> ops::BitAnd for Weighted<T> {
     }
 }
 
+impl<T> ops::BitAnd<NameTree<T>> for Weighted<T> {
+    type Output = NameTree<T>;
+    /// Add an unweighted tree to a union, with the [`DEFAULT_WEIGHT`].
+    ///
+    /// [`DEFAULT_WEIGHT`]: constant.DEFAULT_WEIGHT.html
+    #[inline] fn bitand(self, rhs: NameTree<T>) -> NameTree<T> {
+        Union(self, rhs.weighted(DEFAULT_WEIGHT))
+    }
+}
+
+impl<T> ops::BitAnd<Weighted<T>> for NameTree<T> {
+    type Output = NameTree<T>;
+    /// Add a weighted tree to a union.
+    ///
+    /// If this tree is itself a union, as in `W(0.5) * a & W(0.3) * b & W(0.2)
+    /// * c`, it is weighted by the sum of its members' weights, so that chains
+    /// of weighted members behave like a single n-ary union. Otherwise, it is
+    /// given the [`DEFAULT_WEIGHT`].
+    ///
+    /// [`DEFAULT_WEIGHT`]: constant.DEFAULT_WEIGHT.html
+    #[inline] fn bitand(self, rhs: Weighted<T>) -> NameTree<T> {
+        let weight = self.member_weight();
+        Union(self.weighted(weight), rhs)
+    }
+}
+
 impl<T, R> ops::BitOr<R> for NameTree<T>
 where R: convert::Into<NameTree<T>> {
     type Output = Self;
@@ -251,31 +1428,62 @@ where R: convert::Into<NameTree<String>> {
     type Output = Weighted<String>;
     #[inline] fn mul(self, rhs: R) -> Self::Output {
         let W(w) = self;
-        Weighted { weight: w, tree: Box::new(rhs.into()) }
+        rhs.into().weighted(w)
+    }
+}
+
+impl<R> ops::Mul<R> for Weight
+where R: convert::Into<NameTree<String>> {
+
+    type Output = Weighted<String>;
+    #[inline] fn mul(self, rhs: R) -> Self::Output {
+        rhs.into().weighted(self)
     }
 }
 
-impl<R> ops::Shr<R> for NameTree<String>
+impl<R> ops::Shr<R> for Prefix
 where R: convert::Into<NameTree<String>> {
     type Output = Dentry;
     #[inline] fn shr(self, rhs: R) -> Self::Output {
-        Dentry { prefix: self, dst: rhs.into() }
+        Dentry::new(self, rhs)
     }
 }
 
+use serde::de::{self, Deserialize, Deserializer};
 use serde::ser::{Serializer};
+
+/// Serialize a name tree as a string.
+///
+/// Together with [`deserialize`], this allows this module to be used with
+/// `#[serde(with = "dtab::nametree")]`.
+///
+/// [`deserialize`]: fn.deserialize.html
 pub fn serialize<S>(name_tree: &NameTree<String>, serializer: S)
                     -> Result<S::Ok, S::Error>
 where S: Serializer {
     serializer.serialize_str(&format!("{}", name_tree))
 }
 
+/// Deserialize a name tree from a string, parsed with the default
+/// [`ParseOptions`], except that labels are read with the [`Any`] policy,
+/// so that every serialized tree reads back.
+///
+/// [`ParseOptions`]: ../parse/struct.ParseOptions.html
+/// [`Any`]: ../path/enum.LabelPolicy.html#variant.Any
+pub fn deserialize<'de, D>(deserializer: D) -> Result<NameTree<String>, D::Error>
+where D: Deserializer<'de> {
+    let tree = String::deserialize(deserializer)?;
+    parse::name_tree(&tree, &ParseOptions::serialized()).map_err(de::Error::custom)
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[allow(unused_imports)]
     use super::NameTree::*;
     use std::convert::From;
+    use Dtab;
 
 
 
@@ -338,9 +1546,79 @@ mod tests {
         );
     }
 
+    #[test]
+    fn exact_weighted_union() {
+        let t = (Weight::ratio(1, 3) * "/humphrys") & (Weight::ratio(2, 3) * "/smitten");
+        assert_eq!( t
+                  , Union( Leaf("/humphrys".to_string()).weighted(Weight::ratio(1, 3))
+                         , Leaf("/smitten".to_string()).weighted(Weight::ratio(2, 3))
+                         )
+        );
+    }
+
+    #[test]
+    fn invalid_float_weights() {
+        let t = (W(-1.0) * "/humphrys") & NameTree::from("/smitten").weighted(f64::NAN);
+        assert_eq!(t, (W(0.0) * "/humphrys") & (W(0.0) * "/smitten"));
+        assert_eq!((W(f64::INFINITY) * "/birite").weight(), Weight::MAX);
+    }
+
+    #[test]
+    fn normalize_weights_is_stable() {
+        let mut t = (W(0.1) * "/humphrys") & (W(0.2) * "/smitten");
+        t.normalize_weights();
+        let once = t.clone();
+        t.normalize_weights();
+        assert_eq!(t, once);
+        assert_eq!( t
+                  , Union( Leaf("/humphrys".to_string()).weighted(Weight::ratio(1, 3))
+                         , Leaf("/smitten".to_string()).weighted(Weight::ratio(2, 3))
+                         )
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let dtab = dtab![
+            "/svc" => (W(0.9) * "/srv/v1") & (W(0.1) * "/srv/v2");
+            "/svc/*/admin" => NameTree::from("/internal") | NameTree::Fail;
+        ];
+        let json = ::serde_json::to_string(&dtab).unwrap();
+        assert_eq!(::serde_json::from_str::<Dtab>(&json).unwrap(), dtab);
+        let text = ::serde_json::to_string(&dtab.to_string()).unwrap();
+        assert_eq!(::serde_json::from_str::<Dtab>(&text).unwrap(), dtab);
+        assert_eq!( ::serde_json::from_str::<Dentry>(r#""/svc => /srv""#).unwrap()
+                  , dtab!["/svc" => "/srv";][0]);
+        assert!(::serde_json::from_str::<Dtab>(r#""/svc => ""#).is_err());
+        assert!(::serde_json::from_str::<Dentry>(r#"{"prefix": "svc", "dst": "/a"}"#).is_err());
+    }
+
+    #[test]
+    fn even_union() {
+        for n in 1..=12 {
+            let leaves = (0..n).map(|i| format!("/{}", i)).collect::<Vec<_>>();
+            let tree: NameTree<String> = NameTree::even_union(leaves);
+            assert_eq!(tree.leaves().count(), n);
+            // the weights are written exactly, so they survive a round trip.
+            let parsed: NameTree<String> = tree.to_string().parse().unwrap();
+            assert_eq!(parsed, tree);
+            let mut normalized = parsed;
+            normalized.normalize_weights();
+            let strict = WeightPolicy::Require { tolerance: Weight::ZERO };
+            assert!(normalized.apply_weight_policy(strict).is_ok());
+        }
+        assert_eq!( NameTree::even_union(vec!["/a", "/b", "/c"])
+                  , Union( Union( Leaf("/a".to_string()).weighted(Weight::ONE)
+                                , Leaf("/b".to_string()).weighted(Weight::ONE))
+                              .weighted(Weight::ratio(2, 1))
+                         , Leaf("/c".to_string()).weighted(Weight::ONE)));
+        assert_eq!(NameTree::even_union(vec!["/a"]), Leaf("/a".to_string()));
+        assert_eq!(NameTree::even_union(Vec::<NameTree<String>>::new()), Neg);
+    }
+
     #[test]
     fn simple_weighted_union() {
-        let t = W(0.7) * "/humphrys" & W(0.3) * "/smitten";
+        let t = (W(0.7) * "/humphrys") & (W(0.3) * "/smitten");
         assert_eq!( t
                   , Union( W(0.7) * Leaf("/humphrys".to_string())
                          , W(0.3) * Leaf("/smitten".to_string())
@@ -348,38 +1626,228 @@ mod tests {
         );
     }
 
-}
 
-// impl ops::BitOr for NameTree {
-//
-// }
+    #[test]
+    fn alternate_display() {
+        let tree = (W(0.9) * (NameTree::from("/a") | "/b")) & (W(0.1) * "/c")
+                 | NameTree::from("/d") & "/e";
+        assert_eq!( &format!("{}", tree)
+                  , "0.9 * (/a | /b) & 0.1 * /c | 0.5 * /d & 0.5 * /e");
+        assert_eq!( &format!("{:#}", tree)
+                  , "(0.9 * (/a | /b) & 0.1 * /c) | (0.5 * /d & 0.5 * /e)");
 
-// mod parser {
-//   use super::NameTree;
-//   use std::str::FromStr;
-//
-//   named!{ weighted<&str, (f64, NameTree)>,
-//     do_parse!(w: map_res!(re_match!(r"[0-9]*\.?[0-9]+"), f64::from_str) >>
-//               tag!("*") >>
-//               t: tree   >>
-//               (w, t)
-//     )
-//   }
-//   named!{union<&str, NameTree>,
-//     do_parse!(w: map_res!(re_match!(r"[0-9]*\.?[0-9]+"), f64::from_str) >>
-//               tag!("*") >>
-//               t: tree   >>
-//               (w, t)
-//     )
-//   }
-//   named!{pub tree<&str, NameTree>,
-//     alt!(name |
-//     )
-//   }
-//   named!(name<&str, NameTree>,
-//     alt!( map!(tag!("!"), |_| { NameTree::Fail })  |
-//           map!(tag!("~"), |_| { NameTree::Neg })   |
-//           map!(tag!("$"), |_| { NameTree::Empty }) )
-//   );
-//
-// }
+        let dtab = dtab![
+            "/iceCreamStore" => tree;
+            "/smitten" => "/USA/CA/SF/Harrison/2790";
+        ];
+        assert_eq!( &format!("{:#}", dtab)
+                  , "/iceCreamStore=>0.9*(/a|/b)&0.1*/c|0.5*/d&0.5*/e;\
+                     /smitten=>/USA/CA/SF/Harrison/2790");
+        assert_eq!( ::parse::dtab(&format!("{:#}", dtab), &Default::default())
+                  , Ok(dtab));
+    }
+
+    #[test]
+    fn debug() {
+        let tree = NameTree::from("/a") | "~" | (W(0.9) * "/b") & (W(0.1) * "!");
+        assert_eq!( &format!("{:?}", tree)
+                  , r#"Alt(Alt("/a" | Neg) | Union(0.9 * "/b" & 0.1 * Fail))"#);
+        assert_eq!( &format!("{:#?}", W(1.0) * tree), r#"1 * Alt(
+    Alt(
+        "/a"
+      | Neg
+    )
+  | Union(
+        0.9 * "/b"
+      & 0.1 * Fail
+    )
+)"#);
+        assert_eq!( &format!("{:?}", Dentry::default())
+                  , "Dentry { prefix: Prefix(/), dst: Neg }");
+    }
+
+    #[test]
+    fn into_iter() {
+        let tree = NameTree::from("/a") | "!" | NameTree::from("/b") & "/c" | "/d";
+        let borrowed: Vec<String> = (&tree).into_iter().cloned().collect();
+        let mut owned = Vec::new();
+        for leaf in tree {
+            owned.push(leaf);
+        }
+        assert_eq!(owned, vec!["/a", "/b", "/c", "/d"]);
+        assert_eq!(owned, borrowed);
+        assert_eq!(NameTree::<String>::Neg.into_iter().next(), None);
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(NameTree::<String>::default(), Neg);
+        assert_eq!(&Dentry::default().to_string(), "/ => ~;");
+        assert!(Dtab::default().is_default());
+        assert_eq!(dtab![], Dtab::default());
+        assert!(!dtab!["/a" => "/b";].is_default());
+    }
+
+    #[test]
+    fn n_ary_constructors() {
+        assert_eq!(NameTree::alts(Vec::<NameTree<String>>::new()), Neg);
+        assert_eq!( NameTree::alts(vec!["/a", "/b", "/c"])
+                  , NameTree::from("/a") | "/b" | "/c");
+        assert_eq!( NameTree::union(vec![NameTree::from("/a")])
+                  , NameTree::from("/a"));
+        assert_eq!( NameTree::union(vec![ NameTree::from("/a")
+                                         , NameTree::from("/b")
+                                         , NameTree::from("/c")])
+                  , Union( (NameTree::from("/a") & "/b").weighted(1.0)
+                         , NameTree::from("/c").weighted(0.5)));
+
+        let mut tree = NameTree::union(vec![ W(2.0) * "/a", W(1.0) * "/b"
+                                            , W(1.0) * "/c"]);
+        tree.normalize_weights();
+        assert_eq!( &tree.to_string()
+                  , "0.75 * (0.666666666666666666667 * /a & 0.333333333333333333333 * /b) \
+                     & 0.25 * /c");
+    }
+
+    #[test]
+    fn weight_policies() {
+        use weight::{WeightPolicy, WeightSumError};
+        let strict = WeightPolicy::Require { tolerance: Weight::per_mille(1) };
+        let mut dtab = Dtab::from(vec![
+            dentry!("/svc" => 0.5 * "/a" & 0.5 * "/b")
+          , dentry!("/svc/c" => NameTree::from("/c") | NameTree::union(vec![ W(0.9) * "/d"
+                                                                           , W(0.3) * "/e"]))
+        ]);
+        let raw = dtab.clone();
+        dtab.apply_weight_policy(WeightPolicy::Raw).unwrap();
+        assert_eq!(dtab, raw);
+        assert_eq!( dtab.apply_weight_policy(strict)
+                  , Err(WeightSumError { entry: Some(1)
+                                       , sum: Weight::ratio(6, 5)
+                                       , tolerance: Weight::per_mille(1) }));
+        assert_eq!(dtab, raw);
+        dtab.apply_weight_policy(WeightPolicy::Normalize).unwrap();
+        assert_eq!(&dtab[1].dst().to_string(), "/c | 0.75 * /d & 0.25 * /e");
+        assert_eq!(dtab.apply_weight_policy(strict), Ok(()));
+
+        // Nested unions are checked separately.
+        let mut tree = dentry!("/svc" => 0.5 * "/a" & 0.3 * "/b" & 0.2 * "/c")
+            .into_parts().1;
+        assert_eq!(tree.apply_weight_policy(strict).unwrap_err().sum, Weight::ratio(4, 5));
+    }
+
+    #[test]
+    fn simplified() {
+        let simplified = |tree: NameTree<String>| tree.simplified().to_string();
+        assert_eq!(simplified(NameTree::from("/a")), "/a");
+        assert_eq!(simplified(NameTree::from("~") | "~"), "~");
+        assert_eq!( simplified(NameTree::from("~") | (NameTree::from("/a") | "~")
+                               | NameTree::from("/b") | "!" | "/c")
+                  , "/a | /b | !");
+        assert_eq!(simplified(NameTree::from("~") | "!" | "/a"), "!");
+        assert_eq!(simplified(NameTree::from("$") | "/a"), "$ | /a");
+
+        // Removed members keep the remaining members' shares.
+        assert_eq!( simplified(NameTree::union(vec![ W(0.5) * "/a", W(0.25) * "~"
+                                                    , W(0.25) * "/b"]))
+                  , "0.5 * /a & 0.25 * /b");
+        assert_eq!( simplified((W(0.5) * ((W(0.5) * "/a") & (W(0.5) * "!"))) & (W(0.5) * "/b"))
+                  , "0.25 * /a & 0.5 * /b");
+        assert_eq!( simplified((W(0.0) * "/a") & (NameTree::from("~") | "/b").weighted(1.0))
+                  , "/b");
+        assert_eq!(simplified((W(0.5) * "~") & (W(0.5) * "!")), "!");
+        assert_eq!(simplified((W(0.0) * "!") & (W(1.0) * "~")), "~");
+        assert_eq!( simplified(NameTree::from("~") | ((W(0.5) * "~") & (W(0.5) * "~")) | "/a")
+                  , "/a");
+
+        let tree = NameTree::from("/a") & "/b" | "/c";
+        assert_eq!(tree.clone().simplified(), tree);
+    }
+
+    #[test]
+    fn deep_trees() {
+        let mut alts = NameTree::from("/a");
+        let mut unions = NameTree::from("/a");
+        for _ in 0..100_000 {
+            alts = alts | "~";
+            unions = NameTree::from("/b") & unions;
+        }
+        assert_eq!(alts.complexity().depth, 100_001);
+        assert_eq!(unions.complexity().leaves, 100_001);
+        assert_eq!(format!("{:?}", alts).matches("Neg").count(), 100_000);
+        assert_eq!(format!("{:?}", unions).matches("Union(").count(), 100_000);
+
+        let lengths = unions.map(|leaf| leaf.len());
+        assert_eq!(lengths.leaves().sum::<usize>(), 2 * 100_001);
+        assert_eq!(alts.simplified(), NameTree::from("/a"));
+        let failed = NameTree::Fail | lengths;
+        assert_eq!(failed.simplified(), NameTree::Fail);
+        // and each of these trees is dropped without recursing
+    }
+
+    #[test]
+    fn append_residual() {
+        let path = |s| Path::read(s).unwrap();
+        let tree = NameTree::Leaf(path("/a")).weighted(0.75)
+                 & NameTree::Leaf(path("/")).weighted(0.25)
+                 | NameTree::Fail;
+        assert_eq!( &tree.clone().append_residual(&path("/x/y")).to_string()
+                  , "0.75 * /a/x/y & 0.25 * /x/y | !");
+        assert_eq!(tree.clone().append_residual(&Path::empty()), tree);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn sample() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut sample = |tree: &NameTree<String>| tree.sample(&mut rng).cloned();
+
+        let tree = (W(3.0) * "/a") & (W(1.0) * "/b");
+        let picked_a = (0..4000).filter(|_| sample(&tree) == Some("/a".to_string())).count();
+        assert!(2800 < picked_a && picked_a < 3200, "picked /a {} times", picked_a);
+
+        let tree = (W(0.5) * "!") & (W(0.0) * "/a")
+                 & (NameTree::from("~") | "/b").weighted(0.5);
+        for _ in 0..100 {
+            assert_eq!(sample(&tree), Some("/b".to_string()));
+        }
+        assert_eq!(sample(&(NameTree::from("~") | "~" | "/a")), Some("/a".to_string()));
+        assert_eq!(sample(&(NameTree::from("$") | "/a")), None);
+        assert_eq!(sample(&(((W(0.5) * "~") & (W(0.5) * "!")) | "/a")), None);
+        assert_eq!(sample(&(((W(0.5) * "~") & (W(0.5) * "~")) | "/a"))
+                  , Some("/a".to_string()));
+    }
+
+    #[test]
+    fn weights_in_macros() {
+        let dentry = dentry!("/svc" => 0.5 * "/a" & 0.3 * "/b" & 0.2 * "/c");
+        assert_eq!( dentry.dst
+                  , Union( Union( Leaf("/a".to_string()).weighted(0.5)
+                                , Leaf("/b".to_string()).weighted(0.3))
+                              .weighted(0.8)
+                         , Leaf("/c".to_string()).weighted(0.2)));
+
+        let dentry = dentry!("/svc" => 1 * ("/a" | "/b") & "/c" | "~");
+        assert_eq!( &dentry.to_string()
+                  , "/svc => 1 * (/a | /b) & 0.5 * /c | ~;");
+
+        let dentry = dentry!("/svc" => NameTree::from("/a").weighted(0.3) & "/b");
+        assert_eq!(&dentry.to_string(), "/svc => 0.3 * /a & 0.5 * /b;");
+    }
+
+    #[test]
+    fn bare_destinations_in_macros() {
+        let path = Path::read("/USA/CA/SF/Harrison/2790").unwrap();
+        let dtab = dtab![
+            "/smitten" => path;
+            "/iceCreamStore" => NameTree::from("/humphrys") | "/smitten";
+            "/bakery" => "~";
+            "/birite" => "/USA/CA/SF/18th/3692".to_string();
+        ];
+        assert_eq!(dtab[0].dst, Leaf("/USA/CA/SF/Harrison/2790".to_string()));
+        assert_eq!(dtab[2].dst, Neg);
+        assert_eq!(dtab[3].dst, Leaf("/USA/CA/SF/18th/3692".to_string()));
+    }
+}
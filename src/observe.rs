@@ -0,0 +1,97 @@
+//! Hooks for observing delegation.
+//!
+//! A [`DelegationObserver`] is notified of events as paths are delegated
+//! through a dtab: lookups which do or do not match any dentries, each
+//! dentry that fires, names which delegate to the negation, and the depth
+//! each delegation reached. Embedders can implement it to feed counters and
+//! histograms into whichever metrics system they use.
+//!
+//! Every method has a default implementation which does nothing, so an
+//! observer need only implement the events it cares about. The unit type,
+//! `()`, is an observer which ignores every event.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use dtab::observe::DelegationObserver;
+//! use dtab::path::Path;
+//!
+//! #[derive(Default)]
+//! struct Counters {
+//!     hits: AtomicUsize
+//!   , misses: AtomicUsize
+//! }
+//!
+//! impl DelegationObserver for Counters {
+//!     fn lookup_hit(&self, _: &Path, _: usize) {
+//!         self.hits.fetch_add(1, Ordering::Relaxed);
+//!     }
+//!
+//!     fn lookup_miss(&self, _: &Path) {
+//!         self.misses.fetch_add(1, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let counters = Counters::default();
+//! let path = Path::read("/svc/users").unwrap();
+//! counters.lookup_hit(&path, 2);
+//! counters.lookup_miss(&path);
+//! counters.neg(&path);
+//! assert_eq!(counters.hits.load(Ordering::Relaxed), 1);
+//! assert_eq!(counters.misses.load(Ordering::Relaxed), 1);
+//! ```
+//!
+//! [`DelegationObserver`]: trait.DelegationObserver.html
+use super::Dentry;
+use super::path::Path;
+
+/// Callbacks for events which occur during delegation.
+///
+/// Observers are shared by reference, so implementations which record
+/// state should use interior mutability (such as atomics).
+pub trait DelegationObserver {
+    /// A lookup of `path` matched `dentries` dentries.
+    #[inline] fn lookup_hit(&self, path: &Path, dentries: usize) {
+        let _ = (path, dentries);
+    }
+
+    /// A lookup of `path` matched no dentries.
+    #[inline] fn lookup_miss(&self, path: &Path) { let _ = path; }
+
+    /// The dentry at `index` in the dtab fired, rewriting `path`.
+    #[inline] fn dentry_fired(&self, path: &Path, index: usize, dentry: &Dentry) {
+        let _ = (path, index, dentry);
+    }
+
+    /// `path` delegated to the negation.
+    #[inline] fn neg(&self, path: &Path) { let _ = path; }
+
+    /// Delegation of `path` finished, having rewritten it to a depth of
+    /// `depth`.
+    #[inline] fn depth_reached(&self, path: &Path, depth: usize) {
+        let _ = (path, depth);
+    }
+}
+
+/// Ignores every event.
+impl DelegationObserver for () {}
+
+impl<O> DelegationObserver for &O
+where O: DelegationObserver + ?Sized {
+    #[inline] fn lookup_hit(&self, path: &Path, dentries: usize) {
+        (**self).lookup_hit(path, dentries)
+    }
+
+    #[inline] fn lookup_miss(&self, path: &Path) { (**self).lookup_miss(path) }
+
+    #[inline] fn dentry_fired(&self, path: &Path, index: usize, dentry: &Dentry) {
+        (**self).dentry_fired(path, index, dentry)
+    }
+
+    #[inline] fn neg(&self, path: &Path) { (**self).neg(path) }
+
+    #[inline] fn depth_reached(&self, path: &Path, depth: usize) {
+        (**self).depth_reached(path, depth)
+    }
+}
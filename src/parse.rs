@@ -0,0 +1,2581 @@
+//! Parsing dtabs and name trees from text.
+//!
+//! The parser accepts the standard Finagle syntax:
+//!
+//! ```notrust
+//! dtab     ::= [ dentry { ';' dentry } [ ';' ] ]
+//! dentry   ::= prefix '=>' tree
+//! tree     ::= union { '|' union }
+//! union    ::= weighted { '&' weighted }
+//! weighted ::= [ weight '*' ] simple
+//! simple   ::= path | '~' | '!' | '$' | '(' tree ')'
+//! ```
+//!
+//! `&` binds more tightly than `|`, and both are left-associative. Union
+//! members without an explicit weight are given the [`DEFAULT_WEIGHT`].
+//! Whitespace, including tabs and newlines, may appear between any two
+//! tokens, so dentries may be indented or split across lines freely. A
+//! leading byte order mark is ignored.
+//!
+//! A `#` which begins a token starts a comment, which extends to the end of
+//! the line. Comments are ignored by the parser, but [`comments`] returns
+//! where they appear, so that tools such as the [formatter] can preserve
+//! them. A `#` within a path, as in `/#/io.l5d.fs`, is part of the path.
+//!
+//! Destination paths are kept as text, rather than read into [`Path`]s, but
+//! any non-ASCII characters in them are replaced with `\xNN` escapes of
+//! their UTF-8 bytes, so that destinations are written in the same
+//! ASCII-safe form as prefixes.
+//!
+//! Parsing the `Display` form of a dtab, name tree, or dentry returns the
+//! same value, as long as every weight has a terminating decimal expansion
+//! (see [`Weight`]), and the labels are read with the policy they were
+//! first read with. This holds for prefixes and destinations alike, since
+//! both are written with the same escapes. Labels containing bytes which
+//! must be escaped, which the default [`Strict`] policy never produces, are
+//! only read back by the policies which accept escapes: [`Utf8`] for
+//! escaped UTF-8 characters, and [`Finagle`] for any other bytes. The
+//! [`Any`] policy reads back every dtab, however its labels were read, and
+//! is used when deserializing.
+//!
+//! Since dtabs are often received from untrusted sources (such as request
+//! headers), the parser never recurses, and [`ParseOptions`] can limit the
+//! size of the input, the length of labels, and the depth of the resulting
+//! trees. The depth limit is enforced by default, because deeply nested
+//! trees are expensive to clone and compare.
+//!
+//! [`ParseOptions`] also controls which inputs are accepted at all:
+//! [`ParseOptions::strict`] rejects comments and duplicate prefixes, and
+//! [`ParseOptions::lenient`] skips empty entries.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::parse::{self, ParseOptions};
+//!
+//! let options = ParseOptions::default();
+//! let dtab = parse::dtab( "/smitten => /USA/CA/SF/Harrison/2790;\n\
+//!                          /iceCreamStore => /humphrys | /smitten;"
+//!                       , &options).unwrap();
+//! assert_eq!(dtab.len(), 2);
+//! assert_eq!(&dtab[1].to_string(), "/iceCreamStore => /humphrys | /smitten;");
+//!
+//! let nested = format!("{}/a{}", "(".repeat(100_000), ")".repeat(100_000));
+//! assert!(parse::name_tree(&nested, &options).is_err());
+//! ```
+//!
+//! [`comments`]: fn.comments.html
+//! [`DEFAULT_WEIGHT`]: ../nametree/constant.DEFAULT_WEIGHT.html
+//! [formatter]: ../format/index.html
+//! [`ParseOptions`]: struct.ParseOptions.html
+//! [`ParseOptions::strict`]: struct.ParseOptions.html#method.strict
+//! [`ParseOptions::lenient`]: struct.ParseOptions.html#method.lenient
+//! [`Weight`]: ../weight/struct.Weight.html
+//! [`Path`]: ../path/struct.Path.html
+//! [`Strict`]: ../path/enum.LabelPolicy.html#variant.Strict
+//! [`Utf8`]: ../path/enum.LabelPolicy.html#variant.Utf8
+//! [`Finagle`]: ../path/enum.LabelPolicy.html#variant.Finagle
+//! [`Any`]: ../path/enum.LabelPolicy.html#variant.Any
+use std::{cmp, error, fmt, io, mem};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::{Dentry, Dtab, NameTree, Prefix};
+use super::nametree::DEFAULT_WEIGHT;
+use super::path::{self, LabelPolicy, PathError};
+use super::weight::{IntoWeight, Weight};
+
+/// Options controlling the parser.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ParseOptions {
+    /// Which characters are permitted in path labels.
+    pub labels: LabelPolicy
+  , /// The maximum length of the input, in bytes.
+    pub max_input_bytes: Option<usize>
+  , /// The maximum length of a single label, in bytes.
+    pub max_label_bytes: Option<usize>
+  , /// The maximum depth of a parsed name tree, counting both nested
+    /// operators and parentheses.
+    ///
+    /// By default, this is [`DEFAULT_MAX_DEPTH`].
+    ///
+    /// [`DEFAULT_MAX_DEPTH`]: constant.DEFAULT_MAX_DEPTH.html
+    pub max_depth: Option<usize>
+  , /// The maximum number of members of a single union in a parsed name
+    /// tree, counting the members of unions nested directly within it, as
+    /// [`Complexity::union_width`] does.
+    ///
+    /// [`Complexity::union_width`]: ../nametree/struct.Complexity.html#structfield.union_width
+    pub max_union_width: Option<usize>
+  , /// The maximum number of leaves in a parsed name tree, such as the
+    /// destination of a single dentry.
+    pub max_leaves: Option<usize>
+  , /// The maximum number of dentries in a dtab.
+    pub max_entries: Option<usize>
+  , /// Whether empty entries, such as the second entry of `/a => /b;;`, are
+    /// skipped rather than rejected.
+    pub allow_empty_entries: bool
+  , /// Whether more than one dentry may have the same prefix.
+    pub allow_duplicate_prefixes: bool
+  , /// Whether `#` comments are accepted.
+    pub allow_comments: bool
+  , /// The weight given to union members without an explicit weight.
+    ///
+    /// By default, this is [`DEFAULT_WEIGHT`].
+    ///
+    /// [`DEFAULT_WEIGHT`]: ../nametree/constant.DEFAULT_WEIGHT.html
+    pub default_weight: Weight
+}
+
+/// The default maximum depth of a parsed name tree.
+pub const DEFAULT_MAX_DEPTH: usize = 1_000;
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions { labels: LabelPolicy::Strict
+                     , max_input_bytes: None
+                     , max_label_bytes: None
+                     , max_depth: Some(DEFAULT_MAX_DEPTH)
+                     , max_union_width: None
+                     , max_leaves: None
+                     , max_entries: None
+                     , allow_empty_entries: false
+                     , allow_duplicate_prefixes: true
+                     , allow_comments: true
+                     , default_weight: DEFAULT_WEIGHT.into_weight()
+                     }
+    }
+}
+
+impl ParseOptions {
+    /// Returns options suitable for parsing dtabs from untrusted sources,
+    /// such as request headers: inputs are limited to 8 KiB, labels to 256
+    /// bytes, trees to a depth of 32, unions to 16 members, the destination
+    /// of each dentry to 64 leaves, and dtabs to 256 entries.
+    pub fn untrusted() -> Self {
+        ParseOptions { labels: LabelPolicy::Strict
+                     , max_input_bytes: Some(8 * 1024)
+                     , max_label_bytes: Some(256)
+                     , max_depth: Some(32)
+                     , max_union_width: Some(16)
+                     , max_leaves: Some(64)
+                     , max_entries: Some(256)
+                     , ..ParseOptions::default()
+                     }
+    }
+
+    /// Returns options which accept only dtabs written exactly as `Display`
+    /// would write them: without comments, empty entries, or more than one
+    /// dentry with the same prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::parse::{self, ParseOptions};
+    ///
+    /// let strict = ParseOptions::strict();
+    /// assert!(parse::dtab("/a => /b;\n/c => /d;", &strict).is_ok());
+    /// assert!(parse::dtab("/a => /b; # why\n", &strict).is_err());
+    /// assert!(parse::dtab("/a => /b;\n/a => /c;", &strict).is_err());
+    /// ```
+    pub fn strict() -> Self {
+        ParseOptions { allow_duplicate_prefixes: false
+                     , allow_comments: false
+                     , ..ParseOptions::default()
+                     }
+    }
+
+    /// Returns options which accept everything the defaults do, and also
+    /// skip empty entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::parse::{self, ParseOptions};
+    ///
+    /// let dtab = parse::dtab(";/a => /b;;\n/c => /d;;", &ParseOptions::lenient()).unwrap();
+    /// assert_eq!(dtab.len(), 2);
+    /// ```
+    pub fn lenient() -> Self {
+        ParseOptions { allow_empty_entries: true, ..ParseOptions::default() }
+    }
+
+    /// Returns options which accept the same dtabs as Finagle's `Dtab.read`,
+    /// and read them the same way, for dtabs written by Scala services.
+    ///
+    /// These differ from the defaults in two ways:
+    ///
+    /// - labels may contain `\xNN` escapes, using the [`Finagle`] label
+    ///   policy, and
+    /// - union members without a weight are given a weight of 1, as in
+    ///   Finagle, rather than the [`DEFAULT_WEIGHT`].
+    ///
+    /// Everything else already matches Finagle: weights are decimal numbers
+    /// such as `1`, `0.5`, `.5` or `1.`, optionally with an exponent as in
+    /// `1.0E-4`, the form Java uses for small `double`s; a trailing `;` is optional; `#`
+    /// starts a comment which runs to the end of the line, except within a
+    /// path; and whitespace may appear between any two tokens.
+    ///
+    /// A few inputs Finagle rejects are still accepted: the parser treats
+    /// every Unicode whitespace character as whitespace, where Java excludes
+    /// non-breaking spaces. Unlike Finagle, the depth of name trees is still
+    /// limited, since Finagle's recursive parser overflows its stack on
+    /// deeply nested input anyway.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::parse::{self, ParseOptions};
+    ///
+    /// let finagle = ParseOptions::finagle();
+    /// let dtab = parse::dtab("/s\\x41 => /a & 2 * /b", &finagle).unwrap();
+    /// assert_eq!(&dtab.to_string(), "/sA => 1 * /a & 2 * /b;\n");
+    /// assert!(parse::dtab("/s\\x41 => /a", &ParseOptions::default()).is_err());
+    /// ```
+    ///
+    /// [`Finagle`]: ../path/enum.LabelPolicy.html#variant.Finagle
+    /// [`DEFAULT_WEIGHT`]: ../nametree/constant.DEFAULT_WEIGHT.html
+    pub fn finagle() -> Self {
+        ParseOptions { labels: LabelPolicy::Finagle
+                     , default_weight: Weight::ONE
+                     , ..ParseOptions::default()
+                     }
+    }
+
+    /// Returns the options used to deserialize dtabs, dentries and name
+    /// trees: the defaults, with the [`Any`] label policy, so that whatever
+    /// is serialized can be deserialized, whichever policy its labels were
+    /// read with.
+    ///
+    /// [`Any`]: ../path/enum.LabelPolicy.html#variant.Any
+    pub(crate) fn serialized() -> Self {
+        ParseOptions { labels: LabelPolicy::Any, ..ParseOptions::default() }
+    }
+}
+
+/// The kinds of error that may occur while parsing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A token was found where a different one was expected.
+    Unexpected { expected: &'static str, found: String }
+  , /// The input ended where a token was expected.
+    UnexpectedEof { expected: &'static str }
+  , /// A character which cannot begin any token was found.
+    InvalidCharacter(char)
+  , /// A path or prefix was invalid.
+    InvalidPath(PathError)
+  , /// A weight could not be parsed.
+    InvalidWeight(String)
+  , /// The input exceeded `max_input_bytes`.
+    InputTooLong { max: usize, len: usize }
+  , /// A label exceeded `max_label_bytes`.
+    LabelTooLong { max: usize, len: usize }
+  , /// A tree exceeded `max_depth`.
+    TooDeep { max: usize }
+  , /// A union exceeded `max_union_width`.
+    UnionTooWide { max: usize }
+  , /// A tree exceeded `max_leaves`.
+    TooManyLeaves { max: usize }
+  , /// A dtab exceeded `max_entries`.
+    TooManyEntries { max: usize }
+  , /// A prefix appeared more than once, and `allow_duplicate_prefixes` was
+    /// false.
+    DuplicatePrefix(String)
+  , /// A comment was found, and `allow_comments` was false.
+    UnexpectedComment
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Unexpected { expected, ref found } =>
+                write!(f, "expected {}, found {:?}", expected, found)
+          , ErrorKind::UnexpectedEof { expected } =>
+                write!(f, "expected {}, found end of input", expected)
+          , ErrorKind::InvalidCharacter(ch) =>
+                write!(f, "unexpected character {:?}", ch)
+          , ErrorKind::InvalidPath(ref e) => write!(f, "invalid path: {}", e)
+          , ErrorKind::InvalidWeight(ref w) => write!(f, "invalid weight {:?}", w)
+          , ErrorKind::InputTooLong { max, len } =>
+                write!(f, "input of {} bytes exceeds the limit of {}", len, max)
+          , ErrorKind::LabelTooLong { max, len } =>
+                write!(f, "label of {} bytes exceeds the limit of {}", len, max)
+          , ErrorKind::TooDeep { max } =>
+                write!(f, "name tree exceeds the maximum depth of {}", max)
+          , ErrorKind::UnionTooWide { max } =>
+                write!(f, "union exceeds the limit of {} members", max)
+          , ErrorKind::TooManyLeaves { max } =>
+                write!(f, "name tree exceeds the limit of {} leaves", max)
+          , ErrorKind::TooManyEntries { max } =>
+                write!(f, "dtab exceeds the limit of {} entries", max)
+          , ErrorKind::DuplicatePrefix(ref prefix) =>
+                write!(f, "duplicate prefix {}", prefix)
+          , ErrorKind::UnexpectedComment => f.write_str("comments are not allowed")
+        }
+    }
+}
+
+/// An error which occurred while parsing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// What went wrong.
+    pub kind: ErrorKind
+  , /// The byte offset in the input at which the error occurred.
+    pub at: usize
+}
+
+impl fmt::Display for ParseError {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.at)
+    }
+}
+
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::InvalidPath(ref e) => Some(e)
+          , _ => None
+        }
+    }
+}
+
+impl ParseError {
+    /// Returns the span of the text in `input` responsible for this error.
+    ///
+    /// `input` must be the input which produced this error. Where possible,
+    /// the span covers the offending token or character; errors at the end
+    /// of the input have an empty span.
+    pub fn span(&self, input: &str) -> Span {
+        let at = floor_char_boundary(input, self.at);
+        let token = || {
+            let options = ParseOptions { max_input_bytes: None, ..ParseOptions::default() };
+            let mut parser = Parser { input, pos: at, peeked: None, last_end: at
+                                    , comments: Vec::new(), checks: EntryChecks::default()
+                                    , partial: false, options: &options };
+            match parser.lex() {
+                Ok(Some((start, token))) => Span { start, end: start + token.len() }
+              , _ => Span { start: at, end: at }
+            }
+        };
+        match self.kind {
+            ErrorKind::UnexpectedEof { .. } => Span { start: at, end: at }
+          , ErrorKind::InvalidCharacter(ch) => Span { start: at, end: at + ch.len_utf8() }
+          , ErrorKind::InputTooLong { .. } => Span { start: at, end: input.len() }
+          , ErrorKind::UnexpectedComment => {
+                let line = input[at..].lines().next().unwrap_or("");
+                Span { start: at, end: at + line.trim_end().len() }
+            }
+          , ErrorKind::LabelTooLong { len, .. } =>
+                Span { start: at, end: floor_char_boundary(input, at + len) }
+          , ErrorKind::InvalidPath(ref error) => {
+                let path = token();
+                error.span_in(&input[path.start..path.end])
+                    .map_or(path, |span| Span { start: path.start + span.start
+                                              , end: path.start + span.end })
+            }
+          , _ => token()
+        }
+    }
+
+    /// Locate this error in `input`, the input which produced it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::parse::{self, Location, ParseOptions, Span};
+    ///
+    /// let input = "/a => /b;\n/c => /d | /e?f;";
+    /// let error = parse::dtab(input, &ParseOptions::default()).unwrap_err();
+    /// let located = error.locate(input);
+    /// assert_eq!(located.span, Span { start: 23, end: 24 });
+    /// assert_eq!(located.start, Location { line: 2, column: 14 });
+    /// assert_eq!( &located.to_string()
+    ///           , "2:14: invalid path: invalid character '?' at position 1 in \"e?f\"");
+    /// ```
+    pub fn locate(&self, input: &str) -> SourceError {
+        let span = self.span(input);
+        SourceError { kind: self.kind.clone()
+                    , span
+                    , start: Location::of(input, span.start)
+                    , end: Location::of(input, span.end)
+                    }
+    }
+
+    /// Render this error for display to a person, with the line of `input`
+    /// on which it occurred and a caret under the offending text.
+    ///
+    /// `input` must be the input which produced this error. If the
+    /// offending text spans several lines, only its first line is marked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::parse::{self, ParseOptions};
+    ///
+    /// let input = "/a => /b;\n/c => /d | /e?f;";
+    /// let error = parse::dtab(input, &ParseOptions::default()).unwrap_err();
+    /// assert_eq!( &error.render(input)
+    ///           , "error: invalid path: invalid character '?' at position 1 in \"e?f\"\n \
+    ///              --> 2:14\n  \
+    ///               |\n\
+    ///              2 | /c => /d | /e?f;\n  \
+    ///               |              ^\n");
+    /// ```
+    pub fn render(&self, input: &str) -> String {
+        let error = self.locate(input);
+        let Span { start, end } = error.span;
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = input[line_start..].lines().next().unwrap_or("");
+        let end = cmp::max(start, cmp::min(end, line_start + line.len()));
+        // keep tabs, so that the caret lines up however they're displayed.
+        let indent = input[line_start..start].chars()
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let carets = "^".repeat(cmp::max(input[start..end].chars().count(), 1));
+        let number = error.start.line.to_string();
+        let gutter = " ".repeat(number.len());
+        format!( "error: {}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}\n"
+               , error.kind, gutter, error.start, gutter, number, line, gutter
+               , indent, carets)
+    }
+}
+
+/// A position in the input, as a line and column.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    /// The line, counting from one.
+    pub line: usize
+  , /// The column, counting characters from one.
+    pub column: usize
+}
+
+impl Location {
+    /// Returns the location of the byte offset `offset` in `input`.
+    ///
+    /// Offsets past the end of the input are treated as the end of the
+    /// input, and offsets within a character as the start of it.
+    pub fn of(input: &str, offset: usize) -> Self {
+        let before = &input[..floor_char_boundary(input, offset)];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location { line: before.matches('\n').count() + 1
+                 , column: before[line_start..].chars().count() + 1
+                 }
+    }
+}
+
+impl fmt::Display for Location {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A [`ParseError`], located in the input which produced it.
+///
+/// [`ParseError`]: struct.ParseError.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceError {
+    /// What went wrong.
+    pub kind: ErrorKind
+  , /// The bytes in the input responsible for the error.
+    pub span: Span
+  , /// The location of the start of `span`.
+    pub start: Location
+  , /// The location of the end of `span`.
+    pub end: Location
+}
+
+impl SourceError {
+    /// Locate an error reading a path or prefix which begins at the byte
+    /// offset `at` in `input`, such as an error from `Prefix::try_from`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryFrom;
+    /// use dtab::Prefix;
+    /// use dtab::parse::{Location, SourceError};
+    ///
+    /// let input = "routes:\n  /svc//users";
+    /// let error = Prefix::try_from(&input[10..]).unwrap_err();
+    /// let located = SourceError::from_path(error, input, 10);
+    /// assert_eq!(located.start, Location { line: 2, column: 7 });
+    /// ```
+    pub fn from_path(error: PathError, input: &str, at: usize) -> Self {
+        ParseError { kind: ErrorKind::InvalidPath(error), at }.locate(input)
+    }
+}
+
+impl fmt::Display for SourceError {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.start, self.kind)
+    }
+}
+
+impl error::Error for SourceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::InvalidPath(ref e) => Some(e)
+          , _ => None
+        }
+    }
+}
+
+/// Returns the largest character boundary in `input` no greater than
+/// `offset`.
+fn floor_char_boundary(input: &str, offset: usize) -> usize {
+    let mut offset = offset.min(input.len());
+    while !input.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// An error converting a prefix and destination pair into a dentry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PairError {
+    /// The prefix of a pair was invalid.
+    InvalidPrefix { entry: usize, error: PathError }
+  , /// The destination of a pair could not be parsed.
+    InvalidDestination { entry: usize, error: ParseError }
+}
+
+impl PairError {
+    /// Returns the index of the pair which could not be converted.
+    pub fn entry(&self) -> usize {
+        match *self {
+            PairError::InvalidPrefix { entry, .. } |
+            PairError::InvalidDestination { entry, .. } => entry
+        }
+    }
+}
+
+impl fmt::Display for PairError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PairError::InvalidPrefix { entry, ref error } =>
+                write!(f, "invalid prefix in entry {}: {}", entry, error)
+          , PairError::InvalidDestination { entry, ref error } =>
+                write!(f, "invalid destination in entry {}: {}", entry, error)
+        }
+    }
+}
+
+impl error::Error for PairError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            PairError::InvalidPrefix { ref error, .. } => Some(error)
+          , PairError::InvalidDestination { ref error, .. } => Some(error)
+        }
+    }
+}
+
+/// Build a dtab from pairs of prefixes and destination trees, in text form.
+///
+/// Byte offsets in destination errors are relative to the destination
+/// string of the failing pair.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, PairError, ParseOptions};
+///
+/// let rules = vec![ ("/iceCreamStore", "/humphrys | /smitten")
+///                 , ("/smitten", "/USA/CA/SF/Harrison/2790") ];
+/// let dtab = parse::pairs(rules, &ParseOptions::default()).unwrap();
+/// assert_eq!(dtab.len(), 2);
+///
+/// let rules = vec![("/iceCreamStore", "/humphrys"), ("/smitten", "/a |")];
+/// let err = parse::pairs(rules, &ParseOptions::default()).unwrap_err();
+/// assert_eq!(err.entry(), 1);
+/// ```
+pub fn pairs<I, P, D>(pairs: I, options: &ParseOptions) -> Result<Dtab, PairError>
+where I: IntoIterator<Item = (P, D)>
+    , P: AsRef<str>
+    , D: AsRef<str> {
+    pairs.into_iter().enumerate()
+        .map(|(entry, (prefix, dst))| {
+            let prefix = Prefix::read_with(prefix.as_ref().trim(), options.labels)
+                .map_err(|error| PairError::InvalidPrefix { entry, error })?;
+            let dst = name_tree(dst.as_ref(), options)
+                .map_err(|error| PairError::InvalidDestination { entry, error })?;
+            Ok(Dentry::new(prefix, dst))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Dtab::from)
+}
+
+/// An error reading dtab overrides from command-line arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgsError {
+    /// The argument at this index was `--dtab`, with no value after it.
+    MissingValue { arg: usize }
+  , /// The value of the `--dtab` argument at this index was not a valid
+    /// dtab. Byte offsets in the error are relative to the value.
+    Invalid { arg: usize, error: ParseError }
+}
+
+impl ArgsError {
+    /// Returns the index of the argument which could not be read.
+    pub fn arg(&self) -> usize {
+        match *self {
+            ArgsError::MissingValue { arg } |
+            ArgsError::Invalid { arg, .. } => arg
+        }
+    }
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArgsError::MissingValue { arg } =>
+                write!(f, "missing value for --dtab in argument {}", arg)
+          , ArgsError::Invalid { arg, ref error } =>
+                write!(f, "invalid dtab in argument {}: {}", arg, error)
+        }
+    }
+}
+
+impl error::Error for ArgsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ArgsError::Invalid { ref error, .. } => Some(error)
+          , _ => None
+        }
+    }
+}
+
+/// Build a dtab from the `--dtab` overrides in a list of command-line
+/// arguments.
+///
+/// Each override is given either as `--dtab <dtab>` or as `--dtab=<dtab>`,
+/// and may contain any number of dentries, separated by `;`. The overrides
+/// are appended in the order they're given, so later overrides take
+/// precedence over earlier ones. Other arguments are ignored, so the
+/// arguments can be passed in full, but the value of another flag which
+/// happens to be `--dtab` is read as an override.
+///
+/// Each override is parsed separately, so limits such as `max_entries`
+/// apply to each one on its own.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, ArgsError, ParseOptions};
+///
+/// let args = vec![ "--port", "8080", "--dtab", "/svc => /srv"
+///                , "--dtab=/svc/users => /srv/users-v2; /svc/web => /srv/web" ];
+/// let dtab = parse::args(args, &ParseOptions::default()).unwrap();
+/// assert_eq!( &format!("{:#}", dtab)
+///           , "/svc=>/srv;/svc/users=>/srv/users-v2;/svc/web=>/srv/web");
+///
+/// let err = parse::args(vec!["--dtab", "/svc => /srv", "--dtab"], &ParseOptions::default());
+/// assert_eq!(err, Err(ArgsError::MissingValue { arg: 2 }));
+/// ```
+pub fn args<I, S>(args: I, options: &ParseOptions) -> Result<Dtab, ArgsError>
+where I: IntoIterator<Item = S>
+    , S: AsRef<str> {
+    let mut overrides = Dtab::new();
+    let mut args = args.into_iter().enumerate();
+    while let Some((arg, flag)) = args.next() {
+        let flag = flag.as_ref();
+        let (arg, parsed) = if flag == "--dtab" {
+            let (arg, value) = args.next().ok_or(ArgsError::MissingValue { arg })?;
+            (arg, dtab(value.as_ref(), options))
+        } else if let Some(value) = flag.strip_prefix("--dtab=") {
+            (arg, dtab(value, options))
+        } else {
+            continue
+        };
+        overrides.extend(parsed.map_err(|error| ArgsError::Invalid { arg, error })?);
+    }
+    Ok(overrides)
+}
+
+/// The length of the number at the start of `bytes`: digits and `.`s,
+/// optionally followed by an exponent such as `e-4`.
+fn number_len(bytes: &[u8]) -> usize {
+    let digits = |from: usize| bytes[from..].iter()
+        .position(|b| !b.is_ascii_digit())
+        .map_or(bytes.len(), |len| from + len);
+    let len = bytes.iter()
+        .position(|&b| !(b.is_ascii_digit() || b == b'.'))
+        .unwrap_or(bytes.len());
+    match bytes.get(len) {
+        Some(b'e') | Some(b'E') => match bytes.get(len + 1) {
+            Some(b'+') | Some(b'-') => digits(len + 2)
+          , _ => digits(len + 1)
+        }
+      , _ => len
+    }
+}
+
+/// Parse a dtab.
+pub fn dtab(input: &str, options: &ParseOptions) -> Result<Dtab, ParseError> {
+    spanned_dtab(input, options)
+        .map(|dentries| dentries.into_iter().map(|spanned| spanned.dentry).collect())
+}
+
+/// Parse a dtab, skipping invalid dentries rather than stopping at the
+/// first error.
+///
+/// After an error, parsing resumes after the next `;`, so each invalid
+/// dentry is reported once, and every valid dentry is returned. Returns the
+/// valid dentries, and an error for each invalid dentry, in input order.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, ParseOptions};
+///
+/// let input = "/a => /b;\n/c => ;\n/d => /e;\n/f => /g h;\n/i => /j";
+/// let (dtab, errors) = parse::dtab_lenient(input, &ParseOptions::default());
+/// assert_eq!(&dtab.to_string(), "/a => /b;\n/d => /e;\n/i => /j;\n");
+/// assert_eq!(errors.iter().map(|e| e.at).collect::<Vec<_>>(), vec![16, 37]);
+/// ```
+pub fn dtab_lenient(input: &str, options: &ParseOptions) -> (Dtab, Vec<ParseError>) {
+    let mut parser = match Parser::new(input, options) {
+        Ok(parser) => parser
+      , Err(error) => return (Dtab::new(), vec![error])
+    };
+    let mut dtab = Dtab::new();
+    let mut errors = Vec::new();
+    loop {
+        let result = parser.skip_empty_entries()
+            .and_then(|()| parser.peek())
+            .and_then(|token| match token {
+            None => Ok(None)
+          , Some(_) => {
+                let dentry = parser.dentry()?;
+                match parser.next()? {
+                    None | Some((_, Token::Semi)) => Ok(Some(dentry))
+                  , token => Err(parser.unexpected("';'", token))
+                }
+            }
+        });
+        match result {
+            Ok(Some(dentry)) => dtab.push(dentry)
+          , Ok(None) => break
+          , Err(error) => {
+                parser.skip_past_semi(error.at);
+                errors.push(error);
+            }
+        }
+    }
+    (dtab, errors)
+}
+
+/// Parse as many dentries as possible from the start of `input`, returning
+/// them and the rest of the input.
+///
+/// This is for dtabs embedded in a larger format, where the dtab is
+/// followed by text that isn't part of it. Parsing stops at the first
+/// character which can't appear in a dtab, or at the first dentry which
+/// isn't valid, and the rest of the input starts just after the last
+/// dentry or `;` which was read. A dentry is read as long as it's complete,
+/// even if it isn't followed by a `;`. Text ending a dtab must be separated
+/// from a path by whitespace or a `;`, since otherwise it's read as part of
+/// the path, making the path invalid.
+///
+/// If `max_input_bytes` is set, at most that many bytes are read, and a
+/// final dentry which reaches the limit without a `;` is left in the rest of
+/// the input, since it may continue past the limit.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, ParseOptions};
+///
+/// let input = "/a => /b; /c => /d | /e } # more config";
+/// let (dtab, rest) = parse::dtab_partial(input, &ParseOptions::default());
+/// assert_eq!(&dtab.to_string(), "/a => /b;\n/c => /d | /e;\n");
+/// assert_eq!(rest, " } # more config");
+///
+/// let (dtab, rest) = parse::dtab_partial("/a => /b;\n/c => /d | }", &ParseOptions::default());
+/// assert_eq!(dtab.len(), 1);
+/// assert_eq!(rest, "\n/c => /d | }");
+/// ```
+pub fn dtab_partial<'a>(input: &'a str, options: &ParseOptions) -> (Dtab, &'a str) {
+    let limit = options.max_input_bytes
+        .map_or(input.len(), |max| floor_char_boundary(input, max));
+    let truncated = limit < input.len();
+    let mut parser = match Parser::new(&input[..limit], options) {
+        Ok(parser) => parser
+      , Err(_) => return (Dtab::new(), input)
+    };
+    parser.partial = true;
+    let mut dtab = Dtab::new();
+    let mut end = 0;
+    loop {
+        let dentry = parser.skip_empty_entries()
+            .and_then(|()| {
+                end = parser.last_end;
+                parser.peek()
+            })
+            .and_then(|token| match token {
+                None => Ok(None)
+              , Some(_) => parser.dentry().map(Some)
+            });
+        let dentry = match dentry {
+            Ok(Some(dentry)) => dentry
+          , _ => break
+        };
+        match parser.peek() {
+            Ok(Some((_, Token::Semi))) => {
+                dtab.push(dentry);
+                let _ = parser.next();
+                end = parser.last_end;
+            }
+          , Ok(None) if truncated && parser.pos == limit => break
+          , _ => {
+                dtab.push(dentry);
+                end = parser.last_end;
+                break
+            }
+        }
+    }
+    (dtab, &input[end..])
+}
+
+/// A range of bytes in the input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// The offset of the first byte in the range.
+    pub start: usize
+  , /// The offset just past the last byte in the range.
+    pub end: usize
+}
+
+impl Span {
+    /// Returns true if `offset` falls within this span. The end of a span
+    /// is considered to be within it, so that a cursor placed just after a
+    /// token is on that token.
+    #[inline] pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+}
+
+/// A dentry, and where it appeared in the input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedDentry {
+    /// The dentry.
+    pub dentry: Dentry
+  , /// The span of the whole dentry, excluding any trailing `;`.
+    pub span: Span
+  , /// The span of the dentry's prefix.
+    pub prefix: Span
+}
+
+/// Parse a dtab, recording where each dentry appeared in the input.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, ParseOptions, Span};
+///
+/// let input = "/a => /b;\n/c => /d | /e;";
+/// let dentries = parse::spanned_dtab(input, &ParseOptions::default()).unwrap();
+/// assert_eq!(dentries[1].span, Span { start: 10, end: 23 });
+/// assert_eq!(&input[dentries[1].prefix.start..dentries[1].prefix.end], "/c");
+/// ```
+pub fn spanned_dtab(input: &str, options: &ParseOptions)
+                    -> Result<Vec<SpannedDentry>, ParseError> {
+    let mut parser = Parser::new(input, options)?;
+    let mut dentries = Vec::new();
+    loop {
+        parser.skip_empty_entries()?;
+        let (start, token) = match parser.peek()? {
+            Some(token) => token
+          , None => break
+        };
+        let prefix = Span { start, end: start + token.len() };
+        let dentry = parser.dentry()?;
+        dentries.push(SpannedDentry { dentry
+                                    , span: Span { start, end: parser.last_end }
+                                    , prefix });
+        match parser.next()? {
+            None => break
+          , Some((_, Token::Semi)) => {}
+          , Some((at, token)) => return Err(ParseError {
+                kind: ErrorKind::Unexpected {
+                    expected: "';'", found: token.to_string()
+                }
+              , at
+            })
+        }
+    }
+    Ok(dentries)
+}
+
+/// A dentry which borrows its prefix and the paths in its destination from
+/// the input it was parsed from.
+///
+/// This is returned by [`borrowed_dtab`].
+///
+/// [`borrowed_dtab`]: fn.borrowed_dtab.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorrowedDentry<'a> {
+    /// The text of the dentry's prefix.
+    pub prefix: &'a str
+  , /// The dentry's destination, whose leaves are the text of each path.
+    pub dst: NameTree<&'a str>
+  , /// The label policy the dentry was parsed with.
+    pub labels: LabelPolicy
+}
+
+impl<'a> BorrowedDentry<'a> {
+    /// Copy this dentry out of the input it was parsed from, reading its
+    /// prefix with the label policy it was parsed with.
+    ///
+    /// This only fails if the prefix has been changed to one which isn't
+    /// valid under that policy.
+    pub fn to_dentry(&self) -> Result<Dentry, PathError> {
+        let prefix = Prefix::read_with(self.prefix, self.labels)?;
+        Ok(Dentry::new(prefix, self.dst.clone().map(path::escape_non_ascii)))
+    }
+}
+
+impl<'a> fmt::Display for BorrowedDentry<'a> {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::fmt::Write;
+        write!(path::EscapeNonAscii(&mut *f), "{}", self.prefix)?;
+        write!(f, " => {};", self.dst)
+    }
+}
+
+/// Parse a dtab without copying it, borrowing the text of each prefix and
+/// path from the input.
+///
+/// Every label is validated exactly as by [`dtab`], but no labels are
+/// allocated, so this is much cheaper when dtabs only need to be checked,
+/// or when only a few dentries will be used. [`BorrowedDentry::to_dentry`]
+/// converts a dentry to its owned form.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, ParseOptions};
+///
+/// let input = "/svc => /srv | /#/io.l5d.fs;";
+/// let dentries = parse::borrowed_dtab(input, &ParseOptions::default()).unwrap();
+/// assert_eq!(dentries[0].prefix, "/svc");
+/// assert_eq!(dentries[0].dst.leaves().collect::<Vec<_>>(), vec![&"/srv", &"/#/io.l5d.fs"]);
+/// assert_eq!(dentries[0].to_dentry(), Ok(parse::dentry(input, &ParseOptions::default()).unwrap()));
+///
+/// assert!(parse::borrowed_dtab("/svc => /s rv;", &ParseOptions::default()).is_err());
+/// ```
+///
+/// [`dtab`]: fn.dtab.html
+/// [`BorrowedDentry::to_dentry`]: struct.BorrowedDentry.html#method.to_dentry
+pub fn borrowed_dtab<'a>(input: &'a str, options: &ParseOptions)
+                         -> Result<Vec<BorrowedDentry<'a>>, ParseError> {
+    let mut parser = Parser::new(input, options)?;
+    let mut dentries = Vec::new();
+    while parser.skip_empty_entries().and_then(|()| parser.peek())?.is_some() {
+        dentries.push(parser.borrowed_dentry()?);
+        match parser.next()? {
+            None => break
+          , Some((_, Token::Semi)) => {}
+          , token => return Err(parser.unexpected("';'", token))
+        }
+    }
+    Ok(dentries)
+}
+
+/// An iterator which parses a dtab one dentry at a time.
+///
+/// This is returned by [`dentries`].
+///
+/// [`dentries`]: fn.dentries.html
+pub struct DentryStream<'a, 'o> {
+    parser: Option<Parser<'a, 'o>>
+  , /// An error creating the parser, which is returned first.
+    error: Option<ParseError>
+}
+
+impl<'a, 'o> Iterator for DentryStream<'a, 'o> {
+    type Item = Result<Dentry, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error))
+        }
+        let result = {
+            let parser = self.parser.as_mut()?;
+            parser.comments.clear();
+            parser.skip_empty_entries()
+                .and_then(|()| parser.peek())
+                .and_then(|token| match token {
+                None => Ok(None)
+              , Some(_) => {
+                    let dentry = parser.dentry()?;
+                    match parser.next()? {
+                        None | Some((_, Token::Semi)) => Ok(Some(dentry))
+                      , token => Err(parser.unexpected("';'", token))
+                    }
+                }
+            })
+        };
+        match result {
+            Ok(Some(dentry)) => Some(Ok(dentry))
+          , Ok(None) => { self.parser = None; None }
+          , Err(error) => { self.parser = None; Some(Err(error)) }
+        }
+    }
+}
+
+/// Parse a dtab lazily, returning an iterator over its dentries.
+///
+/// Each dentry is parsed only when the iterator reaches it, so a large
+/// dtab can be processed without building a `Vec` of all of its dentries.
+/// The iterator ends after the first error.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, ParseOptions};
+///
+/// let options = ParseOptions::default();
+/// let mut dentries = parse::dentries("/a => /b;\n/c => /d;\n/e => %;", &options);
+/// assert_eq!(&dentries.next().unwrap().unwrap().to_string(), "/a => /b;");
+/// assert_eq!(&dentries.next().unwrap().unwrap().to_string(), "/c => /d;");
+/// assert_eq!(dentries.next().unwrap().unwrap_err().at, 26);
+/// assert!(dentries.next().is_none());
+/// ```
+pub fn dentries<'a, 'o>(input: &'a str, options: &'o ParseOptions) -> DentryStream<'a, 'o> {
+    match Parser::new(input, options) {
+        Ok(parser) => DentryStream { parser: Some(parser), error: None }
+      , Err(error) => DentryStream { parser: None, error: Some(error) }
+    }
+}
+
+/// An error reading a dtab from an `io::Read`.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The input could not be read, or was not valid UTF-8.
+    Io(io::Error)
+  , /// The input was not a valid dtab.
+    Parse(ParseError)
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref e) => write!(f, "error reading dtab: {}", e)
+          , ReadError::Parse(ref e) => fmt::Display::fmt(e, f)
+        }
+    }
+}
+
+impl error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ReadError::Io(ref e) => Some(e)
+          , ReadError::Parse(ref e) => Some(e)
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    #[inline] fn from(e: io::Error) -> Self { ReadError::Io(e) }
+}
+
+impl From<ParseError> for ReadError {
+    #[inline] fn from(e: ParseError) -> Self { ReadError::Parse(e) }
+}
+
+/// An error reading or writing a dtab file.
+#[derive(Debug)]
+pub enum FileError {
+    /// The file could not be read or written, or was not valid UTF-8.
+    Io { path: PathBuf, error: io::Error }
+  , /// The file was not a valid dtab. Offsets in the error are offsets in
+    /// the file.
+    Parse { path: PathBuf, error: ParseError }
+}
+
+impl FileError {
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        match *self {
+            FileError::Io { ref path, .. } | FileError::Parse { ref path, .. } => path
+        }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FileError::Io { ref path, ref error } =>
+                write!(f, "error accessing {}: {}", path.display(), error)
+          , FileError::Parse { ref path, ref error } =>
+                write!(f, "{}: {}", path.display(), error)
+        }
+    }
+}
+
+impl error::Error for FileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            FileError::Io { ref error, .. } => Some(error)
+          , FileError::Parse { ref error, .. } => Some(error)
+        }
+    }
+}
+
+/// An iterator which reads and parses a dtab one dentry at a time.
+///
+/// Only the text of the dentry being parsed is held in memory, so this can
+/// parse dtabs far larger than would fit in a single string. Byte offsets in
+/// parse errors are relative to the start of the input. The input length
+/// limit in [`ParseOptions`] applies to each dentry, rather than to the
+/// whole input. The iterator ends after the first error.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{DentryReader, ParseOptions};
+///
+/// let input = "# generated\n/a => /b;\n/c => /d\n";
+/// let dentries = DentryReader::new(input.as_bytes(), ParseOptions::default())
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(dentries.len(), 2);
+/// ```
+///
+/// [`ParseOptions`]: struct.ParseOptions.html
+pub struct DentryReader<R> {
+    reader: R
+  , options: ParseOptions
+  , /// Text which has been read but not yet parsed.
+    buf: String
+  , /// How much of `buf` has been scanned for the end of a dentry.
+    scanned: usize
+  , /// The offset of the start of `buf` in the input.
+    offset: usize
+  , /// Whether the scan position is within a comment, or a path.
+    in_comment: bool
+  , in_path: bool
+  , /// Whether any token has been scanned since the last `;`.
+    has_token: bool
+  , checks: EntryChecks
+  , done: bool
+}
+
+impl<R: io::BufRead> DentryReader<R> {
+    /// Read dentries from `reader`.
+    pub fn new(reader: R, options: ParseOptions) -> Self {
+        DentryReader { reader, options, buf: String::new(), scanned: 0, offset: 0
+                     , in_comment: false, in_path: false, has_token: false
+                     , checks: EntryChecks::default(), done: false }
+    }
+
+    /// Scan the unscanned part of the buffer, returning the offset of the
+    /// first `;` which ends a dentry, if there is one.
+    fn scan(&mut self) -> Option<usize> {
+        for (i, ch) in self.buf[self.scanned..].char_indices() {
+            let i = self.scanned + i;
+            if self.in_comment {
+                self.in_comment = ch != '\n';
+                continue
+            }
+            if self.in_path {
+                if !ends_path(ch) {
+                    continue
+                }
+                self.in_path = false;
+            }
+            match ch {
+                ';' => {
+                    self.scanned = i + 1;
+                    return Some(i)
+                }
+              , '#' => {
+                    // a comment is an error to report, if comments aren't
+                    // allowed.
+                    self.in_comment = true;
+                    self.has_token |= !self.options.allow_comments;
+                }
+              , '/' => { self.in_path = true; self.has_token = true }
+              , ch if ch.is_whitespace() => {}
+              , _ => self.has_token = true
+            }
+        }
+        self.scanned = self.buf.len();
+        None
+    }
+
+    /// Parse the first `len` bytes of the buffer, which hold a single
+    /// entry, as a dentry. Returns `None` if the entry is empty, and empty
+    /// entries are allowed.
+    fn parse(&mut self, len: usize) -> Option<Result<Dentry, ParseError>> {
+        let result = if !self.has_token && self.options.allow_empty_entries {
+            None
+        } else {
+            let DentryReader { ref buf, ref options, ref mut checks, offset, .. } = *self;
+            let result = Parser::new(&buf[..len], options).and_then(|mut parser| {
+                parser.checks = mem::take(checks);
+                let result = parser.only_dentry();
+                *checks = parser.checks;
+                result
+            });
+            Some(result.map_err(|e| ParseError { at: e.at + offset, ..e }))
+        };
+        self.buf.drain(..len);
+        self.offset += len;
+        self.scanned = 0;
+        self.has_token = false;
+        result
+    }
+}
+
+impl<R: io::BufRead> Iterator for DentryReader<R> {
+    type Item = Result<Dentry, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        let result = loop {
+            if let Some(semi) = self.scan() {
+                match self.parse(semi + 1) {
+                    Some(result) => break Some(result.map_err(ReadError::from))
+                  , None => continue
+                }
+            }
+            if let Some(max) = self.options.max_input_bytes {
+                if self.buf.len() > max {
+                    let len = self.buf.len();
+                    break Some(Err(ReadError::Parse(ParseError {
+                        kind: ErrorKind::InputTooLong { max, len }
+                      , at: self.offset + max
+                    })))
+                }
+            }
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) if self.has_token => {
+                    let len = self.buf.len();
+                    break self.parse(len).map(|result| result.map_err(ReadError::from))
+                }
+              , Ok(0) => break None
+              , Ok(_) => {}
+              , Err(e) => break Some(Err(ReadError::Io(e)))
+            }
+        };
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+/// Returns the path token containing `offset`, and its span, if there is
+/// one. Lexing stops at the first invalid character.
+#[cfg(feature = "lsp")]
+pub(crate) fn path_at(input: &str, offset: usize) -> Option<(Span, &str)> {
+    let options = ParseOptions { max_input_bytes: None, ..ParseOptions::default() };
+    let mut parser = Parser::new(input, &options).ok()?;
+    while let Ok(Some((start, token))) = parser.next() {
+        let span = Span { start, end: start + token.len() };
+        if span.start > offset {
+            return None
+        }
+        match token {
+            Token::Path(path) if span.contains(offset) => return Some((span, path))
+          , _ => {}
+        }
+    }
+    None
+}
+
+/// The kinds of token reported by [`highlight`].
+///
+/// [`highlight`]: fn.highlight.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// The labels of a dentry's prefix.
+    Prefix
+  , /// A wildcard (`*`) element of a dentry's prefix.
+    Wildcard
+  , /// A path in a destination tree.
+    Path
+  , /// A union weight.
+    Weight
+  , /// One of the operators `*`, `&`, or `|`.
+    Operator
+  , /// One of the special leaves `~`, `!`, or `$`.
+    SpecialLeaf
+  , /// The arrow `=>`, a `;`, or a parenthesis.
+    Punctuation
+  , /// A comment, from `#` to the end of the line.
+    Comment
+  , /// A character which cannot begin any token.
+    Invalid
+}
+
+/// Classify the tokens of a dtab, for syntax highlighting.
+///
+/// Tokens are classified as the parser would lex them, but the input need
+/// not be a valid dtab: invalid characters are reported as
+/// [`TokenKind::Invalid`], and highlighting continues after them.
+/// Whitespace is not reported. Prefixes containing wildcards are split into
+/// separate `Prefix` and `Wildcard` spans.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, Span, TokenKind};
+///
+/// let input = "/svc/* => 0.9 * /a | ~;";
+/// let tokens = parse::highlight(input).into_iter()
+///     .map(|(Span { start, end }, kind)| (&input[start..end], kind))
+///     .collect::<Vec<_>>();
+/// assert_eq!(tokens, vec![ ("/svc/", TokenKind::Prefix)
+///                        , ("*", TokenKind::Wildcard)
+///                        , ("=>", TokenKind::Punctuation)
+///                        , ("0.9", TokenKind::Weight)
+///                        , ("*", TokenKind::Operator)
+///                        , ("/a", TokenKind::Path)
+///                        , ("|", TokenKind::Operator)
+///                        , ("~", TokenKind::SpecialLeaf)
+///                        , (";", TokenKind::Punctuation) ]);
+/// ```
+///
+/// [`TokenKind::Invalid`]: enum.TokenKind.html#variant.Invalid
+pub fn highlight(input: &str) -> Vec<(Span, TokenKind)> {
+    let options = ParseOptions { max_input_bytes: None, ..ParseOptions::default() };
+    let mut lexer = Parser::new(input, &options).expect("input length is unlimited");
+    let mut tokens = Vec::new();
+    let mut in_prefix = true;
+    loop {
+        let (start, token) = match lexer.lex() {
+            Ok(Some(token)) => token
+          , Ok(None) => {
+                highlight_comments(&mut lexer, &mut tokens);
+                break
+            }
+          , Err(ParseError { at, .. }) => {
+                highlight_comments(&mut lexer, &mut tokens);
+                let len = input[at..].chars().next().map_or(1, char::len_utf8);
+                tokens.push((Span { start: at, end: at + len }, TokenKind::Invalid));
+                lexer.pos = at + len;
+                continue
+            }
+        };
+        highlight_comments(&mut lexer, &mut tokens);
+        let span = Span { start, end: start + token.len() };
+        let kind = match token {
+            Token::Path(path) if in_prefix => {
+                highlight_prefix(path, start, &mut tokens);
+                continue
+            }
+          , Token::Path(_) => TokenKind::Path
+          , Token::Number(_) => TokenKind::Weight
+          , Token::Star | Token::Amp | Token::Pipe => TokenKind::Operator
+          , Token::Neg | Token::Fail | Token::Empty => TokenKind::SpecialLeaf
+          , Token::Arrow => { in_prefix = false; TokenKind::Punctuation }
+          , Token::Semi => { in_prefix = true; TokenKind::Punctuation }
+          , Token::LParen | Token::RParen => TokenKind::Punctuation
+        };
+        tokens.push((span, kind));
+    }
+    tokens
+}
+
+/// Report the comments skipped by `lexer` since the last call.
+fn highlight_comments(lexer: &mut Parser, tokens: &mut Vec<(Span, TokenKind)>) {
+    tokens.extend(lexer.comments.drain(..).map(|span| (span, TokenKind::Comment)));
+}
+
+/// Returns the span of every comment in `input`, in order, excluding the
+/// newline which ends each one. The input need not be a valid dtab.
+///
+/// # Examples
+///
+/// ```
+/// use dtab::parse::{self, ParseOptions, Span};
+///
+/// let input = "# ingress\n/svc => /#/io.l5d.fs; # discovery\n";
+/// assert_eq!( parse::comments(input)
+///           , vec![Span { start: 0, end: 9 }, Span { start: 32, end: 43 }]);
+/// assert_eq!(parse::dtab(input, &ParseOptions::default()).unwrap().len(), 1);
+/// ```
+pub fn comments(input: &str) -> Vec<Span> {
+    highlight(input).into_iter()
+        .filter(|&(_, kind)| kind == TokenKind::Comment)
+        .map(|(span, _)| span)
+        .collect()
+}
+
+/// Split a prefix beginning at `start` into `Prefix` and `Wildcard` spans.
+fn highlight_prefix(prefix: &str, start: usize, tokens: &mut Vec<(Span, TokenKind)>) {
+    let mut run = start;
+    let mut offset = start;
+    for segment in prefix.split('/') {
+        if segment == "*" {
+            if run < offset {
+                tokens.push((Span { start: run, end: offset }, TokenKind::Prefix));
+            }
+            tokens.push((Span { start: offset, end: offset + 1 }, TokenKind::Wildcard));
+            run = offset + 1;
+        }
+        offset += segment.len() + 1;
+    }
+    let end = start + prefix.len();
+    if run < end {
+        tokens.push((Span { start: run, end }, TokenKind::Prefix));
+    }
+}
+
+/// Parse a single dentry, with an optional trailing semicolon.
+pub fn dentry(input: &str, options: &ParseOptions) -> Result<Dentry, ParseError> {
+    Parser::new(input, options)?.only_dentry()
+}
+
+/// Parse a name tree.
+pub fn name_tree(input: &str, options: &ParseOptions)
+                 -> Result<NameTree<String>, ParseError> {
+    let mut parser = Parser::new(input, options)?;
+    let tree = parser.tree()?;
+    parser.end()?;
+    Ok(tree)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token<'a> {
+    Path(&'a str)
+  , Number(&'a str)
+  , Star
+  , Amp
+  , Pipe
+  , LParen
+  , RParen
+  , Neg
+  , Fail
+  , Empty
+  , Arrow
+  , Semi
+}
+
+impl<'a> Token<'a> {
+    /// Returns the length of this token in the input, in bytes.
+    fn len(&self) -> usize {
+        match *self {
+            Token::Path(s) | Token::Number(s) => s.len()
+          , Token::Arrow => 2
+          , _ => 1
+        }
+    }
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Path(s) | Token::Number(s) => f.write_str(s)
+          , Token::Star => f.write_str("*")
+          , Token::Amp => f.write_str("&")
+          , Token::Pipe => f.write_str("|")
+          , Token::LParen => f.write_str("(")
+          , Token::RParen => f.write_str(")")
+          , Token::Neg => f.write_str("~")
+          , Token::Fail => f.write_str("!")
+          , Token::Empty => f.write_str("$")
+          , Token::Arrow => f.write_str("=>")
+          , Token::Semi => f.write_str(";")
+        }
+    }
+}
+
+/// Returns true if `ch` ends a path token.
+#[inline] fn ends_path(ch: char) -> bool {
+    ch.is_whitespace() || ";|&()=".contains(ch)
+}
+
+/// An in-progress union: its tree so far, total weight, depth, and width,
+/// counting the members of directly nested unions.
+struct UnionAcc<T> { tree: NameTree<T>, weight: Weight, depth: usize, width: usize }
+
+/// The state of one level of parenthesization.
+struct Frame<T> {
+    /// Where the opening parenthesis was, if this isn't the top level.
+    opened_at: Option<usize>
+  , /// The weight applied to this parenthesized group, if any.
+    weight: Option<Weight>
+  , /// The alternation parsed so far, its depth, and its width as a member
+    /// of a union: its union's width if it's a single union, and 1
+    /// otherwise.
+    alt: Option<(NameTree<T>, usize, usize)>
+  , /// The union parsed so far.
+    union: Option<UnionAcc<T>>
+}
+
+impl<T> Default for Frame<T> {
+    #[inline] fn default() -> Self {
+        Frame { opened_at: None, weight: None, alt: None, union: None }
+    }
+}
+
+struct Parser<'a, 'o> {
+    input: &'a str
+  , pos: usize
+  , peeked: Option<Option<(usize, Token<'a>)>>
+  , /// The offset just past the last token returned by `next`.
+    last_end: usize
+  , /// The spans of the comments skipped by the lexer so far.
+    comments: Vec<Span>
+  , checks: EntryChecks
+  , /// Whether a character which can't appear in a dtab ends the input,
+    /// rather than being an error.
+    partial: bool
+  , options: &'o ParseOptions
+}
+
+/// Checks which apply across all of the dentries in a dtab.
+#[derive(Default)]
+struct EntryChecks {
+    /// The number of dentries parsed so far.
+    entries: usize
+  , /// Every prefix parsed so far, if duplicate prefixes aren't allowed.
+    prefixes: HashSet<Prefix>
+}
+
+impl EntryChecks {
+    /// Check a dentry with the prefix returned by `prefix`, found at `at`.
+    fn check<F>(&mut self, options: &ParseOptions, at: usize, prefix: F)
+                -> Result<(), ParseError>
+    where F: FnOnce() -> Prefix {
+        self.entries += 1;
+        match options.max_entries {
+            Some(max) if self.entries > max =>
+                return Err(ParseError { kind: ErrorKind::TooManyEntries { max }, at })
+          , _ => {}
+        }
+        if !options.allow_duplicate_prefixes {
+            let prefix = prefix();
+            if self.prefixes.contains(&prefix) {
+                return Err(ParseError {
+                    kind: ErrorKind::DuplicatePrefix(prefix.to_string()), at
+                })
+            }
+            self.prefixes.insert(prefix);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'o> Parser<'a, 'o> {
+    fn new(input: &'a str, options: &'o ParseOptions) -> Result<Self, ParseError> {
+        if let Some(max) = options.max_input_bytes {
+            if input.len() > max {
+                return Err(ParseError {
+                    kind: ErrorKind::InputTooLong { max, len: input.len() }
+                  , at: max
+                })
+            }
+        }
+        let pos = if input.starts_with('\u{feff}') { '\u{feff}'.len_utf8() } else { 0 };
+        Ok(Parser { input, pos, peeked: None, last_end: pos, comments: Vec::new()
+                  , checks: EntryChecks::default(), partial: false, options })
+    }
+
+    fn lex(&mut self) -> Result<Option<(usize, Token<'a>)>, ParseError> {
+        let rest = &self.input[self.pos..];
+        let mut trimmed = rest.trim_start();
+        while trimmed.starts_with('#') {
+            let start = self.input.len() - trimmed.len();
+            if !self.options.allow_comments {
+                return Err(ParseError { kind: ErrorKind::UnexpectedComment, at: start })
+            }
+            let line = trimmed.find('\n').map_or(trimmed, |end| &trimmed[..end]);
+            let comment = line.trim_end();
+            self.comments.push(Span { start, end: start + comment.len() });
+            trimmed = trimmed[line.len()..].trim_start();
+        }
+        let start = self.input.len() - trimmed.len();
+        let mut chars = trimmed.chars();
+        let ch = match chars.next() {
+            Some(ch) => ch
+          , None => { self.pos = start; return Ok(None) }
+        };
+        let single = |token| Ok((1, token));
+        let (len, token) = match ch {
+            '*' => single(Token::Star)
+          , '&' => single(Token::Amp)
+          , '|' => single(Token::Pipe)
+          , '(' => single(Token::LParen)
+          , ')' => single(Token::RParen)
+          , '~' => single(Token::Neg)
+          , '!' => single(Token::Fail)
+          , '$' => single(Token::Empty)
+          , ';' => single(Token::Semi)
+          , '=' if trimmed.starts_with("=>") => Ok((2, Token::Arrow))
+          , '/' => {
+                let len = trimmed.find(ends_path).unwrap_or(trimmed.len());
+                Ok((len, Token::Path(&trimmed[..len])))
+            }
+          , '0'..='9' | '.' => {
+                let len = number_len(trimmed.as_bytes());
+                Ok((len, Token::Number(&trimmed[..len])))
+            }
+          , _ if self.partial => { self.pos = start; return Ok(None) }
+          , ch => Err(ParseError { kind: ErrorKind::InvalidCharacter(ch)
+                                 , at: start })
+        }?;
+        self.pos = start + len;
+        Ok(Some((start, token)))
+    }
+
+    fn peek(&mut self) -> Result<Option<(usize, Token<'a>)>, ParseError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lex()?);
+        }
+        Ok(self.peeked.clone().and_then(|t| t))
+    }
+
+    fn next(&mut self) -> Result<Option<(usize, Token<'a>)>, ParseError> {
+        let token = match self.peeked.take() {
+            Some(token) => token
+          , None => self.lex()?
+        };
+        if let Some((start, ref token)) = token {
+            self.last_end = start + token.len();
+        }
+        Ok(token)
+    }
+
+    /// Skip to just past the first `;` at or after `at`, or to the end of
+    /// the input if there isn't one.
+    fn skip_past_semi(&mut self, at: usize) {
+        self.pos = self.input[at..].find(';')
+            .map_or(self.input.len(), |i| at + i + 1);
+        self.peeked = None;
+    }
+
+    fn unexpected(&self, expected: &'static str, token: Option<(usize, Token)>)
+                  -> ParseError {
+        match token {
+            Some((at, token)) => ParseError {
+                kind: ErrorKind::Unexpected { expected, found: token.to_string() }
+              , at
+            }
+          , None => ParseError { kind: ErrorKind::UnexpectedEof { expected }
+                               , at: self.input.len() }
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.next()? {
+            None => Ok(())
+          , token => Err(self.unexpected("end of input", token))
+        }
+    }
+
+    fn check_labels(&self, at: usize, path: &str) -> Result<(), ParseError> {
+        if let Some(max) = self.options.max_label_bytes {
+            let mut offset = at;
+            for label in path.split('/') {
+                if label.len() > max {
+                    return Err(ParseError {
+                        kind: ErrorKind::LabelTooLong { max, len: label.len() }
+                      , at: offset
+                    })
+                }
+                offset += label.len() + 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_depth(&self, depth: usize, at: usize) -> Result<(), ParseError> {
+        match self.options.max_depth {
+            Some(max) if depth > max =>
+                Err(ParseError { kind: ErrorKind::TooDeep { max }, at })
+          , _ => Ok(())
+        }
+    }
+
+    fn check_union_width(&self, width: usize, at: usize) -> Result<(), ParseError> {
+        match self.options.max_union_width {
+            Some(max) if width > max =>
+                Err(ParseError { kind: ErrorKind::UnionTooWide { max }, at })
+          , _ => Ok(())
+        }
+    }
+
+    fn check_leaves(&self, leaves: usize, at: usize) -> Result<(), ParseError> {
+        match self.options.max_leaves {
+            Some(max) if leaves > max =>
+                Err(ParseError { kind: ErrorKind::TooManyLeaves { max }, at })
+          , _ => Ok(())
+        }
+    }
+
+    /// Skip any empty entries, if they're allowed.
+    fn skip_empty_entries(&mut self) -> Result<(), ParseError> {
+        if self.options.allow_empty_entries {
+            while let Some((_, Token::Semi)) = self.peek()? {
+                self.next()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a single dentry, with an optional trailing `;`, followed by the
+    /// end of the input.
+    fn only_dentry(&mut self) -> Result<Dentry, ParseError> {
+        let dentry = self.dentry()?;
+        if let Some((_, Token::Semi)) = self.peek()? {
+            self.next()?;
+        }
+        self.end()?;
+        Ok(dentry)
+    }
+
+    fn dentry(&mut self) -> Result<Dentry, ParseError> {
+        let labels = self.options.labels;
+        let (prefix, dst) = self.dentry_with( |path| Prefix::read_with(path, labels)
+                                            , path::escape_non_ascii)?;
+        Ok(Dentry::new(prefix, dst))
+    }
+
+    fn borrowed_dentry(&mut self) -> Result<BorrowedDentry<'a>, ParseError> {
+        let labels = self.options.labels;
+        let (prefix, dst) = self.dentry_with( |path| Prefix::validate(path, labels)
+                                                          .map(|()| path)
+                                            , |path| path)?;
+        Ok(BorrowedDentry { prefix, dst, labels })
+    }
+
+    /// Parse a dentry, constructing its prefix with `prefix` and each leaf
+    /// of its destination with `leaf`.
+    fn dentry_with<P, T, F, G>(&mut self, prefix: F, leaf: G)
+                               -> Result<(P, NameTree<T>), ParseError>
+    where F: FnOnce(&'a str) -> Result<P, PathError>
+        , G: Fn(&'a str) -> T {
+        let (at, text, prefix) = match self.next()? {
+            Some((at, Token::Path(path))) => {
+                self.check_labels(at, path)?;
+                let prefix = prefix(path)
+                    .map_err(|e| ParseError { kind: ErrorKind::InvalidPath(e), at })?;
+                (at, path, prefix)
+            }
+          , token => return Err(self.unexpected("a prefix", token))
+        };
+        match self.next()? {
+            Some((_, Token::Arrow)) => {}
+          , token => return Err(self.unexpected("'=>'", token))
+        }
+        let dst = self.tree_with(leaf)?;
+        let labels = self.options.labels;
+        self.checks.check(self.options, at, || {
+            Prefix::read_with(text, labels)
+                .expect("prefix was validated with the same policy when parsed")
+        })?;
+        Ok((prefix, dst))
+    }
+
+    /// Parse a name tree, stopping before a `;` or the end of the input.
+    #[inline] fn tree(&mut self) -> Result<NameTree<String>, ParseError> {
+        self.tree_with(path::escape_non_ascii)
+    }
+
+    /// Parse a name tree, stopping before a `;` or the end of the input, and
+    /// constructing each leaf from the text of its path with `leaf`.
+    fn tree_with<T, F>(&mut self, leaf: F) -> Result<NameTree<T>, ParseError>
+    where F: Fn(&'a str) -> T {
+        let mut frames = vec![Frame::default()];
+        let mut leaves = 0;
+        loop {
+            // parse a weighted simple expression, or open a new frame.
+            let weight = match self.peek()? {
+                Some((at, Token::Number(number))) => {
+                    self.next()?;
+                    let weight = Weight::from_decimal(number)
+                        .ok_or_else(|| ParseError {
+                            kind: ErrorKind::InvalidWeight(number.to_string())
+                          , at
+                        })?;
+                    match self.next()? {
+                        Some((_, Token::Star)) => {}
+                      , token => return Err(self.unexpected("'*'", token))
+                    }
+                    Some(weight)
+                }
+              , _ => None
+            };
+            let (at, simple) = match self.next()? {
+                Some((at, Token::Path(path))) => {
+                    self.check_labels(at, path)?;
+                    Prefix::validate(path, self.options.labels)
+                        .map_err(|e| ParseError {
+                            kind: ErrorKind::InvalidPath(e), at
+                        })?;
+                    leaves += 1;
+                    self.check_leaves(leaves, at)?;
+                    (at, NameTree::Leaf(leaf(path)))
+                }
+              , Some((at, Token::Neg)) => (at, NameTree::Neg)
+              , Some((at, Token::Fail)) => (at, NameTree::Fail)
+              , Some((at, Token::Empty)) => (at, NameTree::Empty)
+              , Some((at, Token::LParen)) => {
+                    self.check_depth(frames.len(), at)?;
+                    frames.push(Frame { opened_at: Some(at)
+                                      , weight
+                                      , ..Frame::default() });
+                    continue
+                }
+              , token => return Err(self.unexpected("a name tree", token))
+            };
+            let mut item = (simple, 1, 1, weight, at);
+
+            // add the item to the current union, then handle the operator
+            // that follows it, closing as many frames as necessary.
+            loop {
+                let (tree, depth, width, weight, at) = item;
+                self.push_union(frames.last_mut().expect("frame"), tree, depth
+                               , width, weight, at)?;
+                match self.peek()? {
+                    Some((_, Token::Amp)) => { self.next()?; break }
+                  , Some((at, Token::Pipe)) => {
+                        self.next()?;
+                        let frame = frames.last_mut().expect("frame");
+                        self.push_alt(frame, at)?;
+                        break
+                    }
+                  , Some((at, Token::RParen)) if frames.len() > 1 => {
+                        self.next()?;
+                        let mut frame = frames.pop().expect("frame");
+                        let weight = frame.weight;
+                        let (tree, depth, width) = self.finish(&mut frame, at)?;
+                        let opened_at = frame.opened_at.unwrap_or(at);
+                        item = (tree, depth, width, weight, opened_at);
+                    }
+                  , token if frames.len() == 1
+                             && (self.partial || matches!(token, Some((_, Token::Semi)) | None)) => {
+                        // when parsing partially, whatever follows is left
+                        // for the caller.
+                        let mut frame = frames.pop().expect("frame");
+                        let at = self.pos;
+                        return self.finish(&mut frame, at).map(|(tree, _, _)| tree)
+                    }
+                  , token => {
+                        let expected = if frames.len() > 1 {
+                            "'&', '|', or ')'"
+                        } else {
+                            "'&', '|', ';', or end of input"
+                        };
+                        return Err(self.unexpected(expected, token))
+                    }
+                }
+            }
+        }
+    }
+
+    fn push_union<T>( &self, frame: &mut Frame<T>, tree: NameTree<T>
+                 , depth: usize, width: usize, weight: Option<Weight>, at: usize)
+                 -> Result<(), ParseError> {
+        let weight = weight.unwrap_or(self.options.default_weight);
+        frame.union = Some(match frame.union.take() {
+            None => UnionAcc { tree, weight, depth, width }
+          , Some(acc) => {
+                let depth = acc.depth.max(depth) + 1;
+                self.check_depth(depth, at)?;
+                let width = acc.width + width;
+                self.check_union_width(width, at)?;
+                UnionAcc { tree: NameTree::Union( acc.tree.weighted(acc.weight)
+                                                , tree.weighted(weight))
+                         , weight: acc.weight + weight
+                         , depth
+                         , width }
+            }
+        });
+        Ok(())
+    }
+
+    fn push_alt<T>(&self, frame: &mut Frame<T>, at: usize) -> Result<(), ParseError> {
+        let union = frame.union.take().expect("alternation of empty union");
+        frame.alt = Some(match frame.alt.take() {
+            None => (union.tree, union.depth, union.width)
+          , Some((alt, depth, _)) => {
+                let depth = depth.max(union.depth) + 1;
+                self.check_depth(depth, at)?;
+                (NameTree::Alt(Box::new(alt), Box::new(union.tree)), depth, 1)
+            }
+        });
+        Ok(())
+    }
+
+    fn finish<T>(&self, frame: &mut Frame<T>, at: usize)
+                 -> Result<(NameTree<T>, usize, usize), ParseError> {
+        self.push_alt(frame, at)?;
+        Ok(frame.alt.take().expect("finished empty frame"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nametree::W;
+
+    fn tree(s: &str) -> NameTree<String> {
+        name_tree(s, &ParseOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn simple() {
+        assert_eq!(tree("/smitten"), NameTree::from("/smitten"));
+        assert_eq!(tree("  ~ "), NameTree::Neg);
+        assert_eq!(tree("!"), NameTree::Fail);
+        assert_eq!(tree("$"), NameTree::Empty);
+        assert_eq!(tree("((/smitten))"), NameTree::from("/smitten"));
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!( tree("/a | /b & /c | /d")
+                  , NameTree::from("/a") | (NameTree::from("/b") & "/c") | "/d");
+        assert_eq!( tree("/a & (/b | /c)")
+                  , NameTree::from("/a") & (NameTree::from("/b") | "/c"));
+        assert_eq!( tree("0.7 * /a & 0.3*/b")
+                  , (W(0.7) * "/a") & (W(0.3) * "/b"));
+        assert_eq!( tree("0.7 * /a & 0.2 * /b & 0.1 * /c")
+                  , (W(0.9) * ((W(0.7) * "/a") & (W(0.2) * "/b"))) & (W(0.1) * "/c"));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in &[ "/a | (/b | /c)"
+                  , "0.5 * (/a | /b) & 0.5 * /c"
+                  , "1 * (0.5 * /a & 0.5 * /b) & 0.5 * /c | ~"
+                  , "/svc/*/x | !"
+                  ] {
+            let t = tree(s);
+            assert_eq!(tree(&t.to_string()), t, "{}", s);
+        }
+    }
+
+    #[test]
+    fn display_round_trips_generated() {
+        use generate::{GenConfig, Generator};
+        let options = ParseOptions::default();
+        for seed in 0..200 {
+            let config = GenConfig { dentries: 10, tree_depth: 5, wildcard_rate: 0.3, seed
+                                   , ..GenConfig::default() };
+            let generated = Generator::new(config).dtab();
+            assert_eq!(dtab(&generated.to_string(), &options).as_ref(), Ok(&generated));
+            assert_eq!(dtab(&format!("{:#}", generated), &options), Ok(generated));
+        }
+    }
+
+    #[test]
+    fn display_round_trips_exactly() {
+        use prefix::Elem;
+        use weight::Weight;
+        let weights = [ Weight::ZERO, Weight::ratio(1, 1 << 60), Weight::ratio(u64::MAX, 1)
+                      , Weight::ratio(1, u64::MAX - u64::MAX % 5u64.pow(27))
+                      , Weight::ratio(12_345_678_901, 1_000_000_000)
+                      , Weight::ratio(7, 5u64.pow(27)), Weight::ratio(1, 3)
+                      , Weight::ratio(u64::MAX, u64::MAX - 1) ];
+        let trees = weights.windows(2)
+            .map(|w| NameTree::Union( NameTree::from("/a").weighted(w[0])
+                                    , (NameTree::Neg | NameTree::Fail | NameTree::Empty)
+                                          .weighted(w[1])));
+        for tree in trees {
+            let text = tree.to_string();
+            assert_eq!(name_tree(&text, &ParseOptions::default()).as_ref(), Ok(&tree), "{}", text);
+        }
+        for n in 1..12 {
+            let tree = NameTree::even_union((0..n).map(|i| format!("/{}", i)));
+            let text = tree.to_string();
+            assert_eq!(name_tree(&text, &ParseOptions::default()).as_ref(), Ok(&tree), "{}", text);
+        }
+
+        let finagle = ParseOptions { labels: LabelPolicy::Finagle, ..ParseOptions::default() };
+        let labels = vec![ Elem::Label(b"*".to_vec()), Elem::AnyElem
+                         , Elem::Label("café".as_bytes().to_vec())
+                         , Elem::Label(b"\\x41\xff\x00 /;".to_vec()) ];
+        let prefix = Prefix::new(labels).unwrap();
+        let dentry = Dentry::new(prefix, NameTree::from("/b"));
+        let text = dentry.to_string();
+        assert_eq!(&text, "/\\x2a/*/caf\\xc3\\xa9/\\x5cx41\\xff\\x00\\x20\\x2f\\x3b => /b;");
+        assert_eq!(super::dentry(&text, &finagle), Ok(dentry));
+    }
+
+    #[test]
+    fn utf8_destinations_are_escaped() {
+        let utf8 = ParseOptions { labels: LabelPolicy::Utf8, ..ParseOptions::default() };
+        let parsed = dentry("/café => /crêperie | 2 * /日/a & 0.5 * /b\\x41", &utf8).unwrap();
+        assert_eq!( parsed.dst().leaves().collect::<Vec<_>>()
+                  , vec!["/cr\\xc3\\xaaperie", "/\\xe6\\x97\\xa5/a", "/b\\x41"]);
+        let text = parsed.to_string();
+        assert_eq!( &text
+                  , "/caf\\xc3\\xa9 => /cr\\xc3\\xaaperie | 2 * /\\xe6\\x97\\xa5/a & 0.5 * /b\\x41;");
+        assert!(text.is_ascii());
+
+        let borrowed = borrowed_dtab("/café => /crêperie", &utf8).unwrap();
+        assert_eq!(&borrowed[0].to_string(), "/caf\\xc3\\xa9 => /cr\\xc3\\xaaperie;");
+        assert_eq!(borrowed[0].to_dentry(), Ok(dentry("/café => /crêperie", &utf8).unwrap()));
+    }
+
+    /// Both sides of a dentry read with the UTF-8 policy come back from its
+    /// `Display` form under the `Any` policy.
+    #[test]
+    fn utf8_dentry_round_trips_through_any() {
+        use path::Path;
+        let utf8 = ParseOptions { labels: LabelPolicy::Utf8, ..ParseOptions::default() };
+        let any = ParseOptions { labels: LabelPolicy::Any, ..ParseOptions::default() };
+        let parsed = dentry("/café/* => /crêperie/ß | 0.5 * /日 & 0.5 * ~", &utf8).unwrap();
+        let read = dentry(&parsed.to_string(), &any).unwrap();
+        assert_eq!(read, parsed);
+        assert_eq!( read.prefix().to_string()
+                  , Prefix::read_with("/café/*", LabelPolicy::Utf8).unwrap().to_string());
+        let leaves = read.dst().leaves()
+            .map(|leaf| ::read_leaf(leaf).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!( leaves
+                  , vec![ Path::read_with("/crêperie/ß", LabelPolicy::Utf8).unwrap()
+                        , Path::read_with("/日", LabelPolicy::Utf8).unwrap() ]);
+        assert_eq!(dentry(&read.to_string(), &utf8), Ok(parsed));
+    }
+
+    /// Dtabs with random labels needing escapes read back from their
+    /// `Display` forms with the policy they were read with, or the `Any`
+    /// policy, and from their serialized forms.
+    #[test]
+    fn display_round_trips_escaped_labels() {
+        fn below(state: &mut u64, n: usize) -> usize {
+            *state ^= *state << 13;
+            *state ^= *state >> 7;
+            *state ^= *state << 17;
+            (*state % n as u64) as usize
+        }
+        fn utf8_label(state: &mut u64) -> String {
+            (0..1 + below(state, 4)).map(|_| {
+                let chars = ['a', 'Z', '0', '_', ':', '.', '#', '$', '%', '-', 'é', 'ß', '日', '🦀'];
+                let ch = chars[below(state, chars.len())];
+                let mut buf = [0; 4];
+                match ch.encode_utf8(&mut buf).as_bytes() {
+                    bytes if bytes.len() > 1 && below(state, 2) == 0 =>
+                        bytes.iter().map(|b| format!("\\x{:02x}", b)).collect()
+                  , _ => ch.to_string()
+                }
+            }).collect()
+        }
+        fn finagle_label(state: &mut u64) -> String {
+            (0..1 + below(state, 4)).map(|_| match below(state, 256) as u8 {
+                b if ::path::is_showable(b) => (b as char).to_string()
+              , b => format!("\\x{:02x}", b)
+            }).collect()
+        }
+
+        let any = ParseOptions { labels: LabelPolicy::Any, ..ParseOptions::default() };
+        let utf8 = ParseOptions { labels: LabelPolicy::Utf8, ..ParseOptions::default() };
+        let finagle = ParseOptions::finagle();
+        let mut state = 0x5eed;
+        for round in 0..200 {
+            let (options, label): (_, fn(&mut u64) -> String) = if round % 2 == 0 {
+                (&utf8, utf8_label)
+            } else {
+                (&finagle, finagle_label)
+            };
+            let mut input = String::new();
+            for i in 0..4 {
+                let mut label = || label(&mut state);
+                let (a, b, c, d, e) = (label(), label(), label(), label(), label());
+                let wildcard = if i % 2 == 0 { "/*" } else { "" };
+                input.push_str(&format!( "/{}{}/{} => /{} | 0.25 * /{}/{} & 0.75 * ~;\n"
+                                       , a, wildcard, b, c, d, e));
+            }
+            let parsed = dtab(&input, options).unwrap();
+            for text in &[parsed.to_string(), format!("{:#}", parsed)] {
+                assert_eq!(dtab(text, options).as_ref(), Ok(&parsed), "{}", text);
+                assert_eq!(dtab(text, &any).as_ref(), Ok(&parsed), "{}", text);
+            }
+            let json = ::serde_json::to_string(&parsed).unwrap();
+            assert_eq!(::serde_json::from_str::<Dtab>(&json).unwrap(), parsed, "{}", json);
+            let json = ::serde_json::to_string(&parsed.to_string()).unwrap();
+            assert_eq!(::serde_json::from_str::<Dtab>(&json).unwrap(), parsed, "{}", json);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let options = ParseOptions::default();
+        let err = |s| name_tree(s, &options).unwrap_err();
+        assert_eq!( err("/a |")
+                  , ParseError { kind: ErrorKind::UnexpectedEof {
+                                     expected: "a name tree" }
+                               , at: 4 });
+        assert_eq!( err("(/a")
+                  , ParseError { kind: ErrorKind::UnexpectedEof {
+                                     expected: "'&', '|', or ')'" }
+                               , at: 3 });
+        assert_eq!( err("/a )")
+                  , ParseError { kind: ErrorKind::Unexpected {
+                                     expected: "'&', '|', ';', or end of input"
+                                   , found: ")".to_string() }
+                               , at: 3 });
+        assert_eq!( err("0.5 /a").kind
+                  , ErrorKind::Unexpected { expected: "'*'"
+                                          , found: "/a".to_string() });
+        assert_eq!(err("1.2.3 * /a").kind, ErrorKind::InvalidWeight("1.2.3".to_string()));
+        assert_eq!(err("1e30 * /a").kind, ErrorKind::InvalidWeight("1e30".to_string()));
+        assert_eq!(err("/a b").kind, ErrorKind::InvalidCharacter('b'));
+        assert_eq!( err("/a/b c").at, 5);
+        match err("/a//b").kind {
+            ErrorKind::InvalidPath(PathError::EmptyLabel) => {}
+          , other => panic!("unexpected error {:?}", other)
+        }
+    }
+
+    #[test]
+    fn dtabs() {
+        let options = ParseOptions::default();
+        assert_eq!(dtab("", &options).unwrap(), Dtab::default());
+        let parsed = dtab("/a => /b;\n/c => /d | /e", &options).unwrap();
+        assert_eq!( parsed
+                  , dtab![ "/a" => NameTree::from("/b");
+                           "/c" => NameTree::from("/d") | "/e"; ]);
+        assert_eq!(dtab(&parsed.to_string(), &options).unwrap(), parsed);
+        assert_eq!(format!("{:#}", parsed).parse::<Dtab>().unwrap(), parsed);
+        assert_eq!(Dtab::read("/a => ").unwrap_err().at, 6);
+        assert_eq!( dtab("/a => /b /c => /d", &options).unwrap_err()
+                  , ParseError { kind: ErrorKind::Unexpected {
+                                     expected: "'&', '|', ';', or end of input"
+                                   , found: "/c".to_string() }
+                               , at: 9 });
+        assert_eq!( dtab("/a => /b;;", &options).unwrap_err().kind
+                  , ErrorKind::Unexpected { expected: "a prefix"
+                                          , found: ";".to_string() });
+        assert!(dentry("/a => /b;", &options).is_ok());
+        assert!(dentry("/a => /b; /c => /d", &options).is_err());
+    }
+
+    #[test]
+    fn whitespace() {
+        let options = ParseOptions::default();
+        let expected = dtab![ "/a" => NameTree::from("/b") | "/c";
+                              "/d" => (W(0.5) * "/e") & (W(0.5) * "/f"); ];
+        for input in &[ "/a=>/b|/c;/d=>0.5*/e&0.5*/f"
+                      , "\t/a\n  =>\n\t/b\n  | /c\n ;\n\n\t\t/d =>\r\n  0.5\t*\t/e\n& 0.5 * /f\n"
+                      , "\u{feff}/a => ( /b\n| /c ) ; /d\u{a0}=> 0.5 * /e & 0.5 * /f ;\n\n"
+                      ] {
+            assert_eq!(dtab(input, &options).unwrap(), expected, "{:?}", input);
+        }
+        assert_eq!( pairs(vec![(" /a\t", "\n/b | /c ")], &options).unwrap()
+                  , dtab!["/a" => NameTree::from("/b") | "/c";]);
+        assert_eq!(highlight("\u{feff}/a")[0].0, Span { start: 3, end: 5 });
+    }
+
+    #[test]
+    fn comments() {
+        let options = ParseOptions::default();
+        let input = "#!/a => /b\n/#/x => /y # trailing\n  # indented\r\n;/z => /#/w#";
+        assert_eq!( dtab(input, &options).unwrap()
+                  , dtab!["/#/x" => "/y"; "/z" => "/#/w#";]);
+        assert_eq!( super::comments(input).iter()
+                        .map(|span| &input[span.start..span.end])
+                        .collect::<Vec<_>>()
+                  , vec!["#!/a => /b", "# trailing", "# indented"]);
+        assert_eq!(dtab("# nothing but comments", &options).unwrap(), Dtab::new());
+        assert!(dentry("/a => # /b\n", &options).is_err());
+    }
+
+    #[test]
+    fn lenient() {
+        let options = ParseOptions::default();
+        let (parsed, errors) = dtab_lenient("/a => /b; %; /c => (/d; /e => /f", &options);
+        assert_eq!(parsed, dtab!["/a" => "/b"; "/e" => "/f";]);
+        assert_eq!( errors.iter().map(|e| e.kind.clone()).collect::<Vec<_>>()
+                  , vec![ ErrorKind::InvalidCharacter('%')
+                        , ErrorKind::Unexpected { expected: "'&', '|', or ')'"
+                                                , found: ";".to_string() } ]);
+        assert_eq!(dtab_lenient("", &options), (Dtab::new(), Vec::new()));
+        assert_eq!(dtab_lenient("/a => /b |", &options).1.len(), 1);
+        let limited = ParseOptions { max_input_bytes: Some(4), ..options };
+        assert_eq!(dtab_lenient("/a => /b", &limited).1[0].at, 4);
+    }
+
+    #[test]
+    fn borrowed() {
+        let options = ParseOptions::default();
+        let input = "/a => 2 * (/b | ~) & /c/*; /d/* => !;\n/e => /f";
+        let dentries = borrowed_dtab(input, &options).unwrap();
+        assert_eq!( dentries.iter().map(BorrowedDentry::to_dentry).collect::<Result<Dtab, _>>()
+                  , Ok(dtab(input, &options).unwrap()));
+        assert_eq!(&dentries[1].to_string(), "/d/* => !;");
+        assert_eq!(borrowed_dtab("", &options), Ok(Vec::new()));
+
+        let utf8 = ParseOptions { labels: LabelPolicy::Utf8, ..options };
+        let escaped = borrowed_dtab("/caf\\xc3\\xa9 => /é", &utf8).unwrap();
+        assert_eq!(escaped[0].dst, NameTree::Leaf("/é"));
+        assert_eq!(escaped[0].to_dentry(), Ok(dentry("/caf\\xc3\\xa9 => /é", &utf8).unwrap()));
+
+        // dentries are copied with the policy they were parsed with.
+        let finagle = borrowed_dtab("/a\\xff => /b", &ParseOptions::finagle()).unwrap();
+        assert_eq!(finagle[0].labels, LabelPolicy::Finagle);
+        assert_eq!( finagle[0].to_dentry()
+                  , Ok(Dentry::new( Prefix::read_with("/a\\xff", LabelPolicy::Finagle).unwrap()
+                                  , "/b")));
+        let changed = BorrowedDentry { prefix: "/a b", ..finagle[0].clone() };
+        assert!(changed.to_dentry().is_err());
+
+        for input in &["/a => /b//c", "/a\\x => /b", "/é => /b", "/a => /b /c", "/a =>"] {
+            assert_eq!( borrowed_dtab(input, &options).unwrap_err()
+                      , dtab(input, &options).unwrap_err());
+        }
+    }
+
+    #[test]
+    fn streams() {
+        use std::io::BufReader;
+        let options = ParseOptions::default();
+        let read = |input: &str, options: ParseOptions| {
+            DentryReader::new(BufReader::with_capacity(3, input.as_bytes()), options)
+                .map(|result| result.map_err(|e| match e {
+                    ReadError::Parse(e) => e
+                  , ReadError::Io(e) => panic!("unexpected I/O error: {}", e)
+                }))
+                .collect::<Result<Dtab, _>>()
+        };
+        for input in &[ "", "# only ; a comment", "\u{feff}/a => /b"
+                      , "/a => /#/b; # c; d\n/e=>(/f\n|/g);\n\n/h => ~;  "
+                      , "/a => /b;\n/c => %;", "/a => /b;;", "/a => /b\n/c => /d" ] {
+            assert_eq!(read(input, options), dtab(input, &options));
+            assert_eq!(dentries(input, &options).collect::<Result<Dtab, _>>(), dtab(input, &options));
+        }
+
+        let limited = ParseOptions { max_input_bytes: Some(12), ..options };
+        assert_eq!(read("/a => /b;\n/c => /d;\n", limited), dtab("/a=>/b;/c=>/d", &options));
+        assert_eq!( read("/a => /b;\n/c => /d | /e;", limited).unwrap_err()
+                  , ParseError { kind: ErrorKind::InputTooLong { max: 12, len: 15 }, at: 21 });
+        assert_eq!(dentries("/a => /b;", &limited).count(), 1);
+
+        let invalid: &[u8] = b"/a => /b;\n/\xff => /c;";
+        let mut reader = DentryReader::new(invalid, options);
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(ReadError::Io(_))) => {}
+          , other => panic!("expected an I/O error, got {:?}", other)
+        }
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn entry_options() {
+        let read = |input: &str, options: &ParseOptions| {
+            DentryReader::new(input.as_bytes(), *options)
+                .collect::<Result<Dtab, _>>()
+                .map_err(|e| match e {
+                    ReadError::Parse(e) => e
+                  , ReadError::Io(e) => panic!("unexpected I/O error: {}", e)
+                })
+        };
+        let check = |input: &str, options: &ParseOptions, expected: Result<Dtab, ParseError>| {
+            assert_eq!(dtab(input, options), expected);
+            assert_eq!(dentries(input, options).collect::<Result<Dtab, _>>(), expected);
+            assert_eq!(read(input, options), expected);
+            assert_eq!( borrowed_dtab(input, options).map(|dentries| {
+                            dentries.iter().map(|dentry| dentry.to_dentry().unwrap()).collect()
+                        })
+                      , expected);
+        };
+
+        let empty = ";\n/a => /b;; ;\n/c => /d;;";
+        check(empty, &ParseOptions::lenient(), Ok(dtab!["/a" => "/b"; "/c" => "/d";]));
+        assert_eq!( dtab(empty, &ParseOptions::default()).unwrap_err().kind
+                  , ErrorKind::Unexpected { expected: "a prefix", found: ";".to_string() });
+        assert_eq!(dtab_lenient(empty, &ParseOptions::lenient()).1, Vec::new());
+
+        let duplicates = "/a => /b;\n/c => /d;\n/a => /e;";
+        check(duplicates, &ParseOptions::default(), dtab(duplicates, &ParseOptions::default()));
+        check( duplicates, &ParseOptions::strict()
+             , Err(ParseError { kind: ErrorKind::DuplicatePrefix("/a".to_string()), at: 20 }));
+
+        let limited = ParseOptions { max_entries: Some(2), ..ParseOptions::default() };
+        check( duplicates, &limited
+             , Err(ParseError { kind: ErrorKind::TooManyEntries { max: 2 }, at: 20 }));
+        check("/a => /b;/c => /d;", &limited, Ok(dtab!["/a" => "/b"; "/c" => "/d";]));
+
+        let comments = "/a => /#/b; # why\n";
+        let error = ParseError { kind: ErrorKind::UnexpectedComment, at: 12 };
+        check(comments, &ParseOptions::strict(), Err(error.clone()));
+        assert_eq!(error.span(comments), Span { start: 12, end: 17 });
+        check("/a => /#/b;", &ParseOptions::strict(), Ok(dtab!["/a" => "/#/b";]));
+    }
+
+    /// Inputs and how Finagle's `Dtab.read` reads them, as the canonical form
+    /// of the dtab it reads, or `None` if it rejects them.
+    #[test]
+    fn finagle_compat() {
+        let options = ParseOptions::finagle();
+        let cases = [ ("", Some(""))
+                    , ("/a=>/b", Some("/a => /b;\n"))
+                    , ("/a => /b;", Some("/a => /b;\n"))
+                    , ("  /a\t=>\n/b ;\r\n/c => /d  ", Some("/a => /b;\n/c => /d;\n"))
+                    , ("# header\n/a => /b; # trailing", Some("/a => /b;\n"))
+                    , ("/a => 1 * /b & 0.5 * /c", Some("/a => 1 * /b & 0.5 * /c;\n"))
+                    , ("/a => .5 * /b & 1. * /c", Some("/a => 0.5 * /b & 1 * /c;\n"))
+                    , ("/a => /b & /c", Some("/a => 1 * /b & 1 * /c;\n"))
+                    , ("/a => /b | (/c | !) | ~ | $", Some("/a => /b | (/c | !) | ~ | $;\n"))
+                    , ("/s\\x41/* => /a\\x2fb", Some("/sA/* => /a\\x2fb;\n"))
+                    , ("/a => /b;;/c => /d", None)
+                    , ("/a => ", None)
+                    , ("/a => /b & -1 * /c", None)
+                    , ("/a => 0.50 * /b & 1.0E-4 * /c", Some("/a => 0.5 * /b & 0.0001 * /c;\n"))
+                    , ("/a => 1e2 * /b & 2.5e+1 * /c", Some("/a => 100 * /b & 25 * /c;\n"))
+                    , ("/a => 1e * /b", None)
+                    , ("/a b => /c", None)
+                    , ("/s\\x4 => /a", None) ];
+        for &(input, expected) in &cases {
+            let read = dtab(input, &options).map(|dtab| dtab.to_string());
+            assert_eq!(read.as_ref().ok().map(String::as_str), expected, "{:?}", input);
+        }
+
+        // prefixes are compared as the policy reads them, even where the
+        // UTF-8 policy wouldn't accept them.
+        let unique = ParseOptions { allow_duplicate_prefixes: false, ..options };
+        let input = "/a\\xff => /b; /a\\xfe => /c";
+        assert_eq!(dtab(input, &unique).map(|dtab| dtab.len()), Ok(2));
+        assert_eq!(borrowed_dtab(input, &unique).map(|dentries| dentries.len()), Ok(2));
+        assert_eq!( dtab("/a\\xff => /b; /a\\xFF => /c", &unique).unwrap_err().kind
+                  , ErrorKind::DuplicatePrefix("/a\\xff".to_string()));
+        let copied = borrowed_dtab("/a\\xff => /b", &options).unwrap()[0].to_dentry();
+        assert_eq!(copied, Ok(dentry("/a\\xff => /b", &options).unwrap()));
+    }
+
+    #[test]
+    fn locate_errors() {
+        let options = ParseOptions::default();
+        let located = |input: &str| dtab(input, &options).unwrap_err().locate(input);
+        let error = located("/a => /b;\n\n  /c => /d |");
+        assert_eq!(error.span, Span { start: 23, end: 23 });
+        assert_eq!(error.start, Location { line: 3, column: 13 });
+        assert_eq!(located("/a => /b\n/c => /d").span, Span { start: 9, end: 11 });
+        assert_eq!(located("/a => é").span, Span { start: 6, end: 8 });
+        assert_eq!(located("/é/a => /b").span, Span { start: 1, end: 3 });
+        assert_eq!(located("/é/a => /b").end, Location { line: 1, column: 3 });
+        assert_eq!(located("/a//b => /c").span, Span { start: 2, end: 3 });
+        assert_eq!(located("/a => 1.2.3 * /b").span, Span { start: 6, end: 11 });
+
+        let limited = ParseOptions { max_label_bytes: Some(2), ..options };
+        let input = "/a/bcd => /e";
+        let error = dtab(input, &limited).unwrap_err().locate(input);
+        assert_eq!(error.span, Span { start: 3, end: 6 });
+        assert_eq!(&error.to_string(), "1:4: label of 3 bytes exceeds the limit of 2");
+    }
+
+    #[test]
+    fn command_line_args() {
+        let options = ParseOptions::default();
+        let read = |args: &[&str]| super::args(args, &options);
+        assert_eq!(read(&[]), Ok(Dtab::new()));
+        assert_eq!(read(&["-v", "--dtabs", "x"]), Ok(Dtab::new()));
+        assert_eq!( read(&["--dtab", "/a => /b;", "--dtab=", "--dtab=/a => /c"])
+                  , Ok(dtab!["/a" => "/b"; "/a" => "/c";]));
+        // a flag's value is never read as another flag.
+        assert_eq!(read(&["--dtab", "--dtab=/a => /b"]).unwrap_err().arg(), 1);
+        assert_eq!( read(&["-p", "1", "--dtab=/a => /b |"])
+                  , Err(ArgsError::Invalid {
+                        arg: 2
+                      , error: ParseError { kind: ErrorKind::UnexpectedEof {
+                                                expected: "a name tree" }
+                                          , at: 10 } }));
+        assert_eq!(read(&["--dtab"]), Err(ArgsError::MissingValue { arg: 0 }));
+    }
+
+    #[test]
+    fn partial() {
+        let options = ParseOptions::default();
+        let partial = |input| {
+            let (dtab, rest) = dtab_partial(input, &options);
+            (dtab.to_string(), rest)
+        };
+        assert_eq!(partial(""), (String::new(), ""));
+        assert_eq!(partial("/a => /b"), ("/a => /b;\n".to_string(), ""));
+        assert_eq!(partial("/a => /b; "), ("/a => /b;\n".to_string(), " "));
+        assert_eq!(partial("/a => /b /c => /d"), ("/a => /b;\n".to_string(), " /c => /d"));
+        assert_eq!(partial("/a => (/b) ]"), ("/a => /b;\n".to_string(), " ]"));
+        assert_eq!(partial("/a => /b;;"), ("/a => /b;\n".to_string(), ";"));
+        assert_eq!(partial("/a => /b; /c => ( }"), ("/a => /b;\n".to_string(), " /c => ( }"));
+        assert_eq!(partial("/a => /b}"), (String::new(), "/a => /b}"));
+        assert_eq!(partial("= /a => /b"), (String::new(), "= /a => /b"));
+
+        let lenient = ParseOptions::lenient();
+        assert_eq!(dtab_partial(";/a => /b;; x", &lenient).1, " x");
+
+        // a dentry cut short by the limit is left in the rest
+        let limited = ParseOptions { max_input_bytes: Some(14), ..ParseOptions::default() };
+        let (dtab, rest) = dtab_partial("/a => /b; /c => /dddd; /e => /f", &limited);
+        assert_eq!((dtab.len(), rest), (1, " /c => /dddd; /e => /f"));
+        let (dtab, rest) = dtab_partial("/a => /b; /c => /d; /e => /f", &limited);
+        assert_eq!((dtab.len(), rest), (1, " /c => /d; /e => /f"));
+        let (dtab, rest) = dtab_partial("/a => /b; /c => /d} /e => /f", &limited);
+        assert_eq!((dtab.len(), rest), (1, " /c => /d} /e => /f"));
+    }
+
+    #[test]
+    fn render_errors() {
+        let options = ParseOptions::default();
+        let render = |input: &str| dtab(input, &options).unwrap_err().render(input);
+        assert_eq!( &render("/a => 1.2.3 * /b")
+                  , "error: invalid weight \"1.2.3\"\n --> 1:7\n  |\n\
+                     1 | /a => 1.2.3 * /b\n  |       ^^^^^\n");
+        // at the end of the input
+        assert_eq!( &render("/a => /b;\r\n\t/c =>")
+                  , "error: expected a name tree, found end of input\n --> 2:7\n  |\n\
+                     2 | \t/c =>\n  | \t     ^\n");
+        // comments run to the end of the line, and no further
+        let strict = ParseOptions::strict();
+        let input = "# one\n# two\n".repeat(5);
+        assert_eq!( &dtab(&input, &strict).unwrap_err().render(&input)
+                  , "error: comments are not allowed\n --> 1:1\n  |\n1 | # one\n  | ^^^^^\n");
+        let input = format!("{}/a => /é", "\n".repeat(9));
+        assert_eq!( &dtab(&input, &options).unwrap_err().render(&input)
+                  , "error: invalid path: invalid character 'é' at position 0 in \"é\"\n  \
+                     --> 10:8\n   |\n10 | /a => /é\n   |        ^\n");
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let options = ParseOptions::default();
+        let parens = format!("{}/a{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!( name_tree(&parens, &options).unwrap_err()
+                  , ParseError { kind: ErrorKind::TooDeep {
+                                     max: DEFAULT_MAX_DEPTH }
+                               , at: DEFAULT_MAX_DEPTH });
+
+        let alts = vec!["/a"; 100_000].join(" | ");
+        assert_eq!( name_tree(&alts, &options).unwrap_err().kind
+                  , ErrorKind::TooDeep { max: DEFAULT_MAX_DEPTH });
+
+        let unlimited = ParseOptions { max_depth: None, ..options };
+        let deep = name_tree(&vec!["/a"; 100_000].join(" & "), &unlimited)
+            .unwrap();
+        assert_eq!(deep.complexity().leaves, 100_000);
+        // neither display nor dropping recurse, either
+        assert_eq!(deep.to_string().matches("/a").count(), 100_000);
+    }
+
+    #[test]
+    fn size_limits() {
+        let options = ParseOptions::untrusted();
+        let label = format!("/{}", "a".repeat(1 << 20));
+        assert_eq!( name_tree(&label, &options).unwrap_err().kind
+                  , ErrorKind::InputTooLong { max: 8 * 1024, len: label.len() });
+
+        let options = ParseOptions { max_input_bytes: None, ..options };
+        assert_eq!( name_tree(&label, &options).unwrap_err()
+                  , ParseError { kind: ErrorKind::LabelTooLong {
+                                     max: 256, len: 1 << 20 }
+                               , at: 1 });
+        assert_eq!(name_tree(&label, &ParseOptions::default()).unwrap().complexity().leaves, 1);
+
+        let entries = "/a=>/b;".repeat(257);
+        assert_eq!( dtab(&entries, &ParseOptions::untrusted()).unwrap_err()
+                  , ParseError { kind: ErrorKind::TooManyEntries { max: 256 }, at: 256 * 7 });
+
+        let union = vec!["/a"; 17].join(" & ");
+        assert_eq!( name_tree(&union, &ParseOptions::untrusted()).unwrap_err()
+                  , ParseError { kind: ErrorKind::UnionTooWide { max: 16 }, at: 16 * 5 });
+        let group = format!("({})", vec!["/a"; 16].join(" & "));
+        let alts = vec![group; 5].join(" | ");
+        assert_eq!( name_tree(&alts, &ParseOptions::untrusted()).unwrap_err().kind
+                  , ErrorKind::TooManyLeaves { max: 64 });
+    }
+
+    #[test]
+    fn union_width_and_leaf_limits() {
+        let options = ParseOptions { max_union_width: Some(2)
+                                   , max_leaves: Some(3)
+                                   , ..ParseOptions::default() };
+        assert_eq!( name_tree("/a & /b & /c", &options)
+                  , Err(ParseError { kind: ErrorKind::UnionTooWide { max: 2 }, at: 10 }));
+        // directly nested unions count towards the outer union's width...
+        assert_eq!( name_tree("(/a & /b) & /c", &options)
+                  , Err(ParseError { kind: ErrorKind::UnionTooWide { max: 2 }, at: 12 }));
+        assert_eq!( name_tree("2 * (/a & /b) & /c", &options).unwrap_err().kind
+                  , ErrorKind::UnionTooWide { max: 2 });
+        // ...but unions within alternations don't
+        assert!(name_tree("(/a | /b) & /c", &options).is_ok());
+        assert!(name_tree("/a & /b | /c", &options).is_ok());
+
+        assert_eq!( name_tree("/a | /b | /c | /d", &options)
+                  , Err(ParseError { kind: ErrorKind::TooManyLeaves { max: 3 }, at: 15 }));
+        assert!(name_tree("/a | ~ | ! | $ | /b", &options).is_ok());
+        // the limit applies to each dentry's destination, not the whole dtab
+        assert_eq!( dtab("/x => /a | /b | /c;\n/y => /d | /e;", &options).unwrap().len()
+                  , 2);
+        assert_eq!( dtab("/x => /a | /b;\n/y => /c & /d & /e;", &options).unwrap_err()
+                  , ParseError { kind: ErrorKind::UnionTooWide { max: 2 }, at: 31 });
+
+        let unlimited = ParseOptions::default();
+        assert_eq!( name_tree(&vec!["/a"; 100].join(" & "), &unlimited).unwrap()
+                        .complexity().union_width
+                  , 100);
+    }
+
+    #[test]
+    fn content_hash() {
+        let options = ParseOptions::default();
+        let parse = |s| dtab(s, &options).unwrap();
+        let hash = parse("/svc => 0.9 * /a & 0.1 * /b;\n/srv => /c;").content_hash();
+        assert_eq!(hash, parse("/svc=>0.9*/a&0.1*/b;/srv=>/c").content_hash());
+        assert_eq!(hash, parse(" /svc =>\t0.9 * (/a) & 0.1 * /b ; /srv => /c ;").content_hash());
+        assert!(hash != parse("/srv => /c; /svc => 0.9 * /a & 0.1 * /b").content_hash());
+        assert!(hash != parse("/svc => 0.9 * /a & 0.1 * /b").content_hash());
+        assert_eq!(Dtab::new().content_hash(), 0xcbf2_9ce4_8422_2325);
+    }
+
+    #[test]
+    fn highlight_tokens() {
+        let input = "/a/*/b=>/c;\n/d => (/e & é)@";
+        let tokens = highlight(input).into_iter()
+            .map(|(span, kind)| (&input[span.start..span.end], kind))
+            .collect::<Vec<_>>();
+        assert_eq!(tokens, vec![ ("/a/", TokenKind::Prefix)
+                               , ("*", TokenKind::Wildcard)
+                               , ("/b", TokenKind::Prefix)
+                               , ("=>", TokenKind::Punctuation)
+                               , ("/c", TokenKind::Path)
+                               , (";", TokenKind::Punctuation)
+                               , ("/d", TokenKind::Prefix)
+                               , ("=>", TokenKind::Punctuation)
+                               , ("(", TokenKind::Punctuation)
+                               , ("/e", TokenKind::Path)
+                               , ("&", TokenKind::Operator)
+                               , ("é", TokenKind::Invalid)
+                               , (")", TokenKind::Punctuation)
+                               , ("@", TokenKind::Invalid) ]);
+        assert_eq!(highlight("  "), vec![]);
+    }
+
+    #[test]
+    fn from_pairs() {
+        use std::convert::TryFrom;
+        let pairs = vec![ ("/iceCreamStore".to_string(), "/humphrys | /smitten".to_string())
+                        , ("/smitten".to_string(), "/USA/CA/SF/Harrison/2790".to_string()) ];
+        assert_eq!( Dtab::try_from(pairs)
+                  , Ok(dtab![
+                        "/iceCreamStore" => NameTree::from("/humphrys") | "/smitten";
+                        "/smitten" => "/USA/CA/SF/Harrison/2790";
+                    ]));
+
+        let dtab = dtab![
+            "/svc/*" => NameTree::from("/a") & "/b" | "~";
+            "/smitten" => "/USA/CA/SF/Harrison/2790";
+        ];
+        assert_eq!(Dtab::try_from(Vec::<(String, String)>::from(&dtab)), Ok(dtab));
+
+        let err = Dtab::try_from(vec![("/a", "/b"), ("/c", "/d"), ("e", "/f")])
+            .unwrap_err();
+        assert_eq!(err.entry(), 2);
+        assert_eq!( &err.to_string()
+                  , "invalid prefix in entry 2: path \"e\" does not begin with '/'");
+
+        assert_eq!( Dtab::try_from(vec![("/a", "/b &")])
+                  , Err(PairError::InvalidDestination {
+                        entry: 0
+                      , error: ParseError {
+                            kind: ErrorKind::UnexpectedEof { expected: "a name tree" }
+                          , at: 4 }
+                    }));
+    }
+}
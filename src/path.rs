@@ -1,32 +1,464 @@
-use std::{convert, fmt, iter, ops};
+//! Finagle/linkerd paths.
+//!
+//! A [`Path`] is a sequence of byte-string labels, written as `/`-separated
+//! text such as `/svc/users`. This follows Finagle's [`Path`][scala]: labels
+//! may not be empty, and bytes outside of the set of "showable" characters
+//! (ASCII letters and digits, and `_:.#$%-`) are written as `\xNN` escapes.
+//!
+//! Which labels are accepted is controlled by a [`LabelPolicy`]. By default,
+//! labels are limited to showable ASCII characters; the [`Utf8`] policy
+//! additionally accepts non-ASCII UTF-8 labels, which are stored as their raw
+//! bytes and escaped whenever the path is displayed, and the [`Finagle`]
+//! policy accepts exactly what Finagle's `Path.read` does: showable
+//! characters, and `\xNN` escapes for any other byte. The [`Any`] policy
+//! accepts everything the others do, so it reads back the text form of
+//! any path.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::path::{Path, LabelPolicy};
+//!
+//! let path: Path = "/svc/users".parse().unwrap();
+//! assert_eq!(path.len(), 2);
+//! assert_eq!(&path.to_string(), "/svc/users");
+//!
+//! // non-ASCII labels are rejected unless the UTF-8 policy is used...
+//! assert!(Path::read("/svc/café").is_err());
+//!
+//! // ...in which case they're escaped on output.
+//! let path = Path::read_with("/svc/café", LabelPolicy::Utf8).unwrap();
+//! assert_eq!(&path.to_string(), "/svc/caf\\xc3\\xa9");
+//! ```
+//!
+//! [`Path`]: struct.Path.html
+//! [`LabelPolicy`]: enum.LabelPolicy.html
+//! [`Utf8`]: enum.LabelPolicy.html#variant.Utf8
+//! [`Finagle`]: enum.LabelPolicy.html#variant.Finagle
+//! [`Any`]: enum.LabelPolicy.html#variant.Any
+//! [scala]: https://github.com/twitter/finagle/blob/develop/finagle-core/src/main/scala/com/twitter/finagle/Path.scala
+use std::{error, fmt, iter, ops, str};
 
-pub struct Path<'bytes>(pub Vec<&'bytes [u8]>);
+use super::parse::Span;
 
-impl<'bytes> Path<'bytes> {
-    pub fn append<'b, T>(&mut self, path: T) -> Result<&mut Self, PathError>
-    where T: convert::Into<&'b [u8]>
-        , 'b: 'bytes {
-        self.0.push(path.into());
+/// Returns true if `b` may appear unescaped in the text form of a label.
+#[inline]
+pub const fn is_showable(b: u8) -> bool {
+    matches!(b, b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z'
+              | b'_' | b':' | b'.' | b'#' | b'$' | b'%' | b'-')
+}
+
+/// Policy controlling which characters are permitted in path labels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LabelPolicy {
+    /// Labels may only contain showable ASCII characters.
+    ///
+    /// This is the default.
+    Strict
+  , /// Labels may also contain non-ASCII UTF-8 characters and `\xNN` byte
+    /// escapes, which must decode to characters this policy allows. Labels
+    /// are stored as raw bytes and escaped on output, so the text form of a
+    /// path is always ASCII.
+    Utf8
+  , /// Labels may contain showable ASCII characters and `\xNN` byte escapes,
+    /// as in Finagle. Labels given as raw bytes may contain any bytes.
+    Finagle
+  , /// Labels may contain anything another policy accepts: showable ASCII
+    /// characters, non-ASCII UTF-8 characters, and `\xNN` escapes of any
+    /// byte. Labels given as raw bytes may contain any bytes.
+    ///
+    /// This reads the text form of every path, however its labels were
+    /// read, so it's used to deserialize paths, and to read the leaves of
+    /// name trees, which keep the text they were parsed from.
+    Any
+}
+
+impl Default for LabelPolicy {
+    #[inline] fn default() -> Self { LabelPolicy::Strict }
+}
+
+impl LabelPolicy {
+    /// Returns true if `ch` may appear unescaped in a label under this
+    /// policy.
+    #[inline] pub fn allows(&self, ch: char) -> bool {
+        match *self {
+            LabelPolicy::Strict | LabelPolicy::Finagle =>
+                ch.is_ascii() && is_showable(ch as u8)
+          , LabelPolicy::Utf8 | LabelPolicy::Any => !ch.is_ascii() || is_showable(ch as u8)
+        }
+    }
+
+    /// Validate a raw (unescaped) label under this policy.
+    pub fn check_label(&self, label: &[u8]) -> Result<(), PathError> {
+        if label.is_empty() {
+            return Err(PathError::EmptyLabel)
+        }
+        if let LabelPolicy::Finagle | LabelPolicy::Any = *self {
+            return Ok(())
+        }
+        let text = str::from_utf8(label)
+            .map_err(|e| PathError::InvalidUtf8 {
+                at: e.valid_up_to()
+              , elem: String::from_utf8_lossy(label).into_owned()
+            })?;
+        match text.char_indices().find(|&(_, ch)| !self.allows(ch)) {
+            Some((at, ch)) => Err(PathError::InvalidCharacter {
+                ch, at, elem: text.to_string()
+            })
+          , None => Ok(())
+        }
+    }
+
+    /// Parse the text form of a single label, decoding escapes.
+    pub(crate) fn read_label(&self, text: &str) -> Result<Vec<u8>, PathError> {
+        let mut label = Vec::with_capacity(text.len());
+        self.scan_label(text, |bytes| label.extend_from_slice(bytes))?;
+        Ok(label)
+    }
+
+    /// Validate the text form of a single label, decoding escapes, and pass
+    /// each decoded piece of the label to `push`.
+    ///
+    /// This lets a label be checked without allocating it.
+    pub(crate) fn scan_label<F>(&self, text: &str, mut push: F) -> Result<(), PathError>
+    where F: FnMut(&[u8]) {
+        if text.is_empty() {
+            return Err(PathError::EmptyLabel)
+        }
+        let mut chars = text.char_indices();
+        // under the UTF-8 policy, escaped bytes must decode to characters
+        // the policy allows, just as a raw label's bytes must, so they're
+        // collected until they make up a whole character.
+        let mut escaped = EscapedChar { bytes: [0; 4], len: 0, at: 0 };
+        while let Some((at, ch)) = chars.next() {
+            if ch == '\\' && *self != LabelPolicy::Strict {
+                let byte = text.get(at + 1..at + 4)
+                    .filter(|e| e.starts_with('x') &&
+                                e[1..].bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|e| u8::from_str_radix(&e[1..], 16).ok())
+                    .ok_or_else(|| PathError::InvalidEscape {
+                        at, elem: text.to_string()
+                    })?;
+                chars.nth(2);
+                if *self != LabelPolicy::Utf8 {
+                    push(&[byte]);
+                    continue
+                }
+                if escaped.len == 0 {
+                    escaped.at = at;
+                }
+                escaped.bytes[escaped.len] = byte;
+                escaped.len += 1;
+                let width = match escaped.bytes[0] {
+                    0x00..=0x7f => 1
+                  , 0xc0..=0xdf => 2
+                  , 0xe0..=0xef => 3
+                  , 0xf0..=0xf7 => 4
+                  , _ => 0
+                };
+                if escaped.len < width {
+                    continue
+                }
+                let bytes = &escaped.bytes[..escaped.len];
+                let ch = str::from_utf8(bytes).ok()
+                    .and_then(|decoded| decoded.chars().next())
+                    .ok_or_else(|| PathError::InvalidUtf8 {
+                        at: escaped.at, elem: text.to_string()
+                    })?;
+                if !self.allows(ch) {
+                    return Err(PathError::InvalidCharacter {
+                        ch, at: escaped.at, elem: text.to_string()
+                    })
+                }
+                push(bytes);
+                escaped.len = 0;
+            } else if escaped.len > 0 {
+                break
+            } else if self.allows(ch) {
+                let mut buf = [0; 4];
+                push(ch.encode_utf8(&mut buf).as_bytes());
+            } else {
+                return Err(PathError::InvalidCharacter {
+                    ch, at, elem: text.to_string()
+                })
+            }
+        }
+        if escaped.len > 0 {
+            // the escapes ended part way through a character.
+            return Err(PathError::InvalidUtf8 { at: escaped.at, elem: text.to_string() })
+        }
+        Ok(())
+    }
+}
+
+/// A character being decoded from `\xNN` escapes.
+struct EscapedChar {
+    bytes: [u8; 4]
+  , len: usize
+  , /// The position of its first escape.
+    at: usize
+}
+
+/// A path: a sequence of non-empty labels.
+///
+/// Paths own their labels, rather than borrowing them from the text they
+/// were read from: labels written with `\xNN` escapes don't appear in that
+/// text as raw bytes, so there's nothing to borrow. The labels are private
+/// so that every label in a path has been checked by some [`LabelPolicy`];
+/// use [`from_labels_with`] and [`into_labels`] to convert to and from raw
+/// labels.
+///
+/// [`LabelPolicy`]: enum.LabelPolicy.html
+/// [`from_labels_with`]: #method.from_labels_with
+/// [`into_labels`]: #method.into_labels
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path(Vec<Vec<u8>>);
+
+impl Path {
+    /// Returns the empty path, `/`.
+    #[inline] pub fn empty() -> Self { Path(Vec::new()) }
+
+    /// Parse a path from its text form, using the strict label policy.
+    #[inline] pub fn read(s: &str) -> Result<Self, PathError> {
+        Path::read_with(s, LabelPolicy::Strict)
+    }
+
+    /// Parse a path from its text form, validating labels with the given
+    /// [`LabelPolicy`].
+    ///
+    /// [`LabelPolicy`]: enum.LabelPolicy.html
+    pub fn read_with(s: &str, policy: LabelPolicy) -> Result<Self, PathError> {
+        if !s.starts_with('/') {
+            return Err(PathError::MissingLeadingSlash { path: s.to_string() })
+        }
+        if s == "/" {
+            return Ok(Path::empty())
+        }
+        s[1..].split('/')
+            .map(|label| policy.read_label(label))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Path)
+    }
+
+    /// Construct a path from labels which are already known to be valid.
+    #[inline] pub(crate) fn from_labels(labels: Vec<Vec<u8>>) -> Self {
+        Path(labels)
+    }
+
+    /// Construct a path from raw labels, validating each of them with the
+    /// given policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::path::{Path, LabelPolicy};
+    ///
+    /// let labels: Vec<&[u8]> = vec![b"svc", b"caf\xc3\xa9"];
+    /// let path = Path::from_labels_with(labels, LabelPolicy::Utf8).unwrap();
+    /// assert_eq!(&path.to_string(), "/svc/caf\\xc3\\xa9");
+    /// assert_eq!(path.into_labels(), vec![b"svc".to_vec(), "café".into()]);
+    ///
+    /// let labels: Vec<&[u8]> = vec![b"svc", b""];
+    /// assert!(Path::from_labels_with(labels, LabelPolicy::Utf8).is_err());
+    /// ```
+    pub fn from_labels_with<I>(labels: I, policy: LabelPolicy)
+                               -> Result<Self, PathError>
+    where I: IntoIterator
+        , I::Item: AsRef<[u8]> {
+        let mut path = Path::empty();
+        for label in labels {
+            path.append_with(label, policy)?;
+        }
+        Ok(path)
+    }
+
+    /// Consume this path, returning its raw labels.
+    #[inline] pub fn into_labels(self) -> Vec<Vec<u8>> { self.0 }
+
+    /// Append a raw label to this path, validating it with the strict label
+    /// policy.
+    #[inline]
+    pub fn append<T>(&mut self, label: T) -> Result<&mut Self, PathError>
+    where T: AsRef<[u8]> {
+        self.append_with(label, LabelPolicy::Strict)
+    }
+
+    /// Append a raw label to this path, validating it with the given policy.
+    pub fn append_with<T>(&mut self, label: T, policy: LabelPolicy)
+                          -> Result<&mut Self, PathError>
+    where T: AsRef<[u8]> {
+        let label = label.as_ref();
+        policy.check_label(label)?;
+        self.0.push(label.to_vec());
         Ok(self)
     }
+
+    /// Returns an iterator over the raw labels in this path.
+    #[inline] pub fn labels(&self) -> Labels<'_> {
+        Labels(self.0.iter())
+    }
+
+    /// Returns the number of labels in this path.
+    #[inline] pub fn len(&self) -> usize { self.0.len() }
+
+    /// Returns true if this is the empty path.
+    #[inline] pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Returns this path followed by the labels of `suffix`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::path::Path;
+    ///
+    /// let srv = Path::read("/srv").unwrap();
+    /// let residual = Path::read("/users/get").unwrap();
+    /// assert_eq!(srv.concat(&residual), Path::read("/srv/users/get").unwrap());
+    /// assert_eq!(srv.concat(&Path::empty()), srv);
+    /// ```
+    pub fn concat(&self, suffix: &Path) -> Path {
+        let mut labels = Vec::with_capacity(self.len() + suffix.len());
+        labels.extend(self.0.iter().cloned());
+        labels.extend(suffix.0.iter().cloned());
+        Path(labels)
+    }
+
+    /// Returns the rest of this path after `prefix`, or `None` if this path
+    /// doesn't begin with every label of `prefix`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::path::Path;
+    ///
+    /// let path = Path::read("/svc/users/get").unwrap();
+    /// let svc = Path::read("/svc").unwrap();
+    /// assert_eq!(path.strip_prefix(&svc), Some(Path::read("/users/get").unwrap()));
+    /// assert_eq!(path.strip_prefix(&path), Some(Path::empty()));
+    /// assert_eq!(svc.strip_prefix(&path), None);
+    /// ```
+    pub fn strip_prefix(&self, prefix: &Path) -> Option<Path> {
+        if self.0.starts_with(&prefix.0) {
+            Some(Path(self.0[prefix.len()..].to_vec()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Iterator over the raw labels of a [`Path`].
+///
+/// [`Path`]: struct.Path.html
+#[derive(Clone, Debug)]
+pub struct Labels<'a>(::std::slice::Iter<'a, Vec<u8>>);
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+    #[inline] fn next(&mut self) -> Option<&'a [u8]> {
+        self.0.next().map(Vec::as_slice)
+    }
+    #[inline] fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Labels<'a> {
+    #[inline] fn next_back(&mut self) -> Option<&'a [u8]> {
+        self.0.next_back().map(Vec::as_slice)
+    }
 }
 
-impl<'a, 'b, R> ops::Div<R> for &'a mut Path<'a>
-where R: convert::Into<&'b [u8]>
-    , 'b: 'a
-    {
+impl<'a> ExactSizeIterator for Labels<'a> {}
+
+impl str::FromStr for Path {
+    type Err = PathError;
+    #[inline] fn from_str(s: &str) -> Result<Self, PathError> {
+        Path::read(s)
+    }
+}
+
+/// Paths are debugged in their text form, as `Path(/a/b)`.
+impl fmt::Debug for Path {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Path({})", self)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("/")
+        }
+        for label in &self.0 {
+            f.write_str("/")?;
+            write_label(f, label)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the text form of a raw label, escaping any bytes that aren't
+/// showable.
+pub(crate) fn write_label(f: &mut fmt::Formatter, label: &[u8]) -> fmt::Result {
+    for &b in label {
+        if is_showable(b) {
+            write!(f, "{}", b as char)?;
+        } else {
+            write!(f, "\\x{:02x}", b)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes text through to `W`, replacing each non-ASCII character with
+/// `\xNN` escapes of its UTF-8 bytes, as `write_label` would write them.
+///
+/// This keeps the text form of a name tree ASCII, whose leaves are the
+/// text of paths rather than raw labels.
+pub(crate) struct EscapeNonAscii<W>(pub(crate) W);
+
+impl<W> fmt::Write for EscapeNonAscii<W>
+where W: fmt::Write {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut rest = s;
+        while let Some(at) = rest.find(|ch: char| !ch.is_ascii()) {
+            self.0.write_str(&rest[..at])?;
+            let len = rest[at..].chars().next().map_or(0, char::len_utf8);
+            for b in rest[at..at + len].bytes() {
+                write!(self.0, "\\x{:02x}", b)?;
+            }
+            rest = &rest[at + len..];
+        }
+        self.0.write_str(rest)
+    }
+}
+
+/// Returns `text` with each non-ASCII character replaced with `\xNN`
+/// escapes of its UTF-8 bytes.
+pub(crate) fn escape_non_ascii(text: &str) -> String {
+    use std::fmt::Write;
+    let mut escaped = EscapeNonAscii(String::with_capacity(text.len()));
+    escaped.write_str(text).expect("writing to a string can't fail");
+    escaped.0
+}
+
+impl<R> ops::Div<R> for Path
+where R: AsRef<[u8]> {
     type Output = Self;
-    fn div(self, rhs: R) -> Self {
+    /// Append a label to this path.
+    ///
+    /// # Panics
+    ///
+    /// If the label is not valid under the strict label policy.
+    fn div(mut self, rhs: R) -> Self {
         self.append(rhs)
-            .expect("Error appending to path from iterator")
+            .expect("Error appending to path");
+        self
     }
-
 }
 
-impl <'a, 'b, T> iter::Extend<T> for Path<'a>
-where T: convert::Into<&'b [u8]>
-    , 'b: 'a
-    {
+impl<T> iter::Extend<T> for Path
+where T: AsRef<[u8]> {
     fn extend<I>(&mut self, iter: I)
     where I: iter::IntoIterator<Item=T> {
         for elem in iter {
@@ -36,19 +468,218 @@ where T: convert::Into<&'b [u8]>
     }
 }
 
-pub enum PathError<'bytes> {
-    InvalidCharacter { ch: char, at: usize, elem: &'bytes str }
+/// An error indicating that a path element was invalid.
+///
+/// `PathError`s own the offending element, so that they may outlive the
+/// input they were produced from and be passed up the call stack with `?`
+/// or boxed as a `Box<Error + Send + Sync>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathError {
+    InvalidCharacter { ch: char, at: usize, elem: String }
+  , InvalidEscape { at: usize, elem: String }
+  , InvalidUtf8 { at: usize, elem: String }
+  , EmptyLabel
+  , MissingLeadingSlash { path: String }
 }
 
-impl<'bytes> fmt::Debug for PathError<'bytes> {
+impl fmt::Display for PathError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            PathError::InvalidCharacter { ch, at, elem } =>
+            PathError::InvalidCharacter { ch, at, ref elem } =>
               write!( f
-                    , "Invalid character {ch:?} at position {at} in {elem:?}.`"
+                    , "invalid character {ch:?} at position {at} in {elem:?}"
                     , ch = ch
                     , at = at
                     , elem = elem )
+          , PathError::InvalidEscape { at, ref elem } =>
+              write!( f, "invalid escape at position {} in {:?}", at, elem)
+          , PathError::InvalidUtf8 { at, ref elem } =>
+              write!( f, "invalid UTF-8 at position {} in {:?}", at, elem)
+          , PathError::EmptyLabel => f.write_str("path labels may not be empty")
+          , PathError::MissingLeadingSlash { ref path } =>
+              write!(f, "path {:?} does not begin with '/'", path)
+        }
+    }
+}
+
+impl PathError {
+    /// Returns the span of the text responsible for this error within
+    /// `path`, the text form of the path or prefix which produced it.
+    ///
+    /// `PathError`s only record an offset within the offending label; this
+    /// locates that label within the whole path. Returns `None` if the
+    /// error could not have been produced from `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::Prefix;
+    /// use dtab::parse::Span;
+    ///
+    /// let error = Prefix::read("/svc/*/us?ers").unwrap_err();
+    /// assert_eq!(error.span_in("/svc/*/us?ers"), Some(Span { start: 9, end: 10 }));
+    /// ```
+    pub fn span_in(&self, path: &str) -> Option<Span> {
+        if let PathError::MissingLeadingSlash { .. } = *self {
+            let end = path.chars().next().map_or(0, char::len_utf8);
+            return Some(Span { start: 0, end })
+        }
+        if !path.starts_with('/') {
+            return None
+        }
+        let mut start = 1;
+        for label in path[1..].split('/') {
+            let span = match *self {
+                PathError::EmptyLabel if label.is_empty() =>
+                    Some(Span { start: start - 1, end: start })
+              , PathError::InvalidCharacter { ch, at, ref elem } if elem == label =>
+                    Some(Span { start: start + at, end: start + at + ch.len_utf8() })
+              , PathError::InvalidEscape { at, ref elem } if elem == label =>
+                    // an escape is four bytes long, as in `\x2f`.
+                    Some(Span { start: start + at
+                              , end: start + (at + 4).min(label.len()) })
+              , PathError::InvalidUtf8 { at, ref elem } if elem == label =>
+                    Some(Span { start: start + at, end: start + label.len() })
+              , _ => None
+            };
+            if span.is_some() {
+                return span
+            }
+            start += label.len() + 1;
+        }
+        None
+    }
+}
+
+impl error::Error for PathError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn path_error_display() {
+        let err = PathError::InvalidCharacter {
+            ch: '?', at: 3, elem: "foo?bar".to_string()
+        };
+        assert_eq!( &err.to_string()
+                  , "invalid character '?' at position 3 in \"foo?bar\"");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn path_error_is_boxable() {
+        fn fallible() -> Result<(), Box<dyn Error + Send + Sync>> {
+            Path::read("/foo bar")?;
+            Ok(())
+        }
+        assert!(fallible().is_err());
+    }
+
+    #[test]
+    fn read_strict() {
+        let path = Path::read("/svc/foo-bar/v1.2").unwrap();
+        assert_eq!( path.labels().collect::<Vec<_>>()
+                  , vec![&b"svc"[..], b"foo-bar", b"v1.2"]);
+        assert_eq!(Path::read("/").unwrap(), Path::empty());
+        assert_eq!( Path::read("svc")
+                  , Err(PathError::MissingLeadingSlash {
+                        path: "svc".to_string()
+                    }));
+        assert_eq!(Path::read("/svc//foo"), Err(PathError::EmptyLabel));
+        assert_eq!( Path::read("/svc/föo")
+                  , Err(PathError::InvalidCharacter {
+                        ch: 'ö', at: 1, elem: "föo".to_string()
+                    }));
+        assert!(Path::read("/svc/f\\x6fo").is_err());
+    }
+
+    #[test]
+    fn read_utf8() {
+        let path = Path::read_with("/svc/föo", LabelPolicy::Utf8).unwrap();
+        assert_eq!( path.labels().nth(1).unwrap()
+                  , "föo".as_bytes());
+        assert_eq!(&path.to_string(), "/svc/f\\xc3\\xb6o");
+        assert!(path.to_string().is_ascii());
+
+        let escaped = Path::read_with("/svc/f\\xc3\\xb6o", LabelPolicy::Utf8)
+            .unwrap();
+        assert_eq!(escaped, path);
+
+        assert_eq!( Path::read_with("/svc/f\\xzz", LabelPolicy::Utf8)
+                  , Err(PathError::InvalidEscape {
+                        at: 1, elem: "f\\xzz".to_string()
+                    }));
+        assert!(Path::read_with("/svc/foo bar", LabelPolicy::Utf8).is_err());
+
+        // escaped bytes are held to the same rules as raw ones.
+        let mut raw = Path::empty();
+        assert_eq!( raw.append_with(&[0xff][..], LabelPolicy::Utf8).unwrap_err()
+                  , PathError::InvalidUtf8 { at: 0, elem: "\u{fffd}".to_string() });
+        assert_eq!( Path::read_with("/\\xff", LabelPolicy::Utf8)
+                  , Err(PathError::InvalidUtf8 { at: 0, elem: "\\xff".to_string() }));
+        assert_eq!( Path::read_with("/f\\xc3o", LabelPolicy::Utf8)
+                  , Err(PathError::InvalidUtf8 { at: 1, elem: "f\\xc3o".to_string() }));
+        assert_eq!( Path::read_with("/f\\xc3", LabelPolicy::Utf8)
+                  , Err(PathError::InvalidUtf8 { at: 1, elem: "f\\xc3".to_string() }));
+        assert_eq!( Path::read_with("/a\\x2fb", LabelPolicy::Utf8)
+                  , Err(PathError::InvalidCharacter {
+                        ch: '/', at: 1, elem: "a\\x2fb".to_string()
+                    }));
+        assert_eq!( Path::read_with("/\\x61", LabelPolicy::Utf8)
+                  , Path::read("/a"));
+        assert_eq!( Path::read_with("/\\xff", LabelPolicy::Finagle).unwrap().labels().next()
+                  , Some(&[0xff][..]));
+    }
+
+    #[test]
+    fn read_any() {
+        let any = |s: &str| Path::read_with(s, LabelPolicy::Any);
+        assert_eq!(any("/svc/föo"), Path::read_with("/svc/föo", LabelPolicy::Utf8));
+        assert_eq!(any("/\\xff\\x2f"), Path::read_with("/\\xff\\x2f", LabelPolicy::Finagle));
+        assert_eq!(any("/f\\xc3\\xb6o"), any("/föo"));
+        for policy in &[LabelPolicy::Strict, LabelPolicy::Utf8, LabelPolicy::Finagle] {
+            let path = Path::read_with("/a/b.c", *policy).unwrap();
+            assert_eq!(any(&path.to_string()), Ok(path));
+        }
+        let mut raw = Path::empty();
+        raw.append_with(&[0xff, b'/'][..], LabelPolicy::Any).unwrap();
+        assert_eq!(any(&raw.to_string()), Ok(raw));
+        assert!(any("/svc/foo bar").is_err());
+        assert!(any("/svc/f\\xzz").is_err());
+    }
+
+    #[test]
+    fn append() {
+        let mut path = Path::empty();
+        path.append("svc").unwrap();
+        assert!(path.append("foo/bar").is_err());
+        assert!(path.append("föo").is_err());
+        path.append_with("föo", LabelPolicy::Utf8).unwrap();
+        assert!(path.append_with(&[0xff][..], LabelPolicy::Utf8).is_err());
+        assert_eq!(path.len(), 2);
+
+        let path = Path::empty() / "svc" / "users";
+        assert_eq!(&path.to_string(), "/svc/users");
+    }
+
+    #[test]
+    fn concat_strip_prefix() {
+        let path = |s| Path::read(s).unwrap();
+        let full = path("/svc/users/get");
+        for len in 0..=full.len() {
+            let prefix = Path::from_labels(full.0[..len].to_vec());
+            let rest = full.strip_prefix(&prefix).unwrap();
+            assert_eq!(rest.len(), full.len() - len);
+            assert_eq!(prefix.concat(&rest), full);
         }
+        assert_eq!(full.strip_prefix(&path("/svc/user")), None);
+        assert_eq!(full.strip_prefix(&path("/users")), None);
+        assert_eq!(Path::empty().concat(&Path::empty()), Path::empty());
     }
 }
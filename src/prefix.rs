@@ -0,0 +1,568 @@
+//! Dentry prefixes.
+//!
+//! A [`Prefix`] is the left-hand side of a [`Dentry`]. It is like a
+//! [`Path`], except that any of its elements may be the wildcard `*`, which
+//! matches any single label.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::prefix::{Prefix, Elem};
+//!
+//! let prefix: Prefix = "/svc/*/users".parse().unwrap();
+//! assert_eq!(prefix.len(), 3);
+//! assert_eq!(prefix.elems()[1], Elem::AnyElem);
+//! assert_eq!(&prefix.to_string(), "/svc/*/users");
+//! ```
+//!
+//! Prefixes are serialized as strings. To include a `Prefix` in a
+//! configuration struct, use this module with `serde(with = ...)`:
+//!
+//! ```
+//! # extern crate dtab;
+//! # extern crate serde;
+//! # extern crate serde_json;
+//! # #[macro_use] extern crate serde_derive;
+//! use dtab::Prefix;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Route {
+//!     #[serde(with = "dtab::prefix")]
+//!     prefix: Prefix
+//! }
+//! # fn main() {
+//! let route: Route = serde_json::from_str(r#"{"prefix": "/svc/*"}"#).unwrap();
+//! assert_eq!(&route.prefix.to_string(), "/svc/*");
+//! # }
+//! ```
+//!
+//! [`Prefix`]: struct.Prefix.html
+//! [`Dentry`]: ../struct.Dentry.html
+//! [`Path`]: ../path/struct.Path.html
+use std::{convert, error, fmt, str};
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::Serializer;
+
+use super::{Dentry, NameTree};
+use super::path::{self, LabelPolicy, Path, PathError};
+
+/// An element of a [`Prefix`].
+///
+/// [`Prefix`]: struct.Prefix.html
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Elem {
+    /// A label, which matches only an identical path label.
+    Label(Vec<u8>)
+  , /// The wildcard `*`, which matches any label.
+    AnyElem
+}
+
+impl fmt::Debug for Elem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Elem::Label(ref label) => {
+                f.write_str("Label(")?;
+                path::write_label(f, label)?;
+                f.write_str(")")
+            }
+          , Elem::AnyElem => f.write_str("AnyElem")
+        }
+    }
+}
+
+impl fmt::Display for Elem {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Elem::Label(ref label) => path::write_label(f, label)
+          , Elem::AnyElem => f.write_str("*")
+        }
+    }
+}
+
+/// The prefix of a [`Dentry`]: a sequence of labels and wildcards.
+///
+/// [`Dentry`]: ../struct.Dentry.html
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Prefix(Vec<Elem>);
+
+impl Prefix {
+    /// Returns the empty prefix, `/`, which matches every path.
+    #[inline] pub fn empty() -> Self { Prefix(Vec::new()) }
+
+    /// Construct a prefix from its elements.
+    ///
+    /// Returns an error if any label is empty. Labels may otherwise contain
+    /// any bytes; use [`read`] to validate a prefix's text form.
+    ///
+    /// [`read`]: #method.read
+    pub fn new(elems: Vec<Elem>) -> Result<Self, PathError> {
+        if elems.contains(&Elem::Label(Vec::new())) {
+            return Err(PathError::EmptyLabel)
+        }
+        Ok(Prefix(elems))
+    }
+
+    /// Parse a prefix from its text form, using the strict label policy.
+    #[inline] pub fn read(s: &str) -> Result<Self, PathError> {
+        Prefix::read_with(s, LabelPolicy::Strict)
+    }
+
+    /// Parse a prefix from its text form, validating labels with the given
+    /// [`LabelPolicy`].
+    ///
+    /// [`LabelPolicy`]: ../path/enum.LabelPolicy.html
+    pub fn read_with(s: &str, policy: LabelPolicy) -> Result<Self, PathError> {
+        if !s.starts_with('/') {
+            return Err(PathError::MissingLeadingSlash { path: s.to_string() })
+        }
+        if s == "/" {
+            return Ok(Prefix::empty())
+        }
+        s[1..].split('/')
+            .map(|elem| match elem {
+                "*" => Ok(Elem::AnyElem)
+              , label => policy.read_label(label).map(Elem::Label)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Prefix)
+    }
+
+    /// Check that `s` is the text form of a prefix under `policy`, returning
+    /// the same error as [`read_with`] would, without building the prefix.
+    ///
+    /// [`read_with`]: #method.read_with
+    pub(crate) fn validate(s: &str, policy: LabelPolicy) -> Result<(), PathError> {
+        if !s.starts_with('/') {
+            return Err(PathError::MissingLeadingSlash { path: s.to_string() })
+        }
+        if s == "/" {
+            return Ok(())
+        }
+        s[1..].split('/')
+            .filter(|&elem| elem != "*")
+            .try_for_each(|label| policy.scan_label(label, |_| {}))
+    }
+
+    /// Construct a prefix from labels which have already been split apart,
+    /// validating each with the strict label policy. A segment of `*` is a
+    /// wildcard.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::Prefix;
+    ///
+    /// let prefix = Prefix::from_segments(&["svc", "*", "users"]).unwrap();
+    /// assert_eq!(prefix, Prefix::read("/svc/*/users").unwrap());
+    /// assert!(Prefix::from_segments(&["svc", "foo/bar"]).is_err());
+    /// ```
+    #[inline]
+    pub fn from_segments<S>(segments: &[S]) -> Result<Self, PathError>
+    where S: AsRef<str> {
+        Prefix::from_segments_with(segments, LabelPolicy::Strict)
+    }
+
+    /// Construct a prefix from labels which have already been split apart,
+    /// validating each with the given [`LabelPolicy`].
+    ///
+    /// Unlike [`read_with`], segments are raw labels, so escape sequences are
+    /// not interpreted.
+    ///
+    /// [`LabelPolicy`]: ../path/enum.LabelPolicy.html
+    /// [`read_with`]: #method.read_with
+    pub fn from_segments_with<S>(segments: &[S], policy: LabelPolicy)
+                                 -> Result<Self, PathError>
+    where S: AsRef<str> {
+        segments.iter()
+            .map(|segment| match segment.as_ref() {
+                "*" => Ok(Elem::AnyElem)
+              , label => policy.check_label(label.as_bytes())
+                    .map(|_| Elem::Label(label.as_bytes().to_vec()))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Prefix)
+    }
+
+    /// Returns the elements of this prefix.
+    #[inline] pub fn elems(&self) -> &[Elem] { &self.0 }
+
+    /// Consume this prefix, returning its elements.
+    #[inline] pub fn into_elems(self) -> Vec<Elem> { self.0 }
+
+    /// Returns the number of elements in this prefix.
+    #[inline] pub fn len(&self) -> usize { self.0.len() }
+
+    /// Returns true if this is the empty prefix.
+    #[inline] pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Returns true if any element of this prefix is a wildcard.
+    #[inline] pub fn has_wildcards(&self) -> bool {
+        self.0.contains(&Elem::AnyElem)
+    }
+
+    /// Returns this prefix as a [`Path`], if it contains no wildcards.
+    ///
+    /// [`Path`]: ../path/struct.Path.html
+    pub fn to_path(&self) -> Option<Path> {
+        self.0.iter()
+            .map(|elem| match *elem {
+                Elem::Label(ref label) => Some(label.clone())
+              , Elem::AnyElem => None
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(Path::from_labels)
+    }
+
+    /// Match `path` against this prefix, returning the rest of the path
+    /// after the matched labels, or `None` if it doesn't match.
+    ///
+    /// A prefix matches a path which starts with its elements, where each
+    /// wildcard matches any single label. The residual is what a dentry
+    /// appends to its destination when it rewrites the path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::Prefix;
+    /// use dtab::path::Path;
+    ///
+    /// let prefix = Prefix::read("/svc/*").unwrap();
+    /// let path = Path::read("/svc/users/v2/get").unwrap();
+    /// assert_eq!(prefix.matches(&path), Some(Path::read("/v2/get").unwrap()));
+    /// assert_eq!( prefix.matches(&Path::read("/svc/users").unwrap())
+    ///           , Some(Path::empty()));
+    /// assert_eq!(prefix.matches(&Path::read("/svc").unwrap()), None);
+    /// assert_eq!(prefix.matches(&Path::read("/srv/users").unwrap()), None);
+    /// ```
+    pub fn matches(&self, path: &Path) -> Option<Path> {
+        if path.len() < self.len() {
+            return None
+        }
+        let mut labels = path.labels();
+        let matched = self.0.iter().zip(labels.by_ref())
+            .all(|(elem, label)| match *elem {
+                Elem::AnyElem => true
+              , Elem::Label(ref elem) => elem.as_slice() == label
+            });
+        if matched {
+            Some(Path::from_labels(labels.map(<[u8]>::to_vec).collect()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if every path matched by `other` is also matched by this
+    /// prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::Prefix;
+    ///
+    /// let svc = Prefix::read("/svc/*").unwrap();
+    /// assert!(svc.covers(&Prefix::read("/svc/users/v2").unwrap()));
+    /// assert!(svc.covers(&Prefix::read("/svc/*").unwrap()));
+    /// assert!(!svc.covers(&Prefix::read("/svc").unwrap()));
+    /// ```
+    pub fn covers(&self, other: &Prefix) -> bool {
+        self.len() <= other.len() &&
+            self.0.iter().zip(&other.0).all(|(elem, other)| match *elem {
+                Elem::AnyElem => true
+              , Elem::Label(_) => elem == other
+            })
+    }
+}
+
+impl str::FromStr for Prefix {
+    type Err = PathError;
+    #[inline] fn from_str(s: &str) -> Result<Self, PathError> {
+        Prefix::read(s)
+    }
+}
+
+impl<'a> convert::TryFrom<&'a str> for Prefix {
+    type Error = PathError;
+    #[inline] fn try_from(s: &'a str) -> Result<Self, PathError> {
+        Prefix::read(s)
+    }
+}
+
+impl convert::TryFrom<String> for Prefix {
+    type Error = PathError;
+    #[inline] fn try_from(s: String) -> Result<Self, PathError> {
+        Prefix::read(&s)
+    }
+}
+
+/// Prefixes are debugged in their text form, as `Prefix(/svc/*)`.
+impl fmt::Debug for Prefix {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Prefix({})", self)
+    }
+}
+
+/// Every path is also a prefix, which matches that path and the paths
+/// beneath it.
+impl<'a> convert::From<&'a Path> for Prefix {
+    #[inline] fn from(path: &'a Path) -> Self {
+        Prefix(path.labels().map(|label| Elem::Label(label.to_vec())).collect())
+    }
+}
+
+impl convert::From<Path> for Prefix {
+    #[inline] fn from(path: Path) -> Self { Prefix::from(&path) }
+}
+
+/// An error converting a [`Prefix`] which contains wildcards into a path.
+///
+/// [`Prefix`]: struct.Prefix.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WildcardError(pub Prefix);
+
+impl fmt::Display for WildcardError {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "prefix {} contains wildcards, and is not a path", self.0)
+    }
+}
+
+impl error::Error for WildcardError {}
+
+impl<'a> convert::TryFrom<&'a Prefix> for Path {
+    type Error = WildcardError;
+    #[inline] fn try_from(prefix: &'a Prefix) -> Result<Self, WildcardError> {
+        prefix.to_path().ok_or_else(|| WildcardError(prefix.clone()))
+    }
+}
+
+/// A prefix without wildcards can be used as a destination leaf.
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use dtab::{NameTree, Prefix};
+///
+/// let prefix = Prefix::read("/smitten").unwrap();
+/// assert_eq!(NameTree::try_from(&prefix), Ok(NameTree::from("/smitten")));
+///
+/// let wildcard = Prefix::read("/svc/*").unwrap();
+/// assert!(NameTree::try_from(&wildcard).is_err());
+/// ```
+impl<'a> convert::TryFrom<&'a Prefix> for NameTree<String> {
+    type Error = WildcardError;
+    #[inline] fn try_from(prefix: &'a Prefix) -> Result<Self, WildcardError> {
+        Path::try_from(prefix).map(NameTree::from)
+    }
+}
+
+impl<'a, 'b> convert::TryFrom<&'a [&'b str]> for Prefix {
+    type Error = PathError;
+    #[inline] fn try_from(segments: &'a [&'b str]) -> Result<Self, PathError> {
+        Prefix::from_segments(segments)
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("/")
+        }
+        for elem in &self.0 {
+            write!(f, "/{}", elem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if `s` is a valid prefix under the strict label policy.
+///
+/// This is a `const fn`, so that the [`prefix!`] macro can validate prefix
+/// literals at compile time.
+///
+/// [`prefix!`]: ../macro.prefix.html
+pub const fn is_valid(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    if bytes.is_empty() || bytes[0] != b'/' {
+        return false
+    }
+    if bytes.len() == 1 {
+        return true
+    }
+    let (mut i, mut start) = (1, 1);
+    while i <= bytes.len() {
+        if i == bytes.len() || bytes[i] == b'/' {
+            if i == start {
+                return false
+            }
+            let wildcard = i - start == 1 && bytes[start] == b'*';
+            let mut j = start;
+            while !wildcard && j < i {
+                if !path::is_showable(bytes[j]) {
+                    return false
+                }
+                j += 1;
+            }
+            start = i + 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Extension trait for constructing a [`Dentry`] from a string prefix.
+///
+/// This is a fallible alternative to the `>>` operator, for prefixes which
+/// aren't known until runtime.
+///
+/// # Examples
+///
+/// ```
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// use dtab::{DelegateTo, NameTree};
+///
+/// let dentry = "/iceCreamStore".delegate_to(NameTree::from("/humphrys") | "/smitten")?;
+/// assert_eq!(&dentry.to_string(), "/iceCreamStore => /humphrys | /smitten;");
+///
+/// assert!("iceCreamStore".delegate_to("/smitten").is_err());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Dentry`]: ../struct.Dentry.html
+pub trait DelegateTo {
+    /// Construct a dentry delegating this prefix to `dst`.
+    fn delegate_to<R>(&self, dst: R) -> Result<Dentry, PathError>
+    where R: convert::Into<NameTree<String>>;
+}
+
+impl DelegateTo for str {
+    #[inline]
+    fn delegate_to<R>(&self, dst: R) -> Result<Dentry, PathError>
+    where R: convert::Into<NameTree<String>> {
+        Prefix::read(self).map(|prefix| prefix >> dst)
+    }
+}
+
+impl DelegateTo for String {
+    #[inline]
+    fn delegate_to<R>(&self, dst: R) -> Result<Dentry, PathError>
+    where R: convert::Into<NameTree<String>> {
+        self.as_str().delegate_to(dst)
+    }
+}
+
+/// Serialize a prefix as a string.
+///
+/// Together with [`deserialize`], this allows this module to be used with
+/// `#[serde(with = "dtab::prefix")]`.
+///
+/// [`deserialize`]: fn.deserialize.html
+pub fn serialize<S>(prefix: &Prefix, serializer: S)
+                    -> Result<S::Ok, S::Error>
+where S: Serializer {
+    serializer.serialize_str(&format!("{}", prefix))
+}
+
+/// Deserialize a prefix from a string, with the [`Any`] label policy, so
+/// that every serialized prefix reads back, whichever policy its labels
+/// were read with.
+///
+/// [`Any`]: ../path/enum.LabelPolicy.html#variant.Any
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Prefix, D::Error>
+where D: Deserializer<'de> {
+    let prefix = String::deserialize(deserializer)?;
+    Prefix::read_with(&prefix, LabelPolicy::Any).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let prefix = Prefix::read("/svc/*/users").unwrap();
+        assert_eq!( prefix.elems()
+                  , &[ Elem::Label(b"svc".to_vec())
+                     , Elem::AnyElem
+                     , Elem::Label(b"users".to_vec())
+                     ]);
+        assert!(prefix.has_wildcards());
+        assert_eq!(Prefix::read("/").unwrap(), Prefix::empty());
+        assert_eq!(Prefix::read("/svc//users"), Err(PathError::EmptyLabel));
+        assert!(Prefix::read("/svc/**").is_err());
+        assert!(Prefix::read("svc").is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let path = |s| Path::read(s).unwrap();
+        let matches = |prefix, p| Prefix::read(prefix).unwrap().matches(&path(p));
+        assert_eq!(matches("/", "/"), Some(path("/")));
+        assert_eq!(matches("/", "/a/b"), Some(path("/a/b")));
+        assert_eq!(matches("/a", "/a/b"), Some(path("/b")));
+        assert_eq!(matches("/a/b", "/a/b"), Some(path("/")));
+        assert_eq!(matches("/*/*", "/a/b/c"), Some(path("/c")));
+        assert_eq!(matches("/a/*/c", "/a/b/c/d"), Some(path("/d")));
+        assert_eq!(matches("/a/*/c", "/a/b/d"), None);
+        assert_eq!(matches("/a/b/c", "/a/b"), None);
+        assert_eq!(matches("/*", "/"), None);
+        assert_eq!(matches("/ab", "/a/b"), None);
+        assert_eq!(matches("/a", "/ab"), None);
+    }
+
+    #[test]
+    fn new() {
+        let elems = vec![Elem::Label(b"svc".to_vec()), Elem::AnyElem];
+        let prefix = Prefix::new(elems.clone()).unwrap();
+        assert_eq!(prefix, Prefix::read("/svc/*").unwrap());
+        assert_eq!(prefix.into_elems(), elems);
+        assert_eq!( Prefix::new(vec![Elem::Label(Vec::new())])
+                  , Err(PathError::EmptyLabel));
+    }
+
+    #[test]
+    fn paths() {
+        use std::convert::TryFrom;
+        let path = Path::read_with("/svc/café", LabelPolicy::Utf8).unwrap();
+        let prefix = Prefix::from(&path);
+        assert_eq!(Path::try_from(&prefix), Ok(path));
+        assert_eq!( NameTree::try_from(&prefix)
+                  , Ok(NameTree::Leaf("/svc/caf\\xc3\\xa9".to_string())));
+
+        let wildcard = Prefix::read("/svc/*").unwrap();
+        assert_eq!(wildcard.to_path(), None);
+        assert_eq!( &Path::try_from(&wildcard).unwrap_err().to_string()
+                  , "prefix /svc/* contains wildcards, and is not a path");
+    }
+
+    #[test]
+    fn from_segments() {
+        use std::convert::TryFrom;
+        let segments: &[&str] = &["svc", "*", "users"];
+        assert_eq!( Prefix::try_from(segments)
+                  , Prefix::read("/svc/*/users"));
+        assert_eq!( Prefix::from_segments::<&str>(&[]), Ok(Prefix::empty()));
+        assert_eq!( Prefix::from_segments(&["svc", ""])
+                  , Err(PathError::EmptyLabel));
+        assert!(Prefix::from_segments(&["caf\\xc3\\xa9"]).is_err());
+        assert_eq!( Prefix::from_segments_with(&["café"], LabelPolicy::Utf8)
+                  , Prefix::read_with("/café", LabelPolicy::Utf8));
+    }
+
+    #[test]
+    fn is_valid_matches_read() {
+        for s in &[ "/", "/svc", "/svc/*/users", "svc", "/svc/", "//", "/**"
+                  , "/svc/föo", "/a b", "/$/inet/127.0.0.1/80" ] {
+            assert_eq!(is_valid(s), Prefix::read(s).is_ok(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn display_escapes_labels() {
+        let prefix = Prefix::read_with("/svc/*/café", LabelPolicy::Utf8)
+            .unwrap();
+        assert_eq!(&prefix.to_string(), "/svc/*/caf\\xc3\\xa9");
+    }
+}
@@ -0,0 +1,178 @@
+//! Environment profiles: a base dtab, with named overlays.
+//!
+//! Deployments often share most of their routing rules between
+//! environments, differing only in a few dentries. A [`Profiles`] holds the
+//! shared base dtab, and an overlay dtab for each environment. Resolving a
+//! profile produces the environment's effective dtab, along with a report of
+//! which base rules its overlay overrode, so that drift between environments
+//! stays visible.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::profile::Profiles;
+//!
+//! let mut profiles = Profiles::new(dtab![
+//!     "/svc" => "/#/io.l5d.k8s/prod/http";
+//!     "/svc/billing" => "/#/io.l5d.k8s/billing/http";
+//! ]);
+//! profiles.insert("staging", dtab!["/svc" => "/#/io.l5d.k8s/staging/http";]);
+//!
+//! let staging = profiles.resolve_profile("staging").unwrap();
+//! assert_eq!( staging.dtab
+//!           , dtab![
+//!                 "/svc/billing" => "/#/io.l5d.k8s/billing/http";
+//!                 "/svc" => "/#/io.l5d.k8s/staging/http";
+//!             ]);
+//! assert_eq!(staging.overridden.len(), 1);
+//! assert_eq!(staging.overridden[0].base_entry, 0);
+//! # }
+//! ```
+//!
+//! [`Profiles`]: struct.Profiles.html
+use std::{error, fmt};
+use std::collections::BTreeMap;
+
+use super::{Dentry, Dtab};
+
+/// A base dtab, and a set of named overlays.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profiles {
+    base: Dtab
+  , overlays: BTreeMap<String, Dtab>
+}
+
+/// The effective dtab of a profile, and the base rules its overlay
+/// overrode.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ResolvedProfile {
+    /// The effective dtab.
+    pub dtab: Dtab
+  , /// Every base dentry which was overridden by the overlay, in the order
+    /// in which they appear in the base dtab.
+    pub overridden: Vec<Override>
+}
+
+/// A base dentry which was overridden by a profile's overlay.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Override {
+    /// The index of the overridden dentry in the base dtab.
+    pub base_entry: usize
+  , /// The overridden dentry.
+    pub dentry: Dentry
+  , /// The index of the overriding dentry in the overlay. If the overlay
+    /// has more than one dentry with the same prefix, this is the last.
+    pub overlay_entry: usize
+}
+
+/// An error indicating that there is no overlay with the given name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnknownProfile(pub String);
+
+impl fmt::Display for UnknownProfile {
+    #[inline] fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown profile {:?}", self.0)
+    }
+}
+
+impl error::Error for UnknownProfile {}
+
+impl Profiles {
+    /// Returns a new set of profiles sharing the given base dtab.
+    #[inline] pub fn new(base: Dtab) -> Self {
+        Profiles { base, overlays: BTreeMap::new() }
+    }
+
+    /// Returns the base dtab.
+    #[inline] pub fn base(&self) -> &Dtab { &self.base }
+
+    /// Set the overlay for the named profile, returning its previous
+    /// overlay, if any.
+    pub fn insert<N>(&mut self, name: N, overlay: Dtab) -> Option<Dtab>
+    where N: Into<String> {
+        self.overlays.insert(name.into(), overlay)
+    }
+
+    /// Remove the named profile, returning its overlay.
+    pub fn remove(&mut self, name: &str) -> Option<Dtab> {
+        self.overlays.remove(name)
+    }
+
+    /// Returns the overlay for the named profile.
+    pub fn overlay(&self, name: &str) -> Option<&Dtab> {
+        self.overlays.get(name)
+    }
+
+    /// Returns an iterator over the names of every profile, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.overlays.keys().map(String::as_str)
+    }
+
+    /// Resolve the effective dtab of the named profile.
+    ///
+    /// Every base dentry whose prefix is also the prefix of a dentry in the
+    /// overlay is overridden, and removed. The effective dtab is the
+    /// remaining base dentries, in order, followed by the overlay, so that
+    /// the overlay's rules take precedence.
+    pub fn resolve_profile(&self, name: &str)
+                           -> Result<ResolvedProfile, UnknownProfile> {
+        let overlay = self.overlays.get(name)
+            .ok_or_else(|| UnknownProfile(name.to_string()))?;
+        let mut dtab = Dtab::new();
+        let mut overridden = Vec::new();
+        for (base_entry, dentry) in self.base.iter().enumerate() {
+            let overlay_entry = overlay.iter()
+                .rposition(|o| o.prefix() == dentry.prefix());
+            match overlay_entry {
+                Some(overlay_entry) => overridden.push(Override {
+                    base_entry, overlay_entry, dentry: dentry.clone()
+                })
+              , None => dtab.push(dentry.clone())
+            }
+        }
+        dtab.extend(overlay.iter().cloned());
+        Ok(ResolvedProfile { dtab, overridden })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_profile() {
+        let mut profiles = Profiles::new(dtab![
+            "/svc" => "/srv/prod";
+            "/svc/a" => "/srv/a";
+            "/svc" => "/srv/legacy";
+        ]);
+        profiles.insert("dev", dtab![
+            "/svc" => "/srv/dev";
+            "/svc/b" => "/srv/dev-b";
+        ]);
+        profiles.insert("prod", Dtab::new());
+
+        let dev = profiles.resolve_profile("dev").unwrap();
+        assert_eq!( dev.dtab
+                  , dtab![
+                        "/svc/a" => "/srv/a";
+                        "/svc" => "/srv/dev";
+                        "/svc/b" => "/srv/dev-b";
+                    ]);
+        assert_eq!( dev.overridden.iter()
+                        .map(|o| (o.base_entry, o.overlay_entry))
+                        .collect::<Vec<_>>()
+                  , vec![(0, 0), (2, 0)]);
+        assert_eq!(&dev.overridden[1].dentry.to_string(), "/svc => /srv/legacy;");
+
+        let prod = profiles.resolve_profile("prod").unwrap();
+        assert_eq!(&prod.dtab, profiles.base());
+        assert!(prod.overridden.is_empty());
+
+        assert_eq!( profiles.resolve_profile("staging")
+                  , Err(UnknownProfile("staging".to_string())));
+        assert_eq!(profiles.names().collect::<Vec<_>>(), vec!["dev", "prod"]);
+    }
+}
@@ -0,0 +1,247 @@
+//! An interactive shell for exploring delegation.
+//!
+//! The REPL holds a dtab loaded from a file, along with a working copy that
+//! can be edited. Typing a path shows which dentries match it, in
+//! precedence order, and what each rewrites it to. Lines beginning with `:`
+//! are commands:
+//!
+//! | command        | effect                                              |
+//! |----------------|-----------------------------------------------------|
+//! | `:trace PATH`  | delegate `PATH`, showing every lookup along the way |
+//! | `:show`        | print the working dtab, with entry numbers          |
+//! | `:add DENTRY`  | append a dentry to the working dtab                 |
+//! | `:rm N`        | remove entry `N` from the working dtab              |
+//! | `:diff`        | compare the working dtab with the loaded dtab       |
+//! | `:reset`       | discard all edits                                   |
+//! | `:help`        | list the commands                                   |
+//! | `:quit`        | exit                                                |
+//!
+//! This module requires the `cli` feature, and backs the `dtab repl`
+//! command.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::repl::Repl;
+//!
+//! let mut repl = Repl::new(dtab!["/svc" => "/srv";]);
+//! assert_eq!( repl.eval("/svc/users").unwrap()
+//!           , "entry 0: /svc => /srv;\n  \
+//!                rewrites to /srv/users\n");
+//!
+//! repl.eval(":add /svc/users => /srv/v2/users").unwrap();
+//! assert_eq!( repl.eval(":diff").unwrap()
+//!           , "  /svc => /srv;\n\
+//!              + /svc/users => /srv/v2/users;\n");
+//! # }
+//! ```
+use std::io::{self, BufRead, Write};
+
+use super::Dtab;
+use super::parse::{self, ParseOptions};
+use super::path::Path;
+
+const HELP: &str = "\
+PATH          show the dentries matching PATH, and what they rewrite it to
+:trace PATH   delegate PATH through the working dtab, showing every lookup
+:show         print the working dtab, with entry numbers
+:add DENTRY   append a dentry to the working dtab
+:rm N         remove entry N from the working dtab
+:diff         compare the working dtab with the loaded dtab
+:reset        discard all edits
+:help         list the commands
+:quit         exit
+";
+
+/// The state of an interactive session.
+#[derive(Clone, Debug)]
+pub struct Repl {
+    loaded: Dtab
+  , working: Dtab
+  , options: ParseOptions
+}
+
+impl Repl {
+    /// Start a session exploring `dtab`.
+    #[inline] pub fn new(dtab: Dtab) -> Self {
+        Repl::with_options(dtab, ParseOptions::default())
+    }
+
+    /// Start a session exploring `dtab`, parsing input with the given
+    /// options.
+    #[inline] pub fn with_options(dtab: Dtab, options: ParseOptions) -> Self {
+        Repl { working: dtab.clone(), loaded: dtab, options }
+    }
+
+    /// Returns the working dtab, including any edits.
+    #[inline] pub fn dtab(&self) -> &Dtab { &self.working }
+
+    /// Evaluate a line of input, returning the text to print, or `None` if
+    /// the session should end.
+    pub fn eval(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        let (command, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim())
+          , None => (line, "")
+        };
+        let output = match command {
+            "" => String::new()
+          , ":quit" | ":q" => return None
+          , ":help" => HELP.to_string()
+          , ":show" => self.working.iter().enumerate()
+                .map(|(i, dentry)| format!("{:3}  {}\n", i, dentry))
+                .collect()
+          , ":add" => match parse::dentry(arg, &self.options) {
+                Ok(dentry) => {
+                    self.working.push(dentry);
+                    format!("added entry {}\n", self.working.len() - 1)
+                }
+              , Err(e) => format!("error: {}\n", e)
+            }
+          , ":rm" => match arg.parse::<usize>() {
+                Ok(i) if i < self.working.len() =>
+                    format!("removed {}\n", self.working.remove(i))
+              , _ => format!("error: no entry {:?}\n", arg)
+            }
+          , ":trace" => match Path::read_with(arg, self.options.labels) {
+                Ok(path) => format!("{}\n", self.working.trace(&path))
+              , Err(e) => format!("error: {}\n", e)
+            }
+          , ":diff" => diff(&self.loaded, &self.working)
+          , ":reset" => {
+                self.working = self.loaded.clone();
+                "discarded all edits\n".to_string()
+            }
+          , _ if line.starts_with('/') => match Path::read_with(line, self.options.labels) {
+                Ok(path) => self.explain(&path)
+              , Err(e) => format!("error: {}\n", e)
+            }
+          , _ => format!("error: unknown command {:?}; try :help\n", command)
+        };
+        Some(output)
+    }
+
+    /// Run a session, reading lines from `input` until it is exhausted or
+    /// `:quit` is entered, and writing responses to `output`.
+    pub fn run<R, W>(&mut self, input: R, mut output: W) -> io::Result<()>
+    where R: BufRead
+        , W: Write {
+        write!(output, "dtab> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.eval(&line?) {
+                Some(response) => output.write_all(response.as_bytes())?
+              , None => return Ok(())
+            }
+            write!(output, "dtab> ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Describe the dentries matching `path`, in precedence order.
+    fn explain(&self, path: &Path) -> String {
+        let mut output = String::new();
+        for (i, dentry) in self.working.iter().enumerate().rev() {
+            match dentry.rewrite(path) {
+                Ok(Some(rewritten)) =>
+                    output.push_str(&format!( "entry {}: {}\n  rewrites to {}\n"
+                                            , i, dentry, rewritten))
+              , Ok(None) => {}
+              , Err(error) =>
+                    output.push_str(&format!( "entry {}: {}\n  has an invalid destination: {}\n"
+                                            , i, dentry, error))
+            }
+        }
+        if output.is_empty() {
+            output = format!("no dentries match {}; it delegates to ~\n", path);
+        }
+        output
+    }
+}
+
+/// A line-by-line diff of two dtabs, marking removed dentries with `-` and
+/// added dentries with `+`.
+fn diff(old: &Dtab, new: &Dtab) -> String {
+    let (old, new) = (&old[..], &new[..]);
+    // lengths[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..].
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut output = String::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            output.push_str(&format!("  {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            output.push_str(&format!("+ {}\n", new[j]));
+            j += 1;
+        } else {
+            output.push_str(&format!("- {}\n", old[i]));
+            i += 1;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NameTree;
+
+    #[test]
+    fn session() {
+        let mut repl = Repl::new(dtab![
+            "/svc" => "/srv";
+            "/svc/*" => NameTree::from("/a") & "/b";
+        ]);
+        assert_eq!( repl.eval("/svc/users/v2").unwrap()
+                  , "entry 1: /svc/* => 0.5 * /a & 0.5 * /b;\n  \
+                       rewrites to 0.5 * /a/v2 & 0.5 * /b/v2\n\
+                     entry 0: /svc => /srv;\n  \
+                       rewrites to /srv/users/v2\n");
+        assert_eq!( repl.eval("/srv").unwrap()
+                  , "no dentries match /srv; it delegates to ~\n");
+
+        assert_eq!( repl.eval(":trace /svc").unwrap()
+                  , "/svc\n  0: /svc => /srv;  =>  /srv\n  /srv is a name\n= /srv\n");
+        assert!(repl.eval(":trace svc").unwrap().starts_with("error: "));
+
+        assert_eq!(repl.eval(":rm 0").unwrap(), "removed /svc => /srv;\n");
+        assert_eq!(repl.eval(":rm 7").unwrap(), "error: no entry \"7\"\n");
+        assert_eq!(repl.eval(":add /srv => /$/inet/localhost/8080;").unwrap(), "added entry 1\n");
+        assert!(repl.eval(":add /srv =>").unwrap().starts_with("error: "));
+        assert_eq!( repl.eval(":diff").unwrap()
+                  , "- /svc => /srv;\n  \
+                     /svc/* => 0.5 * /a & 0.5 * /b;\n\
+                     + /srv => /$/inet/localhost/8080;\n");
+        assert_eq!( repl.eval(":show").unwrap()
+                  , "  0  /svc/* => 0.5 * /a & 0.5 * /b;\n  \
+                       1  /srv => /$/inet/localhost/8080;\n");
+        repl.eval(":reset").unwrap();
+        assert_eq!(repl.eval(":diff").unwrap().lines().count(), 2);
+        assert!(repl.eval(":frobnicate").unwrap().starts_with("error: unknown command"));
+        assert_eq!(repl.eval(":quit"), None);
+    }
+
+    #[test]
+    fn run() {
+        let mut repl = Repl::new(dtab!["/svc" => "/srv";]);
+        let mut output = Vec::new();
+        repl.run(&b"/svc\n:quit\n/svc\n"[..], &mut output).unwrap();
+        assert_eq!( String::from_utf8(output).unwrap()
+                  , "dtab> entry 0: /svc => /srv;\n  rewrites to /srv\ndtab> ");
+    }
+}
@@ -0,0 +1,135 @@
+//! Reproducible sampling of name trees.
+//!
+//! [`NameTree::sample`] picks a leaf using whichever random number
+//! generator it is given, so sampling is only as reproducible as that
+//! generator. It draws only raw `u64`s from the generator, in a documented
+//! order, and picks union members with exact integer arithmetic, so a given
+//! sequence of `u64`s always picks the same leaves.
+//!
+//! Most generators, including `rand`'s `StdRng`, make no promise that a
+//! seed produces the same sequence in every version of the crate. A
+//! [`SeededRng`] does: it is the SplitMix64 generator, whose output is
+//! fully specified, so sampling with it picks the same leaves for the same
+//! seed on every platform and in every version of this crate. Tests which
+//! exercise the load balancing a dtab describes can use it to get the same
+//! picks on every run, while anything else can keep using a generator
+//! seeded from entropy.
+//!
+//! This module requires the `rand` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate dtab;
+//! # extern crate rand;
+//! # fn main() {
+//! use rand::SeedableRng;
+//! use dtab::nametree::W;
+//! use dtab::sample::SeededRng;
+//!
+//! let tree = (W(0.5) * "/a") & (W(0.25) * "/b") & (W(0.25) * "/c");
+//! let picks = |seed| {
+//!     let mut rng = SeededRng::seed_from_u64(seed);
+//!     (0..8).map(|_| tree.sample(&mut rng).unwrap().clone()).collect::<Vec<_>>()
+//! };
+//! assert_eq!(picks(7), picks(7));
+//! assert_eq!(picks(7), vec!["/c", "/a", "/c", "/a", "/a", "/b", "/c", "/a"]);
+//! # }
+//! ```
+//!
+//! [`NameTree::sample`]: ../enum.NameTree.html#method.sample
+//! [`SeededRng`]: struct.SeededRng.html
+use rand::{Error, RngCore, SeedableRng};
+
+/// A small, fast generator whose output for each seed never changes.
+///
+/// This is Sebastiano Vigna's SplitMix64. It is not suitable for
+/// cryptography.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SeededRng { state: u64 }
+
+impl RngCore for SeededRng {
+    #[inline] fn next_u32(&mut self) -> u32 { (self.next_u64() >> 32) as u32 }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    #[inline] fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for SeededRng {
+    type Seed = [u8; 8];
+
+    /// Returns a generator whose state is `seed`, read as a little-endian
+    /// integer.
+    #[inline] fn from_seed(seed: [u8; 8]) -> Self {
+        SeededRng { state: u64::from_le_bytes(seed) }
+    }
+
+    /// Returns a generator whose state is `state`.
+    #[inline] fn seed_from_u64(state: u64) -> Self { SeededRng { state } }
+}
+
+/// Returns a number drawn uniformly from `0..n`, using only whole `u64`s
+/// from `rng`, so that the result depends only on the values it produces.
+///
+/// # Panics
+///
+/// If `n` is zero.
+pub(crate) fn below<R>(rng: &mut R, n: u64) -> u64
+where R: RngCore + ?Sized {
+    assert!(n != 0, "cannot sample from an empty range");
+    // rejecting the `2^64 mod n` largest values keeps every result equally
+    // likely.
+    let rejected = (u64::MAX % n + 1) % n;
+    loop {
+        let x = rng.next_u64();
+        if rejected == 0 || x < rejected.wrapping_neg() {
+            return x % n
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitmix64() {
+        // the reference implementation's first outputs for a state of zero.
+        let mut rng = SeededRng::seed_from_u64(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+        assert_eq!(rng.next_u64(), 0x06c4_5d18_8009_454f);
+        assert_eq!(SeededRng::from_seed([1, 0, 0, 0, 0, 0, 0, 0]), SeededRng::seed_from_u64(1));
+
+        let mut bytes = [0; 10];
+        SeededRng::seed_from_u64(0).fill_bytes(&mut bytes);
+        assert_eq!(bytes, [0xaf, 0xcd, 0x1d, 0x7b, 0x39, 0xa8, 0x20, 0xe2, 0xf4, 0x65]);
+    }
+
+    #[test]
+    fn below_is_in_range() {
+        let mut rng = SeededRng::seed_from_u64(42);
+        for n in &[1, 2, 3, 7, 1 << 63, u64::MAX] {
+            for _ in 0..100 {
+                assert!(below(&mut rng, *n) < *n);
+            }
+        }
+    }
+}
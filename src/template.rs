@@ -0,0 +1,311 @@
+//! Dtab templates, with named variables and per-profile overlays.
+//!
+//! A template is dtab source with `${NAME}` placeholders, as understood by
+//! the [`interpolate`] module, and a few directives, each on a line of its
+//! own:
+//!
+//! - `@var NAME` declares a variable which must be given a value, and
+//!   `@var NAME = VALUE` declares one with a default value;
+//! - `@profile NAME` starts the overlay for a profile, which runs until the
+//!   next `@profile` directive or the end of the template.
+//!
+//! The dentries before the first `@profile` are the base dtab.
+//! [`Template::render`] fills in the base and the profile's overlay, then
+//! combines them as [`Profiles`] would, so the overlay's dentries replace
+//! base dentries with the same prefix. Only declared variables may be used
+//! or given values, so a misspelled variable name is an error rather than
+//! an empty label.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::template::Template;
+//!
+//! let template = Template::parse("
+//!     @var CLUSTER
+//!     @var NAMESPACE = default
+//!     /svc => /#/io.l5d.k8s/${NAMESPACE}/http;
+//!     /svc/billing => /#/io.l5d.k8s/billing/http;
+//!
+//!     @profile dev
+//!     /svc => /#/io.l5d.k8s/dev-${CLUSTER}/http;
+//!
+//!     @profile prod
+//! ").unwrap();
+//!
+//! let dev = template.render("dev", vec![("CLUSTER", "a")]).unwrap();
+//! assert_eq!( dev
+//!           , dtab![ "/svc/billing" => "/#/io.l5d.k8s/billing/http";
+//!                    "/svc" => "/#/io.l5d.k8s/dev-a/http"; ]);
+//!
+//! let prod = template.render("prod", vec![("CLUSTER", "b"), ("NAMESPACE", "prod")])
+//!     .unwrap();
+//! assert_eq!( prod
+//!           , dtab![ "/svc" => "/#/io.l5d.k8s/prod/http";
+//!                    "/svc/billing" => "/#/io.l5d.k8s/billing/http"; ]);
+//!
+//! assert!(template.render("prod", vec![("CLUSTR", "b")]).is_err());
+//! # }
+//! ```
+//!
+//! [`interpolate`]: ../interpolate/index.html
+//! [`Template::render`]: struct.Template.html#method.render
+//! [`Profiles`]: ../profile/struct.Profiles.html
+use std::{error, fmt, ops};
+use std::collections::BTreeMap;
+
+use super::Dtab;
+use super::interpolate::{self, InterpolateError};
+use super::parse::{ParseError, ParseOptions};
+use super::profile::{Profiles, UnknownProfile};
+
+/// A dtab template, with variables and per-profile overlays.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    /// The template source, with every directive replaced by spaces, so
+    /// that offsets in it are offsets in the original source.
+    source: String
+  , /// Each variable, and its default value, if it has one.
+    variables: BTreeMap<String, Option<String>>
+  , /// The source of the base dtab.
+    base: ops::Range<usize>
+  , /// The source of each profile's overlay.
+    profiles: BTreeMap<String, ops::Range<usize>>
+  , options: ParseOptions
+}
+
+/// An error reading or rendering a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    /// The directive at this offset was not understood.
+    InvalidDirective { at: usize }
+  , /// The variable declared at this offset was already declared.
+    DuplicateVariable { name: String, at: usize }
+  , /// The profile started at this offset was already started.
+    DuplicateProfile { name: String, at: usize }
+  , /// There is no profile with this name.
+    UnknownProfile(UnknownProfile)
+  , /// A value was given for a variable which the template doesn't declare.
+    UnknownVariable(String)
+  , /// No value was given for a variable which has no default.
+    MissingVariable(String)
+  , /// The base dtab or an overlay could not be filled in or parsed. Offsets
+    /// in the error are offsets in the template source; a placeholder for
+    /// an undeclared variable is reported as undefined.
+    Interpolate(InterpolateError)
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TemplateError::InvalidDirective { at } =>
+                write!(f, "invalid directive at byte {}", at)
+          , TemplateError::DuplicateVariable { ref name, at } =>
+                write!(f, "variable {:?} declared again at byte {}", name, at)
+          , TemplateError::DuplicateProfile { ref name, at } =>
+                write!(f, "profile {:?} started again at byte {}", name, at)
+          , TemplateError::UnknownProfile(ref e) => fmt::Display::fmt(e, f)
+          , TemplateError::UnknownVariable(ref name) =>
+                write!(f, "unknown variable {:?}", name)
+          , TemplateError::MissingVariable(ref name) =>
+                write!(f, "no value for variable {:?}", name)
+          , TemplateError::Interpolate(ref e) => fmt::Display::fmt(e, f)
+        }
+    }
+}
+
+impl error::Error for TemplateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            TemplateError::UnknownProfile(ref e) => Some(e)
+          , TemplateError::Interpolate(ref e) => Some(e)
+          , _ => None
+        }
+    }
+}
+
+impl Template {
+    /// Read a template, whose dtabs will be parsed with the default
+    /// options.
+    #[inline] pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        Template::parse_with(source, &ParseOptions::default())
+    }
+
+    /// Read a template, whose dtabs will be parsed with the given options.
+    ///
+    /// Only the directives are read here; the dtabs are parsed when the
+    /// template is rendered, once their variables have values.
+    pub fn parse_with(source: &str, options: &ParseOptions) -> Result<Self, TemplateError> {
+        let mut blanked = String::with_capacity(source.len());
+        let mut variables = BTreeMap::new();
+        let mut profiles = BTreeMap::new();
+        // the current section: the name and start of its profile, if any.
+        let mut section: (Option<&str>, usize) = (None, 0);
+        let mut base = None;
+        for line in source.split_inclusive('\n') {
+            let line_start = blanked.len();
+            let directive = line.trim();
+            if !directive.starts_with('@') {
+                blanked.push_str(line);
+                continue
+            }
+            let at = line_start + line.find('@').expect("directive");
+            let (name, value) = match directive.find(char::is_whitespace) {
+                Some(i) => (&directive[..i], directive[i..].trim_start())
+              , None => (directive, "")
+            };
+            match name {
+                "@var" => {
+                    let (var, default) = match value.find('=') {
+                        Some(i) => (value[..i].trim_end(), Some(value[i + 1..].trim()))
+                      , None => (value, None)
+                    };
+                    if !interpolate::is_name(var) {
+                        return Err(TemplateError::InvalidDirective { at })
+                    }
+                    if variables.contains_key(var) {
+                        let name = var.to_string();
+                        return Err(TemplateError::DuplicateVariable { name, at })
+                    }
+                    variables.insert(var.to_string(), default.map(str::to_string));
+                }
+              , "@profile" if !value.is_empty() && !value.contains(char::is_whitespace) => {
+                    match section {
+                        (None, start) => base = Some(start..at)
+                      , (Some(name), start) => { profiles.insert(name.to_string(), start..at); }
+                    }
+                    if profiles.contains_key(value) {
+                        let name = value.to_string();
+                        return Err(TemplateError::DuplicateProfile { name, at })
+                    }
+                    section = (Some(value), line_start + line.len());
+                }
+              , _ => return Err(TemplateError::InvalidDirective { at })
+            }
+            blanked.extend(line.chars().map(|ch| if ch == '\n' { ch } else { ' ' }));
+        }
+        let end = blanked.len();
+        match section {
+            (None, start) => base = Some(start..end)
+          , (Some(name), start) => { profiles.insert(name.to_string(), start..end); }
+        }
+        Ok(Template { source: blanked
+                    , variables
+                    , base: base.expect("base dtab")
+                    , profiles
+                    , options: *options })
+    }
+
+    /// Returns an iterator over the names of every variable, and their
+    /// default values, in order.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.variables.iter().map(|(name, default)| (name.as_str(), default.as_deref()))
+    }
+
+    /// Returns an iterator over the names of every profile, in order.
+    pub fn profiles(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Render the effective dtab of `profile`, with the given values for the
+    /// template's variables.
+    ///
+    /// Variables which aren't given values take their defaults. The base
+    /// dtab and the profile's overlay are filled in and parsed separately,
+    /// and then resolved as by [`Profiles::resolve_profile`].
+    ///
+    /// [`Profiles::resolve_profile`]: ../profile/struct.Profiles.html#method.resolve_profile
+    pub fn render<I, K, V>(&self, profile: &str, vars: I) -> Result<Dtab, TemplateError>
+    where I: IntoIterator<Item = (K, V)>
+        , K: AsRef<str>
+        , V: AsRef<str> {
+        let overlay = self.profiles.get(profile).ok_or_else(|| {
+            TemplateError::UnknownProfile(UnknownProfile(profile.to_string()))
+        })?;
+        let mut values = BTreeMap::new();
+        for (name, value) in vars {
+            let name = name.as_ref();
+            if !self.variables.contains_key(name) {
+                return Err(TemplateError::UnknownVariable(name.to_string()))
+            }
+            values.insert(name.to_string(), value.as_ref().to_string());
+        }
+        for (name, default) in &self.variables {
+            if !values.contains_key(name) {
+                let value = default.clone()
+                    .ok_or_else(|| TemplateError::MissingVariable(name.clone()))?;
+                values.insert(name.clone(), value);
+            }
+        }
+        let mut profiles = Profiles::new(self.section(&self.base, &values)?);
+        profiles.insert(profile, self.section(overlay, &values)?);
+        let resolved = profiles.resolve_profile(profile)
+            .expect("profile was just inserted");
+        Ok(resolved.dtab)
+    }
+
+    /// Fill in and parse one section of the template.
+    fn section(&self, range: &ops::Range<usize>, values: &BTreeMap<String, String>)
+               -> Result<Dtab, TemplateError> {
+        let text = &self.source[range.clone()];
+        interpolate::dtab(text, &self.options, |name| values.get(name).cloned())
+            .map_err(|e| TemplateError::Interpolate(match e {
+                InterpolateError::Unclosed { at } =>
+                    InterpolateError::Unclosed { at: range.start + at }
+              , InterpolateError::InvalidName { name, at } =>
+                    InterpolateError::InvalidName { name, at: range.start + at }
+              , InterpolateError::Undefined { name, at } =>
+                    InterpolateError::Undefined { name, at: range.start + at }
+              , InterpolateError::Parse(e) =>
+                    InterpolateError::Parse(ParseError { at: range.start + e.at, ..e })
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives() {
+        let template = Template::parse("@var A\n@var B = x = y\n/a => /${A};\n\
+                                        @profile one\n@profile two\n/b => /${B};\n")
+            .unwrap();
+        assert_eq!( template.variables().collect::<Vec<_>>()
+                  , vec![("A", None), ("B", Some("x = y"))]);
+        assert_eq!(template.profiles().collect::<Vec<_>>(), vec!["one", "two"]);
+        assert_eq!(template.render("one", vec![("A", "a")]), Ok(dtab!["/a" => "/a";]));
+        assert_eq!( template.render("two", vec![("A", "a"), ("B", "b")])
+                  , Ok(dtab!["/a" => "/a"; "/b" => "/b";]));
+
+        let error = |source: &str| Template::parse(source).unwrap_err();
+        assert_eq!(error("/a => /b;\n  @include x\n"), TemplateError::InvalidDirective { at: 12 });
+        assert_eq!(error("@var 1A\n"), TemplateError::InvalidDirective { at: 0 });
+        assert_eq!(error("@profile\n"), TemplateError::InvalidDirective { at: 0 });
+        assert_eq!( error("@var A\n@var A = b\n")
+                  , TemplateError::DuplicateVariable { name: "A".to_string(), at: 7 });
+        assert_eq!( error("@profile a\n@profile b\n@profile a\n")
+                  , TemplateError::DuplicateProfile { name: "a".to_string(), at: 22 });
+    }
+
+    #[test]
+    fn render_errors() {
+        let template = Template::parse("@var A\n/a => /${A};\n@profile p\n/b => /${C};\n")
+            .unwrap();
+        assert_eq!( template.render("q", vec![("A", "a")])
+                  , Err(TemplateError::UnknownProfile(UnknownProfile("q".to_string()))));
+        assert_eq!( template.render("p", Vec::<(&str, &str)>::new())
+                  , Err(TemplateError::MissingVariable("A".to_string())));
+        assert_eq!( template.render("p", vec![("A", "a"), ("B", "b")])
+                  , Err(TemplateError::UnknownVariable("B".to_string())));
+        assert_eq!( template.render("p", vec![("A", "a")])
+                  , Err(TemplateError::Interpolate(InterpolateError::Undefined {
+                        name: "C".to_string(), at: 38 })));
+        match template.render("p", vec![("A", "a b")]) {
+            Err(TemplateError::Interpolate(InterpolateError::Parse(e))) => assert_eq!(e.at, 14)
+          , result => panic!("expected a parse error, got {:?}", result)
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Validation of dentry destinations.
+//!
+//! A [`Dentry`]'s prefix is always a valid [`Prefix`], but its destination
+//! is a `NameTree<String>`, whose leaves may be arbitrary strings. Validation
+//! checks that every destination leaf is a well-formed path.
+//!
+//! In [`Strict`] mode, validation additionally rejects wildcard (`*`)
+//! elements in destination leaves. Wildcards are only meaningful in
+//! prefixes; in a destination, a `*` is a literal label which almost
+//! certainly isn't what the author intended.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::NameTree;
+//! use dtab::validate::{Mode, Validation, ValidationError};
+//!
+//! let dtab = dtab![
+//!   "/svc/*" => NameTree::from("/srv/*");
+//! ];
+//! let strict = Validation { mode: Mode::Strict, ..Validation::default() };
+//!
+//! assert!(dtab.validate(&Validation::default()).is_ok());
+//! match dtab.validate(&strict) {
+//!     Err(ValidationError::WildcardInDestination { entry, .. }) =>
+//!         assert_eq!(entry, 0)
+//!   , other => panic!("unexpected result {:?}", other)
+//! }
+//! # }
+//! ```
+//!
+//! Validation can also enforce [`Limits`] on the size and shape of
+//! destination trees, so that dentries from untrusted sources (such as
+//! per-request overrides) can't contain pathologically large trees:
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use dtab::{NameTree, Prefix};
+//! use dtab::validate::{Limit, Limits, Validation, ValidationError};
+//!
+//! let validation = Validation {
+//!     limits: Limits { max_leaves: Some(2), ..Limits::default() }
+//!   , ..Validation::default()
+//! };
+//! let dst = NameTree::from("/humphrys") | "/smitten" | "/birite";
+//! let prefix = Prefix::read("/iceCreamStore").unwrap();
+//!
+//! match validation.dentry(prefix, dst) {
+//!     Err(ValidationError::LimitExceeded { limit, max, actual, .. }) => {
+//!         assert_eq!(limit, Limit::Leaves);
+//!         assert_eq!((max, actual), (2, 3));
+//!     }
+//!   , other => panic!("unexpected result {:?}", other)
+//! }
+//! # }
+//! ```
+//!
+//! [`Dentry`]: ../struct.Dentry.html
+//! [`Limits`]: struct.Limits.html
+//! [`Prefix`]: ../prefix/struct.Prefix.html
+//! [`Strict`]: enum.Mode.html#variant.Strict
+use std::{error, fmt};
+
+use super::{Dentry, Dtab, NameTree, Prefix};
+use super::nametree::Complexity;
+use super::path::{LabelPolicy, PathError};
+
+/// How strictly destinations are validated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Destination leaves must be well-formed paths, but may contain
+    /// wildcard elements.
+    ///
+    /// This is the default.
+    Lenient
+  , /// Destination leaves must be well-formed paths, and may not contain
+    /// wildcard elements.
+    Strict
+}
+
+impl Default for Mode {
+    #[inline] fn default() -> Self { Mode::Lenient }
+}
+
+/// Limits on the [complexity] of destination trees.
+///
+/// Each limit is optional; by default, no limits are enforced.
+///
+/// [complexity]: ../nametree/struct.Complexity.html
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Limits {
+    /// The maximum depth of a destination tree.
+    pub max_depth: Option<usize>
+  , /// The maximum number of members of a single union.
+    pub max_union_width: Option<usize>
+  , /// The maximum number of leaves in a destination tree.
+    pub max_leaves: Option<usize>
+}
+
+/// Identifies one of the [`Limits`].
+///
+/// [`Limits`]: struct.Limits.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Limit { Depth, UnionWidth, Leaves }
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::Depth => f.write_str("tree depth")
+          , Limit::UnionWidth => f.write_str("union width")
+          , Limit::Leaves => f.write_str("number of leaves")
+        }
+    }
+}
+
+impl Limits {
+    /// Check a tree's complexity against these limits, returning the first
+    /// limit exceeded along with its maximum and the actual value.
+    pub fn check(&self, complexity: &Complexity)
+                 -> Result<(), (Limit, usize, usize)> {
+        let checks = [ (Limit::Depth, self.max_depth, complexity.depth)
+                     , ( Limit::UnionWidth, self.max_union_width
+                       , complexity.union_width)
+                     , (Limit::Leaves, self.max_leaves, complexity.leaves)
+                     ];
+        for &(limit, max, actual) in &checks {
+            match max {
+                Some(max) if actual > max => return Err((limit, max, actual))
+              , _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options controlling validation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Validation {
+    /// How strictly to validate destinations.
+    pub mode: Mode
+  , /// Which characters are permitted in destination labels.
+    pub labels: LabelPolicy
+  , /// Limits on the complexity of destination trees.
+    pub limits: Limits
+}
+
+/// An error describing an invalid dentry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A destination leaf was not a valid path.
+    InvalidDestination { entry: usize, leaf: String, error: PathError }
+  , /// A destination leaf contained a wildcard element, in strict mode.
+    WildcardInDestination { entry: usize, leaf: String }
+  , /// A destination tree exceeded one of the configured [`Limits`].
+    ///
+    /// [`Limits`]: struct.Limits.html
+    LimitExceeded { entry: usize, limit: Limit, max: usize, actual: usize }
+}
+
+impl ValidationError {
+    /// Returns the index of the dentry that failed validation.
+    pub fn entry(&self) -> usize {
+        match *self {
+            ValidationError::InvalidDestination { entry, .. } |
+            ValidationError::WildcardInDestination { entry, .. } |
+            ValidationError::LimitExceeded { entry, .. } => entry
+        }
+    }
+
+    fn at_entry(self, at: usize) -> Self {
+        match self {
+            ValidationError::InvalidDestination { leaf, error, .. } =>
+                ValidationError::InvalidDestination { entry: at, leaf, error }
+          , ValidationError::WildcardInDestination { leaf, .. } =>
+                ValidationError::WildcardInDestination { entry: at, leaf }
+          , ValidationError::LimitExceeded { limit, max, actual, .. } =>
+                ValidationError::LimitExceeded { entry: at, limit, max, actual }
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::InvalidDestination { entry, ref leaf, .. } =>
+                write!( f, "invalid destination {:?} in entry {}"
+                      , leaf, entry)
+          , ValidationError::WildcardInDestination { entry, ref leaf } =>
+                write!( f, "wildcard in destination {:?} in entry {}"
+                      , leaf, entry)
+          , ValidationError::LimitExceeded { entry, limit, max, actual } =>
+                write!( f, "{} of {} in entry {} exceeds the limit of {}"
+                      , limit, actual, entry, max)
+        }
+    }
+}
+
+impl error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ValidationError::InvalidDestination { ref error, .. } => Some(error)
+          , ValidationError::WildcardInDestination { .. } |
+            ValidationError::LimitExceeded { .. } => None
+        }
+    }
+}
+
+impl Validation {
+    /// Validate a single dentry.
+    ///
+    /// Errors returned by this function report an entry index of zero.
+    pub fn check_dentry(&self, dentry: &Dentry) -> Result<(), ValidationError> {
+        self.limits.check(&dentry.dst.complexity())
+            .map_err(|(limit, max, actual)| ValidationError::LimitExceeded {
+                entry: 0, limit, max, actual
+            })?;
+        for leaf in dentry.dst.leaves() {
+            self.check_leaf(leaf)?;
+        }
+        Ok(())
+    }
+
+    /// Construct a dentry, returning an error if it is not valid.
+    pub fn dentry(&self, prefix: Prefix, dst: NameTree<String>)
+                  -> Result<Dentry, ValidationError> {
+        let dentry = Dentry::new(prefix, dst);
+        self.check_dentry(&dentry)?;
+        Ok(dentry)
+    }
+
+    /// Validate every dentry in a dtab, returning the first error.
+    pub fn check_dtab(&self, dtab: &Dtab) -> Result<(), ValidationError> {
+        for (i, dentry) in dtab.iter().enumerate() {
+            self.check_dentry(dentry).map_err(|e| e.at_entry(i))?;
+        }
+        Ok(())
+    }
+
+    fn check_leaf(&self, leaf: &str) -> Result<(), ValidationError> {
+        let invalid = |error| ValidationError::InvalidDestination {
+            entry: 0, leaf: leaf.to_string(), error
+        };
+        if !leaf.starts_with('/') {
+            return Err(invalid(PathError::MissingLeadingSlash {
+                path: leaf.to_string()
+            }))
+        }
+        if leaf == "/" {
+            return Ok(())
+        }
+        for label in leaf[1..].split('/') {
+            if label == "*" {
+                match self.mode {
+                    Mode::Strict =>
+                        return Err(ValidationError::WildcardInDestination {
+                            entry: 0, leaf: leaf.to_string()
+                        })
+                  , Mode::Lenient => continue
+                }
+            }
+            self.labels.read_label(label).map_err(&invalid)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use NameTree;
+
+    #[test]
+    fn valid_destinations() {
+        let dentry = dentry!("/svc/*" => NameTree::from("/$/inet/127.0.0.1/80")
+                                       | "/#/io.l5d.k8s/default/http"
+                                       | "~");
+        let strict = Validation { mode: Mode::Strict, ..Validation::default() };
+        assert_eq!(strict.check_dentry(&dentry), Ok(()));
+    }
+
+    #[test]
+    fn invalid_destination() {
+        let dtab = dtab![
+            "/svc" => NameTree::from("/srv");
+            "/svc/*" => NameTree::from("/srv") | "/srv/foo bar";
+        ];
+        let err = Validation::default().check_dtab(&dtab).unwrap_err();
+        assert_eq!(err.entry(), 1);
+        assert_eq!( &err.to_string()
+                  , "invalid destination \"/srv/foo bar\" in entry 1");
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn wildcard_in_destination() {
+        let dentry = dentry!("/svc" => NameTree::from("/srv/*/users"));
+        assert_eq!(Validation::default().check_dentry(&dentry), Ok(()));
+        let strict = Validation { mode: Mode::Strict, ..Validation::default() };
+        assert_eq!( strict.check_dentry(&dentry)
+                  , Err(ValidationError::WildcardInDestination {
+                        entry: 0, leaf: "/srv/*/users".to_string()
+                    }));
+    }
+
+    #[test]
+    fn limits() {
+        let dentry = dentry!("/svc" => NameTree::from("/a") & "/b" & "/c"
+                                     | NameTree::from("/d") & "/e");
+        let limited = |limits| Validation { limits, ..Validation::default() };
+        assert_eq!(limited(Limits::default()).check_dentry(&dentry), Ok(()));
+        assert_eq!( limited(Limits { max_union_width: Some(2)
+                                   , ..Limits::default() })
+                        .check_dentry(&dentry)
+                  , Err(ValidationError::LimitExceeded {
+                        entry: 0, limit: Limit::UnionWidth, max: 2, actual: 3
+                    }));
+        assert_eq!( limited(Limits { max_depth: Some(3)
+                                   , ..Limits::default() })
+                        .check_dentry(&dentry)
+                  , Err(ValidationError::LimitExceeded {
+                        entry: 0, limit: Limit::Depth, max: 3, actual: 4
+                    }));
+        assert_eq!( limited(Limits { max_leaves: Some(5)
+                                   , ..Limits::default() })
+                        .check_dentry(&dentry)
+                  , Ok(()));
+    }
+}
@@ -0,0 +1,255 @@
+//! Sharing a dtab which changes over time.
+//!
+//! Finagle keeps the base dtab in a variable, `Dtab.base`, which is
+//! replaced when the routing configuration changes, and whose observers are
+//! notified of each new dtab. A [`DtabCell`] does the same: it holds the
+//! current dtab, which can be read from any thread, and replaced atomically,
+//! and consumers which need to react to changes, such as a
+//! [`DelegationCache`] which must forget its results, can [`subscribe`] to
+//! a [`DtabWatch`] receiving each new dtab.
+//!
+//! With the `futures` feature, [`DtabCell::stream`] returns the changes as
+//! a `Stream` instead, for consumers running on an async runtime.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate dtab;
+//! # fn main() {
+//! use std::sync::Arc;
+//! use std::thread;
+//! use dtab::watch::DtabCell;
+//!
+//! let cell = Arc::new(DtabCell::new(dtab!["/svc" => "/srv";]));
+//! let watch = cell.subscribe();
+//!
+//! let writer = Arc::clone(&cell);
+//! thread::spawn(move || {
+//!     writer.update(|dtab| dtab.push(dentry!("/svc/users" => "/srv/users-v2")));
+//! }).join().unwrap();
+//!
+//! let latest = watch.recv().unwrap();
+//! assert_eq!(latest.len(), 2);
+//! assert_eq!(cell.get(), latest);
+//! # }
+//! ```
+//!
+//! [`DtabCell`]: struct.DtabCell.html
+//! [`DtabCell::stream`]: struct.DtabCell.html#method.stream
+//! [`DtabWatch`]: struct.DtabWatch.html
+//! [`DelegationCache`]: ../delegate/struct.DelegationCache.html
+//! [`subscribe`]: struct.DtabCell.html#method.subscribe
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::{self, Receiver, RecvError, Sender, TryIter, TryRecvError};
+
+#[cfg(feature = "futures")]
+use futures::channel::mpsc::{self as channel, UnboundedReceiver, UnboundedSender};
+
+use super::Dtab;
+
+/// Holds the current dtab, notifying subscribers each time it is replaced.
+///
+/// A cell is usually shared between threads in an `Arc`. Readers get the
+/// current dtab as an `Arc<Dtab>`, so they never hold a lock while they use
+/// it, and a writer replacing it doesn't wait for them to finish.
+#[derive(Default)]
+pub struct DtabCell {
+    current: RwLock<Arc<Dtab>>
+  , subscribers: Mutex<Vec<Subscriber>>
+}
+
+/// Where to send each new dtab.
+enum Subscriber {
+    Channel(Sender<Arc<Dtab>>)
+  , #[cfg(feature = "futures")]
+    Stream(UnboundedSender<Arc<Dtab>>)
+}
+
+impl Subscriber {
+    /// Send `dtab`, returning false if the subscriber has gone away.
+    fn send(&self, dtab: &Arc<Dtab>) -> bool {
+        match *self {
+            Subscriber::Channel(ref tx) => tx.send(Arc::clone(dtab)).is_ok()
+          , #[cfg(feature = "futures")]
+            Subscriber::Stream(ref tx) => tx.unbounded_send(Arc::clone(dtab)).is_ok()
+        }
+    }
+}
+
+impl DtabCell {
+    /// Returns a cell holding `dtab`, with no subscribers.
+    pub fn new(dtab: Dtab) -> Self {
+        DtabCell { current: RwLock::new(Arc::new(dtab)), subscribers: Mutex::new(Vec::new()) }
+    }
+
+    /// Returns the current dtab.
+    pub fn get(&self) -> Arc<Dtab> {
+        Arc::clone(&self.current.read().expect("dtab cell poisoned"))
+    }
+
+    /// Replace the current dtab with `dtab`, notifying every subscriber, and
+    /// return the previous dtab.
+    pub fn set(&self, dtab: Dtab) -> Arc<Dtab> {
+        let mut current = self.current.write().expect("dtab cell poisoned");
+        let previous = ::std::mem::replace(&mut *current, Arc::new(dtab));
+        self.notify(&current);
+        previous
+    }
+
+    /// Replace the current dtab with a copy changed by `f`, notifying every
+    /// subscriber.
+    ///
+    /// No other change is made between reading the current dtab and
+    /// replacing it, so concurrent updates are never lost.
+    pub fn update<F>(&self, f: F)
+    where F: FnOnce(&mut Dtab) {
+        let mut current = self.current.write().expect("dtab cell poisoned");
+        let mut dtab = Dtab::clone(&current);
+        f(&mut dtab);
+        *current = Arc::new(dtab);
+        self.notify(&current);
+    }
+
+    /// Returns a watch which receives every dtab this cell holds from now
+    /// on, in the order they are set.
+    ///
+    /// The watch doesn't receive the current dtab, which can be read with
+    /// [`get`].
+    ///
+    /// [`get`]: #method.get
+    pub fn subscribe(&self) -> DtabWatch {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().expect("dtab cell poisoned").push(Subscriber::Channel(tx));
+        DtabWatch { rx }
+    }
+
+    /// Returns a stream of every dtab this cell holds from now on, in the
+    /// order they are set, as [`subscribe`] does.
+    ///
+    /// This requires the `futures` feature.
+    ///
+    /// [`subscribe`]: #method.subscribe
+    #[cfg(feature = "futures")]
+    pub fn stream(&self) -> UnboundedReceiver<Arc<Dtab>> {
+        let (tx, rx) = channel::unbounded();
+        self.subscribers.lock().expect("dtab cell poisoned").push(Subscriber::Stream(tx));
+        rx
+    }
+
+    /// Send `dtab` to every subscriber, forgetting those which have gone
+    /// away.
+    ///
+    /// This is called while the current dtab is locked for writing, so
+    /// subscribers receive each dtab in the order it was set.
+    fn notify(&self, dtab: &Arc<Dtab>) {
+        self.subscribers.lock().expect("dtab cell poisoned")
+            .retain(|subscriber| subscriber.send(dtab));
+    }
+}
+
+impl From<Dtab> for DtabCell {
+    #[inline] fn from(dtab: Dtab) -> Self { DtabCell::new(dtab) }
+}
+
+impl fmt::Debug for DtabCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DtabCell")
+         .field("current", &self.get())
+         .field("subscribers", &self.subscribers.lock().expect("dtab cell poisoned").len())
+         .finish()
+    }
+}
+
+/// Receives each dtab a [`DtabCell`] is set to after subscribing.
+///
+/// This is returned by [`DtabCell::subscribe`]. The cell stops sending to
+/// it once it is dropped.
+///
+/// [`DtabCell`]: struct.DtabCell.html
+/// [`DtabCell::subscribe`]: struct.DtabCell.html#method.subscribe
+#[derive(Debug)]
+pub struct DtabWatch {
+    rx: Receiver<Arc<Dtab>>
+}
+
+impl DtabWatch {
+    /// Wait for the next dtab, returning an error if the cell has been
+    /// dropped and every dtab it sent has been received.
+    #[inline] pub fn recv(&self) -> Result<Arc<Dtab>, RecvError> { self.rx.recv() }
+
+    /// Returns the next dtab, if one has been set since it was last
+    /// received, without waiting.
+    #[inline] pub fn try_recv(&self) -> Result<Arc<Dtab>, TryRecvError> { self.rx.try_recv() }
+
+    /// Returns the most recent dtab set since the last one received,
+    /// skipping any before it, or `None` if none has been.
+    #[inline] pub fn latest(&self) -> Option<Arc<Dtab>> { self.rx.try_iter().last() }
+
+    /// Returns an iterator over every dtab set since the last one received,
+    /// which doesn't wait for any more.
+    #[inline] pub fn try_iter(&self) -> TryIter<'_, Arc<Dtab>> { self.rx.try_iter() }
+}
+
+impl IntoIterator for DtabWatch {
+    type Item = Arc<Dtab>;
+    type IntoIter = mpsc::IntoIter<Arc<Dtab>>;
+
+    /// Returns an iterator which waits for each dtab, ending once the cell
+    /// has been dropped.
+    #[inline] fn into_iter(self) -> Self::IntoIter { self.rx.into_iter() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn notifications() {
+        let cell = DtabCell::new(dtab!["/a" => "/b";]);
+        let first = cell.subscribe();
+        assert_eq!(cell.set(dtab!["/a" => "/c";]), Arc::new(dtab!["/a" => "/b";]));
+        let second = cell.subscribe();
+        cell.update(|dtab| dtab.push(dentry!("/b" => "/d")));
+
+        assert_eq!(first.try_iter().map(|dtab| dtab.len()).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(second.latest(), Some(cell.get()));
+        assert_eq!(second.try_recv(), Err(TryRecvError::Empty));
+
+        drop(first);
+        cell.set(Dtab::new());
+        drop(cell);
+        assert_eq!(second.into_iter().collect::<Vec<_>>(), vec![Arc::new(Dtab::new())]);
+    }
+
+    #[test]
+    fn concurrent_updates() {
+        let cell = Arc::new(DtabCell::default());
+        let watch = cell.subscribe();
+        let writers: Vec<_> = (0..4).map(|_| {
+            let cell = Arc::clone(&cell);
+            thread::spawn(move || for _ in 0..25 {
+                cell.update(|dtab| dtab.push(dentry!("/a" => "/b")));
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(cell.get().len(), 100);
+        let lens: Vec<usize> = watch.try_iter().map(|dtab| dtab.len()).collect();
+        assert_eq!(lens, (1..=100).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn stream() {
+        use futures::executor::block_on_stream;
+        let cell = DtabCell::new(Dtab::new());
+        let stream = cell.stream();
+        cell.set(dtab!["/a" => "/b";]);
+        drop(cell);
+        let dtabs: Vec<_> = block_on_stream(stream).collect();
+        assert_eq!(dtabs, vec![Arc::new(dtab!["/a" => "/b";])]);
+    }
+}
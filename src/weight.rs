@@ -0,0 +1,902 @@
+//! Exact weights for weighted unions.
+//!
+//! Union weights are conceptually real numbers, but repeatedly renormalizing
+//! `f64` weights accumulates rounding error, and makes comparing `NameTree`s
+//! for equality unreliable. A [`Weight`] is instead stored as an exact,
+//! reduced fraction. Weights written as decimal `f64`s (such as `0.7`) are
+//! converted exactly, based on their shortest decimal representation.
+//! Adding, multiplying and dividing weights is exact as long as the result
+//! fits in 64 bits; the operators approximate results which don't, and
+//! [`checked_add`], [`checked_mul`] and [`checked_div`] return `None`
+//! instead.
+//!
+//! A weight is displayed as its exact decimal expansion whenever that
+//! expansion terminates, which is the case for every weight read from a
+//! dtab or converted from an `f64`. Other weights, such as `1/3`, are
+//! displayed rounded to enough places that the weight is the simplest
+//! fraction which rounds to what's displayed, and that's how a decimal too
+//! precise to be read exactly is read, so parsing the displayed form of any
+//! weight returns the same weight.
+//!
+//! # Examples
+//!
+//! ```
+//! use dtab::weight::Weight;
+//!
+//! let third = Weight::ratio(1, 3);
+//! assert_eq!(third + third + third, Weight::ratio(1, 1));
+//! assert_eq!(&third.to_string(), "0.333333333333333333333");
+//! assert_eq!(Weight::from_decimal(&third.to_string()), Some(third));
+//!
+//! let w = |f| Weight::from_f64(f).unwrap();
+//! assert_eq!(w(0.1) + w(0.2), w(0.3));
+//! assert_eq!(Weight::per_mille(300), w(0.3));
+//! ```
+//!
+//! [`Weight`]: struct.Weight.html
+//! [`checked_add`]: struct.Weight.html#method.checked_add
+//! [`checked_mul`]: struct.Weight.html#method.checked_mul
+//! [`checked_div`]: struct.Weight.html#method.checked_div
+use std::{cmp, error, fmt, iter, ops, str};
+
+/// An exact, non-negative rational weight.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Weight { num: u64, den: u64 }
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+impl Weight {
+    /// The weight zero.
+    pub const ZERO: Weight = Weight { num: 0, den: 1 };
+    /// The weight one.
+    pub const ONE: Weight = Weight { num: 1, den: 1 };
+    /// The largest weight, `u64::MAX`.
+    pub const MAX: Weight = Weight { num: u64::MAX, den: 1 };
+
+    /// Returns the weight `num / den`.
+    ///
+    /// # Panics
+    ///
+    /// If `den` is zero.
+    #[inline] pub fn ratio(num: u64, den: u64) -> Self {
+        assert!(den != 0, "weight denominator may not be zero");
+        Weight::reduce(u128::from(num), u128::from(den))
+    }
+
+    /// Returns the weight `per_mille / 1000`.
+    #[inline] pub fn per_mille(per_mille: u64) -> Self {
+        Weight::ratio(per_mille, 1000)
+    }
+
+    /// Converts a floating-point weight exactly, based on its shortest
+    /// decimal representation.
+    ///
+    /// Returns `None` if `f` is negative, NaN, or larger than [`MAX`].
+    ///
+    /// [`MAX`]: #associatedconstant.MAX
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !(0.0..u64::MAX as f64).contains(&f) {
+            return None
+        }
+        // `{:e}` formats the shortest representation that round-trips,
+        // e.g. `7e-1` or `1.2345e3`.
+        let repr = format!("{:e}", f);
+        let (mantissa, exp) = repr.split_at(repr.find('e')?);
+        let exp: i32 = exp[1..].parse().ok()?;
+        let (int, frac) = match mantissa.find('.') {
+            Some(i) => (&mantissa[..i], &mantissa[i + 1..])
+          , None => (mantissa, "")
+        };
+        let digits: u128 = format!("{}{}", int, frac).parse().ok()?;
+        let exp = exp - frac.len() as i32;
+        let exact = if exp >= 0 {
+            10u128.checked_pow(exp as u32)
+                .and_then(|scale| digits.checked_mul(scale))
+                .map(|num| (num, 1))
+        } else {
+            10u128.checked_pow(-exp as u32).map(|den| (digits, den))
+        };
+        match exact {
+            Some((num, den)) => Some(Weight::reduce(num, den))
+          , None => Some(Weight::approximate(f))
+        }
+    }
+
+    /// Converts a floating-point weight as [`from_f64`] does, except that
+    /// weights which can't be represented are clamped, as an `as` cast
+    /// would: negative and NaN weights become zero, and those larger than
+    /// [`MAX`] become `MAX`.
+    ///
+    /// [`from_f64`]: #method.from_f64
+    /// [`MAX`]: #associatedconstant.MAX
+    pub fn saturating_from_f64(f: f64) -> Self {
+        if f.is_nan() || f <= 0.0 {
+            Weight::ZERO
+        } else {
+            Weight::from_f64(f).unwrap_or(Weight::MAX)
+        }
+    }
+
+    /// Reads a weight written as a decimal number, such as `1`, `0.25`,
+    /// `.5`, `2.` or `1.0E-4`, exactly.
+    ///
+    /// Returns `None` if `s` is not a decimal number, or is larger than
+    /// [`MAX`]. A number with too many significant digits to be represented
+    /// exactly is read as the simplest weight which rounds to it, or, if it
+    /// has more than 128 digits, as the nearest `f64`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::weight::Weight;
+    ///
+    /// assert_eq!(Weight::from_decimal(".25"), Some(Weight::ratio(1, 4)));
+    /// assert_eq!(Weight::from_decimal("3."), Some(Weight::ratio(3, 1)));
+    /// assert_eq!(Weight::from_decimal("2.5e-3"), Some(Weight::ratio(1, 400)));
+    /// let tiny = Weight::ratio(1, 1 << 60);
+    /// assert_eq!(Weight::from_decimal(&tiny.to_string()), Some(tiny));
+    /// assert_eq!(Weight::from_decimal("."), None);
+    /// assert_eq!(Weight::from_decimal("1e"), None);
+    /// assert_eq!(Weight::from_decimal("-1"), None);
+    /// assert_eq!(Weight::from_decimal("0.33333333333333333333333"), Some(Weight::ratio(1, 3)));
+    /// assert_eq!(Weight::from_decimal("1e30"), None);
+    /// ```
+    ///
+    /// [`MAX`]: #associatedconstant.MAX
+    pub fn from_decimal(s: &str) -> Option<Self> {
+        let (mantissa, exp) = match s.find(['e', 'E']) {
+            Some(i) => {
+                let exp = &s[i + 1..];
+                let digits = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+                if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return None
+                }
+                // an exponent too large for an `i64` is certainly too large
+                // to read exactly.
+                let saturated = if exp.starts_with('-') { i64::MIN } else { i64::MAX };
+                (&s[..i], exp.parse::<i64>().unwrap_or(saturated))
+            }
+          , None => (s, 0)
+        };
+        let (int, frac) = match mantissa.find('.') {
+            Some(i) => (&mantissa[..i], &mantissa[i + 1..])
+          , None => (mantissa, "")
+        };
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if int.len() + frac.len() == 0 || !is_digits(int) || !is_digits(frac) {
+            return None
+        }
+        let mut digits = format!("{}{}", int, frac).into_bytes();
+        let scale = exp.saturating_sub(frac.len() as i64);
+        if !decimal_at_most_max(&digits, scale) {
+            return None
+        }
+        if scale >= 0 {
+            // the value is an integer, which is certainly too large if it
+            // doesn't fit in 128 bits.
+            let zeros = (scale as u64).min(MAX_DECIMAL_DIGITS as u64 + 1) as usize;
+            digits.resize(digits.len() + zeros, b'0');
+            let num = str::from_utf8(&digits).ok()?.parse::<u128>().ok()?;
+            return Weight::exact(num, 1)
+        }
+        let places = scale.unsigned_abs();
+        if places > MAX_DECIMAL_DIGITS as u64 || digits.len() > MAX_DECIMAL_DIGITS {
+            return s.parse::<f64>().ok().map(Weight::approximate)
+        }
+        let places = places as usize;
+        let value = Big::from_decimal(&digits);
+        // the value is `digits / (2^places * 5^places)`, and the numerator
+        // may not fit in 128 bits until the factors it shares with the
+        // denominator are divided out, so that's done on the decimal digits.
+        let (mut twos, mut fives) = (places as u32, places as u32);
+        while fives > 0 && divide_decimal(&mut digits, 5) { fives -= 1 }
+        while twos > 0 && divide_decimal(&mut digits, 2) { twos -= 1 }
+        let exact = str::from_utf8(&digits).ok()
+            .and_then(|digits| digits.parse::<u128>().ok())
+            .and_then(|num| {
+                let den = 2u128.checked_pow(twos)?.checked_mul(5u128.checked_pow(fives)?)?;
+                Weight::exact(num, den)
+            });
+        exact.or_else(|| Weight::rounding_to(&value, places))
+            .or_else(|| s.parse::<f64>().ok().map(Weight::approximate))
+    }
+
+    /// Returns this weight as an `f64`.
+    #[inline] pub fn to_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    /// Returns true if this weight is zero.
+    #[inline] pub fn is_zero(&self) -> bool { self.num == 0 }
+
+    /// Returns the numerator of this weight, in lowest terms.
+    #[inline] pub fn numer(&self) -> u64 { self.num }
+
+    /// Returns the denominator of this weight, in lowest terms.
+    #[inline] pub fn denom(&self) -> u64 { self.den }
+
+    /// Returns `self + rhs` exactly, or `None` if the sum can't be
+    /// represented exactly, because its numerator or denominator doesn't fit
+    /// in 64 bits even in lowest terms.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dtab::weight::Weight;
+    ///
+    /// let third = Weight::ratio(1, 3);
+    /// assert_eq!(third.checked_add(third), Some(Weight::ratio(2, 3)));
+    ///
+    /// let tiny = Weight::ratio(1, u64::MAX);
+    /// assert_eq!(tiny.checked_add(Weight::ratio(1, u64::MAX - 1)), None);
+    /// assert_eq!(Weight::MAX.checked_add(Weight::ONE), None);
+    /// ```
+    pub fn checked_add(self, rhs: Weight) -> Option<Self> {
+        let (a, b) = (u128::from(self.num), u128::from(self.den));
+        let (c, d) = (u128::from(rhs.num), u128::from(rhs.den));
+        let g = gcd(b, d);
+        (a * (d / g)).checked_add(c * (b / g))
+            .and_then(|num| Weight::exact(num, b * (d / g)))
+    }
+
+    /// Returns `self * rhs` exactly, or `None` if the product can't be
+    /// represented exactly, because its numerator or denominator doesn't fit
+    /// in 64 bits even in lowest terms.
+    pub fn checked_mul(self, rhs: Weight) -> Option<Self> {
+        Weight::exact( u128::from(self.num) * u128::from(rhs.num)
+                     , u128::from(self.den) * u128::from(rhs.den))
+    }
+
+    /// Returns `self / rhs` exactly, or `None` if `rhs` is zero, or the
+    /// quotient can't be represented exactly, because its numerator or
+    /// denominator doesn't fit in 64 bits even in lowest terms.
+    pub fn checked_div(self, rhs: Weight) -> Option<Self> {
+        if rhs.is_zero() {
+            return None
+        }
+        Weight::exact( u128::from(self.num) * u128::from(rhs.den)
+                     , u128::from(self.den) * u128::from(rhs.num))
+    }
+
+    /// Reduce `num / den` to lowest terms, falling back to the closest
+    /// float-derived approximation if it still doesn't fit in 64 bits.
+    ///
+    /// This is only for conversions, which are approximate anyway;
+    /// arithmetic uses the `checked_` methods, so that it's clear where
+    /// precision is lost.
+    fn reduce(num: u128, den: u128) -> Self {
+        Weight::exact(num, den)
+            .unwrap_or_else(|| Weight::approximate(num as f64 / den as f64))
+    }
+
+    /// Reduce `num / den` to lowest terms, if it then fits in 64 bits.
+    fn exact(num: u128, den: u128) -> Option<Self> {
+        let g = gcd(num, den).max(1);
+        let (num, den) = (num / g, den / g);
+        if num <= u128::from(u64::MAX) && den <= u128::from(u64::MAX) {
+            Some(Weight { num: num as u64, den: den as u64 })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the simplest weight which rounds to `value / 10^places`, at
+    /// `places` decimal places, if there's one which fits in 64 bits.
+    ///
+    /// Two different weights differ by at least `1 / 2^128`, so a weight
+    /// written to enough places that it is rounded by less than half that
+    /// is the only weight with a denominator as small which rounds to what
+    /// was written, and is read back exactly.
+    fn rounding_to(value: &Big, places: usize) -> Option<Self> {
+        // the weight lies within half a unit in the last place, so between
+        // `(2 * value - 1) / (2 * 10^places)` and `(2 * value + 1) / ...`.
+        let den = Big::from(2u64).shifted(places);
+        let mut lo = value.clone();
+        lo.mul_add(2, 0);
+        let mut hi = lo.clone();
+        hi.mul_add(1, 1);
+        if lo.is_zero() {
+            return Some(Weight::ZERO)
+        }
+        lo.sub(&Big::from(1u64));
+        let (num, den) = simplest_between((lo, den.clone()), (hi, den))?;
+        Weight::exact(num, den)
+    }
+
+    fn approximate(f: f64) -> Self {
+        if f >= u64::MAX as f64 {
+            // saturate, rather than overflowing again.
+            return Weight::MAX
+        }
+        // as many decimal places as fit, up to 18.
+        let mut scale = 1u64;
+        while scale < 1_000_000_000_000_000_000 && f * (scale as f64) * 10.0 < u64::MAX as f64 {
+            scale *= 10;
+        }
+        Weight::reduce((f * scale as f64).round() as u128, u128::from(scale))
+    }
+}
+
+/// The most digits [`Weight::from_decimal`] reads exactly. Every weight's
+/// decimal expansion is shorter, if it terminates.
+///
+/// [`Weight::from_decimal`]: struct.Weight.html#method.from_decimal
+const MAX_DECIMAL_DIGITS: usize = 128;
+
+/// Returns true if the decimal number `digits * 10^scale` is no larger than
+/// [`Weight::MAX`].
+///
+/// [`Weight::MAX`]: struct.Weight.html#associatedconstant.MAX
+fn decimal_at_most_max(digits: &[u8], scale: i64) -> bool {
+    let max = u64::MAX.to_string();
+    let digits = match digits.iter().position(|&d| d != b'0') {
+        Some(start) => &digits[start..]
+      , None => return true
+    };
+    let end = digits.iter().rposition(|&d| d != b'0').map_or(0, |i| i + 1);
+    let scale = scale.saturating_add((digits.len() - end) as i64);
+    let digits = &digits[..end];
+    // how many digits precede the point.
+    let int_len = (digits.len() as i64).saturating_add(scale);
+    if int_len != max.len() as i64 {
+        return int_len < max.len() as i64
+    }
+    // as many digits as the largest weight: compare them, and then any
+    // digits after them are a fraction, which must be zero.
+    let int = digits.iter().cloned()
+        .chain(iter::repeat(b'0'))
+        .take(max.len())
+        .collect::<Vec<u8>>();
+    int.as_slice() < max.as_bytes()
+        || (int.as_slice() == max.as_bytes() && digits.len() <= max.len())
+}
+
+/// Divide the decimal number `digits` by `divisor` if it's divisible,
+/// returning true if it was.
+fn divide_decimal(digits: &mut Vec<u8>, divisor: u8) -> bool {
+    let mut quotient = Vec::with_capacity(digits.len());
+    let mut rem = 0;
+    for &digit in digits.iter() {
+        let n = rem * 10 + (digit - b'0');
+        if !(quotient.is_empty() && n < divisor) {
+            quotient.push(b'0' + n / divisor);
+        }
+        rem = n % divisor;
+    }
+    if rem != 0 {
+        return false
+    }
+    if quotient.is_empty() {
+        quotient.push(b'0');
+    }
+    *digits = quotient;
+    true
+}
+
+/// Returns the simplest fraction in the interval `[lo, hi]`, whose bounds
+/// are given as `(numerator, denominator)`, if its terms fit in 128 bits.
+///
+/// The simplest fraction is the one with the smallest denominator, which is
+/// found by expanding both bounds as continued fractions until they differ.
+fn simplest_between(mut lo: (Big, Big), mut hi: (Big, Big)) -> Option<(u128, u128)> {
+    // the result is `(h1 * t + h0) / (k1 * t + k0)`, where `t` is the
+    // simplest fraction in the interval that remains.
+    let (mut h0, mut h1, mut k0, mut k1) = (0u128, 1u128, 1u128, 0u128);
+    let finish = |t: u128, h0: u128, h1: u128, k0: u128, k1: u128| {
+        Some(( h1.checked_mul(t)?.checked_add(h0)?
+             , k1.checked_mul(t)?.checked_add(k0)?))
+    };
+    loop {
+        let (lo_int, lo_rem) = lo.0.div_rem(&lo.1);
+        let lo_int = lo_int.to_u128()?;
+        if lo_rem.is_zero() {
+            return finish(lo_int, h0, h1, k0, k1)
+        }
+        let (hi_int, hi_rem) = hi.0.div_rem(&hi.1);
+        if Big::from(lo_int) < hi_int {
+            return finish(lo_int.checked_add(1)?, h0, h1, k0, k1)
+        }
+        // both bounds are `n + 1 / x` for the same `n`, so the result is
+        // `n + 1 / t`, where `t` is the simplest fraction between the
+        // reciprocals of their remainders.
+        let h = h1.checked_mul(lo_int)?.checked_add(h0)?;
+        let k = k1.checked_mul(lo_int)?.checked_add(k0)?;
+        h0 = h1;
+        h1 = h;
+        k0 = k1;
+        k1 = k;
+        let (lo_den, hi_den) = (lo.1, hi.1);
+        lo = (hi_den, hi_rem);
+        hi = (lo_den, lo_rem);
+    }
+}
+
+/// A natural number of any size, in little-endian 32-bit limbs, with no
+/// trailing zero limbs, for reading decimals which don't fit in a `u128`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Big(Vec<u32>);
+
+impl Big {
+    fn from_decimal(digits: &[u8]) -> Self {
+        let mut n = Big(Vec::new());
+        for &digit in digits {
+            n.mul_add(10, u32::from(digit - b'0'));
+        }
+        n
+    }
+
+    /// Returns this number times `10^places`.
+    fn shifted(mut self, places: usize) -> Self {
+        for _ in 0..places {
+            self.mul_add(10, 0);
+        }
+        self
+    }
+
+    #[inline] fn is_zero(&self) -> bool { self.0.is_empty() }
+
+    /// Set this number to `self * m + a`.
+    fn mul_add(&mut self, m: u32, a: u32) {
+        let mut carry = u64::from(a);
+        for limb in &mut self.0 {
+            let n = u64::from(*limb) * u64::from(m) + carry;
+            *limb = n as u32;
+            carry = n >> 32;
+        }
+        if carry != 0 {
+            self.0.push(carry as u32);
+        }
+        self.trim();
+    }
+
+    /// Subtract `other`, which must be no larger than this number.
+    fn sub(&mut self, other: &Big) {
+        let mut borrow = 0;
+        for (i, limb) in self.0.iter_mut().enumerate() {
+            let rhs = u64::from(other.0.get(i).cloned().unwrap_or(0)) + borrow;
+            let lhs = u64::from(*limb);
+            borrow = u64::from(lhs < rhs);
+            *limb = (lhs + (borrow << 32) - rhs) as u32;
+        }
+        self.trim();
+    }
+
+    /// Returns the quotient and remainder of dividing by `d`, one bit at a
+    /// time.
+    fn div_rem(&self, d: &Big) -> (Big, Big) {
+        let mut quotient = Big(vec![0; self.0.len()]);
+        let mut rem = Big(Vec::new());
+        for bit in (0..self.0.len() * 32).rev() {
+            rem.mul_add(2, (self.0[bit / 32] >> (bit % 32)) & 1);
+            if rem >= *d {
+                rem.sub(d);
+                quotient.0[bit / 32] |= 1 << (bit % 32);
+            }
+        }
+        quotient.trim();
+        (quotient, rem)
+    }
+
+    fn to_u128(&self) -> Option<u128> {
+        if self.0.len() > 4 {
+            return None
+        }
+        Some(self.0.iter().rev().fold(0, |n, &limb| n << 32 | u128::from(limb)))
+    }
+
+    fn trim(&mut self) {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+    }
+}
+
+impl From<u128> for Big {
+    fn from(mut n: u128) -> Self {
+        let mut limbs = Vec::new();
+        while n != 0 {
+            limbs.push(n as u32);
+            n >>= 32;
+        }
+        Big(limbs)
+    }
+}
+
+impl From<u64> for Big {
+    #[inline] fn from(n: u64) -> Self { Big::from(u128::from(n)) }
+}
+
+impl PartialOrd for Big {
+    #[inline] fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Big {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.len().cmp(&other.0.len())
+            .then_with(|| self.0.iter().rev().cmp(other.0.iter().rev()))
+    }
+}
+
+/// A value which can be used as the weight of a union member.
+///
+/// This is implemented for exact [`Weight`]s, and for `f64`s, which are
+/// converted with [`Weight::saturating_from_f64`], so that weights written
+/// as floats never panic.
+///
+/// [`Weight`]: struct.Weight.html
+/// [`Weight::saturating_from_f64`]: struct.Weight.html#method.saturating_from_f64
+pub trait IntoWeight {
+    /// Converts this value into a weight.
+    fn into_weight(self) -> Weight;
+}
+
+impl IntoWeight for Weight {
+    #[inline] fn into_weight(self) -> Weight { self }
+}
+
+impl IntoWeight for f64 {
+    #[inline] fn into_weight(self) -> Weight { Weight::saturating_from_f64(self) }
+}
+
+impl From<Weight> for f64 {
+    #[inline] fn from(w: Weight) -> f64 { w.to_f64() }
+}
+
+impl Default for Weight {
+    #[inline] fn default() -> Self { Weight::ZERO }
+}
+
+impl fmt::Display for Weight {
+    /// Writes the exact decimal expansion of this weight if it terminates.
+    ///
+    /// Otherwise, the expansion is rounded to 20 more places than the
+    /// denominator has digits, which is enough that this weight is the
+    /// simplest one which rounds to what's written, so that reading it with
+    /// [`Weight::from_decimal`] returns this weight exactly.
+    ///
+    /// If a precision is given, this is written as the nearest `f64` with
+    /// that precision instead.
+    ///
+    /// [`Weight::from_decimal`]: struct.Weight.html#method.from_decimal
+    // `u64::is_multiple_of` is only stable since Rust 1.87.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.precision().is_some() {
+            return fmt::Display::fmt(&self.to_f64(), f)
+        }
+        // the expansion terminates if the only prime factors of the
+        // denominator are 2 and 5.
+        let mut den = self.den;
+        while den % 2 == 0 { den /= 2 }
+        while den % 5 == 0 { den /= 5 }
+        let max_places = if den == 1 {
+            usize::MAX
+        } else {
+            // so that `10^max_places > 2^65 * self.den`.
+            20 + self.den.to_string().len()
+        };
+        let mut digits = (self.num / self.den).to_string().into_bytes();
+        let (mut rem, den) = (u128::from(self.num % self.den), u128::from(self.den));
+        let mut places = 0;
+        while rem != 0 && places < max_places {
+            rem *= 10;
+            digits.push(b'0' + (rem / den) as u8);
+            rem %= den;
+            places += 1;
+        }
+        if rem * 2 > den {
+            // round up, carrying into the digits before.
+            let mut carry = true;
+            for digit in digits.iter_mut().rev() {
+                if *digit == b'9' {
+                    *digit = b'0';
+                } else {
+                    *digit += 1;
+                    carry = false;
+                    break
+                }
+            }
+            if carry {
+                digits.insert(0, b'1');
+            }
+        }
+        let mut decimal = String::from_utf8(digits).expect("digits are ASCII");
+        if places > 0 {
+            decimal.insert(decimal.len() - places, '.');
+        }
+        f.pad(&decimal)
+    }
+}
+
+impl PartialOrd for Weight {
+    #[inline] fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Weight {
+    #[inline] fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (u128::from(self.num) * u128::from(other.den))
+            .cmp(&(u128::from(other.num) * u128::from(self.den)))
+    }
+}
+
+/// Weights are added exactly whenever [`checked_add`] would return the sum.
+/// Otherwise, the sum is approximated from the `f64` sum of the weights, as
+/// [`saturating_from_f64`] converts it, saturating at [`MAX`].
+///
+/// [`checked_add`]: struct.Weight.html#method.checked_add
+/// [`saturating_from_f64`]: struct.Weight.html#method.saturating_from_f64
+/// [`MAX`]: struct.Weight.html#associatedconstant.MAX
+impl ops::Add for Weight {
+    type Output = Self;
+    #[inline] fn add(self, rhs: Self) -> Self {
+        self.checked_add(rhs)
+            .unwrap_or_else(|| Weight::saturating_from_f64(self.to_f64() + rhs.to_f64()))
+    }
+}
+
+/// Weights are multiplied exactly whenever [`checked_mul`] would return the
+/// product. Otherwise, the product is approximated from the `f64` product of
+/// the weights, as [`saturating_from_f64`] converts it, saturating at
+/// [`MAX`].
+///
+/// [`checked_mul`]: struct.Weight.html#method.checked_mul
+/// [`saturating_from_f64`]: struct.Weight.html#method.saturating_from_f64
+/// [`MAX`]: struct.Weight.html#associatedconstant.MAX
+impl ops::Mul for Weight {
+    type Output = Self;
+    #[inline] fn mul(self, rhs: Self) -> Self {
+        self.checked_mul(rhs)
+            .unwrap_or_else(|| Weight::saturating_from_f64(self.to_f64() * rhs.to_f64()))
+    }
+}
+
+/// Weights are divided exactly whenever [`checked_div`] would return the
+/// quotient. Otherwise, the quotient is approximated from the `f64` quotient
+/// of the weights, as [`saturating_from_f64`] converts it, saturating at
+/// [`MAX`].
+///
+/// [`checked_div`]: struct.Weight.html#method.checked_div
+/// [`saturating_from_f64`]: struct.Weight.html#method.saturating_from_f64
+/// [`MAX`]: struct.Weight.html#associatedconstant.MAX
+impl ops::Div for Weight {
+    type Output = Self;
+    /// # Panics
+    ///
+    /// If `rhs` is zero.
+    #[inline] fn div(self, rhs: Self) -> Self {
+        assert!(!rhs.is_zero(), "attempt to divide a weight by zero");
+        self.checked_div(rhs)
+            .unwrap_or_else(|| Weight::saturating_from_f64(self.to_f64() / rhs.to_f64()))
+    }
+}
+
+impl ::std::iter::Sum for Weight {
+    fn sum<I: Iterator<Item=Weight>>(iter: I) -> Self {
+        iter.fold(Weight::ZERO, |acc, w| acc + w)
+    }
+}
+
+/// How union weights should be treated when a tree is written out.
+///
+/// Consumers of dtabs disagree about union weights: some renormalize them
+/// themselves, some use them as-is, and some expect the weights of every
+/// union to already sum to one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WeightPolicy {
+    /// Leave weights exactly as they are.
+    Raw
+  , /// Rescale the weights of every union so that they sum to one.
+    Normalize
+  , /// Require the weights of every union to sum to one, within the given
+    /// tolerance.
+    Require { tolerance: Weight }
+}
+
+impl Default for WeightPolicy {
+    #[inline] fn default() -> Self { WeightPolicy::Raw }
+}
+
+impl WeightPolicy {
+    /// Returns true if a union whose weights sum to `sum` is acceptable
+    /// under this policy.
+    ///
+    /// Only `Require` rejects any sums; unions are rescaled by `Normalize`
+    /// rather than checked.
+    pub fn accepts(&self, sum: Weight) -> bool {
+        match *self {
+            WeightPolicy::Require { tolerance } =>
+                sum <= Weight::ONE + tolerance && sum + tolerance >= Weight::ONE
+          , _ => true
+        }
+    }
+}
+
+/// An error indicating that a union's weights did not sum to one, as
+/// required by [`WeightPolicy::Require`].
+///
+/// [`WeightPolicy::Require`]: enum.WeightPolicy.html#variant.Require
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WeightSumError {
+    /// The index of the dentry containing the union, if the policy was
+    /// applied to a dtab.
+    pub entry: Option<usize>
+  , /// The sum of the union's weights.
+    pub sum: Weight
+  , /// The tolerance that was required.
+    pub tolerance: Weight
+}
+
+impl fmt::Display for WeightSumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!( f, "union weights sum to {}, which is not within {} of 1"
+              , self.sum, self.tolerance)?;
+        if let Some(entry) = self.entry {
+            write!(f, " (in entry {})", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for WeightSumError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn w(f: f64) -> Weight { Weight::from_f64(f).unwrap() }
+
+    #[test]
+    fn checked_arithmetic() {
+        let (third, half) = (Weight::ratio(1, 3), Weight::ratio(1, 2));
+        assert_eq!(third.checked_add(half), Some(Weight::ratio(5, 6)));
+        assert_eq!(third.checked_mul(half), Some(Weight::ratio(1, 6)));
+        assert_eq!(third.checked_div(half), Some(Weight::ratio(2, 3)));
+        assert_eq!(third.checked_div(Weight::ZERO), None);
+        // sums over a shared denominator needn't overflow.
+        let big = Weight::ratio(1, u64::MAX);
+        assert_eq!(big.checked_add(big), Some(Weight::ratio(2, u64::MAX)));
+
+        // results which don't fit are approximated by the operators, and
+        // saturate rather than overflowing.
+        let (a, b) = (Weight::ratio(1, u64::MAX), Weight::ratio(1, u64::MAX - 1));
+        assert_eq!(a.checked_add(b), None);
+        assert!(((a + b).to_f64() - (a.to_f64() + b.to_f64())).abs() < 1e-18);
+        let huge = Weight::ratio(u64::MAX, u64::MAX - 1);
+        assert_eq!(huge.checked_add(Weight::ratio(u64::MAX - 2, u64::MAX - 3)), None);
+        assert_eq!(Weight::MAX.checked_add(Weight::MAX), None);
+        assert_eq!(Weight::MAX + Weight::MAX, Weight::MAX);
+        assert_eq!(Weight::MAX.checked_mul(Weight::MAX), None);
+        assert_eq!(Weight::MAX * Weight::MAX, Weight::MAX);
+        assert_eq!(Weight::MAX.checked_div(Weight::ratio(1, 2)), None);
+        assert_eq!(Weight::MAX / Weight::ratio(1, 2), Weight::MAX);
+    }
+
+    #[test]
+    fn from_f64_is_exact() {
+        assert_eq!(w(0.7), Weight::ratio(7, 10));
+        assert_eq!(w(0.5), Weight::ratio(1, 2));
+        assert_eq!(w(2.0), Weight::ratio(2, 1));
+        assert_eq!(w(1.25e-3), Weight::ratio(1, 800));
+        assert_eq!(w(0.0), Weight::ZERO);
+        assert_eq!(Weight::from_f64(-0.5), None);
+        assert_eq!(Weight::from_f64(f64::NAN), None);
+        assert_eq!(Weight::from_f64(f64::INFINITY), None);
+        assert_eq!(Weight::from_f64(1e30), None);
+    }
+
+    #[test]
+    fn saturating_from_f64() {
+        assert_eq!(Weight::saturating_from_f64(0.7), w(0.7));
+        assert_eq!(Weight::saturating_from_f64(-1.0), Weight::ZERO);
+        assert_eq!(Weight::saturating_from_f64(f64::NAN), Weight::ZERO);
+        assert_eq!(Weight::saturating_from_f64(1e30), Weight::MAX);
+        assert_eq!(Weight::saturating_from_f64(f64::INFINITY), Weight::MAX);
+    }
+
+    #[test]
+    fn from_decimal_exponents() {
+        assert_eq!(Weight::from_decimal("1.0E-4"), Some(Weight::ratio(1, 10_000)));
+        assert_eq!(Weight::from_decimal("5e-1"), Some(Weight::ratio(1, 2)));
+        assert_eq!(Weight::from_decimal(".5E+1"), Some(Weight::ratio(5, 1)));
+        assert_eq!(Weight::from_decimal("0.50e0"), Some(Weight::ratio(1, 2)));
+        assert_eq!(Weight::from_decimal("0e99999999999999999999"), Some(Weight::ZERO));
+        assert_eq!(Weight::from_decimal("1e-400"), Some(Weight::ZERO));
+        assert_eq!(Weight::from_decimal("1e400"), None);
+        assert_eq!(Weight::from_decimal("18446744073709551615"), Some(Weight::MAX));
+        assert_eq!(Weight::from_decimal("18446744073709551615.5"), None);
+        assert_eq!(Weight::from_decimal("18446744073709551616"), None);
+        assert_eq!(Weight::from_decimal("1e-99999999999999999999"), Some(Weight::ZERO));
+        assert_eq!(Weight::from_decimal("1.8446744073709551615e19"), Some(Weight::MAX));
+        assert_eq!(Weight::from_decimal("0001844674407370955161.5e1"), Some(Weight::MAX));
+        let long = format!("18446744073709551614.{}", "9".repeat(200));
+        assert!(Weight::from_decimal(&long).is_some());
+        let long = format!("18446744073709551615.{}1", "0".repeat(200));
+        assert_eq!(Weight::from_decimal(&long), None);
+        assert_eq!(Weight::from_decimal("1e+"), None);
+        assert_eq!(Weight::from_decimal("1e2e3"), None);
+        assert_eq!(Weight::from_decimal("e5"), None);
+    }
+
+    #[test]
+    fn huge_weights_saturate() {
+        let max = Weight::MAX;
+        assert_eq!(max + max, max);
+        assert_eq!(max * max, max);
+        assert_eq!(max / Weight::ratio(1, 2), max);
+    }
+
+    #[test]
+    fn arithmetic_does_not_drift() {
+        let tenth = w(0.1);
+        let sum: Weight = (0..10).map(|_| tenth).sum();
+        assert_eq!(sum, Weight::ONE);
+        assert_eq!(Weight::ratio(1, 3) / Weight::ratio(2, 3), Weight::ratio(1, 2));
+        assert!(Weight::ratio(1, 3) < w(0.34));
+    }
+
+    #[test]
+    fn policy_tolerance() {
+        let policy = WeightPolicy::Require { tolerance: Weight::per_mille(1) };
+        assert!(policy.accepts(Weight::ONE));
+        assert!(policy.accepts(w(0.999)));
+        assert!(policy.accepts(w(1.001)));
+        assert!(!policy.accepts(w(0.998)));
+        assert!(!policy.accepts(w(1.002)));
+        assert!(WeightPolicy::Raw.accepts(Weight::ZERO));
+    }
+
+    #[test]
+    fn display_as_float() {
+        assert_eq!(&Weight::ratio(7, 10).to_string(), "0.7");
+        assert_eq!(&Weight::ONE.to_string(), "1");
+        assert_eq!(&format!("{:.3}", Weight::ratio(2, 3)), "0.667");
+    }
+
+    #[test]
+    fn display_round_trips() {
+        assert_eq!(&Weight::ratio(2, 3).to_string(), "0.666666666666666666667");
+        assert_eq!(&Weight::ratio(5, 3).to_string(), "1.666666666666666666667");
+
+        let mut weights = vec![ Weight::ratio(1, u64::MAX), Weight::ratio(u64::MAX, u64::MAX - 1)
+                              , Weight::ratio(u64::MAX - 1, u64::MAX)
+                              , Weight::ratio(u64::MAX, 3), Weight::ratio(1, 3 << 62) ];
+        // a linear congruential generator, for reproducible fractions of
+        // every size.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            state >> (state % 64)
+        };
+        for _ in 0..2000 {
+            weights.push(Weight::ratio(next(), next().max(1)));
+        }
+        for n in 1..64 {
+            weights.extend((0..=n).map(|k| Weight::ratio(k, n)));
+        }
+        for weight in weights {
+            let text = weight.to_string();
+            assert_eq!(Weight::from_decimal(&text), Some(weight), "{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_simplest_rounding() {
+        assert_eq!(Weight::from_decimal("0.142857142857142857142857"), Some(Weight::ratio(1, 7)));
+        // not every precise decimal rounds from a simpler weight.
+        let pi = "3.14159265358979323846264338327950288419716939937510582097494";
+        assert_eq!(Weight::from_decimal(pi).map(|pi| pi.to_f64()), Some(::std::f64::consts::PI));
+        assert_eq!( Weight::from_decimal("18446744073709551615.0000000000000000000000001")
+                  , None);
+    }
+}